@@ -0,0 +1,497 @@
+// Adjustment layers: color adjustments that carry no pixels of their own
+// and instead change the composite of the layers below them. A layer holds
+// one as `Layer::adjustment`, and the compositor applies it in place of
+// drawing the layer's image.
+
+use std::any::Any;
+use image::{DynamicImage, GenericImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use crate::core::Color;
+use crate::filters::{adjust_hsl_pixel, adjust_value, hsl_to_rgb, rgb_to_hsl, ChannelMixerFilter, ColorBalanceFilter, ColorBalanceTones, Filter, InvertFilter, LevelsChannel, LevelsFilter, PaletteFilter, PosterizeFilter, ShadowsHighlights, ThresholdFilter, VibranceFilter};
+
+/// Adjustment layer trait
+pub trait AdjustmentLayer: std::fmt::Debug + Send + Sync + AdjustmentEq {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage;
+    fn get_type(&self) -> AdjustmentType;
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer>;
+    /// The type and settings of this adjustment, for saving
+    fn settings(&self) -> AdjustmentSettings;
+}
+
+impl Clone for Box<dyn AdjustmentLayer> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Comparison of boxed adjustments, so layers holding them can be compared.
+/// Implemented for every adjustment that is `PartialEq`.
+pub trait AdjustmentEq {
+    fn as_any(&self) -> &dyn Any;
+    /// Whether `other` is the same kind of adjustment with the same settings
+    fn eq_adjustment(&self, other: &dyn AdjustmentLayer) -> bool;
+}
+
+impl<T: AdjustmentLayer + PartialEq + 'static> AdjustmentEq for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_adjustment(&self, other: &dyn AdjustmentLayer) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+impl PartialEq for dyn AdjustmentLayer {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_adjustment(other)
+    }
+}
+
+/// Adjustment type
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdjustmentType {
+    HSL,
+    Curves,
+    Levels,
+    BlackAndWhite,
+    ColorBalance,
+    Vibrance,
+    Brightness,
+    Exposure,
+    Gradient,
+    Invert,
+    Threshold,
+    Posterize,
+    SelectiveColor,
+    ChannelMixer,
+    ShadowsHighlights,
+    Palette,
+}
+
+/// An adjustment's type and settings in a form serde can save; turn it
+/// back into an adjustment with `into_adjustment`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AdjustmentSettings {
+    Invert,
+    ShadowsHighlights { shadows: f32, highlights: f32, radius: f32 },
+    HSL(HSLAdjustment),
+    Levels(LevelsAdjustment),
+    ColorBalance(ColorBalanceAdjustment),
+    Palette(PaletteAdjustment),
+    Posterize(PosterizeAdjustment),
+    Threshold(ThresholdAdjustment),
+    Vibrance(VibranceAdjustment),
+    ChannelMixer(ChannelMixerAdjustment),
+}
+
+impl AdjustmentSettings {
+    /// Rebuild the adjustment these settings describe
+    pub fn into_adjustment(self) -> Box<dyn AdjustmentLayer> {
+        match self {
+            AdjustmentSettings::Invert => Box::new(InvertFilter::new()),
+            AdjustmentSettings::ShadowsHighlights { shadows, highlights, radius } => {
+                Box::new(ShadowsHighlights::new(shadows, highlights, radius))
+            },
+            AdjustmentSettings::HSL(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::Levels(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::ColorBalance(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::Palette(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::Posterize(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::Threshold(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::Vibrance(adjustment) => Box::new(adjustment),
+            AdjustmentSettings::ChannelMixer(adjustment) => Box::new(adjustment),
+        }
+    }
+}
+
+impl AdjustmentLayer for InvertFilter {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        DynamicImage::ImageRgba8(Filter::apply(self, &image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Invert
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(InvertFilter::new())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Invert
+    }
+}
+
+impl AdjustmentLayer for ShadowsHighlights {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        DynamicImage::ImageRgba8(Filter::apply(self, &image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::ShadowsHighlights
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(ShadowsHighlights::new(self.shadows, self.highlights, self.radius))
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::ShadowsHighlights {
+            shadows: self.shadows,
+            highlights: self.highlights,
+            radius: self.radius,
+        }
+    }
+}
+
+// HSL Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HSLAdjustment {
+    pub hue: f32,       // -180 to 180
+    pub saturation: f32, // -100 to 100
+    pub lightness: f32,  // -100 to 100
+    pub colorize: bool,
+    pub color_hue: f32,     // 0 to 360
+    pub color_saturation: f32, // 0 to 100
+    pub ranges: HSLRanges,  // Range adjustments for specific colors
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HSLRanges {
+    pub reds: (f32, f32, f32),     // (hue, saturation, lightness)
+    pub yellows: (f32, f32, f32),
+    pub greens: (f32, f32, f32),
+    pub cyans: (f32, f32, f32),
+    pub blues: (f32, f32, f32),
+    pub magentas: (f32, f32, f32),
+}
+
+impl Default for HSLRanges {
+    fn default() -> Self {
+        Self {
+            reds: (0.0, 0.0, 0.0),
+            yellows: (0.0, 0.0, 0.0),
+            greens: (0.0, 0.0, 0.0),
+            cyans: (0.0, 0.0, 0.0),
+            blues: (0.0, 0.0, 0.0),
+            magentas: (0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl HSLRanges {
+    /// The range deltas in hue order: reds, yellows, greens, cyans, blues, magentas
+    pub fn as_array(&self) -> [(f32, f32, f32); 6] {
+        [self.reds, self.yellows, self.greens, self.cyans, self.blues, self.magentas]
+    }
+}
+
+impl Default for HSLAdjustment {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 0.0,
+            lightness: 0.0,
+            colorize: false,
+            color_hue: 0.0,
+            color_saturation: 0.0,
+            ranges: HSLRanges::default(),
+        }
+    }
+}
+
+impl AdjustmentLayer for HSLAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let mut output = image.clone();
+        let (width, height) = output.dimensions();
+        let global = (self.hue, self.saturation, self.lightness);
+        let ranges = self.ranges.as_array();
+
+        // Apply HSL adjustment to each pixel
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = output.get_pixel(x, y);
+
+                if self.colorize {
+                    // Override hue and saturation, adjust lightness
+                    let (_, _, l) = rgb_to_hsl(pixel[0], pixel[1], pixel[2]);
+                    let h = self.color_hue / 360.0;
+                    let s = self.color_saturation / 100.0;
+                    let l = adjust_value(l, self.lightness / 100.0);
+                    let (r2, g2, b2) = hsl_to_rgb(h, s, l);
+                    output.put_pixel(x, y, image::Rgba([r2, g2, b2, pixel[3]]));
+                } else {
+                    // Global adjustment plus the blended per-range deltas
+                    output.put_pixel(x, y, adjust_hsl_pixel(pixel, global, &ranges));
+                }
+            }
+        }
+
+        output
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::HSL
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::HSL(self.clone())
+    }
+}
+
+// Levels Adjustment
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LevelsAdjustment {
+    pub composite: LevelsChannel,
+    pub red: LevelsChannel,
+    pub green: LevelsChannel,
+    pub blue: LevelsChannel,
+}
+
+impl AdjustmentLayer for LevelsAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = LevelsFilter::new(self.composite, self.red, self.green, self.blue);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Levels
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Levels(self.clone())
+    }
+}
+
+// Color Balance Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorBalanceAdjustment {
+    pub shadows: ColorBalanceTones,
+    pub midtones: ColorBalanceTones,
+    pub highlights: ColorBalanceTones,
+    pub preserve_luminosity: bool,
+}
+
+impl Default for ColorBalanceAdjustment {
+    fn default() -> Self {
+        Self {
+            shadows: ColorBalanceTones::default(),
+            midtones: ColorBalanceTones::default(),
+            highlights: ColorBalanceTones::default(),
+            preserve_luminosity: true,
+        }
+    }
+}
+
+impl AdjustmentLayer for ColorBalanceAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = ColorBalanceFilter::new(self.shadows, self.midtones, self.highlights, self.preserve_luminosity);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::ColorBalance
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::ColorBalance(self.clone())
+    }
+}
+
+// Reduce to Palette Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaletteAdjustment {
+    pub palette: Vec<[u8; 3]>,
+    pub dither: bool,
+}
+
+impl PaletteAdjustment {
+    pub fn new(palette: Vec<Color>, dither: bool) -> Self {
+        Self::from_filter(&PaletteFilter::new(palette, dither))
+    }
+
+    pub fn game_boy(dither: bool) -> Self {
+        Self::from_filter(&PaletteFilter::game_boy(dither))
+    }
+
+    pub fn cga(dither: bool) -> Self {
+        Self::from_filter(&PaletteFilter::cga(dither))
+    }
+
+    pub fn nes(dither: bool) -> Self {
+        Self::from_filter(&PaletteFilter::nes(dither))
+    }
+
+    fn from_filter(filter: &PaletteFilter) -> Self {
+        Self {
+            palette: filter.palette().to_vec(),
+            dither: filter.dither,
+        }
+    }
+}
+
+impl AdjustmentLayer for PaletteAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = PaletteFilter::from_rgb(self.palette.clone(), self.dither);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Palette
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Palette(self.clone())
+    }
+}
+
+// Posterize Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PosterizeAdjustment {
+    pub levels: u8,
+    /// Band red, green and blue separately rather than just the luminance
+    pub per_channel: bool,
+}
+
+impl Default for PosterizeAdjustment {
+    fn default() -> Self {
+        Self {
+            levels: 4,
+            per_channel: true,
+        }
+    }
+}
+
+impl AdjustmentLayer for PosterizeAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = PosterizeFilter::new(self.levels, self.per_channel);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Posterize
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Posterize(self.clone())
+    }
+}
+
+// Threshold Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdAdjustment {
+    pub threshold: u8,
+    pub keep_alpha: bool,
+}
+
+impl Default for ThresholdAdjustment {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            keep_alpha: true,
+        }
+    }
+}
+
+impl AdjustmentLayer for ThresholdAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = ThresholdFilter::new(self.threshold, self.keep_alpha);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Threshold
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Threshold(self.clone())
+    }
+}
+
+// Vibrance Adjustment
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VibranceAdjustment {
+    pub amount: f32,
+}
+
+impl AdjustmentLayer for VibranceAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        DynamicImage::ImageRgba8(VibranceFilter::new(self.amount).apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::Vibrance
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::Vibrance(self.clone())
+    }
+}
+
+// Channel Mixer Adjustment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelMixerAdjustment {
+    /// Row per output channel: weights of the input red, green and blue
+    pub matrix: [[f32; 3]; 3],
+    pub offsets: [f32; 3],
+    pub monochrome: bool,
+}
+
+impl Default for ChannelMixerAdjustment {
+    fn default() -> Self {
+        let identity = ChannelMixerFilter::identity();
+        Self {
+            matrix: identity.matrix,
+            offsets: identity.offsets,
+            monochrome: false,
+        }
+    }
+}
+
+impl AdjustmentLayer for ChannelMixerAdjustment {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = ChannelMixerFilter::new(self.matrix, self.offsets, self.monochrome);
+        DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
+    }
+
+    fn get_type(&self) -> AdjustmentType {
+        AdjustmentType::ChannelMixer
+    }
+
+    fn clone_box(&self) -> Box<dyn AdjustmentLayer> {
+        Box::new(self.clone())
+    }
+
+    fn settings(&self) -> AdjustmentSettings {
+        AdjustmentSettings::ChannelMixer(self.clone())
+    }
+}
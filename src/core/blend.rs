@@ -0,0 +1,262 @@
+// Blend mode math shared by everything that composites pixels.
+//
+// Formulas follow the W3C Compositing and Blending spec where it defines
+// the mode, and Photoshop's behaviour for the ones it doesn't (the linear
+// and light modes, hard mix, divide, subtract and negation). Colors are
+// straight (not premultiplied) alpha throughout.
+
+use image::Rgba;
+
+/// Blending modes for layers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerBlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    // Advanced modes
+    LinearDodge,
+    LinearBurn,
+    LinearLight,
+    VividLight,
+    PinLight,
+    HardMix,
+    Divide,
+    Subtract,
+    Negation,
+}
+
+impl Default for LayerBlendMode {
+    fn default() -> Self {
+        LayerBlendMode::Normal
+    }
+}
+
+impl std::fmt::Display for LayerBlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayerBlendMode::Normal => write!(f, "Normal"),
+            LayerBlendMode::Multiply => write!(f, "Multiply"),
+            LayerBlendMode::Screen => write!(f, "Screen"),
+            LayerBlendMode::Overlay => write!(f, "Overlay"),
+            LayerBlendMode::Darken => write!(f, "Darken"),
+            LayerBlendMode::Lighten => write!(f, "Lighten"),
+            LayerBlendMode::ColorDodge => write!(f, "Color Dodge"),
+            LayerBlendMode::ColorBurn => write!(f, "Color Burn"),
+            LayerBlendMode::HardLight => write!(f, "Hard Light"),
+            LayerBlendMode::SoftLight => write!(f, "Soft Light"),
+            LayerBlendMode::Difference => write!(f, "Difference"),
+            LayerBlendMode::Exclusion => write!(f, "Exclusion"),
+            LayerBlendMode::Hue => write!(f, "Hue"),
+            LayerBlendMode::Saturation => write!(f, "Saturation"),
+            LayerBlendMode::Color => write!(f, "Color"),
+            LayerBlendMode::Luminosity => write!(f, "Luminosity"),
+            LayerBlendMode::LinearDodge => write!(f, "Linear Dodge"),
+            LayerBlendMode::LinearBurn => write!(f, "Linear Burn"),
+            LayerBlendMode::LinearLight => write!(f, "Linear Light"),
+            LayerBlendMode::VividLight => write!(f, "Vivid Light"),
+            LayerBlendMode::PinLight => write!(f, "Pin Light"),
+            LayerBlendMode::HardMix => write!(f, "Hard Mix"),
+            LayerBlendMode::Divide => write!(f, "Divide"),
+            LayerBlendMode::Subtract => write!(f, "Subtract"),
+            LayerBlendMode::Negation => write!(f, "Negation"),
+        }
+    }
+}
+
+impl From<crate::core::BlendMode> for LayerBlendMode {
+    fn from(mode: crate::core::BlendMode) -> Self {
+        use crate::core::BlendMode;
+        match mode {
+            BlendMode::Normal => LayerBlendMode::Normal,
+            BlendMode::Multiply => LayerBlendMode::Multiply,
+            BlendMode::Screen => LayerBlendMode::Screen,
+            BlendMode::Overlay => LayerBlendMode::Overlay,
+            BlendMode::Darken => LayerBlendMode::Darken,
+            BlendMode::Lighten => LayerBlendMode::Lighten,
+            BlendMode::ColorDodge => LayerBlendMode::ColorDodge,
+            BlendMode::ColorBurn => LayerBlendMode::ColorBurn,
+            BlendMode::HardLight => LayerBlendMode::HardLight,
+            BlendMode::SoftLight => LayerBlendMode::SoftLight,
+            BlendMode::Difference => LayerBlendMode::Difference,
+            BlendMode::Exclusion => LayerBlendMode::Exclusion,
+            BlendMode::Hue => LayerBlendMode::Hue,
+            BlendMode::Saturation => LayerBlendMode::Saturation,
+            BlendMode::Color => LayerBlendMode::Color,
+            BlendMode::Luminosity => LayerBlendMode::Luminosity,
+        }
+    }
+}
+
+/// Composite `over` onto `base` with `mode`, `over`'s alpha scaled by
+/// `opacity` (0.0 - 1.0).
+///
+/// Where `base` is transparent the mode has nothing to blend with, so
+/// `over` shows through as it is; where it is partly transparent the
+/// blended and plain colors are mixed by its alpha.
+pub fn blend_pixel(base: Rgba<u8>, over: Rgba<u8>, mode: LayerBlendMode, opacity: f32) -> Rgba<u8> {
+    let source_alpha = over[3] as f32 / 255.0 * opacity.clamp(0.0, 1.0);
+    if source_alpha <= 0.0 {
+        return base;
+    }
+    let base_alpha = base[3] as f32 / 255.0;
+    let out_alpha = source_alpha + base_alpha * (1.0 - source_alpha);
+
+    let b = [base[0] as f32 / 255.0, base[1] as f32 / 255.0, base[2] as f32 / 255.0];
+    let s = [over[0] as f32 / 255.0, over[1] as f32 / 255.0, over[2] as f32 / 255.0];
+    let blended = blend_rgb(mode, b, s);
+
+    let mut result = [0u8; 4];
+    for c in 0..3 {
+        let mixed = s[c] * (1.0 - base_alpha) + blended[c].clamp(0.0, 1.0) * base_alpha;
+        let value = (mixed * source_alpha + b[c] * base_alpha * (1.0 - source_alpha)) / out_alpha;
+        result[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    result[3] = (out_alpha * 255.0).round().clamp(0.0, 255.0) as u8;
+    Rgba(result)
+}
+
+/// The blend function B(base, source) of `mode` on colors in 0.0 - 1.0
+fn blend_rgb(mode: LayerBlendMode, b: [f32; 3], s: [f32; 3]) -> [f32; 3] {
+    match mode {
+        LayerBlendMode::Hue => set_lum(set_sat(s, sat(b)), lum(b)),
+        LayerBlendMode::Saturation => set_lum(set_sat(b, sat(s)), lum(b)),
+        LayerBlendMode::Color => set_lum(s, lum(b)),
+        LayerBlendMode::Luminosity => set_lum(b, lum(s)),
+        _ => [
+            blend_channel(mode, b[0], s[0]),
+            blend_channel(mode, b[1], s[1]),
+            blend_channel(mode, b[2], s[2]),
+        ],
+    }
+}
+
+/// One channel of a separable blend mode
+fn blend_channel(mode: LayerBlendMode, b: f32, s: f32) -> f32 {
+    match mode {
+        LayerBlendMode::Normal => s,
+        LayerBlendMode::Multiply => b * s,
+        LayerBlendMode::Screen => b + s - b * s,
+        LayerBlendMode::Overlay => hard_light(s, b),
+        LayerBlendMode::Darken => b.min(s),
+        LayerBlendMode::Lighten => b.max(s),
+        LayerBlendMode::ColorDodge => color_dodge(b, s),
+        LayerBlendMode::ColorBurn => color_burn(b, s),
+        LayerBlendMode::HardLight => hard_light(b, s),
+        LayerBlendMode::SoftLight => {
+            if s <= 0.5 {
+                b - (1.0 - 2.0 * s) * b * (1.0 - b)
+            } else {
+                let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+                b + (2.0 * s - 1.0) * (d - b)
+            }
+        },
+        LayerBlendMode::Difference => (b - s).abs(),
+        LayerBlendMode::Exclusion => b + s - 2.0 * b * s,
+        LayerBlendMode::LinearDodge => (b + s).min(1.0),
+        LayerBlendMode::LinearBurn => (b + s - 1.0).max(0.0),
+        LayerBlendMode::LinearLight => (b + 2.0 * s - 1.0).clamp(0.0, 1.0),
+        LayerBlendMode::VividLight => {
+            if s <= 0.5 {
+                color_burn(b, 2.0 * s)
+            } else {
+                color_dodge(b, 2.0 * s - 1.0)
+            }
+        },
+        LayerBlendMode::PinLight => {
+            if s <= 0.5 {
+                b.min(2.0 * s)
+            } else {
+                b.max(2.0 * s - 1.0)
+            }
+        },
+        LayerBlendMode::HardMix => if b + s >= 1.0 { 1.0 } else { 0.0 },
+        LayerBlendMode::Divide => {
+            if s <= 0.0 {
+                if b <= 0.0 { 0.0 } else { 1.0 }
+            } else {
+                (b / s).min(1.0)
+            }
+        },
+        LayerBlendMode::Subtract => (b - s).max(0.0),
+        LayerBlendMode::Negation => 1.0 - (1.0 - b - s).abs(),
+        // Non-separable modes are handled on the whole color
+        LayerBlendMode::Hue | LayerBlendMode::Saturation | LayerBlendMode::Color | LayerBlendMode::Luminosity => s,
+    }
+}
+
+fn hard_light(b: f32, s: f32) -> f32 {
+    if s <= 0.5 {
+        2.0 * b * s
+    } else {
+        1.0 - 2.0 * (1.0 - b) * (1.0 - s)
+    }
+}
+
+fn color_dodge(b: f32, s: f32) -> f32 {
+    if b <= 0.0 {
+        0.0
+    } else if s >= 1.0 {
+        1.0
+    } else {
+        (b / (1.0 - s)).min(1.0)
+    }
+}
+
+fn color_burn(b: f32, s: f32) -> f32 {
+    if b >= 1.0 {
+        1.0
+    } else if s <= 0.0 {
+        0.0
+    } else {
+        1.0 - ((1.0 - b) / s).min(1.0)
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+/// Shift `c` to luminosity `l`, pulling it back into gamut towards its
+/// own luminosity
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let c = [c[0] + d, c[1] + d, c[2] + d];
+    let l = lum(c);
+    let (min, max) = (c[0].min(c[1]).min(c[2]), c[0].max(c[1]).max(c[2]));
+    let mut result = c;
+    for channel in &mut result {
+        if min < 0.0 && l - min > 0.0 {
+            *channel = l + (*channel - l) * l / (l - min);
+        }
+        if max > 1.0 && max - l > 0.0 {
+            *channel = l + (*channel - l) * (1.0 - l) / (max - l);
+        }
+    }
+    result
+}
+
+/// `c` with its saturation (max - min) changed to `s`, keeping hue
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let (max, min) = (c[0].max(c[1]).max(c[2]), c[0].min(c[1]).min(c[2]));
+    if max - min <= 0.0 {
+        return [0.0; 3];
+    }
+    c.map(|v| (v - min) * s / (max - min))
+}
@@ -7,8 +7,11 @@ use crate::vector::{Point, VectorPath, VectorDocument, VectorShape};
 use image::{DynamicImage, ImageBuffer, Rgba};
 use std::collections::HashMap;
 use crate::core::layer::{Layer, LayerManager};
-use crate::core::selection::Selection;
+use crate::core::selection::{Rect, Selection};
+use crate::core::tiles::{self, TileCache, TileKey};
+use log::warn;
 use crate::core::document::Document;
+use crate::filters::{mask_from_rgba, mask_to_rgba, resample_image, warp_perspective, Interpolation, ResampleFilter};
 
 /// Available tools for image editing
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -35,6 +38,21 @@ pub enum SelectionType {
     Color,
 }
 
+/// How document pixels are resampled when drawn to the screen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterpolationMode {
+    /// Filtered scaling, suited to photographs
+    Smooth,
+    /// Hard-edged pixels once zoomed in far enough, for pixel art
+    Nearest,
+}
+
+impl Default for InterpolationMode {
+    fn default() -> Self {
+        InterpolationMode::Smooth
+    }
+}
+
 /// Settings for brush-based tools
 #[derive(Debug, Clone)]
 pub struct BrushSettings {
@@ -43,6 +61,8 @@ pub struct BrushSettings {
     pub opacity: f64,
     pub flow: f64,
     pub pressure_sensitivity: bool,
+    /// How much freehand strokes are smoothed, 0.0 (off) - 1.0
+    pub stabilization: f32,
 }
 
 impl Default for BrushSettings {
@@ -53,6 +73,7 @@ impl Default for BrushSettings {
             opacity: 1.0,
             flow: 1.0,
             pressure_sensitivity: true,
+            stabilization: 0.0,
         }
     }
 }
@@ -80,6 +101,10 @@ pub struct Canvas {
     pub has_vector_mode: bool,
     /// The current document
     pub document: Option<Rc<RefCell<Document>>>,
+    /// Resampling used for the document surface when zoomed
+    pub display_interpolation: InterpolationMode,
+    /// Composited tiles of the layers, filled in as they are drawn
+    tile_cache: RefCell<TileCache>,
 }
 
 impl Canvas {
@@ -104,6 +129,8 @@ impl Canvas {
             vector_document: None,
             has_vector_mode: false,
             document: None,
+            display_interpolation: InterpolationMode::default(),
+            tile_cache: RefCell::new(TileCache::default()),
         }
     }
     
@@ -131,14 +158,18 @@ impl Canvas {
             vector_document: None,
             has_vector_mode: false,
             document: None,
+            display_interpolation: InterpolationMode::default(),
+            tile_cache: RefCell::new(TileCache::default()),
         }
     }
     
-    /// Resize the canvas
-    pub fn resize(&mut self, width: u32, height: u32) {
+    /// Change the canvas size without scaling: layers keep their pixels
+    /// and are clipped or padded with transparency
+    pub fn resize_canvas(&mut self, width: u32, height: u32) {
         self.width = width;
         self.height = height;
         self.layer_manager.resize_all_layers(width, height);
+        self.invalidate_all();
         
         // Update vector document
         if let Some(_vector_doc) = &mut self.vector_document {
@@ -151,12 +182,53 @@ impl Canvas {
         }
     }
     
+    /// Scale the whole canvas to `width` x `height`, resampling every layer
+    /// (including the active one) with `filter`.
+    ///
+    /// Layer offsets and masks scale along with the pixels so the layers
+    /// keep their places relative to each other.
+    pub fn resize(&mut self, width: u32, height: u32, filter: ResampleFilter) {
+        let (width, height) = (width.max(1), height.max(1));
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        let sx = width as f64 / self.width.max(1) as f64;
+        let sy = height as f64 / self.height.max(1) as f64;
+        
+        for index in 0..self.layer_manager.layer_count() {
+            let layer = match self.layer_manager.get_layer_mut(index) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let layer_width = ((layer.image.width() as f64 * sx).round() as u32).max(1);
+            let layer_height = ((layer.image.height() as f64 * sy).round() as u32).max(1);
+            layer.image = resample_image(&layer.image, layer_width, layer_height, filter);
+            if let Some(mask) = &layer.mask {
+                layer.mask = Some(image::imageops::resize(mask, layer_width, layer_height, filter.to_filter_type()));
+            }
+            layer.width = layer_width;
+            layer.height = layer_height;
+            layer.x_offset = (layer.x_offset as f64 * sx).round() as i32;
+            layer.y_offset = (layer.y_offset as f64 * sy).round() as i32;
+        }
+        
+        self.width = width;
+        self.height = height;
+        self.selection = None;
+        self.invalidate_all();
+        
+        if self.vector_document.is_some() {
+            self.vector_document = Some(VectorDocument::new(width as i32, height as i32));
+        }
+    }
+    
     /// Crop the canvas to the selection or given rectangle
     pub fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) {
         self.width = width;
         self.height = height;
         self.layer_manager.crop_all_layers(x, y, width, height);
         self.selection = None; // Clear selection after crop
+        self.invalidate_all();
         
         // Update vector document
         if let Some(_vector_doc) = &mut self.vector_document {
@@ -169,6 +241,67 @@ impl Canvas {
         }
     }
     
+    /// Crop to `rect` turned `angle` degrees clockwise around its center,
+    /// straightening what it covers into an upright `rect`-sized canvas.
+    ///
+    /// Every layer and mask is resampled (bilinear) through the inverse
+    /// rotation; parts of the frame outside a layer come out transparent.
+    /// An angle of zero is a plain pixel crop.
+    pub fn crop_rotated(&mut self, rect: Rect, angle: f64) -> Result<(), String> {
+        let (width, height) = (rect.width.round(), rect.height.round());
+        if width < 1.0 || height < 1.0 {
+            return Err("Crop area is empty".to_string());
+        }
+        if angle.rem_euclid(360.0) == 0.0 {
+            let (x, y) = (rect.x.round().max(0.0) as u32, rect.y.round().max(0.0) as u32);
+            self.crop(x, y, width as u32, height as u32);
+            return Ok(());
+        }
+
+        // Corners of the turned frame: top-left, top-right, bottom-right,
+        // bottom-left, as the warp expects them
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (cx, cy) = (rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+        let corner = |dx: f64, dy: f64| (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos);
+        let (hw, hh) = (width / 2.0, height / 2.0);
+        let quad = [corner(-hw, -hh), corner(hw, -hh), corner(hw, hh), corner(-hw, hh)];
+        let (width, height) = (width as u32, height as u32);
+
+        // Resample everything first so a failure leaves the canvas as it was
+        let mut cropped = Vec::with_capacity(self.layer_manager.layer_count());
+        for layer in self.layer_manager.get_layers() {
+            let layer_quad = quad.map(|(x, y)| (x - layer.x_offset as f64, y - layer.y_offset as f64));
+            let image = warp_perspective(&layer.image, layer_quad, width, height, Interpolation::Bilinear)?;
+            let mask = match &layer.mask {
+                Some(mask) => {
+                    let warped = warp_perspective(&mask_to_rgba(mask), layer_quad, width, height, Interpolation::Bilinear)?;
+                    Some(mask_from_rgba(&warped))
+                },
+                None => None,
+            };
+            cropped.push((image, mask));
+        }
+
+        for (index, (image, mask)) in cropped.into_iter().enumerate() {
+            if let Some(layer) = self.layer_manager.get_layer_mut(index) {
+                layer.image = image;
+                layer.mask = mask;
+                layer.width = width;
+                layer.height = height;
+                layer.x_offset = 0;
+                layer.y_offset = 0;
+            }
+        }
+        self.width = width;
+        self.height = height;
+        self.selection = None;
+        self.invalidate_all();
+        if self.vector_document.is_some() {
+            self.vector_document = Some(VectorDocument::new(width as i32, height as i32));
+        }
+        Ok(())
+    }
+    
     /// Crop to the current selection
     pub fn crop_to_selection(&mut self) -> bool {
         if let Some(selection) = &self.selection {
@@ -249,6 +382,28 @@ impl Canvas {
         // }
     }
     
+    /// Zoom and pan so the whole document fits the viewport, centered
+    pub fn zoom_to_fit(&mut self, viewport_width: u32, viewport_height: u32) {
+        let document = Rect { x: 0.0, y: 0.0, width: self.width as f64, height: self.height as f64 };
+        self.zoom_to_rect(document, viewport_width, viewport_height);
+    }
+    
+    /// Zoom and pan so `rect`, in canvas coordinates, fills as much of the
+    /// viewport as it can while staying whole, centered. The zoom limits of
+    /// `set_zoom` still apply, so a very large or tiny rect may not fit
+    /// exactly. Empty rects and viewports leave the view alone.
+    pub fn zoom_to_rect(&mut self, rect: Rect, viewport_width: u32, viewport_height: u32) {
+        if rect.width <= 0.0 || rect.height <= 0.0 || viewport_width == 0 || viewport_height == 0 {
+            return;
+        }
+        let (view_width, view_height) = (viewport_width as f64, viewport_height as f64);
+        self.set_zoom((view_width / rect.width).min(view_height / rect.height));
+        
+        // Solve canvas_to_screen(center of rect) == center of the viewport
+        self.offset_x = view_width / 2.0 - (rect.x + rect.width / 2.0) * self.zoom;
+        self.offset_y = view_height / 2.0 - (rect.y + rect.height / 2.0) * self.zoom;
+    }
+    
     /// Pan the view
     pub fn pan(&mut self, delta_x: f64, delta_y: f64) {
         self.offset_x += delta_x;
@@ -260,6 +415,19 @@ impl Canvas {
         // }
     }
     
+    /// Zoom level from which `InterpolationMode::Nearest` shows hard pixels.
+    /// Below it a screen pixel covers more than half a document pixel, and
+    /// nearest-neighbour would only add aliasing.
+    pub const NEAREST_ZOOM_THRESHOLD: f64 = 2.0;
+    
+    /// Cairo filter to use for the document surface at the current zoom
+    pub fn display_filter(&self) -> cairo::Filter {
+        match self.display_interpolation {
+            InterpolationMode::Nearest if self.zoom >= Self::NEAREST_ZOOM_THRESHOLD => cairo::Filter::Nearest,
+            _ => cairo::Filter::Good,
+        }
+    }
+    
     /// Render the canvas to a Cairo context
     pub fn render(&self, context: &Context, view_width: u32, view_height: u32) {
         // Clear the background (using a checkerboard pattern for transparency)
@@ -271,7 +439,7 @@ impl Canvas {
         context.scale(self.zoom, self.zoom);
         
         // Render all layers
-        self.layer_manager.render(context, self.width, self.height);
+        self.render_tiles(context);
         
         // Render the selection if present
         if let Some(selection) = &self.selection {
@@ -311,6 +479,70 @@ impl Canvas {
         context.restore();
     }
     
+    /// Forget the composited pixels of the tiles overlapping `rect`, in
+    /// canvas coordinates, so the next draw composites them again. Call it
+    /// after changing layer pixels there; returns the dropped tile keys.
+    pub fn invalidate_region(&self, rect: Rect) -> Vec<TileKey> {
+        self.tile_cache.borrow_mut().invalidate_region(&rect)
+    }
+    
+    /// Forget every composited tile, e.g. after layers were added, removed,
+    /// reordered or had their visibility, opacity or blend mode changed
+    pub fn invalidate_all(&self) {
+        self.tile_cache.borrow_mut().clear();
+    }
+    
+    /// Keys of the tiles that currently hold composited pixels
+    pub fn cached_tiles(&self) -> Vec<TileKey> {
+        let cache = self.tile_cache.borrow();
+        let columns = self.width.div_ceil(cache.tile_size());
+        let rows = self.height.div_ceil(cache.tile_size());
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .filter(|&key| cache.contains(key))
+            .collect()
+    }
+    
+    /// Composited pixels of tile `key`, from the cache if it has them
+    pub fn composite_tile(&self, key: TileKey) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut cache = self.tile_cache.borrow_mut();
+        let (x, y, width, height) = cache.tile_bounds(key, self.width, self.height);
+        cache.get_or_render(key, || self.layer_manager.flatten_region(x as i64, y as i64, width, height))
+            .clone()
+    }
+    
+    /// Draw the tiles inside the context's clip, compositing the ones not
+    /// cached yet. Expects the context to be in canvas coordinates already.
+    fn render_tiles(&self, context: &Context) {
+        let visible = match context.clip_extents() {
+            Ok((x1, y1, x2, y2)) => Rect { x: x1, y: y1, width: x2 - x1, height: y2 - y1 },
+            Err(_) => Rect { x: 0.0, y: 0.0, width: self.width as f64, height: self.height as f64 },
+        };
+        let mut cache = self.tile_cache.borrow_mut();
+        for key in cache.keys_overlapping(&visible, self.width, self.height) {
+            let (x, y, width, height) = cache.tile_bounds(key, self.width, self.height);
+            let tile = cache.get_or_render(key, || self.layer_manager.flatten_region(x as i64, y as i64, width, height));
+            let surface = match tiles::to_cairo_surface(tile) {
+                Ok(surface) => surface,
+                Err(e) => {
+                    warn!("Failed to draw tile {:?}: {}", key, e);
+                    continue;
+                },
+            };
+            
+            context.save().ok();
+            if context.set_source_surface(&surface, x as f64, y as f64).is_ok() {
+                // Pad so neighbouring tiles don't fade into each other at
+                // their seams when the view is scaled
+                context.source().set_extend(cairo::Extend::Pad);
+                context.source().set_filter(self.display_filter());
+                context.rectangle(x as f64, y as f64, width as f64, height as f64);
+                context.fill().ok();
+            }
+            context.restore().ok();
+        }
+    }
+    
     /// Render a checkerboard pattern for transparency
     fn render_transparency_pattern(&self, context: &Context, width: u32, height: u32) {
         let cell_size = 16.0; // Size of each checkerboard cell
@@ -403,7 +635,7 @@ impl Canvas {
         
         // Render regular layers
         if !self.has_vector_mode {
-            self.layer_manager.render(context, self.width, self.height);
+            self.render_tiles(context);
             
             // Render selection outline
             if let Some(selection) = &self.selection {
@@ -514,5 +746,7 @@ impl Canvas {
             self.height = doc.height;
             self.layer_manager = doc.layer_manager.clone();
         }
+        // Tiles cached for the previous document no longer apply
+        self.invalidate_all();
     }
 } 
\ No newline at end of file
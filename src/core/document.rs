@@ -2,10 +2,22 @@ use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::fmt;
 use uuid::Uuid;
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Rgba};
 use crate::core::layer::{Layer, LayerManager};
-use std::collections::HashMap;
+use crate::core::history::{HistoryCommand, HistoryManager, LayerOpacityCommand, LayerPixelsCommand, LayerReplaceCommand};
+use crate::core::export::{self, ExportError, ExportFormat, ExportOptions};
+use crate::core::metadata;
+use crate::core::native;
+use crate::core::Color;
+use crate::core::selection::Selection;
+use crate::filters::{detect_dominant_angle, inpaint, mask_from_rgba, mask_to_rgba, Filter, lab_to_rgb, rgb_to_lab, rotate_image, Interpolation};
+use std::collections::{HashMap, HashSet};
 use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+// `save` writes JPEGs at the image crate's default quality; use `export`
+// to choose another
+const SAVE_JPEG_QUALITY: u8 = 75;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ColorSpace {
@@ -79,7 +91,7 @@ impl DocumentFormat {
             "tif" | "tiff" => Some(DocumentFormat::TIFF),
             "webp" => Some(DocumentFormat::WebP),
             "afphoto" => Some(DocumentFormat::AffinityPhoto),
-            "aprs" => Some(DocumentFormat::Native),
+            "rpho" | "aprs" => Some(DocumentFormat::Native),
             _ => None,
         }
     }
@@ -92,13 +104,153 @@ impl DocumentFormat {
             DocumentFormat::TIFF => "tiff",
             DocumentFormat::WebP => "webp",
             DocumentFormat::AffinityPhoto => "afphoto",
-            DocumentFormat::Native => "aprs",
+            DocumentFormat::Native => "rpho",
+        }
+    }
+}
+
+/// Direction of a ruler guide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuideOrientation {
+    /// A horizontal line at a fixed y
+    Horizontal,
+    /// A vertical line at a fixed x
+    Vertical,
+}
+
+/// A ruler guide placed on the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Guide {
+    pub orientation: GuideOrientation,
+    /// Distance from the top (horizontal) or left (vertical) edge in pixels
+    pub position: u32,
+}
+
+/// What to fill newly added canvas area with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentBackground {
+    /// Leave the new area transparent
+    Transparent,
+    /// Opaque white
+    White,
+    /// Opaque black
+    Black,
+    /// The document's `background_color`
+    BackgroundColor,
+    /// A specific color
+    Color(Rgba<u8>),
+}
+
+/// Result of counting the distinct colors in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniqueColorResult {
+    /// The exact number of distinct RGBA colors
+    Exact(usize),
+    /// More distinct colors than the requested maximum
+    OverMax,
+}
+
+/// What a histogram counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistogramChannel {
+    Red,
+    Green,
+    Blue,
+    /// Rec. 601 luma, the weighting filter previews use
+    Luminance,
+    Alpha,
+}
+
+impl HistogramChannel {
+    fn value(&self, pixel: &Rgba<u8>) -> u8 {
+        match self {
+            HistogramChannel::Red => pixel[0],
+            HistogramChannel::Green => pixel[1],
+            HistogramChannel::Blue => pixel[2],
+            HistogramChannel::Luminance => {
+                (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) as u8
+            },
+            HistogramChannel::Alpha => pixel[3],
+        }
+    }
+}
+
+/// A single channel of a layer's pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Rec. 709 luminance of the RGB channels
+    Luma,
+}
+
+/// Color model used to split a layer into channel layers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelMode {
+    Rgb,
+    /// Naive conversion without an ICC profile; each plate shows ink as dark
+    Cmyk,
+    /// L* scaled to 0 - 255, a* and b* offset by 128
+    Lab,
+}
+
+impl ChannelMode {
+    /// Names given to the channel layers, in order
+    pub fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            ChannelMode::Rgb => &["R", "G", "B"],
+            ChannelMode::Cmyk => &["C", "M", "Y", "K"],
+            ChannelMode::Lab => &["L", "a", "b"],
+        }
+    }
+    
+    fn split(&self, rgb: [u8; 3]) -> Vec<u8> {
+        match self {
+            ChannelMode::Rgb => rgb.to_vec(),
+            ChannelMode::Cmyk => {
+                let [r, g, b] = rgb.map(|v| v as f32 / 255.0);
+                let k = 1.0 - r.max(g).max(b);
+                let ink = |v: f32| if k < 1.0 { (1.0 - v - k) / (1.0 - k) } else { 0.0 };
+                [ink(r), ink(g), ink(b), k].iter()
+                    .map(|amount| ((1.0 - amount) * 255.0).round().clamp(0.0, 255.0) as u8)
+                    .collect()
+            },
+            ChannelMode::Lab => {
+                let [l, a, b] = rgb_to_lab(rgb);
+                vec![
+                    (l * 2.55).round().clamp(0.0, 255.0) as u8,
+                    (a + 128.0).round().clamp(0.0, 255.0) as u8,
+                    (b + 128.0).round().clamp(0.0, 255.0) as u8,
+                ]
+            },
+        }
+    }
+    
+    fn merge(&self, values: &[u8]) -> [u8; 3] {
+        match self {
+            ChannelMode::Rgb => [values[0], values[1], values[2]],
+            ChannelMode::Cmyk => {
+                let k = 1.0 - values[3] as f32 / 255.0;
+                let mut rgb = [0u8; 3];
+                for c in 0..3 {
+                    let ink = 1.0 - values[c] as f32 / 255.0;
+                    rgb[c] = ((1.0 - ink) * (1.0 - k) * 255.0).round().clamp(0.0, 255.0) as u8;
+                }
+                rgb
+            },
+            ChannelMode::Lab => lab_to_rgb([
+                values[0] as f32 / 2.55,
+                values[1] as f32 - 128.0,
+                values[2] as f32 - 128.0,
+            ]),
         }
     }
 }
 
 /// Metadata for a document
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DocumentMetadata {
     /// Title of the document
     pub title: String,
@@ -116,6 +268,9 @@ pub struct DocumentMetadata {
     pub modification_time: SystemTime,
     /// Custom metadata
     pub custom: HashMap<String, String>,
+    /// Camera settings from the EXIF block of the opened photo
+    #[serde(default)]
+    pub exif: Option<metadata::ExifData>,
 }
 
 impl Default for DocumentMetadata {
@@ -129,12 +284,13 @@ impl Default for DocumentMetadata {
             creation_time: SystemTime::now(),
             modification_time: SystemTime::now(),
             custom: HashMap::new(),
+            exif: None,
         }
     }
 }
 
 /// Represents a document in the application
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Document {
     /// Path to the document file
     pub path: Option<PathBuf>,
@@ -152,6 +308,25 @@ pub struct Document {
     pub dpi: f32,
     /// Background color
     pub background_color: Rgba<u8>,
+    /// Ruler guides, in the order they were added
+    pub guides: Vec<Guide>,
+    /// Undo/redo history for edits made through the document API
+    pub history: HistoryManager,
+}
+
+// Two documents are equal when their content is; undo history is not compared
+impl PartialEq for Document {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.format == other.format
+            && self.width == other.width
+            && self.height == other.height
+            && self.layer_manager == other.layer_manager
+            && self.metadata == other.metadata
+            && self.dpi == other.dpi
+            && self.background_color == other.background_color
+            && self.guides == other.guides
+    }
 }
 
 impl Document {
@@ -173,6 +348,8 @@ impl Document {
             metadata: DocumentMetadata::default(),
             dpi: 300.0,
             background_color: Rgba([255, 255, 255, 255]), // White background
+            guides: Vec::new(),
+            history: HistoryManager::new(),
         }
     }
     
@@ -237,6 +414,8 @@ impl Document {
             metadata,
             dpi: 300.0,
             background_color: Rgba([255, 255, 255, 255]), // White background
+            guides: Vec::new(),
+            history: HistoryManager::new(),
         }
     }
     
@@ -245,11 +424,34 @@ impl Document {
         let path = path.as_ref();
         info!("Opening document from path: {:?}", path);
         
+        // Native documents are recognized by content, whatever the extension
+        let bytes = std::fs::read(path).ok();
+        if let Some(bytes) = &bytes {
+            if native::is_native(bytes) {
+                let mut document = native::decode(bytes).map_err(|err| {
+                    error!("Failed to open document: {}", err);
+                    err
+                })?;
+                document.path = Some(path.to_path_buf());
+                document.format = DocumentFormat::Native;
+                info!("Native document loaded successfully");
+                return Ok(document);
+            }
+        }
+        
         // Load the image
         match image::open(path) {
             Ok(img) => {
                 info!("Image loaded successfully");
-                Ok(Self::from_image(img, Some(path.to_path_buf())))
+                // Phones store photos sideways and record how to turn them
+                let exif = bytes.as_deref().and_then(metadata::read_exif);
+                let img = match &exif {
+                    Some(exif) => metadata::apply_orientation(img, exif.orientation),
+                    None => img,
+                };
+                let mut document = Self::from_image(img, Some(path.to_path_buf()));
+                document.metadata.exif = exif;
+                Ok(document)
             },
             Err(err) => {
                 error!("Failed to open image: {}", err);
@@ -284,17 +486,20 @@ impl Document {
         match format {
             DocumentFormat::JPEG => {
                 info!("Saving as JPEG");
-                if let Err(err) = dynamic_image.save_with_format(path, image::ImageFormat::Jpeg) {
-                    error!("Failed to save as JPEG: {}", err);
-                    return Err(format!("Failed to save as JPEG: {}", err));
-                }
+                let rgb = DynamicImage::ImageRgb8(dynamic_image.to_rgb8());
+                self.write_with_metadata(path, &rgb, image::ImageOutputFormat::Jpeg(SAVE_JPEG_QUALITY), metadata::embed_jpeg_xmp)
+                    .map_err(|err| {
+                        error!("Failed to save as JPEG: {}", err);
+                        format!("Failed to save as JPEG: {}", err)
+                    })?;
             }
             DocumentFormat::PNG => {
                 info!("Saving as PNG");
-                if let Err(err) = dynamic_image.save_with_format(path, image::ImageFormat::Png) {
-                    error!("Failed to save as PNG: {}", err);
-                    return Err(format!("Failed to save as PNG: {}", err));
-                }
+                self.write_with_metadata(path, &dynamic_image, image::ImageOutputFormat::Png, metadata::embed_png_text)
+                    .map_err(|err| {
+                        error!("Failed to save as PNG: {}", err);
+                        format!("Failed to save as PNG: {}", err)
+                    })?;
             }
             DocumentFormat::TIFF => {
                 info!("Saving as TIFF");
@@ -310,9 +515,16 @@ impl Document {
                     return Err(format!("Failed to save as WebP: {}", err));
                 }
             }
-            DocumentFormat::AffinityPhoto | DocumentFormat::Native => {
-                error!("Native format saving not implemented");
-                return Err("Saving in native format not yet implemented".to_string());
+            DocumentFormat::Native => {
+                info!("Saving as native document");
+                native::save(self, path).map_err(|err| {
+                    error!("Failed to save native document: {}", err);
+                    err
+                })?;
+            }
+            DocumentFormat::AffinityPhoto => {
+                error!("Affinity Photo saving not implemented");
+                return Err("Saving in Affinity Photo format not yet implemented".to_string());
             }
         }
         
@@ -341,6 +553,210 @@ impl Document {
         self.layer_manager.crop_all_layers(x, y, width, height);
     }
     
+    /// Encode `image` in memory, let `embed` add the document metadata to the
+    /// encoded bytes, then write the result to `path`
+    fn write_with_metadata(
+        &self,
+        path: &Path,
+        image: &DynamicImage,
+        format: image::ImageOutputFormat,
+        embed: fn(&[u8], &[(&str, Vec<String>)]) -> Result<Vec<u8>, String>,
+    ) -> Result<(), String> {
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut encoded, format).map_err(|e| e.to_string())?;
+        
+        let fields = metadata::text_fields(&self.metadata);
+        debug!("Embedding {} metadata fields", fields.len());
+        let bytes = embed(encoded.get_ref(), &fields)?;
+        std::fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+    
+    /// Document title
+    pub fn title(&self) -> &str {
+        &self.metadata.title
+    }
+    
+    pub fn set_title(&mut self, title: &str) {
+        self.metadata.title = title.to_string();
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Author, written to exported files as the creator
+    pub fn author(&self) -> Option<&str> {
+        self.metadata.author.as_deref()
+    }
+    
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.metadata.author = author;
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Free-form description or caption
+    pub fn description(&self) -> Option<&str> {
+        self.metadata.description.as_deref()
+    }
+    
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.metadata.description = description;
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Copyright notice
+    pub fn copyright(&self) -> Option<&str> {
+        self.metadata.copyright.as_deref()
+    }
+    
+    pub fn set_copyright(&mut self, copyright: Option<String>) {
+        self.metadata.copyright = copyright;
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Keywords/tags, in the order they were added
+    pub fn keywords(&self) -> &[String] {
+        &self.metadata.keywords
+    }
+    
+    pub fn set_keywords(&mut self, keywords: Vec<String>) {
+        self.metadata.keywords = keywords;
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Add a keyword unless it is already present (ignoring case)
+    pub fn add_keyword(&mut self, keyword: &str) {
+        let keyword = keyword.trim();
+        if keyword.is_empty() || self.metadata.keywords.iter().any(|k| k.eq_ignore_ascii_case(keyword)) {
+            return;
+        }
+        self.metadata.keywords.push(keyword.to_string());
+        self.metadata.modification_time = SystemTime::now();
+    }
+    
+    /// Remove a keyword, returning whether it was present
+    pub fn remove_keyword(&mut self, keyword: &str) -> bool {
+        let before = self.metadata.keywords.len();
+        self.metadata.keywords.retain(|k| !k.eq_ignore_ascii_case(keyword));
+        let removed = self.metadata.keywords.len() != before;
+        if removed {
+            self.metadata.modification_time = SystemTime::now();
+        }
+        removed
+    }
+    
+    /// Enlarge the canvas by the given margins without scaling anything.
+    ///
+    /// Every layer grows by the same margins, so existing content keeps its
+    /// place relative to the rest of the image. On the bottom layer the new
+    /// area is filled according to `fill`; layers above it stay transparent
+    /// there so they don't hide the fill.
+    pub fn expand_canvas(&mut self, left: u32, top: u32, right: u32, bottom: u32, fill: DocumentBackground) {
+        let width = self.width + left + right;
+        let height = self.height + top + bottom;
+        info!("Expanding canvas from {}x{} to {}x{}", self.width, self.height, width, height);
+        
+        let fill_color = match fill {
+            DocumentBackground::Transparent => Rgba([0, 0, 0, 0]),
+            DocumentBackground::White => Rgba([255, 255, 255, 255]),
+            DocumentBackground::Black => Rgba([0, 0, 0, 255]),
+            DocumentBackground::BackgroundColor => self.background_color,
+            DocumentBackground::Color(color) => color,
+        };
+        
+        for index in 0..self.layer_manager.layer_count() {
+            let layer = match self.layer_manager.get_layer_mut(index) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            
+            let new_area = if index == 0 { fill_color } else { Rgba([0, 0, 0, 0]) };
+            let layer_width = layer.image.width() + left + right;
+            let layer_height = layer.image.height() + top + bottom;
+            let mut image = ImageBuffer::from_pixel(layer_width, layer_height, new_area);
+            image::imageops::replace(&mut image, &layer.image, left as i64, top as i64);
+            
+            layer.width = layer_width;
+            layer.height = layer_height;
+            layer.image = image;
+            layer.reframe_mask(-(left as i64), -(top as i64), layer_width, layer_height);
+        }
+        
+        self.width = width;
+        self.height = height;
+    }
+    
+    /// Add a ruler guide
+    pub fn add_guide(&mut self, orientation: GuideOrientation, position: u32) {
+        debug!("Adding {:?} guide at {}", orientation, position);
+        self.guides.push(Guide { orientation, position });
+    }
+    
+    /// Sorted slice boundaries along one axis, from 0 to `extent`
+    fn guide_cuts(&self, orientation: GuideOrientation, extent: u32) -> Result<Vec<u32>, String> {
+        let mut cuts: Vec<u32> = self.guides.iter()
+            .filter(|guide| guide.orientation == orientation)
+            .map(|guide| guide.position)
+            .collect();
+        cuts.sort_unstable();
+        
+        for window in cuts.windows(2) {
+            if window[0] == window[1] {
+                return Err(format!("Duplicate {:?} guides at {}", orientation, window[0]));
+            }
+        }
+        if let Some(&position) = cuts.iter().find(|&&p| p == 0 || p >= extent) {
+            return Err(format!("{:?} guide at {} is not inside the canvas", orientation, position));
+        }
+        
+        cuts.insert(0, 0);
+        cuts.push(extent);
+        Ok(cuts)
+    }
+    
+    /// Cut the flattened document along its guides and save each piece to
+    /// `dir` as `slice_<row>_<column>.<ext>`, rows and columns counted from 0.
+    ///
+    /// Guides must lie strictly inside the canvas and not coincide. Returns
+    /// the paths written, row by row.
+    pub fn export_slices(&self, dir: &Path, format: DocumentFormat) -> Result<Vec<PathBuf>, String> {
+        let image_format = match format {
+            DocumentFormat::JPEG => image::ImageFormat::Jpeg,
+            DocumentFormat::PNG => image::ImageFormat::Png,
+            DocumentFormat::TIFF => image::ImageFormat::Tiff,
+            DocumentFormat::WebP => image::ImageFormat::WebP,
+            DocumentFormat::AffinityPhoto | DocumentFormat::Native => {
+                return Err(format!("Cannot export slices as {:?}", format));
+            }
+        };
+        
+        let columns = self.guide_cuts(GuideOrientation::Vertical, self.width)?;
+        let rows = self.guide_cuts(GuideOrientation::Horizontal, self.height)?;
+        
+        let flattened = self.layer_manager.flatten();
+        let mut paths = Vec::new();
+        
+        info!("Exporting {}x{} slices to {:?}", rows.len() - 1, columns.len() - 1, dir);
+        for (row, ys) in rows.windows(2).enumerate() {
+            for (column, xs) in columns.windows(2).enumerate() {
+                let slice = image::imageops::crop_imm(&flattened, xs[0], ys[0], xs[1] - xs[0], ys[1] - ys[0]).to_image();
+                let path = dir.join(format!("slice_{}_{}.{}", row, column, format.to_extension()));
+                
+                // JPEG has no alpha channel
+                let result = if format == DocumentFormat::JPEG {
+                    DynamicImage::ImageRgba8(slice).to_rgb8().save_with_format(&path, image_format)
+                } else {
+                    slice.save_with_format(&path, image_format)
+                };
+                result.map_err(|err| {
+                    error!("Failed to save slice {:?}: {}", path, err);
+                    format!("Failed to save slice {:?}: {}", path, err)
+                })?;
+                
+                paths.push(path);
+            }
+        }
+        
+        Ok(paths)
+    }
+    
     /// Add a new layer to the document
     pub fn add_layer(&mut self, layer: Layer) -> usize {
         self.layer_manager.add_layer(layer)
@@ -352,12 +768,628 @@ impl Document {
         self.layer_manager.add_layer(layer)
     }
     
-    /// Export the document as a DynamicImage
-    pub fn export(&self) -> DynamicImage {
+    /// Stamp all visible layers into a new top layer, optionally removing
+    /// the layers that were merged
+    pub fn merge_visible(&mut self, keep_originals: bool) -> Result<usize, String> {
+        self.layer_manager.merge_visible_with(keep_originals)
+    }
+    
+    /// Merge the layer at `index` into the one below it
+    pub fn merge_down(&mut self, index: usize) -> Result<usize, String> {
+        self.layer_manager.merge_down(index)
+    }
+    
+    /// Set a layer's opacity (0.0 - 1.0) as an undoable step
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f64) -> Result<(), String> {
+        let layer = self.layer_manager.get_layer_mut(index)
+            .ok_or_else(|| format!("No layer at index {}", index))?;
+        let old_opacity = layer.opacity;
+        layer.set_opacity(opacity);
+        let new_opacity = layer.opacity;
+        if old_opacity == new_opacity {
+            return Ok(());
+        }
+        
+        let command = LayerOpacityCommand::new(layer.id.clone(), old_opacity, new_opacity);
+        self.history.push_applied(Box::new(command), self.metadata.title.clone());
+        self.record_history_thumbnail();
+        Ok(())
+    }
+    
+    /// Cross-dissolve between two frame layers: `t` = 0.0 gives frame `a`,
+    /// 1.0 gives frame `b`.
+    ///
+    /// Each frame is placed on a document-sized canvas first, so frames with
+    /// different offsets line up, and the mix is done on premultiplied color
+    /// so transparent areas don't pull the result towards black. Used for
+    /// onion skins and for tweening between frames on export. A missing
+    /// layer counts as an empty frame.
+    pub fn blend_frames(&self, a: usize, b: usize, t: f32) -> DynamicImage {
+        let t = t.clamp(0.0, 1.0);
+        let render = |index: usize| match self.layer_manager.get_layer(index) {
+            Some(layer) => layer.render_to_image(self.width, self.height),
+            None => {
+                warn!("No frame layer at index {}", index);
+                ImageBuffer::new(self.width, self.height)
+            },
+        };
+        let (from, to) = (render(a), render(b));
+        
+        let blended = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let (p, q) = (from.get_pixel(x, y), to.get_pixel(x, y));
+            let (pa, qa) = (p[3] as f32 / 255.0, q[3] as f32 / 255.0);
+            let alpha = pa * (1.0 - t) + qa * t;
+            if alpha <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            let mut out = [0u8; 4];
+            for c in 0..3 {
+                let value = (p[c] as f32 * pa * (1.0 - t) + q[c] as f32 * qa * t) / alpha;
+                out[c] = value.round().clamp(0.0, 255.0) as u8;
+            }
+            out[3] = (alpha * 255.0).round() as u8;
+            Rgba(out)
+        });
+        DynamicImage::ImageRgba8(blended)
+    }
+    
+    /// Suggest an auto-straighten angle from the dominant near-horizontal
+    /// line in the flattened document, within 20° of level.
+    ///
+    /// Positive means the line falls towards the right; rotating the
+    /// layers by the negated angle levels it. Returns None when the image
+    /// has no clear horizon.
+    pub fn detect_horizon(&self) -> Option<f64> {
+        const MAX_TILT: f64 = 20.0;
+        detect_dominant_angle(&self.layer_manager.flatten(), MAX_TILT)
+    }
+    
+    /// Rotate a single layer's pixels by `degrees` around its center.
+    ///
+    /// The layer grows to fit the rotated pixels and its offset is adjusted
+    /// so the center stays put. Quarter turns are done losslessly.
+    pub fn rotate_layer(&mut self, index: usize, degrees: f32) -> Result<(), String> {
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| format!("Layer index {} out of bounds", index))?
+            .clone();
+        
+        let turns = degrees / 90.0;
+        let quarter_turns = if (turns - turns.round()).abs() < 1e-6 {
+            Some((turns.round() as i64).rem_euclid(4))
+        } else {
+            None
+        };
+        let image = match quarter_turns {
+            Some(1) => image::imageops::rotate90(&before.image),
+            Some(2) => image::imageops::rotate180(&before.image),
+            Some(3) => image::imageops::rotate270(&before.image),
+            Some(_) => before.image.clone(),
+            None => rotate_image(&before.image, degrees, Interpolation::Bicubic, true),
+        };
+        let mask = before.mask.as_ref().map(|mask| match quarter_turns {
+            Some(1) => image::imageops::rotate90(mask),
+            Some(2) => image::imageops::rotate180(mask),
+            Some(3) => image::imageops::rotate270(mask),
+            Some(_) => mask.clone(),
+            None => mask_from_rgba(&rotate_image(&mask_to_rgba(mask), degrees, Interpolation::Bicubic, true)),
+        });
+        
+        info!("Rotating layer {} by {}°", before.name, degrees);
+        let after = Self::with_new_pixels(&before, image, mask);
+        self.apply_layer_change("Rotate Layer", index, before, after);
+        Ok(())
+    }
+    
+    /// Scale a single layer's pixels by `sx` x `sy` around its center
+    pub fn scale_layer(&mut self, index: usize, sx: f32, sy: f32) -> Result<(), String> {
+        if !(sx > 0.0 && sy > 0.0) {
+            return Err("Scale factors must be positive".to_string());
+        }
+        
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| format!("Layer index {} out of bounds", index))?
+            .clone();
+        
+        let width = ((before.image.width() as f32 * sx).round() as u32).max(1);
+        let height = ((before.image.height() as f32 * sy).round() as u32).max(1);
+        
+        info!("Scaling layer {} to {}x{}", before.name, width, height);
+        let image = image::imageops::resize(&before.image, width, height, image::imageops::FilterType::CatmullRom);
+        let mask = before.mask.as_ref()
+            .map(|mask| image::imageops::resize(mask, width, height, image::imageops::FilterType::CatmullRom));
+        let after = Self::with_new_pixels(&before, image, mask);
+        self.apply_layer_change("Scale Layer", index, before, after);
+        Ok(())
+    }
+    
+    /// Shift the colors of the active layer that are close to `target`
+    /// towards `replacement`.
+    ///
+    /// `fuzziness` (0.0 - 1.0) is the largest normalized RGB distance from
+    /// `target` that is affected; pixels move less the further they are from
+    /// it, so the edges of a replaced area blend in. Each pixel keeps its
+    /// own variation because the shift is `replacement - target`. With
+    /// `preserve_luminosity` the result is rescaled to the original luminance.
+    pub fn replace_color(&mut self, target: Color, replacement: Color, fuzziness: f32, preserve_luminosity: bool) -> Result<(), String> {
+        let index = self.layer_manager.get_active_layer_index();
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| "No active layer".to_string())?
+            .clone();
+        
+        let fuzziness = fuzziness.clamp(0.0, 1.0);
+        let target = [target.r, target.g, target.b];
+        let shift = [replacement.r - target[0], replacement.g - target[1], replacement.b - target[2]];
+        let luminance = |c: &[f32; 3]| 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+        
+        info!("Replacing color {:?} on layer {} (fuzziness {})", target, before.name, fuzziness);
+        let mut image = before.image.clone();
+        for pixel in image.pixels_mut() {
+            let original = [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0];
+            let distance = (0..3)
+                .map(|c| (original[c] - target[c]).powi(2))
+                .sum::<f32>()
+                .sqrt() / 3.0f32.sqrt();
+            
+            let weight = if fuzziness > 0.0 {
+                (1.0 - distance / fuzziness).max(0.0)
+            } else if distance == 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+            if weight == 0.0 {
+                continue;
+            }
+            
+            let mut shifted = [0.0f32; 3];
+            for c in 0..3 {
+                shifted[c] = (original[c] + weight * shift[c]).clamp(0.0, 1.0);
+            }
+            
+            if preserve_luminosity {
+                let wanted = luminance(&original);
+                let current = luminance(&shifted);
+                if current > 1e-4 {
+                    for value in shifted.iter_mut() {
+                        *value = (*value * wanted / current).clamp(0.0, 1.0);
+                    }
+                } else {
+                    shifted = [wanted; 3];
+                }
+            }
+            
+            for c in 0..3 {
+                pixel[c] = (shifted[c] * 255.0).round() as u8;
+            }
+        }
+        
+        let mut after = before.clone();
+        after.image = image;
+        self.apply_layer_change("Replace Color", index, before, after);
+        Ok(())
+    }
+    
+    /// Remove whatever is inside `selection` from the active layer by
+    /// filling it with texture synthesized from the pixels around it.
+    ///
+    /// The selection is in document coordinates; pixels at least half
+    /// selected are replaced. Recorded as one undoable step.
+    pub fn content_aware_fill(&mut self, selection: &Selection) -> Result<(), String> {
+        const PATCH_RADIUS: u32 = 3;
+        
+        let index = self.layer_manager.get_active_layer_index();
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| "No active layer".to_string())?
+            .clone();
+        
+        let (mask_width, mask_height) = selection.mask.dimensions();
+        let mut covered = false;
+        let mask = GrayImage::from_fn(before.image.width(), before.image.height(), |x, y| {
+            let doc_x = x as i64 + before.x_offset as i64;
+            let doc_y = y as i64 + before.y_offset as i64;
+            let inside = doc_x >= 0 && doc_y >= 0 && doc_x < mask_width as i64 && doc_y < mask_height as i64
+                && selection.mask.get_pixel(doc_x as u32, doc_y as u32)[0] >= 128;
+            covered |= inside;
+            Luma([if inside { 255 } else { 0 }])
+        });
+        if !covered {
+            return Err("The selection doesn't cover the active layer".to_string());
+        }
+        
+        info!("Content-aware fill on layer {}", before.name);
+        let mut after = before.clone();
+        after.image = inpaint(&before.image, &mask, PATCH_RADIUS)?;
+        self.apply_layer_change("Content-Aware Fill", index, before, after);
+        Ok(())
+    }
+    
+    /// One channel of the active layer as a grayscale image.
+    ///
+    /// Returns an all-black image of the document's size when there is no
+    /// active layer.
+    pub fn extract_channel(&self, channel: Channel) -> GrayImage {
+        let layer = match self.layer_manager.get_active_layer() {
+            Some(layer) => layer,
+            None => return GrayImage::new(self.width, self.height),
+        };
+        
+        debug!("Extracting {:?} channel of layer {}", channel, layer.name);
+        GrayImage::from_fn(layer.image.width(), layer.image.height(), |x, y| {
+            let p = layer.image.get_pixel(x, y);
+            let value = match channel {
+                Channel::Red => p[0],
+                Channel::Green => p[1],
+                Channel::Blue => p[2],
+                Channel::Alpha => p[3],
+                Channel::Luma => (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32).round() as u8,
+            };
+            Luma([value])
+        })
+    }
+    
+    /// Write `data` into one channel of the active layer.
+    ///
+    /// `data` must match the layer's size. Writing `Channel::Luma` shifts
+    /// each pixel's RGB equally so its luminance becomes the new value,
+    /// which keeps the color differences between channels where possible.
+    pub fn apply_channel(&mut self, channel: Channel, data: &GrayImage) -> Result<(), String> {
+        let index = self.layer_manager.get_active_layer_index();
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| "No active layer".to_string())?
+            .clone();
+        
+        if data.dimensions() != before.image.dimensions() {
+            return Err(format!(
+                "Channel data is {}x{} but the layer is {}x{}",
+                data.width(), data.height(), before.image.width(), before.image.height()
+            ));
+        }
+        
+        info!("Writing {:?} channel of layer {}", channel, before.name);
+        let mut image = before.image.clone();
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let value = data.get_pixel(x, y)[0];
+            match channel {
+                Channel::Red => pixel[0] = value,
+                Channel::Green => pixel[1] = value,
+                Channel::Blue => pixel[2] = value,
+                Channel::Alpha => pixel[3] = value,
+                Channel::Luma => {
+                    let current = 0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32;
+                    let delta = value as f32 - current;
+                    for c in 0..3 {
+                        pixel[c] = (pixel[c] as f32 + delta).round().clamp(0.0, 255.0) as u8;
+                    }
+                },
+            }
+        }
+        
+        let mut after = before.clone();
+        after.image = image;
+        let name = format!("Apply {:?} Channel", channel);
+        self.apply_layer_change(&name, index, before, after);
+        Ok(())
+    }
+    
+    /// Replace the active layer with one grayscale layer per channel of
+    /// `mode`, named after the channels ("R", "G", "B" and so on).
+    ///
+    /// The channel layers take the original's place in channel order, the
+    /// first lowest, and keep its alpha, offset and mask. Returns their
+    /// indices for `merge_channels`. Not recorded in the history.
+    pub fn split_channels(&mut self, mode: ChannelMode) -> Result<Vec<usize>, String> {
+        let index = self.layer_manager.get_active_layer_index();
+        let original = self.layer_manager.get_layer(index)
+            .ok_or_else(|| "No active layer".to_string())?
+            .clone();
+        
+        info!("Splitting layer {} into {:?} channels", original.name, mode);
+        let names = mode.channel_names();
+        let mut planes: Vec<ImageBuffer<Rgba<u8>, Vec<u8>>> = names.iter()
+            .map(|_| ImageBuffer::new(original.image.width(), original.image.height()))
+            .collect();
+        for (x, y, pixel) in original.image.enumerate_pixels() {
+            for (plane, value) in planes.iter_mut().zip(mode.split([pixel[0], pixel[1], pixel[2]])) {
+                plane.put_pixel(x, y, Rgba([value, value, value, pixel[3]]));
+            }
+        }
+        
+        self.layer_manager.remove_layer(index);
+        let mut indices = Vec::with_capacity(planes.len());
+        for (offset, (plane, name)) in planes.into_iter().zip(names).enumerate() {
+            let mut layer = original.duplicate(name.to_string());
+            layer.image = plane;
+            indices.push(self.layer_manager.insert_layer(index + offset, layer));
+        }
+        Ok(indices)
+    }
+    
+    /// Recombine channel layers made by `split_channels` into one color
+    /// layer.
+    ///
+    /// `indices` lists the layers in channel order and must have as many
+    /// entries as `mode` has channels, all the same size. Each layer's red
+    /// channel is read as the channel value and the first layer's alpha is
+    /// kept. The merged layer replaces them at the lowest of their indices
+    /// and becomes active; its index is returned.
+    pub fn merge_channels(&mut self, mode: ChannelMode, indices: &[usize]) -> Result<usize, String> {
+        let names = mode.channel_names();
+        if indices.len() != names.len() {
+            return Err(format!("{:?} needs {} channel layers, got {}", mode, names.len(), indices.len()));
+        }
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        if sorted.len() != indices.len() {
+            return Err("Layer indices must be unique".to_string());
+        }
+        let channels = indices.iter()
+            .map(|&i| self.layer_manager.get_layer(i).ok_or_else(|| format!("No layer at index {}", i)))
+            .collect::<Result<Vec<&Layer>, String>>()?;
+        let dimensions = channels[0].image.dimensions();
+        if channels.iter().any(|layer| layer.image.dimensions() != dimensions) {
+            return Err("Channel layers must all be the same size".to_string());
+        }
+        
+        info!("Merging {} layers as {:?} channels", channels.len(), mode);
+        let image = ImageBuffer::from_fn(dimensions.0, dimensions.1, |x, y| {
+            let values: Vec<u8> = channels.iter().map(|layer| layer.image.get_pixel(x, y)[0]).collect();
+            let [r, g, b] = mode.merge(&values);
+            Rgba([r, g, b, channels[0].image.get_pixel(x, y)[3]])
+        });
+        let mut merged = channels[0].duplicate("Merged".to_string());
+        merged.image = image;
+        
+        for &i in sorted.iter().rev() {
+            self.layer_manager.remove_layer(i);
+        }
+        Ok(self.layer_manager.insert_layer(sorted[0], merged))
+    }
+    
+    /// Copy of `layer` holding `image` and `mask`, transformed the same way,
+    /// re-centered on the old layer's center
+    fn with_new_pixels(layer: &Layer, image: ImageBuffer<Rgba<u8>, Vec<u8>>, mask: Option<GrayImage>) -> Layer {
+        let mut result = layer.clone();
+        result.mask = mask;
+        result.x_offset += (layer.image.width() as i32 - image.width() as i32) / 2;
+        result.y_offset += (layer.image.height() as i32 - image.height() as i32) / 2;
+        result.width = image.width();
+        result.height = image.height();
+        result.image = image;
+        result
+    }
+    
+    fn apply_layer_change(&mut self, name: &str, index: usize, before: Layer, after: Layer) {
+        // A pixel-only edit keeps just the changed rectangle for undo
+        let pixels = if before.same_except_pixels(&after) {
+            LayerPixelsCommand::from_diff(name, after.id.clone(), &before.image, &after.image)
+        } else {
+            None
+        };
+        let command: Box<dyn HistoryCommand> = match pixels {
+            Some(command) => Box::new(command),
+            None => Box::new(LayerReplaceCommand::new(name, index, before, after.clone())),
+        };
+        
+        self.layer_manager.set_layer(index, after);
+        self.history.push_applied(command, self.metadata.title.clone());
+        self.record_history_thumbnail();
+    }
+    
+    /// Run `filter` over the active layer as one undoable step
+    pub fn apply_filter(&mut self, filter: &dyn Filter) -> Result<(), String> {
+        let index = self.layer_manager.get_active_layer_index();
+        let before = self.layer_manager.get_layer(index)
+            .ok_or_else(|| "No active layer".to_string())?
+            .clone();
+        
+        info!("Applying {} to layer {}", filter.name(), before.name);
+        let mut after = before.clone();
+        after.image = filter.apply(&before.image);
+        if after.image.dimensions() != before.image.dimensions() {
+            after.width = after.image.width();
+            after.height = after.image.height();
+        }
+        self.apply_layer_change(filter.name(), index, before, after);
+        Ok(())
+    }
+    
+    /// Keep at most `limit` undo steps, dropping the oldest beyond that
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.history.set_max_undo_levels(limit);
+    }
+    
+    /// Flatten the document and scale it to fit within `max_width` x `max_height`,
+    /// preserving the aspect ratio
+    pub fn generate_thumbnail(&self, max_width: u32, max_height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let flattened = self.layer_manager.flatten();
+        let (width, height) = flattened.dimensions();
+        if width == 0 || height == 0 {
+            return flattened;
+        }
+        
+        let scale = (max_width as f64 / width as f64)
+            .min(max_height as f64 / height as f64)
+            .min(1.0);
+        let thumb_width = ((width as f64 * scale).round() as u32).max(1);
+        let thumb_height = ((height as f64 * scale).round() as u32).max(1);
+        debug!("Generating {}x{} thumbnail for {}", thumb_width, thumb_height, self.metadata.title);
+        image::imageops::thumbnail(&flattened, thumb_width, thumb_height)
+    }
+    
+    /// Count the distinct RGBA colors of the flattened document, giving up
+    /// as soon as there are more than `max`.
+    ///
+    /// An exact count of at most 256 means the image can be saved as an
+    /// indexed palette without losing anything.
+    pub fn count_unique_colors(&self, max: usize) -> UniqueColorResult {
+        let flattened = self.layer_manager.flatten();
+        let mut colors = HashSet::new();
+        
+        for pixel in flattened.pixels() {
+            colors.insert(u32::from_le_bytes(pixel.0));
+            if colors.len() > max {
+                debug!("Document has more than {} unique colors", max);
+                return UniqueColorResult::OverMax;
+            }
+        }
+        
+        debug!("Document has {} unique colors", colors.len());
+        UniqueColorResult::Exact(colors.len())
+    }
+    
+    /// Count how many pixels of the flattened document have each value of
+    /// `channel`
+    pub fn compute_histogram(&self, channel: HistogramChannel) -> [u32; 256] {
+        self.histogram_where(channel, |_, _| true)
+    }
+    
+    /// Like `compute_histogram`, but only over the pixels at least half
+    /// selected in `selection`, which is in document coordinates
+    pub fn compute_selection_histogram(&self, channel: HistogramChannel, selection: &Selection) -> [u32; 256] {
+        let (mask_width, mask_height) = selection.mask.dimensions();
+        self.histogram_where(channel, |x, y| {
+            x < mask_width && y < mask_height && selection.mask.get_pixel(x, y)[0] >= 128
+        })
+    }
+    
+    fn histogram_where(&self, channel: HistogramChannel, include: impl Fn(u32, u32) -> bool) -> [u32; 256] {
+        let flattened = self.layer_manager.flatten();
+        let mut histogram = [0u32; 256];
+        for (x, y, pixel) in flattened.enumerate_pixels() {
+            if include(x, y) {
+                histogram[channel.value(pixel) as usize] += 1;
+            }
+        }
+        debug!("Computed {:?} histogram of {}x{} document", channel, flattened.width(), flattened.height());
+        histogram
+    }
+    
+    // Give the newest history step a preview for the history panel
+    fn record_history_thumbnail(&mut self) {
+        if self.history.thumbnail_budget() == 0 {
+            return;
+        }
+        let size = self.history.thumbnail_size();
+        let thumbnail = self.generate_thumbnail(size, size);
+        self.history.set_latest_thumbnail(thumbnail);
+    }
+    
+    /// Undo the most recent document edit
+    pub fn undo(&mut self) -> bool {
+        let mut history = std::mem::take(&mut self.history);
+        let result = history.undo(self);
+        self.history = history;
+        result
+    }
+    
+    /// Redo the most recently undone document edit
+    pub fn redo(&mut self) -> bool {
+        let mut history = std::mem::take(&mut self.history);
+        let result = history.redo(self);
+        self.history = history;
+        result
+    }
+    
+    /// Jump to a history state; see `HistoryManager::goto`
+    pub fn goto_history(&mut self, state_index: usize) -> bool {
+        let mut history = std::mem::take(&mut self.history);
+        let result = history.goto(state_index, self);
+        self.history = history;
+        result
+    }
+    
+    /// The flattened document as a DynamicImage
+    pub fn export_image(&self) -> DynamicImage {
         let flattened = self.layer_manager.flatten();
         DynamicImage::ImageRgba8(flattened)
     }
     
+    /// Export to `path` with explicit encoder settings.
+    ///
+    /// The visible layers are merged (or just the active layer is taken when
+    /// `options.flatten` is off), optionally resized, and encoded with the
+    /// document's descriptive metadata. Transparency is composited over
+    /// `options.background` for formats without alpha. Unlike `save`, this
+    /// leaves the document's path and format alone.
+    pub fn export<P: AsRef<Path>>(&self, path: P, options: ExportOptions) -> Result<(), ExportError> {
+        let path = path.as_ref();
+        info!("Exporting document to {:?} as {:?}", path, options.format);
+        
+        let image = if options.flatten {
+            self.layer_manager.flatten()
+        } else {
+            self.layer_manager.get_active_layer()
+                .ok_or(ExportError::NoImage)?
+                .image
+                .clone()
+        };
+        
+        let bytes = export::encode(&image, &options, &metadata::text_fields(&self.metadata))?;
+        std::fs::write(path, bytes).map_err(|e| {
+            error!("Failed to export {:?}: {}", path, e);
+            ExportError::Io(e.to_string())
+        })
+    }
+    
+    /// Export the flattened document as the best-quality JPEG that fits in
+    /// `max_bytes`, returning the quality used.
+    ///
+    /// The quality is binary-searched, which assumes file size grows with
+    /// quality (true in practice for JPEG). The image crate only writes
+    /// lossless WebP, so a WebP export either fits at quality 100 or fails.
+    pub fn export_for_web<P: AsRef<Path>>(&self, path: P, max_bytes: usize, format: ExportFormat) -> Result<u8, String> {
+        let path = path.as_ref();
+        let image = self.layer_manager.flatten();
+        let fields = metadata::text_fields(&self.metadata);
+        let encode = |quality: u8| -> Result<Vec<u8>, String> {
+            let options = ExportOptions {
+                format,
+                jpeg_quality: quality,
+                ..ExportOptions::default()
+            };
+            export::encode(&image, &options, &fields).map_err(|e| e.to_string())
+        };
+        
+        let (quality, bytes) = match format {
+            ExportFormat::JPEG => {
+                let smallest = encode(1)?;
+                if smallest.len() > max_bytes {
+                    return Err(format!("Even the lowest quality needs {} bytes, over the {} byte budget",
+                                       smallest.len(), max_bytes));
+                }
+                
+                // Invariant: `best` fits; qualities above `high` don't
+                let mut best = (1u8, smallest);
+                let (mut low, mut high) = (2u8, 100u8);
+                while low <= high {
+                    let quality = low + (high - low) / 2;
+                    let bytes = encode(quality)?;
+                    if bytes.len() <= max_bytes {
+                        best = (quality, bytes);
+                        low = quality + 1;
+                    } else {
+                        high = quality - 1;
+                    }
+                }
+                best
+            },
+            ExportFormat::WebP => {
+                let bytes = encode(100)?;
+                if bytes.len() > max_bytes {
+                    return Err(format!("Lossless WebP needs {} bytes, over the {} byte budget",
+                                       bytes.len(), max_bytes));
+                }
+                (100, bytes)
+            },
+            ExportFormat::PNG => return Err("PNG has no quality setting to fit a size budget".to_string()),
+        };
+        
+        info!("Exporting {:?} for web at quality {} ({} bytes)", path, quality, bytes.len());
+        std::fs::write(path, bytes).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        Ok(quality)
+    }
+    
     /// Open a document from a file path
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
         Self::from_file(path)
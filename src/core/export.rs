@@ -0,0 +1,172 @@
+// Exporting a document to a flat image file with explicit encoder options.
+//
+// Unlike `Document::save`, which picks the format from the extension and
+// uses fixed settings, an export takes an `ExportOptions` describing the
+// format, quality, an optional resize and what to do with transparency.
+
+use std::fmt;
+use std::io::Cursor;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageBuffer, ImageEncoder, Rgba};
+use crate::core::metadata;
+
+/// File formats an export can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    PNG,
+    JPEG,
+    /// Written losslessly; the quality setting doesn't apply
+    WebP,
+}
+
+/// Settings for `Document::export`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// JPEG quality, 0 - 100
+    pub jpeg_quality: u8,
+    /// PNG compression level, 0 (fastest) - 9 (smallest)
+    pub png_compression: u8,
+    /// Export every visible layer merged; otherwise just the active layer
+    pub flatten: bool,
+    /// Scale the result to this size before encoding
+    pub resize: Option<(u32, u32)>,
+    /// Color transparent areas are composited over for formats without alpha
+    pub background: Rgba<u8>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::PNG,
+            jpeg_quality: 90,
+            png_compression: 6,
+            flatten: true,
+            resize: None,
+            background: Rgba([255, 255, 255, 255]),
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn png() -> Self {
+        Self::default()
+    }
+
+    pub fn jpeg(quality: u8) -> Self {
+        Self {
+            format: ExportFormat::JPEG,
+            jpeg_quality: quality,
+            ..Self::default()
+        }
+    }
+
+    pub fn webp() -> Self {
+        Self {
+            format: ExportFormat::WebP,
+            ..Self::default()
+        }
+    }
+}
+
+/// Why an export failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// The options can't be honoured, e.g. a zero-sized resize
+    InvalidOptions(String),
+    /// There was nothing to export
+    NoImage,
+    /// The encoder rejected the image
+    Encode(String),
+    /// The file couldn't be written
+    Io(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::InvalidOptions(reason) => write!(f, "Invalid export options: {}", reason),
+            ExportError::NoImage => write!(f, "Nothing to export"),
+            ExportError::Encode(reason) => write!(f, "Failed to encode image: {}", reason),
+            ExportError::Io(reason) => write!(f, "Failed to write file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Composite `image` over an opaque `background`
+pub fn flatten_onto(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, background: Rgba<u8>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut output = image.clone();
+    for pixel in output.pixels_mut() {
+        let alpha = pixel[3] as f32 / 255.0;
+        for c in 0..3 {
+            let value = pixel[c] as f32 * alpha + background[c] as f32 * (1.0 - alpha);
+            pixel[c] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        pixel[3] = 255;
+    }
+    output
+}
+
+fn png_compression(level: u8) -> CompressionType {
+    match level {
+        0..=3 => CompressionType::Fast,
+        4..=6 => CompressionType::Default,
+        _ => CompressionType::Best,
+    }
+}
+
+/// Encode `image` as `options` describe, embedding `fields` as metadata
+/// where the format supports it
+pub fn encode(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    options: &ExportOptions,
+    fields: &[(&str, Vec<String>)],
+) -> Result<Vec<u8>, ExportError> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(ExportError::NoImage);
+    }
+    if options.jpeg_quality > 100 {
+        return Err(ExportError::InvalidOptions(format!("JPEG quality {} is above 100", options.jpeg_quality)));
+    }
+    if options.png_compression > 9 {
+        return Err(ExportError::InvalidOptions(format!("PNG compression level {} is above 9", options.png_compression)));
+    }
+
+    let image = match options.resize {
+        Some((0, _)) | Some((_, 0)) => {
+            return Err(ExportError::InvalidOptions("Export size must be at least 1x1".to_string()));
+        },
+        Some((width, height)) if (width, height) != image.dimensions() => {
+            image::imageops::resize(image, width, height, image::imageops::FilterType::Lanczos3)
+        },
+        _ => image.clone(),
+    };
+
+    let mut encoded = Vec::new();
+    match options.format {
+        ExportFormat::PNG => {
+            PngEncoder::new_with_quality(&mut encoded, png_compression(options.png_compression), FilterType::Adaptive)
+                .write_image(image.as_raw(), image.width(), image.height(), image::ColorType::Rgba8)
+                .map_err(|e| ExportError::Encode(e.to_string()))?;
+            metadata::embed_png_text(&encoded, fields).map_err(ExportError::Encode)
+        },
+        ExportFormat::JPEG => {
+            // JPEG has no alpha channel
+            let opaque = DynamicImage::ImageRgba8(flatten_onto(&image, options.background)).to_rgb8();
+            JpegEncoder::new_with_quality(&mut encoded, options.jpeg_quality.max(1))
+                .write_image(opaque.as_raw(), opaque.width(), opaque.height(), image::ColorType::Rgb8)
+                .map_err(|e| ExportError::Encode(e.to_string()))?;
+            metadata::embed_jpeg_xmp(&encoded, fields).map_err(ExportError::Encode)
+        },
+        ExportFormat::WebP => {
+            let mut cursor = Cursor::new(encoded);
+            DynamicImage::ImageRgba8(image)
+                .write_to(&mut cursor, image::ImageFormat::WebP)
+                .map_err(|e| ExportError::Encode(e.to_string()))?;
+            Ok(cursor.into_inner())
+        },
+    }
+}
@@ -1,20 +1,31 @@
 use std::fmt;
+use image::{ImageBuffer, Rgba};
+use crate::core::document::Document;
+use crate::core::layer::Layer;
 
-// Trait for commands that can be undone/redone
+// Trait for commands that can be undone/redone against a document
 pub trait HistoryCommand: fmt::Debug {
-    fn execute(&mut self) -> bool;
-    fn undo(&mut self) -> bool;
-    fn redo(&mut self) -> bool {
-        self.execute()
+    fn execute(&mut self, doc: &mut Document) -> bool;
+    fn undo(&mut self, doc: &mut Document) -> bool;
+    fn redo(&mut self, doc: &mut Document) -> bool {
+        self.execute(doc)
     }
     fn get_name(&self) -> String;
+    fn box_clone(&self) -> Box<dyn HistoryCommand>;
+}
+
+impl Clone for Box<dyn HistoryCommand> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
 }
 
 // A single state in the history stack
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HistoryState {
     command: Box<dyn HistoryCommand>,
     document_id: String,
+    thumbnail: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
 }
 
 impl HistoryState {
@@ -22,15 +33,25 @@ impl HistoryState {
         Self {
             command,
             document_id,
+            thumbnail: None,
         }
     }
     
-    pub fn undo(&mut self) -> bool {
-        self.command.undo()
+    /// Preview of the document right after this step, if one was kept
+    pub fn thumbnail(&self) -> Option<&ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        self.thumbnail.as_ref()
+    }
+    
+    fn thumbnail_bytes(&self) -> usize {
+        self.thumbnail.as_ref().map_or(0, |thumbnail| thumbnail.as_raw().len())
     }
     
-    pub fn redo(&mut self) -> bool {
-        self.command.redo()
+    pub fn undo(&mut self, doc: &mut Document) -> bool {
+        self.command.undo(doc)
+    }
+    
+    pub fn redo(&mut self, doc: &mut Document) -> bool {
+        self.command.redo(doc)
     }
     
     pub fn get_name(&self) -> String {
@@ -43,20 +64,30 @@ impl HistoryState {
 }
 
 // Manages a stack of history states
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HistoryManager {
     undo_stack: Vec<HistoryState>,
     redo_stack: Vec<HistoryState>,
     max_undo_levels: usize,
+    // Largest thumbnail edge and total bytes all thumbnails may occupy
+    thumbnail_size: u32,
+    thumbnail_budget: usize,
+}
+
+impl Default for HistoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HistoryManager {
+    /// Default thumbnail edge length in pixels
+    pub const DEFAULT_THUMBNAIL_SIZE: u32 = 64;
+    /// Default memory budget for thumbnails (8 MiB)
+    pub const DEFAULT_THUMBNAIL_BUDGET: usize = 8 * 1024 * 1024;
+    
     pub fn new() -> Self {
-        Self {
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
-            max_undo_levels: 100,
-        }
+        Self::with_max_levels(100)
     }
     
     pub fn with_max_levels(max_levels: usize) -> Self {
@@ -64,32 +95,39 @@ impl HistoryManager {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             max_undo_levels: max_levels,
+            thumbnail_size: Self::DEFAULT_THUMBNAIL_SIZE,
+            thumbnail_budget: Self::DEFAULT_THUMBNAIL_BUDGET,
         }
     }
     
-    pub fn add_command(&mut self, mut command: Box<dyn HistoryCommand>, document_id: String) -> bool {
+    pub fn add_command(&mut self, mut command: Box<dyn HistoryCommand>, document_id: String, doc: &mut Document) -> bool {
         // Execute the command
-        let success = command.execute();
+        let success = command.execute(doc);
         
         if success {
-            // Add to undo stack
-            self.undo_stack.push(HistoryState::new(command, document_id));
-            
-            // Clear redo stack
-            self.redo_stack.clear();
-            
-            // Trim undo stack if needed
-            if self.undo_stack.len() > self.max_undo_levels {
-                self.undo_stack.remove(0);
-            }
+            self.push_applied(command, document_id);
         }
         
         success
     }
     
-    pub fn undo(&mut self) -> bool {
+    /// Record a command whose effect has already been applied to the document
+    pub fn push_applied(&mut self, command: Box<dyn HistoryCommand>, document_id: String) {
+        // Add to undo stack
+        self.undo_stack.push(HistoryState::new(command, document_id));
+        
+        // Clear redo stack
+        self.redo_stack.clear();
+        
+        // Trim undo stack if needed
+        if self.undo_stack.len() > self.max_undo_levels {
+            self.undo_stack.remove(0);
+        }
+    }
+    
+    pub fn undo(&mut self, doc: &mut Document) -> bool {
         if let Some(mut state) = self.undo_stack.pop() {
-            let success = state.undo();
+            let success = state.undo(doc);
             
             if success {
                 self.redo_stack.push(state);
@@ -103,9 +141,9 @@ impl HistoryManager {
         false
     }
     
-    pub fn redo(&mut self) -> bool {
+    pub fn redo(&mut self, doc: &mut Document) -> bool {
         if let Some(mut state) = self.redo_stack.pop() {
-            let success = state.redo();
+            let success = state.redo(doc);
             
             if success {
                 self.undo_stack.push(state);
@@ -119,6 +157,42 @@ impl HistoryManager {
         false
     }
     
+    /// Jump straight to a state, as when clicking a history panel entry.
+    ///
+    /// `state_index` counts the commands applied: 0 is the document before
+    /// the oldest recorded command and `state_count()` is after the newest.
+    /// The document itself is the only full snapshot kept, so the jump
+    /// undoes or redoes commands from the current state, which is always
+    /// the nearest one. Stops and returns false if a command fails, leaving
+    /// the document at the last state reached.
+    pub fn goto(&mut self, state_index: usize, doc: &mut Document) -> bool {
+        if state_index > self.state_count() {
+            return false;
+        }
+        
+        while self.undo_stack.len() > state_index {
+            if !self.undo(doc) {
+                return false;
+            }
+        }
+        while self.undo_stack.len() < state_index {
+            if !self.redo(doc) {
+                return false;
+            }
+        }
+        true
+    }
+    
+    /// Index of the current state, in the numbering `goto` uses
+    pub fn current_state(&self) -> usize {
+        self.undo_stack.len()
+    }
+    
+    /// Number of recorded commands, undone ones included
+    pub fn state_count(&self) -> usize {
+        self.undo_stack.len() + self.redo_stack.len()
+    }
+    
     pub fn can_undo(&self) -> bool {
         !self.undo_stack.is_empty()
     }
@@ -153,17 +227,70 @@ impl HistoryManager {
             self.undo_stack.remove(0);
         }
     }
+    
+    /// States that can be undone, oldest first, for the history panel
+    pub fn undo_states(&self) -> &[HistoryState] {
+        &self.undo_stack
+    }
+    
+    /// Largest edge, in pixels, that thumbnails are generated at
+    pub fn thumbnail_size(&self) -> u32 {
+        self.thumbnail_size
+    }
+    
+    pub fn set_thumbnail_size(&mut self, size: u32) {
+        self.thumbnail_size = size;
+    }
+    
+    /// Memory, in bytes, that thumbnails may use; 0 disables them
+    pub fn thumbnail_budget(&self) -> usize {
+        self.thumbnail_budget
+    }
+    
+    pub fn set_thumbnail_budget(&mut self, bytes: usize) {
+        self.thumbnail_budget = bytes;
+        self.enforce_thumbnail_budget();
+    }
+    
+    /// Total bytes currently held by thumbnails
+    pub fn thumbnail_memory(&self) -> usize {
+        self.undo_stack.iter()
+            .chain(self.redo_stack.iter())
+            .map(HistoryState::thumbnail_bytes)
+            .sum()
+    }
+    
+    /// Attach a preview to the most recent undoable state
+    pub fn set_latest_thumbnail(&mut self, thumbnail: ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        if let Some(state) = self.undo_stack.last_mut() {
+            state.thumbnail = Some(thumbnail);
+            self.enforce_thumbnail_budget();
+        }
+    }
+    
+    // Drop thumbnails until they fit the budget. The oldest undo steps go
+    // first, then redo steps furthest from the current state.
+    fn enforce_thumbnail_budget(&mut self) {
+        let mut used = self.thumbnail_memory();
+        let order = self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut());
+        for state in order {
+            if used <= self.thumbnail_budget {
+                break;
+            }
+            used -= state.thumbnail_bytes();
+            state.thumbnail = None;
+        }
+    }
 }
 
 // Example command implementations for common operations
 
 // Layer visibility change command
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LayerVisibilityCommand {
     layer_id: String,
     old_visibility: bool,
     new_visibility: bool,
-    applied: bool,
 }
 
 impl LayerVisibilityCommand {
@@ -172,32 +299,229 @@ impl LayerVisibilityCommand {
             layer_id,
             old_visibility,
             new_visibility,
-            applied: false,
+        }
+    }
+    
+    fn set_visibility(&self, doc: &mut Document, visible: bool) -> bool {
+        let index = doc.layer_manager.get_layers().iter().position(|layer| layer.id == self.layer_id);
+        match index.and_then(|index| doc.layer_manager.get_layer_mut(index)) {
+            Some(layer) => {
+                layer.set_visible(visible);
+                true
+            },
+            None => false,
         }
     }
 }
 
 impl HistoryCommand for LayerVisibilityCommand {
-    fn execute(&mut self) -> bool {
-        // This would normally modify the document layer's visibility
-        println!("Setting layer {} visibility to {}", self.layer_id, self.new_visibility);
-        self.applied = true;
-        true
+    fn execute(&mut self, doc: &mut Document) -> bool {
+        self.set_visibility(doc, self.new_visibility)
     }
     
-    fn undo(&mut self) -> bool {
-        if !self.applied {
-            return false;
+    fn undo(&mut self, doc: &mut Document) -> bool {
+        self.set_visibility(doc, self.old_visibility)
+    }
+    
+    fn get_name(&self) -> String {
+        "Change Layer Visibility".to_string()
+    }
+    
+    fn box_clone(&self) -> Box<dyn HistoryCommand> {
+        Box::new(self.clone())
+    }
+}
+
+// Layer opacity change command
+#[derive(Debug, Clone)]
+pub struct LayerOpacityCommand {
+    layer_id: String,
+    old_opacity: f64,
+    new_opacity: f64,
+}
+
+impl LayerOpacityCommand {
+    pub fn new(layer_id: String, old_opacity: f64, new_opacity: f64) -> Self {
+        Self {
+            layer_id,
+            old_opacity,
+            new_opacity,
+        }
+    }
+    
+    fn set_opacity(&self, doc: &mut Document, opacity: f64) -> bool {
+        let index = doc.layer_manager.get_layers().iter().position(|layer| layer.id == self.layer_id);
+        match index.and_then(|index| doc.layer_manager.get_layer_mut(index)) {
+            Some(layer) => {
+                layer.set_opacity(opacity);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl HistoryCommand for LayerOpacityCommand {
+    fn execute(&mut self, doc: &mut Document) -> bool {
+        self.set_opacity(doc, self.new_opacity)
+    }
+    
+    fn undo(&mut self, doc: &mut Document) -> bool {
+        self.set_opacity(doc, self.old_opacity)
+    }
+    
+    fn get_name(&self) -> String {
+        "Change Layer Opacity".to_string()
+    }
+    
+    fn box_clone(&self) -> Box<dyn HistoryCommand> {
+        Box::new(self.clone())
+    }
+}
+
+// Restores a rectangle of one layer's pixels. Only the bounding box of the
+// pixels that changed is kept, before and after, so a brush stroke or a
+// filter on a small selection costs little however large the layer is.
+#[derive(Debug, Clone)]
+pub struct LayerPixelsCommand {
+    name: String,
+    layer_id: String,
+    x: u32,
+    y: u32,
+    before: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    after: ImageBuffer<Rgba<u8>, Vec<u8>>,
+}
+
+impl LayerPixelsCommand {
+    /// Record the change from `before` to `after` on the layer `layer_id`.
+    /// Returns None when nothing changed or the sizes differ, in which case
+    /// a `LayerReplaceCommand` is the right fit.
+    pub fn from_diff(
+        name: &str,
+        layer_id: String,
+        before: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        after: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> Option<Self> {
+        if before.dimensions() != after.dimensions() {
+            return None;
         }
         
-        // Revert back to old visibility
-        println!("Reverting layer {} visibility to {}", self.layer_id, self.old_visibility);
-        true
+        let (width, height) = before.dimensions();
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+        let row_len = width as usize * 4;
+        for y in 0..height {
+            let start = y as usize * row_len;
+            let (old, new) = (&before.as_raw()[start..start + row_len], &after.as_raw()[start..start + row_len]);
+            if old == new {
+                continue;
+            }
+            let first = (0..width as usize).find(|&x| old[x * 4..x * 4 + 4] != new[x * 4..x * 4 + 4])?;
+            let last = (0..width as usize).rev().find(|&x| old[x * 4..x * 4 + 4] != new[x * 4..x * 4 + 4])?;
+            min_x = min_x.min(first as u32);
+            max_x = max_x.max(last as u32);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+        if min_x > max_x || min_y > max_y {
+            return None;
+        }
+        
+        let (w, h) = (max_x - min_x + 1, max_y - min_y + 1);
+        Some(Self {
+            name: name.to_string(),
+            layer_id,
+            x: min_x,
+            y: min_y,
+            before: image::imageops::crop_imm(before, min_x, min_y, w, h).to_image(),
+            after: image::imageops::crop_imm(after, min_x, min_y, w, h).to_image(),
+        })
+    }
+    
+    /// The changed rectangle in layer pixels: x, y, width, height
+    pub fn region(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.before.width(), self.before.height())
+    }
+    
+    /// Bytes of pixel data kept for undo and redo
+    pub fn memory_size(&self) -> usize {
+        self.before.as_raw().len() + self.after.as_raw().len()
+    }
+    
+    fn write(&self, doc: &mut Document, patch: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> bool {
+        let index = doc.layer_manager.get_layers().iter().position(|layer| layer.id == self.layer_id);
+        match index.and_then(|index| doc.layer_manager.get_layer_mut(index)) {
+            Some(layer) if self.x + patch.width() <= layer.image.width()
+                && self.y + patch.height() <= layer.image.height() => {
+                image::imageops::replace(&mut layer.image, patch, self.x as i64, self.y as i64);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+impl HistoryCommand for LayerPixelsCommand {
+    fn execute(&mut self, doc: &mut Document) -> bool {
+        self.write(doc, &self.after)
+    }
+    
+    fn undo(&mut self, doc: &mut Document) -> bool {
+        self.write(doc, &self.before)
     }
     
     fn get_name(&self) -> String {
-        format!("Change Layer Visibility")
+        self.name.clone()
+    }
+    
+    fn box_clone(&self) -> Box<dyn HistoryCommand> {
+        Box::new(self.clone())
+    }
+}
+
+// Replaces a whole layer, keeping the previous version for undo. Used for
+// transforms that change a layer's size, where a pixel diff doesn't fit.
+#[derive(Debug, Clone)]
+pub struct LayerReplaceCommand {
+    name: String,
+    index: usize,
+    before: Layer,
+    after: Layer,
+}
+
+impl LayerReplaceCommand {
+    pub fn new(name: &str, index: usize, before: Layer, after: Layer) -> Self {
+        Self {
+            name: name.to_string(),
+            index,
+            before,
+            after,
+        }
+    }
+    
+    fn replace(&self, doc: &mut Document, layer: &Layer) -> bool {
+        if self.index < doc.layer_manager.layer_count() {
+            doc.layer_manager.set_layer(self.index, layer.clone());
+            true
+        } else {
+            false
+        }
     }
 }
 
-// Add more command implementations here... 
\ No newline at end of file
+impl HistoryCommand for LayerReplaceCommand {
+    fn execute(&mut self, doc: &mut Document) -> bool {
+        self.replace(doc, &self.after)
+    }
+    
+    fn undo(&mut self, doc: &mut Document) -> bool {
+        self.replace(doc, &self.before)
+    }
+    
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    
+    fn box_clone(&self) -> Box<dyn HistoryCommand> {
+        Box::new(self.clone())
+    }
+}
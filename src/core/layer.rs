@@ -1,11 +1,19 @@
-use image::{DynamicImage, ImageBuffer, Rgba};
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgba};
+use serde::{Deserialize, Serialize};
 use cairo::{Context, Format, ImageSurface};
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::cell::RefCell;
 use std::rc::Rc;
 use log::{debug, info, warn, error};
+use crate::core::adjustment::AdjustmentLayer;
+use crate::core::blend::blend_pixel;
 use crate::core::document::Document;
+use crate::core::linked::{render_linked_file, LinkedFileCache, LinkedTransform};
+use crate::core::text::{render_text, TextLayerData};
+use crate::filters::blend_with_mask;
 
 /// Represents a layer in the image
 #[derive(Clone, Debug, PartialEq)]
@@ -20,10 +28,68 @@ pub struct Layer {
     pub y_offset: i32,
     pub width: u32,
     pub height: u32,
+    /// Grayscale mask the same size as `image`; black hides, white shows
+    pub mask: Option<GrayImage>,
+    /// Embedded source layers when this layer is a smart object
+    pub smart_object: Option<SmartObject>,
+    /// Set on adjustment layers, which change the composite below them
+    /// instead of drawing `image`
+    pub adjustment: Option<Box<dyn AdjustmentLayer>>,
+    /// Set on text layers, whose pixels are rendered from it when composited
+    pub text: Option<TextLayerData>,
+    /// Set on groups: the layers inside, bottom to top, positioned in
+    /// document coordinates like top-level layers
+    pub children: Option<Vec<Layer>>,
+}
+
+/// The re-editable contents of a smart object layer.
+///
+/// The owning layer's `image` caches the composite of these layers; edit
+/// them through `LayerManager::edit_smart_object` and call
+/// `refresh_smart_object` to update the displayed pixels. A linked smart
+/// object shows another file instead and is rendered whenever composited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmartObject {
+    pub layers: Vec<Layer>,
+    pub width: u32,
+    pub height: u32,
+    /// Set when the contents come from a file on disk
+    pub linked: Option<LinkedFile>,
+}
+
+/// The file shown by a linked smart object
+#[derive(Clone, Debug)]
+pub struct LinkedFile {
+    pub path: PathBuf,
+    pub transform: LinkedTransform,
+    /// Pixels of `path`, read on first render and shared between copies
+    pub cache: LinkedFileCache,
+}
+
+impl PartialEq for LinkedFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.transform == other.transform
+    }
+}
+
+impl SmartObject {
+    /// Composite the embedded layers, bottom to top, or render the linked
+    /// file. A link that can't be read shows a placeholder.
+    pub fn composite(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        if let Some(linked) = &self.linked {
+            return render_linked_file(&linked.cache, &linked.path, self.width, self.height, &linked.transform);
+        }
+        
+        let mut result = ImageBuffer::new(self.width, self.height);
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            composite_layer(&mut result, layer);
+        }
+        result
+    }
 }
 
 /// Layer blend modes for compositing
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlendMode {
     Normal,
     Multiply,
@@ -58,9 +124,54 @@ impl Layer {
             y_offset: 0,
             width,
             height,
+            mask: None,
+            smart_object: None,
+            adjustment: None,
+            text: None,
+            children: None,
         }
     }
     
+    /// Create an adjustment layer covering a `width` x `height` document
+    pub fn new_adjustment(width: u32, height: u32, name: String, adjustment: Box<dyn AdjustmentLayer>) -> Self {
+        info!("Creating {:?} adjustment layer: {}", adjustment.get_type(), name);
+        let mut layer = Self::new(width, height, name);
+        layer.adjustment = Some(adjustment);
+        layer
+    }
+    
+    /// Create a `width` x `height` text layer that draws `data`
+    pub fn new_text(width: u32, height: u32, name: String, data: TextLayerData) -> Self {
+        info!("Creating text layer: {}", name);
+        let mut layer = Self::new(width, height, name);
+        layer.text = Some(data);
+        layer
+    }
+    
+    /// Create a `width` x `height` group holding `children`, bottom to top.
+    /// The children are composited together first and the result is
+    /// blended in with the group's opacity, blend mode and mask.
+    pub fn new_group(width: u32, height: u32, name: String, children: Vec<Layer>) -> Self {
+        info!("Creating layer group: {} ({} layers)", name, children.len());
+        let mut layer = Self::new(width, height, name);
+        layer.children = Some(children);
+        layer
+    }
+    
+    /// Create a `width` x `height` smart object layer showing the file at
+    /// `path`, placed by `transform`. The file is read when first rendered.
+    pub fn new_linked(width: u32, height: u32, name: String, path: PathBuf, transform: LinkedTransform) -> Self {
+        info!("Creating linked smart object layer: {} ({:?})", name, path);
+        let mut layer = Self::new(width, height, name);
+        layer.smart_object = Some(SmartObject {
+            layers: Vec::new(),
+            width,
+            height,
+            linked: Some(LinkedFile { path, transform, cache: LinkedFileCache::default() }),
+        });
+        layer
+    }
+    
     /// Create a layer from an existing image
     pub fn from_image(image: ImageBuffer<Rgba<u8>, Vec<u8>>, name: String) -> Self {
         info!("Creating layer from image: {}", name);
@@ -79,6 +190,11 @@ impl Layer {
             y_offset: 0,
             width,
             height,
+            mask: None,
+            smart_object: None,
+            adjustment: None,
+            text: None,
+            children: None,
         }
     }
     
@@ -107,9 +223,33 @@ impl Layer {
             y_offset: self.y_offset,
             width: self.width,
             height: self.height,
+            mask: self.mask.clone(),
+            smart_object: self.smart_object.clone(),
+            adjustment: self.adjustment.clone(),
+            text: self.text.clone(),
+            children: self.children.clone(),
         }
     }
     
+    /// Whether `other` differs from this layer in its pixels at most
+    pub fn same_except_pixels(&self, other: &Layer) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.visible == other.visible
+            && self.opacity == other.opacity
+            && self.blend_mode == other.blend_mode
+            && self.x_offset == other.x_offset
+            && self.y_offset == other.y_offset
+            && self.width == other.width
+            && self.height == other.height
+            && self.image.dimensions() == other.image.dimensions()
+            && self.mask == other.mask
+            && self.smart_object == other.smart_object
+            && self.adjustment == other.adjustment
+            && self.text == other.text
+            && self.children == other.children
+    }
+    
     /// Resize the layer to the given dimensions
     pub fn resize(&mut self, width: u32, height: u32) {
         let mut new_image = ImageBuffer::new(width, height);
@@ -126,6 +266,7 @@ impl Layer {
         }
         
         self.image = new_image;
+        self.reframe_mask(0, 0, width, height);
     }
     
     /// Crop the layer to the given rectangle
@@ -146,12 +287,78 @@ impl Layer {
         }
         
         self.image = new_image;
+        self.reframe_mask(x as i64, y as i64, width, height);
         
         // Update offset
         self.x_offset -= x as i32;
         self.y_offset -= y as i32;
     }
     
+    /// Bounding box `(x, y, width, height)` of the non-transparent pixels,
+    /// in layer coordinates; None when the layer is fully transparent
+    pub fn content_bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+        let (mut max_x, mut max_y) = (0, 0);
+        for (x, y, pixel) in self.image.enumerate_pixels() {
+            if pixel[3] > 0 {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+        
+        if min_x == u32::MAX {
+            None
+        } else {
+            Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+        }
+    }
+    
+    /// Shrink the layer to its content bounds, moving the offset so nothing
+    /// shifts on the canvas. The mask is trimmed along with the pixels.
+    ///
+    /// A fully transparent layer becomes 0x0 and false is returned.
+    pub fn trim(&mut self) -> bool {
+        let (x, y, width, height) = match self.content_bounds() {
+            Some(bounds) => bounds,
+            None => {
+                self.image = ImageBuffer::new(0, 0);
+                self.mask = self.mask.as_ref().map(|_| GrayImage::new(0, 0));
+                self.width = 0;
+                self.height = 0;
+                return false;
+            }
+        };
+        
+        self.image = image::imageops::crop_imm(&self.image, x, y, width, height).to_image();
+        self.reframe_mask(x as i64, y as i64, width, height);
+        
+        self.width = width;
+        self.height = height;
+        self.x_offset += x as i32;
+        self.y_offset += y as i32;
+        true
+    }
+    
+    /// Cut the mask to the `width` x `height` area at (`x`, `y`) in layer
+    /// coordinates, so it stays lined up with pixels cropped or padded the
+    /// same way. Areas the old mask didn't cover were fully shown and come
+    /// out white.
+    pub fn reframe_mask(&mut self, x: i64, y: i64, width: u32, height: u32) {
+        if let Some(mask) = &self.mask {
+            let framed = GrayImage::from_fn(width, height, |mx, my| {
+                let (sx, sy) = (x + mx as i64, y + my as i64);
+                if sx >= 0 && sy >= 0 && sx < mask.width() as i64 && sy < mask.height() as i64 {
+                    *mask.get_pixel(sx as u32, sy as u32)
+                } else {
+                    image::Luma([255])
+                }
+            });
+            self.mask = Some(framed);
+        }
+    }
+    
     /// Clear the layer (set all pixels to transparent)
     pub fn clear(&mut self) {
         for pixel in self.image.pixels_mut() {
@@ -196,8 +403,89 @@ impl Layer {
         self.y_offset = y_offset;
     }
     
+    /// Bake the mask into the pixels and remove it, multiplying each pixel's
+    /// alpha by the mask value at the same position
+    pub fn apply_mask(&mut self) -> Result<(), String> {
+        let mask = self.mask.take()
+            .ok_or_else(|| format!("Layer '{}' has no mask", self.name))?;
+        for (x, y, pixel) in self.image.enumerate_pixels_mut() {
+            if x < mask.width() && y < mask.height() {
+                let coverage = mask.get_pixel(x, y)[0] as f32 / 255.0;
+                pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Remove the mask without touching the pixels, returning it
+    pub fn delete_mask(&mut self) -> Option<GrayImage> {
+        self.mask.take()
+    }
+    
+    /// Swap what the mask shows and hides. Returns false without a mask.
+    pub fn invert_mask(&mut self) -> bool {
+        match &mut self.mask {
+            Some(mask) => {
+                image::imageops::invert(mask);
+                true
+            },
+            None => false,
+        }
+    }
+    
+    /// Apply this adjustment layer to `below`, the composite of the layers
+    /// underneath it, as flattening would. The adjusted pixels are mixed
+    /// back over `below` by the layer's opacity and mask, so black areas of
+    /// the mask stay untouched. Returns None for layers without an
+    /// adjustment.
+    pub fn apply_adjustment(&self, below: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if self.adjustment.is_none() {
+            return None;
+        }
+        let mut result = below.clone();
+        composite_layer(&mut result, self);
+        Some(result)
+    }
+    
+    /// The pixels this layer draws. Groups are composited from their
+    /// visible children, text layers rendered from their text (falling
+    /// back to the stored `image` if that fails) and linked smart objects
+    /// from their file.
+    pub fn pixels(&self) -> Cow<'_, ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if let Some(children) = &self.children {
+            let mut image = ImageBuffer::new(self.width, self.height);
+            for child in children.iter().filter(|child| child.visible) {
+                composite_layer_at(&mut image, child, self.x_offset as i64, self.y_offset as i64);
+            }
+            return Cow::Owned(image);
+        }
+        if let Some(data) = &self.text {
+            match render_text(data, self.width, self.height) {
+                Ok(image) => return Cow::Owned(image),
+                Err(e) => warn!("Failed to render text layer {}: {}", self.name, e),
+            }
+        }
+        match &self.smart_object {
+            Some(smart) if smart.linked.is_some() => Cow::Owned(smart.composite()),
+            _ => Cow::Borrowed(&self.image),
+        }
+    }
+    
+    /// This layer alone on a transparent `width` x `height` canvas, placed at
+    /// its offset with its opacity and mask applied. Visibility is ignored.
+    pub fn render_to_image(&self, width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut canvas = ImageBuffer::new(width, height);
+        composite_layer(&mut canvas, self);
+        canvas
+    }
+    
     /// Render the layer to a Cairo context
     pub fn render(&self, cr: &Context, width: u32, height: u32) {
+        self.render_with_filter(cr, width, height, cairo::Filter::Good);
+    }
+    
+    /// Render the layer, sampling its pixels with `filter` when the context is scaled
+    pub fn render_with_filter(&self, cr: &Context, _width: u32, _height: u32, filter: cairo::Filter) {
         if !self.visible {
             debug!("Layer {} is not visible, skipping render", self.name);
             return;
@@ -206,7 +494,7 @@ impl Layer {
         debug!("Rendering layer: {}", self.name);
         
         // Create a surface from the image data
-        let data = self.image.as_raw().to_vec();
+        let data = self.pixels().as_raw().to_vec();
         let stride = cairo::Format::Rgb24.stride_for_width(self.width as u32)
             .expect("Failed to calculate stride");
             
@@ -229,6 +517,7 @@ impl Layer {
                 // Draw the image
                 cr.set_source_surface(&surface, 0.0, 0.0)
                     .expect("Failed to set source surface");
+                cr.source().set_filter(filter);
                 cr.paint().expect("Failed to paint surface");
                 
                 // Apply opacity if needed
@@ -291,6 +580,16 @@ impl LayerManager {
         }
     }
     
+    /// Insert a layer at `index`, shifting the layers above it up. The new
+    /// layer becomes active. Returns the index it ended up at.
+    pub fn insert_layer(&mut self, index: usize, layer: Layer) -> usize {
+        info!("Inserting layer: {}", layer.name);
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+        self.active_layer_index = index;
+        index
+    }
+    
     /// Remove a layer at the given index
     pub fn remove_layer(&mut self, index: usize) -> Option<Layer> {
         if index < self.layers.len() {
@@ -331,7 +630,28 @@ impl LayerManager {
         
         true
     }
-    
+
+    /// Move the layer at `from` so it ends up at index `to`, as when a layer
+    /// is dragged to a new slot in the layers panel. The active layer stays
+    /// the same layer, whatever index it lands on.
+    pub fn reorder(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let count = self.layers.len();
+        if from >= count || to >= count {
+            return Err(format!("Cannot move layer {} to {}: there are {} layers", from, to, count));
+        }
+
+        let active_id = self.layers.get(self.active_layer_index).map(|layer| layer.id.clone());
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+
+        if let Some(id) = active_id {
+            if let Some(index) = self.layers.iter().position(|layer| layer.id == id) {
+                self.active_layer_index = index;
+            }
+        }
+        Ok(())
+    }
+
     /// Set the active layer
     pub fn set_active_layer(&mut self, index: usize) -> bool {
         if index < self.layers.len() {
@@ -391,117 +711,346 @@ impl LayerManager {
         
         let mut result = ImageBuffer::new(width, height);
         
-        // Composite all visible layers
-        for layer in &self.layers {
-            if layer.visible {
-                debug!("Compositing layer: {}", layer.name);
-                for (x, y, pixel) in result.enumerate_pixels_mut() {
-                    if x < layer.width && y < layer.height {
-                        let src_pixel = layer.image.get_pixel(x, y);
-                        // Simple alpha compositing
-                        let alpha = (src_pixel[3] as f64 * layer.opacity) as u8;
-                        *pixel = Rgba([
-                            src_pixel[0],
-                            src_pixel[1],
-                            src_pixel[2],
-                            alpha,
-                        ]);
-                    }
-                }
-            }
+        // Composite all visible layers bottom to top
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            debug!("Compositing layer: {}", layer.name);
+            composite_layer(&mut result, layer);
         }
         
         debug!("Layers flattened successfully");
         result
     }
     
+    /// Merge the visible layers over just the `width` x `height` area at
+    /// (`x`, `y`) in document coordinates, as `flatten` would draw it there
+    pub fn flatten_region(&self, x: i64, y: i64, width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut result = ImageBuffer::new(width, height);
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            composite_layer_at(&mut result, layer, x, y);
+        }
+        result
+    }
+    
+    /// Composite all visible layers into a new pixel layer at the top of the
+    /// stack, leaving the originals untouched ("Stamp Visible").
+    ///
+    /// Returns the index of the new layer, which also becomes active.
+    pub fn merge_visible(&mut self) -> Result<usize, String> {
+        self.merge_visible_with(true)
+    }
+    
+    /// Like `merge_visible`, but with `keep_originals == false` the visible
+    /// layers that went into the merge are removed afterwards.
+    pub fn merge_visible_with(&mut self, keep_originals: bool) -> Result<usize, String> {
+        if !self.layers.iter().any(|layer| layer.visible) {
+            return Err("No visible layers to merge".to_string());
+        }
+        
+        info!("Merging visible layers (keep originals: {})", keep_originals);
+        let merged = Layer::from_image(self.flatten(), "Merged".to_string());
+        
+        if !keep_originals {
+            self.layers.retain(|layer| !layer.visible);
+        }
+        
+        Ok(self.add_layer(merged))
+    }
+    
+    /// Copy the layer at `index`, pixels, mask, group and smart object
+    /// contents included, and insert the copy directly above it as the
+    /// active layer.
+    ///
+    /// The copy is named "<name> copy", and it and every layer nested in it
+    /// get fresh ids that don't clash with any in the stack. Returns the
+    /// copy's index, or None if there is no layer at `index`.
+    pub fn duplicate_layer(&mut self, index: usize) -> Option<usize> {
+        let original = self.layers.get(index)?;
+        let mut copy = original.duplicate(format!("{} copy", original.name));
+        
+        let mut taken = HashSet::new();
+        for layer in &self.layers {
+            collect_ids(layer, &mut taken);
+        }
+        assign_fresh_ids(&mut copy, &mut taken);
+        
+        info!("Duplicated layer {} as {}", original.name, copy.name);
+        Some(self.insert_layer(index + 1, copy))
+    }
+    
+    /// Composite the layer at `index` onto the one below it ("Merge Down").
+    ///
+    /// The upper layer is blended with its own opacity, blend mode and mask.
+    /// The lower layer is rasterized first: groups, text and linked files
+    /// are rendered, its mask is baked into its pixels and a smart object
+    /// loses its embedded layers. An adjustment layer has no pixels to merge
+    /// into, so merging onto one is an error.
+    /// The result covers both layers' bounds, keeps the lower layer's name,
+    /// opacity and blend mode, and becomes active. Returns its index.
+    pub fn merge_down(&mut self, index: usize) -> Result<usize, String> {
+        if index == 0 || index >= self.layers.len() {
+            return Err(format!("No layer below index {} to merge into", index));
+        }
+        if self.layers[index - 1].adjustment.is_some() {
+            return Err(format!("Can't merge into adjustment layer '{}'", self.layers[index - 1].name));
+        }
+        
+        let upper = self.layers.remove(index);
+        let lower = &mut self.layers[index - 1];
+        info!("Merging layer {} down into {}", upper.name, lower.name);
+        
+        let left = lower.x_offset.min(upper.x_offset);
+        let top = lower.y_offset.min(upper.y_offset);
+        let right = (lower.x_offset + lower.image.width() as i32).max(upper.x_offset + upper.image.width() as i32);
+        let bottom = (lower.y_offset + lower.image.height() as i32).max(upper.y_offset + upper.image.height() as i32);
+        
+        let rendered = match lower.pixels() {
+            Cow::Owned(image) => Some(image),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(image) = rendered {
+            lower.image = image;
+        }
+        if lower.mask.is_some() {
+            lower.apply_mask()?;
+        }
+        let mut merged = ImageBuffer::new((right - left) as u32, (bottom - top) as u32);
+        image::imageops::replace(&mut merged, &lower.image, (lower.x_offset - left) as i64, (lower.y_offset - top) as i64);
+        
+        if upper.visible {
+            let mut placed = upper;
+            placed.x_offset -= left;
+            placed.y_offset -= top;
+            composite_layer(&mut merged, &placed);
+        }
+        
+        lower.width = merged.width();
+        lower.height = merged.height();
+        lower.image = merged;
+        lower.x_offset = left;
+        lower.y_offset = top;
+        lower.smart_object = None;
+        lower.text = None;
+        lower.children = None;
+        
+        self.active_layer_index = index - 1;
+        Ok(index - 1)
+    }
+    
+    /// Package the layers at `indices` into a single smart object layer.
+    ///
+    /// The originals are kept inside the new layer so they can be edited
+    /// later; the layer itself shows their composite. It takes the place of
+    /// the topmost packaged layer and becomes active. Returns its index.
+    pub fn convert_to_smart_object(&mut self, indices: &[usize]) -> Result<usize, String> {
+        if indices.is_empty() {
+            return Err("No layers selected".to_string());
+        }
+        
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        if sorted.len() != indices.len() {
+            return Err("Layer indices must be unique".to_string());
+        }
+        if let Some(&index) = sorted.iter().find(|&&index| index >= self.layers.len()) {
+            return Err(format!("Layer index {} out of range", index));
+        }
+        
+        info!("Converting {} layers to a smart object", sorted.len());
+        let (width, height) = (self.layers[0].width, self.layers[0].height);
+        let top = *sorted.last().unwrap();
+        
+        // Remove top-down so the remaining indices stay valid
+        let mut inner = Vec::with_capacity(sorted.len());
+        for &index in sorted.iter().rev() {
+            inner.push(self.layers.remove(index));
+        }
+        inner.reverse();
+        
+        let contents = SmartObject { layers: inner, width, height, linked: None };
+        let name = if contents.layers.len() == 1 {
+            contents.layers[0].name.clone()
+        } else {
+            "Smart Object".to_string()
+        };
+        let mut layer = Layer::from_image(contents.composite(), name);
+        layer.smart_object = Some(contents);
+        
+        let position = top + 1 - sorted.len();
+        self.layers.insert(position, layer);
+        self.active_layer_index = position;
+        Ok(position)
+    }
+    
+    /// The embedded layers of the smart object at `index`, for editing.
+    ///
+    /// Call `refresh_smart_object` afterwards so the layer shows the edits.
+    pub fn edit_smart_object(&mut self, index: usize) -> Result<&mut Vec<Layer>, String> {
+        let layer = self.layers.get_mut(index)
+            .ok_or_else(|| format!("Layer index {} out of range", index))?;
+        match layer.smart_object.as_mut() {
+            Some(contents) => Ok(&mut contents.layers),
+            None => Err(format!("Layer '{}' is not a smart object", layer.name)),
+        }
+    }
+    
+    /// Re-render the smart object at `index` from its embedded layers
+    pub fn refresh_smart_object(&mut self, index: usize) -> Result<(), String> {
+        let layer = self.layers.get_mut(index)
+            .ok_or_else(|| format!("Layer index {} out of range", index))?;
+        let image = match &layer.smart_object {
+            Some(contents) => contents.composite(),
+            None => return Err(format!("Layer '{}' is not a smart object", layer.name)),
+        };
+        layer.width = image.width();
+        layer.height = image.height();
+        layer.image = image;
+        Ok(())
+    }
+    
     /// Render all layers to a Cairo context
     pub fn render(&self, context: &Context, _width: u32, _height: u32) {
+        self.render_with_filter(context, _width, _height, cairo::Filter::Good);
+    }
+    
+    /// Render all layers, sampling their pixels with `filter`
+    pub fn render_with_filter(&self, context: &Context, _width: u32, _height: u32, filter: cairo::Filter) {
         // Draw the layers bottom to top
         for layer in &self.layers {
-            layer.render(context, _width, _height);
+            layer.render_with_filter(context, _width, _height, filter);
         }
     }
 
     pub fn get_layers(&self) -> &[Layer] {
         &self.layers
     }
+    
+    /// Find all layers whose name contains `query`, descending into groups.
+    ///
+    /// Each match is returned as a path of indices: `[2]` is the third
+    /// top-level layer, `[2, 0]` the first child of that group, and so on.
+    pub fn find_by_name(&self, query: &str, case_sensitive: bool) -> Vec<Vec<usize>> {
+        let needle = if case_sensitive { query.to_string() } else { query.to_lowercase() };
+        let mut matches = Vec::new();
+        let mut path = Vec::new();
+        find_in_layers(&self.layers, &needle, case_sensitive, &mut path, &mut matches);
+        matches
+    }
+    
+    /// Resolve a path returned by `find_by_name` back to a layer
+    pub fn get_layer_by_path(&self, path: &[usize]) -> Option<&Layer> {
+        let (first, rest) = path.split_first()?;
+        let mut layer = self.layers.get(*first)?;
+        for index in rest {
+            layer = layer.children.as_ref()?.get(*index)?;
+        }
+        Some(layer)
+    }
 }
 
-/// Blend two pixels according to the specified blend mode and opacity
-fn blend_pixels(dst: &Rgba<u8>, src: &Rgba<u8>, blend_mode: BlendMode, opacity: f32) -> Rgba<u8> {
-    // If source is fully transparent, return destination unchanged
-    if src[3] == 0 {
-        return *dst;
-    }
-    
-    // If destination is fully transparent and blend mode is Normal, return source
-    if dst[3] == 0 && blend_mode == BlendMode::Normal {
-        return Rgba([
-            src[0],
-            src[1],
-            src[2],
-            (src[3] as f32 * opacity) as u8,
-        ]);
-    }
-    
-    // Convert to float for calculations
-    let src_r = src[0] as f32 / 255.0;
-    let src_g = src[1] as f32 / 255.0;
-    let src_b = src[2] as f32 / 255.0;
-    let src_a = src[3] as f32 / 255.0 * opacity;
-    
-    let dst_r = dst[0] as f32 / 255.0;
-    let dst_g = dst[1] as f32 / 255.0;
-    let dst_b = dst[2] as f32 / 255.0;
-    let dst_a = dst[3] as f32 / 255.0;
-    
-    // Calculate result color based on blend mode
-    let (_result_r, _result_g, _result_b) = match blend_mode {
-        BlendMode::Normal => (src_r, src_g, src_b),
-        BlendMode::Multiply => (src_r * dst_r, src_g * dst_g, src_b * dst_b),
-        BlendMode::Screen => (
-            1.0 - (1.0 - src_r) * (1.0 - dst_r),
-            1.0 - (1.0 - src_g) * (1.0 - dst_g),
-            1.0 - (1.0 - src_b) * (1.0 - dst_b)
-        ),
-        BlendMode::Overlay => (
-            if dst_r < 0.5 { 2.0 * src_r * dst_r } else { 1.0 - 2.0 * (1.0 - src_r) * (1.0 - dst_r) },
-            if dst_g < 0.5 { 2.0 * src_g * dst_g } else { 1.0 - 2.0 * (1.0 - src_g) * (1.0 - dst_g) },
-            if dst_b < 0.5 { 2.0 * src_b * dst_b } else { 1.0 - 2.0 * (1.0 - src_b) * (1.0 - dst_b) }
-        ),
-        BlendMode::Darken => (
-            src_r.min(dst_r),
-            src_g.min(dst_g),
-            src_b.min(dst_b)
-        ),
-        BlendMode::Lighten => (
-            src_r.max(dst_r),
-            src_g.max(dst_g),
-            src_b.max(dst_b)
-        ),
-        // For more complex blend modes, we simplify to Normal for now
-        _ => (src_r, src_g, src_b),
-    };
-    
-    // Apply alpha compositing
-    let out_a = src_a + dst_a * (1.0 - src_a);
-    
-    // If the result is fully transparent, return transparent
-    if out_a <= 0.0 {
-        return Rgba([0, 0, 0, 0]);
-    }
-    
-    // Apply alpha-weighted blend of colors
-    let out_r = (src_r * src_a + dst_r * dst_a * (1.0 - src_a)) / out_a;
-    let out_g = (src_g * src_a + dst_g * dst_a * (1.0 - src_a)) / out_a;
-    let out_b = (src_b * src_a + dst_b * dst_a * (1.0 - src_a)) / out_a;
-    
-    // Convert back to u8
-    Rgba([
-        (out_r * 255.0).min(255.0).max(0.0) as u8,
-        (out_g * 255.0).min(255.0).max(0.0) as u8,
-        (out_b * 255.0).min(255.0).max(0.0) as u8,
-        (out_a * 255.0).min(255.0).max(0.0) as u8,
-    ])
-} 
\ No newline at end of file
+/// Push the path of every layer in `layers` whose name contains `needle`
+/// onto `matches`, recursing into groups. `path` leads to `layers`.
+fn find_in_layers(layers: &[Layer], needle: &str, case_sensitive: bool, path: &mut Vec<usize>, matches: &mut Vec<Vec<usize>>) {
+    for (index, layer) in layers.iter().enumerate() {
+        path.push(index);
+        
+        let found = if case_sensitive {
+            layer.name.contains(needle)
+        } else {
+            layer.name.to_lowercase().contains(needle)
+        };
+        if found {
+            matches.push(path.clone());
+        }
+        
+        if let Some(children) = &layer.children {
+            find_in_layers(children, needle, case_sensitive, path, matches);
+        }
+        
+        path.pop();
+    }
+}
+
+/// Add the ids of `layer` and everything nested in it to `ids`
+fn collect_ids(layer: &Layer, ids: &mut HashSet<String>) {
+    ids.insert(layer.id.clone());
+    if let Some(smart) = &layer.smart_object {
+        for child in &smart.layers {
+            collect_ids(child, ids);
+        }
+    }
+    for child in layer.children.iter().flatten() {
+        collect_ids(child, ids);
+    }
+}
+
+/// Give `layer` and its nested layers new ids not already in `taken`
+fn assign_fresh_ids(layer: &mut Layer, taken: &mut HashSet<String>) {
+    let mut id = Uuid::new_v4().to_string();
+    while taken.contains(&id) {
+        id = Uuid::new_v4().to_string();
+    }
+    taken.insert(id.clone());
+    layer.id = id;
+    
+    if let Some(smart) = &mut layer.smart_object {
+        for child in &mut smart.layers {
+            assign_fresh_ids(child, taken);
+        }
+    }
+    for child in layer.children.iter_mut().flatten() {
+        assign_fresh_ids(child, taken);
+    }
+}
+
+/// Composite `layer` onto `canvas` honouring its offset, opacity and blend mode
+fn composite_layer(canvas: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, layer: &Layer) {
+    composite_layer_at(canvas, layer, 0, 0);
+}
+
+/// Composite `layer` onto `canvas`, whose top-left pixel is at
+/// (`origin_x`, `origin_y`) in document coordinates
+fn composite_layer_at(canvas: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, layer: &Layer, origin_x: i64, origin_y: i64) {
+    let opacity = layer.opacity.clamp(0.0, 1.0) as f32;
+    let (dx, dy) = (layer.x_offset as i64 - origin_x, layer.y_offset as i64 - origin_y);
+    
+    if let Some(adjustment) = &layer.adjustment {
+        // Adjust everything below, then mix the result back in by opacity
+        // and mask. Outside the mask the adjustment applies in full.
+        let adjusted = adjustment.apply(&DynamicImage::ImageRgba8(canvas.clone())).to_rgba8();
+        let weights = ImageBuffer::from_fn(canvas.width(), canvas.height(), |x, y| {
+            let (mx, my) = (x as i64 - dx, y as i64 - dy);
+            let coverage = match &layer.mask {
+                Some(mask) if mx >= 0 && my >= 0 && mx < mask.width() as i64 && my < mask.height() as i64 => {
+                    mask.get_pixel(mx as u32, my as u32)[0] as f32 / 255.0
+                },
+                _ => 1.0,
+            };
+            let weight = (opacity * coverage * 255.0).round() as u8;
+            Rgba([weight, weight, weight, 255])
+        });
+        *canvas = blend_with_mask(canvas, &adjusted, &weights);
+        return;
+    }
+    
+    let image = layer.pixels();
+    
+    // Only the layer pixels that land on the canvas
+    let left = (-dx).max(0);
+    let top = (-dy).max(0);
+    let right = (canvas.width() as i64 - dx).min(image.width() as i64);
+    let bottom = (canvas.height() as i64 - dy).min(image.height() as i64);
+    
+    for y in top..bottom {
+        for x in left..right {
+            let (x, y) = (x as u32, y as u32);
+            let coverage = match &layer.mask {
+                Some(mask) if x < mask.width() && y < mask.height() => mask.get_pixel(x, y)[0] as f32 / 255.0,
+                _ => 1.0,
+            };
+            let src = image.get_pixel(x, y);
+            let dst = canvas.get_pixel_mut((x as i64 + dx) as u32, (y as i64 + dy) as u32);
+            *dst = blend_pixel(*dst, *src, layer.blend_mode.into(), opacity * coverage);
+        }
+    }
+}
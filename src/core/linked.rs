@@ -0,0 +1,140 @@
+// Linked smart objects: layers that show the contents of another file.
+//
+// The file is only read when the layer is first rendered, then kept in a
+// cache until the link changes. It can be an image in any format the
+// document loader understands or a native document, which shows flattened.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use crate::core::document::Document;
+use crate::filters::{resample_image, rotate_image, Interpolation, ResampleFilter};
+
+/// Placement of a linked file inside its layer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LinkedTransform {
+    /// Top-left corner of the scaled, unrotated file
+    pub x: i32,
+    pub y: i32,
+    /// Clockwise, in degrees, around the center of the scaled file
+    pub rotation: f32,
+    /// Negative factors mirror the file
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Default for LinkedTransform {
+    fn default() -> Self {
+        Self { x: 0, y: 0, rotation: 0.0, scale_x: 1.0, scale_y: 1.0 }
+    }
+}
+
+/// Pixels of a linked file, loaded on first use. Clones share the cache.
+#[derive(Debug, Clone, Default)]
+pub struct LinkedFileCache {
+    entry: Arc<Mutex<Option<(PathBuf, Arc<RgbaImage>)>>>,
+}
+
+impl LinkedFileCache {
+    /// The pixels of `path`, reading the file unless they are cached
+    pub fn load(&self, path: &Path) -> Result<Arc<RgbaImage>, String> {
+        let mut entry = self.entry.lock().map_err(|_| "Linked file cache is poisoned".to_string())?;
+        if let Some((cached_path, image)) = entry.as_ref() {
+            if cached_path == path {
+                return Ok(image.clone());
+            }
+        }
+
+        debug!("Loading linked file {:?}", path);
+        let image = Arc::new(load_linked_file(path)?);
+        *entry = Some((path.to_path_buf(), image.clone()));
+        Ok(image)
+    }
+
+    /// Forget the cached pixels so the next render reads the file again
+    pub fn clear(&self) {
+        if let Ok(mut entry) = self.entry.lock() {
+            *entry = None;
+        }
+    }
+}
+
+/// Read an image or native document, flattened to RGBA
+pub fn load_linked_file(path: &Path) -> Result<RgbaImage, String> {
+    if !path.is_file() {
+        return Err(format!("Linked file {:?} not found", path));
+    }
+    Document::open(path)?
+        .get_image()
+        .ok_or_else(|| format!("Linked file {:?} has no image", path))
+}
+
+/// Draw `source` with `transform` onto a transparent `width` x `height`
+/// image, clipping whatever falls outside
+pub fn place_linked_image(source: &RgbaImage, width: u32, height: u32, transform: &LinkedTransform) -> RgbaImage {
+    let mut output = ImageBuffer::new(width, height);
+    let scaled_width = (source.width() as f32 * transform.scale_x.abs()).round() as u32;
+    let scaled_height = (source.height() as f32 * transform.scale_y.abs()).round() as u32;
+    if scaled_width == 0 || scaled_height == 0 {
+        return output;
+    }
+
+    let mut scaled = if (scaled_width, scaled_height) == source.dimensions() {
+        source.clone()
+    } else {
+        resample_image(source, scaled_width, scaled_height, ResampleFilter::Bilinear)
+    };
+    if transform.scale_x < 0.0 {
+        image::imageops::flip_horizontal_in_place(&mut scaled);
+    }
+    if transform.scale_y < 0.0 {
+        image::imageops::flip_vertical_in_place(&mut scaled);
+    }
+
+    // Rotating grows the image around its center; keep that center put
+    let placed = if transform.rotation % 360.0 == 0.0 {
+        scaled
+    } else {
+        rotate_image(&scaled, transform.rotation, Interpolation::Bilinear, true)
+    };
+    let left = transform.x as i64 - (placed.width() as i64 - scaled_width as i64) / 2;
+    let top = transform.y as i64 - (placed.height() as i64 - scaled_height as i64) / 2;
+    image::imageops::replace(&mut output, &placed, left, top);
+    output
+}
+
+/// Stand-in for a linked file that can't be read: a gray box crossed
+/// out in red, so the broken link is obvious on the canvas
+pub fn missing_link_placeholder(width: u32, height: u32) -> RgbaImage {
+    let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (u, v) = ((x as f32 + 0.5) / w, (y as f32 + 0.5) / h);
+        let thickness = 1.5 / w.min(h);
+        let on_border = x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+        if on_border || (u - v).abs() < thickness || (u + v - 1.0).abs() < thickness {
+            Rgba([200, 40, 40, 255])
+        } else {
+            Rgba([128, 128, 128, 160])
+        }
+    })
+}
+
+/// Render the file at `path` as a `width` x `height` layer, falling back
+/// to the placeholder (and a warning) when it can't be loaded
+pub fn render_linked_file(
+    cache: &LinkedFileCache,
+    path: &Path,
+    width: u32,
+    height: u32,
+    transform: &LinkedTransform,
+) -> RgbaImage {
+    match cache.load(path) {
+        Ok(source) => place_linked_image(&source, width, height, transform),
+        Err(e) => {
+            warn!("Showing a placeholder for smart object: {}", e);
+            missing_link_placeholder(width, height)
+        },
+    }
+}
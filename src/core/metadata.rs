@@ -0,0 +1,348 @@
+// Embedding and reading descriptive metadata (title, author, copyright, ...)
+// in exported PNG and JPEG files.
+//
+// PNG files get one uncompressed iTXt chunk per field, using the standard
+// PNG keywords. JPEG files get an XMP packet in an APP1 segment with the
+// matching Dublin Core properties, which is what other editors read.
+//
+// Camera EXIF data is only ever read, from JPEG and TIFF files on open.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use exif::{In, Tag, Value};
+use image::DynamicImage;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use crate::core::document::DocumentMetadata;
+
+/// PNG keyword / XMP property pairs for each field we write
+const FIELDS: [(&str, &str); 5] = [
+    ("Title", "dc:title"),
+    ("Author", "dc:creator"),
+    ("Description", "dc:description"),
+    ("Copyright", "dc:rights"),
+    ("Keywords", "dc:subject"),
+];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// The fields of `metadata` worth writing, keyed by PNG keyword. Every
+/// field holds a list of values; only keywords ever have more than one.
+pub fn text_fields(metadata: &DocumentMetadata) -> Vec<(&'static str, Vec<String>)> {
+    let mut fields = Vec::new();
+    if !metadata.title.is_empty() {
+        fields.push(("Title", vec![metadata.title.clone()]));
+    }
+    if let Some(author) = &metadata.author {
+        fields.push(("Author", vec![author.clone()]));
+    }
+    if let Some(description) = &metadata.description {
+        fields.push(("Description", vec![description.clone()]));
+    }
+    if let Some(copyright) = &metadata.copyright {
+        fields.push(("Copyright", vec![copyright.clone()]));
+    }
+    if !metadata.keywords.is_empty() {
+        fields.push(("Keywords", metadata.keywords.clone()));
+    }
+    fields
+}
+
+/// CRC-32 as used by PNG chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Insert iTXt chunks for `fields` into an encoded PNG, right after IHDR.
+/// A field with several values gets one chunk per value.
+pub fn embed_png_text(png: &[u8], fields: &[(&str, Vec<String>)]) -> Result<Vec<u8>, String> {
+    if png.len() < 33 || png[..8] != PNG_SIGNATURE {
+        return Err("Not a PNG file".to_string());
+    }
+
+    // Signature (8) + IHDR chunk (4 length + 4 type + 13 data + 4 CRC)
+    let ihdr_end = 8 + 25;
+    let mut output = png[..ihdr_end].to_vec();
+
+    for (keyword, values) in fields {
+        for text in values {
+            // keyword\0, compression flag, compression method, language\0, translated keyword\0, text
+            let mut data = keyword.as_bytes().to_vec();
+            data.extend_from_slice(&[0, 0, 0, 0, 0]);
+            data.extend_from_slice(text.as_bytes());
+
+            let mut chunk = b"iTXt".to_vec();
+            chunk.extend_from_slice(&data);
+            output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            output.extend_from_slice(&chunk);
+            output.extend_from_slice(&crc32(&chunk).to_be_bytes());
+        }
+    }
+
+    output.extend_from_slice(&png[ihdr_end..]);
+    Ok(output)
+}
+
+/// Read tEXt and uncompressed iTXt chunks from a PNG
+fn read_png_text(png: &[u8]) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut fields = HashMap::new();
+    let mut pos = 8;
+
+    while pos + 12 <= png.len() {
+        let length = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let data = png.get(pos + 8..pos + 8 + length)
+            .ok_or_else(|| "Truncated PNG chunk".to_string())?;
+
+        if kind == b"tEXt" || kind == b"iTXt" {
+            if let Some(nul) = data.iter().position(|&b| b == 0) {
+                let keyword = String::from_utf8_lossy(&data[..nul]).to_string();
+                let rest = &data[nul + 1..];
+                let text = if kind == b"tEXt" {
+                    Some(rest.iter().map(|&b| b as char).collect::<String>())
+                } else if rest.len() >= 2 && rest[0] == 0 {
+                    // Skip the language tag and translated keyword
+                    let mut parts = rest[2..].splitn(3, |&b| b == 0);
+                    let _language = parts.next();
+                    let _translated = parts.next();
+                    parts.next().map(|text| String::from_utf8_lossy(text).to_string())
+                } else {
+                    None // compressed iTXt isn't something we write
+                };
+                if let Some(text) = text {
+                    fields.entry(keyword).or_insert_with(Vec::new).push(text);
+                }
+            }
+        } else if kind == b"IEND" {
+            break;
+        }
+
+        pos += 12 + length;
+    }
+
+    Ok(fields)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Build an XMP packet holding `fields`, one list item per value
+fn build_xmp(fields: &[(&str, Vec<String>)]) -> String {
+    let mut properties = String::new();
+    for (keyword, values) in fields {
+        let property = match FIELDS.iter().find(|(k, _)| k == keyword) {
+            Some((_, property)) => *property,
+            None => continue,
+        };
+        let container = match *property {
+            "dc:title" | "dc:description" | "dc:rights" => "rdf:Alt",
+            "dc:creator" => "rdf:Seq",
+            _ => "rdf:Bag",
+        };
+
+        properties.push_str(&format!("   <{}><{}>", property, container));
+        for item in values {
+            let lang = if container == "rdf:Alt" { " xml:lang=\"x-default\"" } else { "" };
+            properties.push_str(&format!("<rdf:li{}>{}</rdf:li>", lang, xml_escape(item)));
+        }
+        properties.push_str(&format!("</{}></{}>\n", container, property));
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+          <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+           <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         {}\
+           </rdf:Description>\n\
+          </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        properties
+    )
+}
+
+/// Insert an XMP APP1 segment holding `fields` into an encoded JPEG
+pub fn embed_jpeg_xmp(jpeg: &[u8], fields: &[(&str, Vec<String>)]) -> Result<Vec<u8>, String> {
+    if jpeg.len() < 4 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+        return Err("Not a JPEG file".to_string());
+    }
+
+    let mut payload = XMP_NAMESPACE.to_vec();
+    payload.extend_from_slice(build_xmp(fields).as_bytes());
+    let length = payload.len() + 2;
+    if length > u16::MAX as usize {
+        return Err("Metadata is too large for a JPEG segment".to_string());
+    }
+
+    // Keep a leading JFIF APP0 segment first, as some readers expect
+    let mut insert_at = 2;
+    if jpeg.len() >= 6 && jpeg[2] == 0xFF && jpeg[3] == 0xE0 {
+        insert_at = 4 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+    }
+
+    let mut output = jpeg[..insert_at].to_vec();
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&(length as u16).to_be_bytes());
+    output.extend_from_slice(&payload);
+    output.extend_from_slice(&jpeg[insert_at..]);
+    Ok(output)
+}
+
+/// Find the XMP packet in a JPEG and pull out the fields we understand
+fn read_jpeg_xmp(jpeg: &[u8]) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut fields = HashMap::new();
+    let mut pos = 2;
+
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        // Start of scan: no more metadata segments follow
+        if marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let segment = jpeg.get(pos + 4..pos + 2 + length)
+            .ok_or_else(|| "Truncated JPEG segment".to_string())?;
+
+        if marker == 0xE1 && segment.starts_with(XMP_NAMESPACE) {
+            let xmp = String::from_utf8_lossy(&segment[XMP_NAMESPACE.len()..]).to_string();
+            for (keyword, property) in FIELDS.iter() {
+                let open = format!("<{}>", property);
+                let close = format!("</{}>", property);
+                let body = match (xmp.find(&open), xmp.find(&close)) {
+                    (Some(start), Some(end)) if start < end => &xmp[start + open.len()..end],
+                    _ => continue,
+                };
+
+                let items: Vec<String> = body.split("<rdf:li")
+                    .skip(1)
+                    .filter_map(|item| {
+                        let text_start = item.find('>')? + 1;
+                        let text_end = item.find("</rdf:li>")?;
+                        Some(xml_unescape(&item[text_start..text_end]))
+                    })
+                    .collect();
+                if !items.is_empty() {
+                    fields.insert(keyword.to_string(), items);
+                }
+            }
+        }
+
+        pos += 2 + length;
+    }
+
+    Ok(fields)
+}
+
+/// Read the descriptive text fields from a PNG or JPEG file, keyed by PNG
+/// keyword ("Title", "Author", "Description", "Copyright", "Keywords")
+pub fn read_text_fields<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Vec<String>>, String> {
+    let bytes = fs::read(path.as_ref())
+        .map_err(|e| format!("Failed to read {:?}: {}", path.as_ref(), e))?;
+
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        read_png_text(&bytes)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        read_jpeg_xmp(&bytes)
+    } else {
+        Err("Metadata can only be read from PNG and JPEG files".to_string())
+    }
+}
+
+/// Camera settings recorded in a photo's EXIF block
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExifData {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub iso: Option<u32>,
+    /// Shutter speed in seconds
+    pub exposure_time: Option<f64>,
+    /// Aperture as an f-number
+    pub f_number: Option<f64>,
+    /// EXIF orientation, 1 - 8. Documents opened from the file already
+    /// have it applied to their pixels.
+    pub orientation: u16,
+    /// When the photo was taken, as EXIF writes it ("YYYY:MM:DD HH:MM:SS")
+    pub date_taken: Option<String>,
+}
+
+/// Read the EXIF block of a JPEG or TIFF file's contents. Returns None for
+/// other formats and files without EXIF data.
+pub fn read_exif(bytes: &[u8]) -> Option<ExifData> {
+    let is_jpeg = bytes.starts_with(&[0xFF, 0xD8]);
+    let is_tiff = bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*");
+    if !is_jpeg && !is_tiff {
+        return None;
+    }
+    
+    let exif = match exif::Reader::new().read_from_container(&mut Cursor::new(bytes)) {
+        Ok(exif) => exif,
+        Err(e) => {
+            debug!("No EXIF data: {}", e);
+            return None;
+        }
+    };
+    let field = |tag: Tag| exif.get_field(tag, In::PRIMARY).map(|field| &field.value);
+    let text = |tag: Tag| match field(tag) {
+        Some(Value::Ascii(values)) => values.first()
+            .map(|value| String::from_utf8_lossy(value).trim_end_matches('\0').trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    };
+    let rational = |tag: Tag| match field(tag) {
+        Some(Value::Rational(values)) => values.first()
+            .filter(|value| value.denom != 0)
+            .map(|value| value.to_f64()),
+        _ => None,
+    };
+    
+    Some(ExifData {
+        camera_make: text(Tag::Make),
+        camera_model: text(Tag::Model),
+        iso: field(Tag::PhotographicSensitivity).and_then(|value| value.get_uint(0)),
+        exposure_time: rational(Tag::ExposureTime),
+        f_number: rational(Tag::FNumber),
+        orientation: field(Tag::Orientation)
+            .and_then(|value| value.get_uint(0))
+            .filter(|orientation| (1..=8).contains(orientation))
+            .unwrap_or(1) as u16,
+        date_taken: text(Tag::DateTimeOriginal).or_else(|| text(Tag::DateTime)),
+    })
+}
+
+/// Rotate and flip `image` so that a photo stored with EXIF `orientation`
+/// displays upright
+pub fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        // Transposed: mirrored across the top-left to bottom-right diagonal
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
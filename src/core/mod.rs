@@ -7,12 +7,26 @@ pub mod canvas;
 pub mod document;
 pub mod history;
 pub mod settings;
+pub mod metadata;
+pub mod native;
+pub mod export;
+pub mod tiles;
+pub mod blend;
+pub mod text;
+pub mod linked;
+pub mod adjustment;
 
 pub use point::Point;
-pub use layer::{Layer, LayerManager, BlendMode};
-pub use selection::Selection;
+pub use layer::{Layer, LayerManager, BlendMode, LinkedFile, SmartObject};
+pub use selection::{Selection, Rect};
 pub use canvas::Canvas;
-pub use document::{Document, DocumentFormat, DocumentMetadata};
+pub use document::{Channel, ChannelMode, Document, DocumentBackground, DocumentFormat, DocumentMetadata, Guide, GuideOrientation, HistogramChannel, UniqueColorResult};
+pub use export::{ExportError, ExportFormat, ExportOptions};
+pub use tiles::{TileCache, TileKey};
+pub use blend::{blend_pixel, LayerBlendMode};
+pub use text::{render_text, TextLayerData};
+pub use linked::{LinkedFileCache, LinkedTransform};
+pub use adjustment::{AdjustmentLayer, AdjustmentSettings, AdjustmentType};
 pub use history::{HistoryManager, HistoryCommand, HistoryState};
 pub use settings::{Settings, PerformanceSettings, SaveSettings, DisplaySettings, SettingsManager};
 
@@ -69,6 +83,83 @@ impl Color {
         trace!("Creating transparent color");
         Self::new(0.0, 0.0, 0.0, 0.0)
     }
+    
+    /// Hue in degrees (0-360), saturation and lightness (0-1). Grays have
+    /// hue and saturation 0.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (h, s, l) = crate::filters::rgb_to_hsl_f32(self.r, self.g, self.b);
+        (h * 360.0, s, l)
+    }
+    
+    /// A color from hue in degrees, saturation and lightness (0-1) and alpha
+    pub fn from_hsl(h: f32, s: f32, l: f32, a: f32) -> Self {
+        let (r, g, b) = crate::filters::hsl_to_rgb_f32(
+            (h / 360.0).rem_euclid(1.0),
+            s.clamp(0.0, 1.0),
+            l.clamp(0.0, 1.0),
+        );
+        Self::new(r, g, b, a)
+    }
+    
+    /// Hue in degrees (0-360), saturation and value (0-1). Grays have hue
+    /// and saturation 0.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        if max - min <= f32::EPSILON {
+            return (0.0, 0.0, max);
+        }
+        
+        // HSV shares its hue with HSL
+        let (h, _, _) = self.to_hsl();
+        (h, (max - min) / max, max)
+    }
+    
+    /// A color from hue in degrees, saturation and value (0-1) and alpha
+    pub fn from_hsv(h: f32, s: f32, v: f32, a: f32) -> Self {
+        let (s, v) = (s.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+        let chroma = v * s;
+        let base = v - chroma;
+        let sector = (h / 60.0).rem_euclid(6.0);
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        Self::new(
+            (r + base).clamp(0.0, 1.0),
+            (g + base).clamp(0.0, 1.0),
+            (b + base).clamp(0.0, 1.0),
+            a,
+        )
+    }
+    
+    /// CIE L*a*b* (D65) of the color's sRGB values
+    pub fn to_lab(&self) -> (f32, f32, f32) {
+        let linear = [
+            crate::filters::srgb_decode(self.r),
+            crate::filters::srgb_decode(self.g),
+            crate::filters::srgb_decode(self.b),
+        ];
+        let [l, a, b] = crate::filters::linear_rgb_to_lab(linear);
+        (l, a, b)
+    }
+    
+    /// A color from CIE L*a*b* (D65) and alpha; colors outside the sRGB
+    /// gamut are clipped channel by channel
+    pub fn from_lab(l: f32, a: f32, b: f32, alpha: f32) -> Self {
+        let [r, g, b] = crate::filters::lab_to_linear_rgb([l, a, b]);
+        Self::new(
+            crate::filters::srgb_encode(r),
+            crate::filters::srgb_encode(g),
+            crate::filters::srgb_encode(b),
+            alpha,
+        )
+    }
 }
 
 // Core application types
@@ -135,11 +226,12 @@ impl Default for Preferences {
 impl AppState {
     pub fn new() -> Self {
         info!("Creating new AppState");
+        let preferences = Preferences::default();
         Self {
             current_document: None,
             documents: Vec::new(),
-            history_manager: HistoryManager::new(),
-            preferences: Preferences::default(),
+            history_manager: HistoryManager::with_max_levels(preferences.undo_limit),
+            preferences,
             clipboard: None,
         }
     }
@@ -147,7 +239,8 @@ impl AppState {
     pub fn new_document(&mut self, width: u32, height: u32, color_space: ColorSpace, bit_depth: BitDepth) -> Document {
         info!("Creating new document: {}x{} with color space {:?} and bit depth {:?}", 
               width, height, color_space, bit_depth);
-        let document = Document::new(width, height);
+        let mut document = Document::new(width, height);
+        document.set_undo_limit(self.preferences.undo_limit);
         self.documents.push(document.clone());
         self.current_document = Some(document.clone());
         debug!("Document created successfully");
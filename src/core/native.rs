@@ -0,0 +1,268 @@
+// The editor's native layered document format (.rpho).
+//
+// A file is the magic bytes `RPHO`, a little-endian u32 format version and
+// a u32 manifest length, followed by the JSON manifest and then a blob
+// section. The manifest describes the document and its layer tree, groups,
+// smart objects, links, text and adjustment settings included; layer
+// pixels and masks are stored as PNG blobs it refers to by offset, so
+// nothing is lost to recompression on a round trip.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageOutputFormat, Rgba};
+use serde::{Deserialize, Serialize};
+use crate::core::adjustment::AdjustmentSettings;
+use crate::core::document::{Document, DocumentMetadata, Guide};
+use crate::core::layer::{BlendMode, Layer, LayerManager, LinkedFile, SmartObject};
+use crate::core::linked::{LinkedFileCache, LinkedTransform};
+use crate::core::text::TextLayerData;
+
+const MAGIC: &[u8; 4] = b"RPHO";
+const VERSION: u32 = 1;
+/// Magic + version + manifest length
+const HEADER_LEN: usize = 12;
+
+/// Location of an encoded image inside the blob section
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct BlobRef {
+    offset: usize,
+    length: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestLink {
+    path: PathBuf,
+    transform: LinkedTransform,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestSmartObject {
+    width: u32,
+    height: u32,
+    layers: Vec<ManifestLayer>,
+    linked: Option<ManifestLink>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestLayer {
+    id: String,
+    name: String,
+    visible: bool,
+    opacity: f64,
+    blend_mode: BlendMode,
+    x_offset: i32,
+    y_offset: i32,
+    width: u32,
+    height: u32,
+    pixels: BlobRef,
+    mask: Option<BlobRef>,
+    smart_object: Option<ManifestSmartObject>,
+    text: Option<TextLayerData>,
+    adjustment: Option<AdjustmentSettings>,
+    /// Set on groups, bottom to top
+    children: Option<Vec<ManifestLayer>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    width: u32,
+    height: u32,
+    dpi: f32,
+    background_color: [u8; 4],
+    metadata: DocumentMetadata,
+    guides: Vec<Guide>,
+    active_layer: usize,
+    /// Bottom to top, as in `LayerManager`
+    layers: Vec<ManifestLayer>,
+}
+
+/// Accumulates PNG-encoded images for the blob section
+struct BlobWriter {
+    data: Vec<u8>,
+}
+
+impl BlobWriter {
+    fn push(&mut self, image: DynamicImage) -> Result<BlobRef, String> {
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, ImageOutputFormat::Png)
+            .map_err(|e| format!("Failed to encode layer data: {}", e))?;
+        let encoded = encoded.into_inner();
+
+        let blob = BlobRef { offset: self.data.len(), length: encoded.len() };
+        self.data.extend_from_slice(&encoded);
+        Ok(blob)
+    }
+}
+
+fn write_layer(layer: &Layer, blobs: &mut BlobWriter) -> Result<ManifestLayer, String> {
+    let pixels = blobs.push(DynamicImage::ImageRgba8(layer.image.clone()))?;
+    let mask = match &layer.mask {
+        Some(mask) => Some(blobs.push(DynamicImage::ImageLuma8(mask.clone()))?),
+        None => None,
+    };
+    let smart_object = match &layer.smart_object {
+        Some(smart) => Some(ManifestSmartObject {
+            width: smart.width,
+            height: smart.height,
+            layers: smart.layers.iter()
+                .map(|child| write_layer(child, blobs))
+                .collect::<Result<_, _>>()?,
+            linked: smart.linked.as_ref().map(|linked| ManifestLink {
+                path: linked.path.clone(),
+                transform: linked.transform,
+            }),
+        }),
+        None => None,
+    };
+    let children = match &layer.children {
+        Some(children) => Some(children.iter()
+            .map(|child| write_layer(child, blobs))
+            .collect::<Result<_, _>>()?),
+        None => None,
+    };
+
+    Ok(ManifestLayer {
+        id: layer.id.clone(),
+        name: layer.name.clone(),
+        visible: layer.visible,
+        opacity: layer.opacity,
+        blend_mode: layer.blend_mode,
+        x_offset: layer.x_offset,
+        y_offset: layer.y_offset,
+        width: layer.width,
+        height: layer.height,
+        pixels,
+        mask,
+        smart_object,
+        text: layer.text.clone(),
+        adjustment: layer.adjustment.as_ref().map(|adjustment| adjustment.settings()),
+        children,
+    })
+}
+
+fn read_blob(blobs: &[u8], blob: BlobRef) -> Result<DynamicImage, String> {
+    let bytes = blob.offset.checked_add(blob.length)
+        .and_then(|end| blobs.get(blob.offset..end))
+        .ok_or_else(|| "Layer data points outside the file".to_string())?;
+    image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to decode layer data: {}", e))
+}
+
+fn read_layer(entry: ManifestLayer, blobs: &[u8]) -> Result<Layer, String> {
+    let image: ImageBuffer<Rgba<u8>, Vec<u8>> = read_blob(blobs, entry.pixels)?.to_rgba8();
+    let mask: Option<GrayImage> = match entry.mask {
+        Some(blob) => Some(read_blob(blobs, blob)?.to_luma8()),
+        None => None,
+    };
+    let smart_object = match entry.smart_object {
+        Some(smart) => Some(SmartObject {
+            width: smart.width,
+            height: smart.height,
+            layers: smart.layers.into_iter()
+                .map(|child| read_layer(child, blobs))
+                .collect::<Result<_, _>>()?,
+            linked: smart.linked.map(|linked| LinkedFile {
+                path: linked.path,
+                transform: linked.transform,
+                cache: LinkedFileCache::default(),
+            }),
+        }),
+        None => None,
+    };
+    let children = match entry.children {
+        Some(children) => Some(children.into_iter()
+            .map(|child| read_layer(child, blobs))
+            .collect::<Result<_, _>>()?),
+        None => None,
+    };
+
+    let mut layer = Layer::from_image(image, entry.name);
+    layer.id = entry.id;
+    layer.visible = entry.visible;
+    layer.opacity = entry.opacity;
+    layer.blend_mode = entry.blend_mode;
+    layer.x_offset = entry.x_offset;
+    layer.y_offset = entry.y_offset;
+    layer.width = entry.width;
+    layer.height = entry.height;
+    layer.mask = mask;
+    layer.smart_object = smart_object;
+    layer.text = entry.text;
+    layer.adjustment = entry.adjustment.map(AdjustmentSettings::into_adjustment);
+    layer.children = children;
+    Ok(layer)
+}
+
+/// Encode `document` in the native format
+pub fn encode(document: &Document) -> Result<Vec<u8>, String> {
+    let mut blobs = BlobWriter { data: Vec::new() };
+    let layers = document.layer_manager.get_layers().iter()
+        .map(|layer| write_layer(layer, &mut blobs))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let manifest = Manifest {
+        width: document.width,
+        height: document.height,
+        dpi: document.dpi,
+        background_color: document.background_color.0,
+        metadata: document.metadata.clone(),
+        guides: document.guides.clone(),
+        active_layer: document.layer_manager.get_active_layer_index(),
+        layers,
+    };
+    let json = serde_json::to_vec(&manifest)
+        .map_err(|e| format!("Failed to write document manifest: {}", e))?;
+
+    let mut output = Vec::with_capacity(HEADER_LEN + json.len() + blobs.data.len());
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&VERSION.to_le_bytes());
+    output.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    output.extend_from_slice(&json);
+    output.extend_from_slice(&blobs.data);
+    Ok(output)
+}
+
+/// Decode a native document. The caller sets `path`; history starts empty.
+pub fn decode(bytes: &[u8]) -> Result<Document, String> {
+    if !is_native(bytes) || bytes.len() < HEADER_LEN {
+        return Err("Not a native document".to_string());
+    }
+    let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if version > VERSION {
+        return Err(format!("Document was saved by a newer version (format {})", version));
+    }
+    let manifest_len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    let json = bytes.get(HEADER_LEN..HEADER_LEN + manifest_len)
+        .ok_or_else(|| "Document manifest is truncated".to_string())?;
+    let blobs = &bytes[HEADER_LEN + manifest_len..];
+
+    let manifest: Manifest = serde_json::from_slice(json)
+        .map_err(|e| format!("Failed to read document manifest: {}", e))?;
+
+    let mut layer_manager = LayerManager::new();
+    for entry in manifest.layers {
+        layer_manager.add_layer(read_layer(entry, blobs)?);
+    }
+    layer_manager.set_active_layer(manifest.active_layer);
+
+    let mut document = Document::new(manifest.width, manifest.height);
+    document.layer_manager = layer_manager;
+    document.dpi = manifest.dpi;
+    document.background_color = Rgba(manifest.background_color);
+    document.metadata = manifest.metadata;
+    document.guides = manifest.guides;
+    Ok(document)
+}
+
+/// Whether `bytes` start like a native document
+pub fn is_native(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Write `document` to `path` in the native format
+pub fn save<P: AsRef<Path>>(document: &Document, path: P) -> Result<(), String> {
+    let bytes = encode(document)?;
+    fs::write(path.as_ref(), bytes)
+        .map_err(|e| format!("Failed to write {:?}: {}", path.as_ref(), e))
+}
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// A 2D point in the document
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
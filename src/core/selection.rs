@@ -1,9 +1,11 @@
 use image::{ImageBuffer, Rgba, GenericImageView};
 use cairo::Context;
+use serde::{Deserialize, Serialize};
 use crate::core::Point;
+use crate::vector::VectorPath;
 
 /// Represents a rectangle with position and size
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Rect {
     pub x: f64,
     pub y: f64,
@@ -57,6 +59,15 @@ pub enum SelectionShape {
     MagicWand,
 }
 
+/// Subsamples per side of a pixel when rasterizing lasso and polygon edges
+const LASSO_SUBSAMPLES: u32 = 4;
+
+/// Mask pixel for a coverage between 0.0 and 1.0
+fn coverage_pixel(coverage: f64) -> Rgba<u8> {
+    let value = (coverage * 255.0).round() as u8;
+    Rgba([value, value, value, value])
+}
+
 /// Represents a selection in the image
 #[derive(Clone)]
 pub struct Selection {
@@ -146,19 +157,21 @@ impl Selection {
         let radius_x = width as f64 / 2.0;
         let radius_y = height as f64 / 2.0;
         
+        // Coverage falls from 1 to 0 over the pixel straddling the edge. The
+        // distance to the edge is the implicit function over its gradient,
+        // which is close enough at a pixel's scale.
         for py in 0..canvas_height {
             for px in 0..canvas_width {
-                let dx = (px as f64 - center_x) / radius_x;
-                let dy = (py as f64 - center_y) / radius_y;
-                let distance = dx * dx + dy * dy;
-                
-                let pixel = if distance <= 1.0 {
-                    Rgba([255, 255, 255, 255])
+                let (ox, oy) = (px as f64 - center_x, py as f64 - center_y);
+                let implicit = (ox / radius_x).powi(2) + (oy / radius_y).powi(2) - 1.0;
+                let gradient = (2.0 * ox / (radius_x * radius_x)).hypot(2.0 * oy / (radius_y * radius_y));
+                let coverage = if gradient < 1e-9 {
+                    if implicit <= 0.0 { 1.0 } else { 0.0 }
                 } else {
-                    Rgba([0, 0, 0, 0])
+                    (0.5 - implicit / gradient).clamp(0.0, 1.0)
                 };
                 
-                selection.mask.put_pixel(px, py, pixel);
+                selection.mask.put_pixel(px, py, coverage_pixel(coverage));
             }
         }
         
@@ -189,17 +202,32 @@ impl Selection {
         selection.width = (max_x - min_x).ceil() as u32;
         selection.height = (max_y - min_y).ceil() as u32;
         
-        // Create mask using point-in-polygon algorithm
+        // Point-in-polygon on a grid of subsamples around each pixel gives
+        // its partial coverage along the edges. Pixels away from the
+        // polygon's bounding box can't be covered at all.
+        let step = 1.0 / LASSO_SUBSAMPLES as f64;
         for py in 0..canvas_height {
             for px in 0..canvas_width {
-                let point = Point::new(px as f64, py as f64);
-                let pixel = if Selection::point_in_polygon(&point, &points) {
-                    Rgba([255, 255, 255, 255])
-                } else {
-                    Rgba([0, 0, 0, 0])
-                };
+                let (fx, fy) = (px as f64, py as f64);
+                if points.is_empty() || fx + 0.5 < min_x || fx - 0.5 > max_x || fy + 0.5 < min_y || fy - 0.5 > max_y {
+                    selection.mask.put_pixel(px, py, Rgba([0, 0, 0, 0]));
+                    continue;
+                }
                 
-                selection.mask.put_pixel(px, py, pixel);
+                let mut inside = 0;
+                for sy in 0..LASSO_SUBSAMPLES {
+                    for sx in 0..LASSO_SUBSAMPLES {
+                        let point = Point::new(
+                            fx - 0.5 + (sx as f64 + 0.5) * step,
+                            fy - 0.5 + (sy as f64 + 0.5) * step,
+                        );
+                        if Selection::point_in_polygon(&point, &points) {
+                            inside += 1;
+                        }
+                    }
+                }
+                let coverage = inside as f64 / (LASSO_SUBSAMPLES * LASSO_SUBSAMPLES) as f64;
+                selection.mask.put_pixel(px, py, coverage_pixel(coverage));
             }
         }
         
@@ -299,6 +327,10 @@ impl Selection {
     
     /// Combine with another selection based on the selection type
     pub fn combine(&mut self, other: &Selection) {
+        // Only the overlapping area of the two masks can be combined
+        let width = self.mask.width().min(other.mask.width());
+        let height = self.mask.height().min(other.mask.height());
+        
         match other.selection_type {
             SelectionType::New => {
                 // Replace the current selection
@@ -312,8 +344,8 @@ impl Selection {
             },
             SelectionType::Add => {
                 // Add to the current selection
-                for y in 0..self.mask.height() {
-                    for x in 0..self.mask.width() {
+                for y in 0..height {
+                    for x in 0..width {
                         let current = self.mask.get_pixel(x, y)[0];
                         let other_val = other.mask.get_pixel(x, y)[0];
                         
@@ -325,8 +357,8 @@ impl Selection {
             },
             SelectionType::Subtract => {
                 // Subtract from the current selection
-                for y in 0..self.mask.height() {
-                    for x in 0..self.mask.width() {
+                for y in 0..height {
+                    for x in 0..width {
                         let current = self.mask.get_pixel(x, y)[0];
                         let other_val = other.mask.get_pixel(x, y)[0];
                         
@@ -338,8 +370,8 @@ impl Selection {
             },
             SelectionType::Intersect => {
                 // Intersect with the current selection
-                for y in 0..self.mask.height() {
-                    for x in 0..self.mask.width() {
+                for y in 0..height {
+                    for x in 0..width {
                         let current = self.mask.get_pixel(x, y)[0];
                         let other_val = other.mask.get_pixel(x, y)[0];
                         
@@ -416,41 +448,195 @@ impl Selection {
         self.points.clear();
     }
     
-    /// Feather the selection by a given radius
+    /// Feather the selection by a given radius.
+    ///
+    /// The mask is blurred with a Gaussian of standard deviation `radius`,
+    /// run as a horizontal and then a vertical 1D pass. A square 2D
+    /// Gaussian kernel factors exactly into the two passes, so this matches
+    /// the full 2D blur while costing O(r) per pixel instead of O(r²).
     pub fn feather(&mut self, radius: f64) {
-        // Create a copy of the original mask
-        let original = self.mask.clone();
-        let width = self.mask.width();
-        let height = self.mask.height();
+        if !(radius > 0.0) {
+            return;
+        }
+        let width = self.mask.width() as usize;
+        let height = self.mask.height() as usize;
+        if width == 0 || height == 0 {
+            return;
+        }
+        
+        // Kernel size based on radius
+        let half_kernel = (radius as i64).max(1);
+        let kernel: Vec<f32> = (-half_kernel..=half_kernel)
+            .map(|k| (-((k * k) as f64) / (2.0 * radius * radius)).exp() as f32)
+            .collect();
+        let kernel_sum: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|w| w / kernel_sum).collect();
+        
+        // Edge pixels are repeated past the border
+        let blur_line = |input: &[f32], output: &mut [f32]| {
+            let last = input.len() as i64 - 1;
+            for (i, value) in output.iter_mut().enumerate() {
+                *value = kernel.iter().enumerate()
+                    .map(|(k, weight)| {
+                        let sample = (i as i64 + k as i64 - half_kernel).clamp(0, last);
+                        input[sample as usize] * weight
+                    })
+                    .sum();
+            }
+        };
+        
+        let mut values: Vec<f32> = self.mask.pixels().map(|p| p[0] as f32).collect();
+        
+        let mut row = vec![0.0f32; width];
+        for y in 0..height {
+            blur_line(&values[y * width..(y + 1) * width], &mut row);
+            values[y * width..(y + 1) * width].copy_from_slice(&row);
+        }
+        
+        let mut column = vec![0.0f32; height];
+        let mut blurred = vec![0.0f32; height];
+        for x in 0..width {
+            for y in 0..height {
+                column[y] = values[y * width + x];
+            }
+            blur_line(&column, &mut blurred);
+            for y in 0..height {
+                values[y * width + x] = blurred[y];
+            }
+        }
+        
+        for (pixel, value) in self.mask.pixels_mut().zip(values) {
+            let v = value.round().clamp(0.0, 255.0) as u8;
+            *pixel = Rgba([v, v, v, 255]);
+        }
+    }
+    
+    /// Expand the selection into neighbouring pixels of similar color.
+    ///
+    /// Growth starts from every selected pixel on the selection's border and
+    /// floods outwards; each new pixel is compared against the color of the
+    /// border pixel it grew from (not its immediate neighbour), so a gradual
+    /// color drift can't carry the selection across a real edge.
+    pub fn grow_by_color(&mut self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, tolerance: u8) {
+        let width = self.mask.width().min(image.width());
+        let height = self.mask.height().min(image.height());
+        let is_selected = |mask: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: u32, y: u32| mask.get_pixel(x, y)[0] >= 128;
         
-        // Apply Gaussian-like blur to the mask
+        let is_similar = |color1: &Rgba<u8>, color2: &Rgba<u8>| -> bool {
+            (0..4).map(|c| (color1[c] as i32 - color2[c] as i32).abs())
+                .max()
+                .unwrap_or(0) <= tolerance as i32
+        };
+        
+        let neighbours = |x: u32, y: u32| {
+            let mut result = Vec::with_capacity(4);
+            if x > 0 { result.push((x - 1, y)); }
+            if x + 1 < width { result.push((x + 1, y)); }
+            if y > 0 { result.push((x, y - 1)); }
+            if y + 1 < height { result.push((x, y + 1)); }
+            result
+        };
+        
+        // Seed with border pixels: selected, with at least one unselected neighbour
+        let mut queue = std::collections::VecDeque::new();
         for y in 0..height {
             for x in 0..width {
-                let mut sum = 0.0;
-                let mut weight_sum = 0.0;
-                
-                // Kernel size based on radius
-                let kernel_size = (radius as i32 * 2 + 1).max(3);
-                let half_kernel = kernel_size / 2;
-                
-                for ky in -half_kernel..=half_kernel {
-                    for kx in -half_kernel..=half_kernel {
-                        let sample_x = (x as i32 + kx).clamp(0, width as i32 - 1) as u32;
-                        let sample_y = (y as i32 + ky).clamp(0, height as i32 - 1) as u32;
-                        
-                        let dist = (kx * kx + ky * ky) as f64;
-                        let weight = (-dist / (2.0 * radius * radius)).exp();
-                        
-                        let sample_val = original.get_pixel(sample_x, sample_y)[0] as f64 / 255.0;
-                        sum += sample_val * weight;
-                        weight_sum += weight;
-                    }
+                if is_selected(&self.mask, x, y)
+                    && neighbours(x, y).iter().any(|&(nx, ny)| !is_selected(&self.mask, nx, ny)) {
+                    queue.push_back((x, y, *image.get_pixel(x, y)));
+                }
+            }
+        }
+        
+        while let Some((x, y, reference)) = queue.pop_front() {
+            for (nx, ny) in neighbours(x, y) {
+                if is_selected(&self.mask, nx, ny) {
+                    continue;
+                }
+                if is_similar(image.get_pixel(nx, ny), &reference) {
+                    self.mask.put_pixel(nx, ny, Rgba([255, 255, 255, 255]));
+                    queue.push_back((nx, ny, reference));
+                }
+            }
+        }
+        
+        self.update_bounds();
+    }
+    
+    /// Refine the selection edge into a soft alpha matte.
+    ///
+    /// Within `radius` pixels of the current boundary the mask is rebuilt
+    /// with a guided filter that uses the image's luminance as the guide, so
+    /// the alpha follows soft transitions in the image (hair, fur, blur)
+    /// instead of the hard outline. `edge_contrast` (0..1) controls how
+    /// strongly the matte snaps to image edges; higher is crisper.
+    pub fn refine_edges(&mut self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, radius: u32, edge_contrast: f32) {
+        let width = self.mask.width().min(image.width());
+        let height = self.mask.height().min(image.height());
+        if width == 0 || height == 0 || radius == 0 {
+            return;
+        }
+        let (w, h) = (width as usize, height as usize);
+        let r = radius as usize;
+        
+        let mut guide = vec![0.0f64; w * h];
+        let mut alpha = vec![0.0f64; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let p = image.get_pixel(x as u32, y as u32);
+                guide[y * w + x] = (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64) / 255.0;
+                alpha[y * w + x] = self.mask.get_pixel(x as u32, y as u32)[0] as f64 / 255.0;
+            }
+        }
+        
+        // Mark boundary pixels; the band around them is the "unknown" trimap region
+        let mut boundary = vec![0.0f64; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let inside = alpha[y * w + x] >= 0.5;
+                let differs = (x > 0 && (alpha[y * w + x - 1] >= 0.5) != inside)
+                    || (y > 0 && (alpha[(y - 1) * w + x] >= 0.5) != inside);
+                if differs {
+                    boundary[y * w + x] = 1.0;
                 }
-                
-                let avg = (sum / weight_sum * 255.0).round().clamp(0.0, 255.0) as u8;
-                self.mask.put_pixel(x, y, Rgba([avg, avg, avg, 255]));
             }
         }
+        let band = box_mean(&boundary, w, h, r);
+        
+        // Guided filter: fit alpha as a local linear function of the guide
+        let eps = (1.0 - edge_contrast.clamp(0.0, 1.0) as f64) * 0.05 + 1e-4;
+        let guide_sq: Vec<f64> = guide.iter().map(|v| v * v).collect();
+        let guide_alpha: Vec<f64> = guide.iter().zip(&alpha).map(|(i, p)| i * p).collect();
+        
+        let mean_i = box_mean(&guide, w, h, r);
+        let mean_p = box_mean(&alpha, w, h, r);
+        let mean_ii = box_mean(&guide_sq, w, h, r);
+        let mean_ip = box_mean(&guide_alpha, w, h, r);
+        
+        let mut coeff_a = vec![0.0f64; w * h];
+        let mut coeff_b = vec![0.0f64; w * h];
+        for i in 0..w * h {
+            let variance = mean_ii[i] - mean_i[i] * mean_i[i];
+            let covariance = mean_ip[i] - mean_i[i] * mean_p[i];
+            coeff_a[i] = covariance / (variance + eps);
+            coeff_b[i] = mean_p[i] - coeff_a[i] * mean_i[i];
+        }
+        let mean_a = box_mean(&coeff_a, w, h, r);
+        let mean_b = box_mean(&coeff_b, w, h, r);
+        
+        for y in 0..h {
+            for x in 0..w {
+                let i = y * w + x;
+                if band[i] <= 0.0 {
+                    continue;
+                }
+                let refined = (mean_a[i] * guide[i] + mean_b[i]).clamp(0.0, 1.0);
+                let value = (refined * 255.0).round() as u8;
+                self.mask.put_pixel(x as u32, y as u32, Rgba([value, value, value, 255]));
+            }
+        }
+        
+        self.update_bounds();
     }
     
     /// Grow the selection by a given number of pixels
@@ -622,35 +808,14 @@ impl Selection {
                 }
             },
             SelectionShape::MagicWand => {
-                // For magic wand, trace the outline of the mask
-                let width = self.mask.width();
-                let height = self.mask.height();
-                
-                // Find the edges of the selection
-                let mut started = false;
-                
-                // This is a simple edge detection approach
-                // A more sophisticated algorithm would use contour tracing
-                for y in 0..height {
-                    for x in 0..width {
-                        let current = self.mask.get_pixel(x, y)[0] > 0;
-                        
-                        // Check if this pixel is at the edge of the selection
-                        let is_edge = current && (
-                            (x == 0 || self.mask.get_pixel(x - 1, y)[0] == 0) ||
-                            (x == width - 1 || self.mask.get_pixel(x + 1, y)[0] == 0) ||
-                            (y == 0 || self.mask.get_pixel(x, y - 1)[0] == 0) ||
-                            (y == height - 1 || self.mask.get_pixel(x, y + 1)[0] == 0)
-                        );
-                        
-                        if is_edge {
-                            if !started {
-                                context.move_to(x as f64, y as f64);
-                                started = true;
-                            } else {
-                                context.line_to(x as f64, y as f64);
-                            }
+                // Follow the mask boundary, holes included
+                for contour in self.contours() {
+                    if let Some(&(x, y)) = contour.first() {
+                        context.move_to(x, y);
+                        for &(x, y) in &contour[1..] {
+                            context.line_to(x, y);
                         }
+                        context.close_path();
                     }
                 }
             }
@@ -660,6 +825,121 @@ impl Selection {
         context.restore();
     }
     
+    /// Boundaries of the selected area as closed polygons through pixel
+    /// centers, found by Moore-neighbor tracing of the mask.
+    ///
+    /// Each selected region (8-connected) gives one outer contour, and each
+    /// hole in it (an unselected 4-connected area not touching the image
+    /// edge) gives an inner contour running along the selected pixels
+    /// around the hole. Pixels count as selected from half coverage up.
+    pub fn contours(&self) -> Vec<Vec<(f64, f64)>> {
+        let (width, height) = (self.mask.width() as i64, self.mask.height() as i64);
+        let selected = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && x < width && y < height && self.mask.get_pixel(x as u32, y as u32)[0] >= 128
+        };
+        let index = |x: i64, y: i64| (y * width + x) as usize;
+        
+        let mut contours = Vec::new();
+        let mut labelled = vec![false; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                if labelled[index(x, y)] {
+                    continue;
+                }
+                let is_selected = selected(x, y);
+                // Selected regions connect diagonally, unselected ones don't,
+                // so a diagonal gap never splits a region or joins two holes
+                let neighbours: &[(i64, i64)] = if is_selected { &MOORE_NEIGHBOURS } else { &[(-1, 0), (0, -1), (1, 0), (0, 1)] };
+                
+                let mut touches_edge = false;
+                let mut stack = vec![(x, y)];
+                labelled[index(x, y)] = true;
+                while let Some((px, py)) = stack.pop() {
+                    touches_edge |= px == 0 || py == 0 || px == width - 1 || py == height - 1;
+                    for &(dx, dy) in neighbours {
+                        let (nx, ny) = (px + dx, py + dy);
+                        if nx >= 0 && ny >= 0 && nx < width && ny < height
+                            && !labelled[index(nx, ny)] && selected(nx, ny) == is_selected {
+                            labelled[index(nx, ny)] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                
+                // (x, y) is the region's first pixel in scan order, so the
+                // pixel to its left is outside the region
+                if is_selected {
+                    contours.push(trace_contour(&selected, (x, y), (x - 1, y)));
+                } else if !touches_edge {
+                    contours.push(trace_contour(&selected, (x - 1, y), (x, y)));
+                }
+            }
+        }
+        contours
+    }
+    
+    /// The selection's boundary as a closed vector path, one subpath per
+    /// contour (see `contours`), so it can be edited as a shape
+    pub fn to_path(&self) -> VectorPath {
+        let mut path = VectorPath::new();
+        for contour in self.contours() {
+            if let Some(&(x, y)) = contour.first() {
+                path.move_to(x, y);
+                for &(x, y) in &contour[1..] {
+                    path.line_to(x, y);
+                }
+                path.close();
+            }
+        }
+        path
+    }
+    
+    /// Select the pixels of a `width` x `height` image whose centers fall
+    /// inside `path`, using the even-odd rule so inner subpaths cut holes.
+    ///
+    /// Centers lying on the outline count as inside: the outline from
+    /// `to_path` runs through the centers of the edge pixels, and they
+    /// belong to the selection.
+    pub fn from_path(path: &VectorPath, width: u32, height: u32) -> Self {
+        let mut selection = Self::new(width, height);
+        selection.shape = SelectionShape::MagicWand;
+        
+        let surface = match cairo::ImageSurface::create(cairo::Format::A8, 1, 1) {
+            Ok(surface) => surface,
+            Err(_) => return selection,
+        };
+        let context = match Context::new(&surface) {
+            Ok(context) => context,
+            Err(_) => return selection,
+        };
+        path.build_path(&context);
+        context.set_fill_rule(cairo::FillRule::EvenOdd);
+        context.set_line_width(0.01);
+        
+        // Only the pixels under the path's extents need testing
+        let (x1, y1, x2, y2) = match context.fill_extents() {
+            Ok(extents) => extents,
+            Err(_) => return selection,
+        };
+        let min_x = x1.floor().max(0.0) as u32;
+        let min_y = y1.floor().max(0.0) as u32;
+        let max_x = (x2.ceil().max(0.0) as u32).min(width);
+        let max_y = (y2.ceil().max(0.0) as u32).min(height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+                let inside = context.in_fill(cx, cy).unwrap_or(false)
+                    || context.in_stroke(cx, cy).unwrap_or(false);
+                if inside {
+                    selection.mask.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                }
+            }
+        }
+        
+        selection.update_bounds();
+        selection
+    }
+    
     /// Check if a point is inside the selection
     pub fn contains_point(&self, point: &Point) -> bool {
         if point.x < self.x || point.x >= self.x + self.width as f64 || 
@@ -719,4 +999,79 @@ impl Selection {
         x >= self.x && x <= self.x + self.width as f64 &&
         y >= self.y && y <= self.y + self.height as f64
     }
-} 
\ No newline at end of file
+}
+
+/// The eight neighbours of a pixel, clockwise on screen starting from the left
+const MOORE_NEIGHBOURS: [(i64, i64); 8] = [(-1, 0), (-1, -1), (0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1)];
+
+/// Moore-neighbor trace of the boundary of the selected region containing
+/// `start`, beginning with the unselected neighbour `backtrack`. Follows
+/// the boundary shared with `backtrack`'s area and stops on returning to
+/// `start` in the same state (Jacob's stopping criterion).
+fn trace_contour<F: Fn(i64, i64) -> bool>(selected: &F, start: (i64, i64), backtrack: (i64, i64)) -> Vec<(f64, f64)> {
+    let center = |(x, y): (i64, i64)| (x as f64 + 0.5, y as f64 + 0.5);
+    let mut contour = Vec::new();
+    let (mut current, mut backtrack) = (start, backtrack);
+    let mut first_move = None;
+    
+    loop {
+        // Sweep clockwise from the backtrack pixel to the next selected one;
+        // the last unselected pixel passed becomes the new backtrack
+        let offset = (backtrack.0 - current.0, backtrack.1 - current.1);
+        let from = MOORE_NEIGHBOURS.iter().position(|&n| n == offset).unwrap_or(0);
+        let step = (1..=8).find_map(|i| {
+            let (dx, dy) = MOORE_NEIGHBOURS[(from + i) % 8];
+            let (bx, by) = MOORE_NEIGHBOURS[(from + i - 1) % 8];
+            let candidate = (current.0 + dx, current.1 + dy);
+            if selected(candidate.0, candidate.1) {
+                Some((candidate, (current.0 + bx, current.1 + by)))
+            } else {
+                None
+            }
+        });
+        let step = match step {
+            Some(step) => step,
+            None => return vec![center(start)], // a lone pixel
+        };
+        
+        if current == start {
+            match first_move {
+                None => first_move = Some(step),
+                Some(first) if first == step => break,
+                Some(_) => {},
+            }
+        }
+        contour.push(center(current));
+        current = step.0;
+        backtrack = step.1;
+    }
+    contour
+}
+
+/// Mean over a (2r+1)x(2r+1) window at every pixel, clipped at the edges,
+/// computed in constant time per pixel from a summed-area table.
+fn box_mean(data: &[f64], width: usize, height: usize, radius: usize) -> Vec<f64> {
+    let stride = width + 1;
+    let mut integral = vec![0.0f64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0.0;
+        for x in 0..width {
+            row_sum += data[y * width + x];
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+    
+    let mut result = vec![0.0f64; width * height];
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = (x + radius + 1).min(width);
+            let sum = integral[y1 * stride + x1] - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0] + integral[y0 * stride + x0];
+            result[y * width + x] = sum / ((x1 - x0) * (y1 - y0)) as f64;
+        }
+    }
+    result
+}
@@ -0,0 +1,90 @@
+// Rasterizing text layers with Pango.
+//
+// A text layer keeps its text and style as `TextLayerData`; pixels are
+// made from it whenever the layer is composited or exported, so the text
+// stays editable.
+
+use cairo::{Context, Format, ImageSurface};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use crate::core::tiles;
+
+/// Text layer data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextLayerData {
+    pub text: String,
+    pub font_family: String,
+    pub font_size: f32,
+    pub color: [u8; 4],
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub alignment: TextAlignment,
+    /// Extra space between characters, in pixels
+    pub letter_spacing: f32,
+    /// Distance between baselines as a multiple of the font size
+    pub line_height: f32,
+}
+
+/// Text alignment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+/// Render `data` onto a transparent `width` x `height` image. Lines wrap
+/// at the image width, breaking between words where possible; text that
+/// runs past the bottom is cut off.
+pub fn render_text(data: &TextLayerData, width: u32, height: u32) -> Result<RgbaImage, String> {
+    if width == 0 || height == 0 {
+        return Err("Text layer has no area to render into".to_string());
+    }
+    let mut surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32)
+        .map_err(|e| format!("Failed to create a {}x{} surface: {}", width, height, e))?;
+    {
+        let context = Context::new(&surface).map_err(|e| format!("Failed to create context: {}", e))?;
+        let layout = pangocairo::functions::create_layout(&context);
+
+        let mut font = pango::FontDescription::new();
+        font.set_family(&data.font_family);
+        font.set_absolute_size(data.font_size.max(1.0) as f64 * pango::SCALE as f64);
+        font.set_weight(if data.bold { pango::Weight::Bold } else { pango::Weight::Normal });
+        font.set_style(if data.italic { pango::Style::Italic } else { pango::Style::Normal });
+        layout.set_font_description(Some(&font));
+
+        // Pango spaces lines by the font's own height; make up the
+        // difference to the requested baseline distance
+        layout.set_text("X");
+        let (_, natural_height) = layout.pixel_size();
+        let spacing = data.line_height as f64 * data.font_size as f64 - natural_height as f64;
+        layout.set_spacing((spacing * pango::SCALE as f64).round() as i32);
+
+        layout.set_width(width as i32 * pango::SCALE);
+        layout.set_wrap(pango::WrapMode::WordChar);
+        layout.set_alignment(match data.alignment {
+            TextAlignment::Left | TextAlignment::Justify => pango::Alignment::Left,
+            TextAlignment::Center => pango::Alignment::Center,
+            TextAlignment::Right => pango::Alignment::Right,
+        });
+        layout.set_justify(data.alignment == TextAlignment::Justify);
+
+        let attributes = pango::AttrList::new();
+        if data.letter_spacing != 0.0 {
+            attributes.insert(pango::AttrInt::new_letter_spacing((data.letter_spacing * pango::SCALE as f32).round() as i32));
+        }
+        if data.underline {
+            attributes.insert(pango::AttrInt::new_underline(pango::Underline::Single));
+        }
+        layout.set_attributes(Some(&attributes));
+        layout.set_text(&data.text);
+
+        let [r, g, b, a] = data.color;
+        context.set_source_rgba(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0, a as f64 / 255.0);
+        context.move_to(0.0, 0.0);
+        pangocairo::functions::show_layout(&context, &layout);
+    }
+    tiles::from_cairo_surface(&mut surface)
+}
@@ -0,0 +1,152 @@
+// Cached tiles of the composited document for drawing the canvas.
+//
+// Compositing every layer of a large document on each redraw is what makes
+// panning slow. The cache keeps the composite in fixed-size tiles keyed by
+// their column and row; drawing only composites tiles it doesn't have yet,
+// and an edit throws away just the tiles it touched.
+
+use std::collections::HashMap;
+use cairo::ImageSurface;
+use image::{ImageBuffer, Rgba};
+use log::trace;
+use crate::core::selection::Rect;
+
+/// Default tile edge length in pixels
+pub const TILE_SIZE: u32 = 256;
+
+/// Column and row of a tile
+pub type TileKey = (u32, u32);
+
+/// Composited tiles of a document
+#[derive(Clone)]
+pub struct TileCache {
+    tile_size: u32,
+    tiles: HashMap<TileKey, ImageBuffer<Rgba<u8>, Vec<u8>>>,
+}
+
+impl Default for TileCache {
+    fn default() -> Self {
+        Self::new(TILE_SIZE)
+    }
+}
+
+impl TileCache {
+    pub fn new(tile_size: u32) -> Self {
+        Self {
+            tile_size: tile_size.max(1),
+            tiles: HashMap::new(),
+        }
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Number of tiles currently cached
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn contains(&self, key: TileKey) -> bool {
+        self.tiles.contains_key(&key)
+    }
+
+    /// Pixel area of tile `key` in a `width` x `height` document: x, y,
+    /// width, height. Tiles on the right and bottom edges are cut short.
+    pub fn tile_bounds(&self, key: TileKey, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x = key.0 * self.tile_size;
+        let y = key.1 * self.tile_size;
+        (x, y, self.tile_size.min(width.saturating_sub(x)), self.tile_size.min(height.saturating_sub(y)))
+    }
+
+    /// Keys of the tiles of a `width` x `height` document that `rect`
+    /// overlaps, row by row
+    pub fn keys_overlapping(&self, rect: &Rect, width: u32, height: u32) -> Vec<TileKey> {
+        let right = (rect.x + rect.width).min(width as f64);
+        let bottom = (rect.y + rect.height).min(height as f64);
+        let (left, top) = (rect.x.max(0.0), rect.y.max(0.0));
+        if right <= left || bottom <= top {
+            return Vec::new();
+        }
+
+        let size = self.tile_size as f64;
+        let (first_column, first_row) = ((left / size).floor() as u32, (top / size).floor() as u32);
+        let (last_column, last_row) = (((right / size).ceil() as u32).max(1) - 1, ((bottom / size).ceil() as u32).max(1) - 1);
+        (first_row..=last_row)
+            .flat_map(|row| (first_column..=last_column).map(move |column| (column, row)))
+            .collect()
+    }
+
+    /// The cached tile `key`, compositing it with `render` first if needed
+    pub fn get_or_render(
+        &mut self,
+        key: TileKey,
+        render: impl FnOnce() -> ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> &ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.tiles.entry(key).or_insert_with(|| {
+            trace!("Rendering tile {:?}", key);
+            render()
+        })
+    }
+
+    /// Drop the cached tiles overlapping `rect`, returning their keys sorted
+    pub fn invalidate_region(&mut self, rect: &Rect) -> Vec<TileKey> {
+        let size = self.tile_size as f64;
+        let mut dropped: Vec<TileKey> = self.tiles.keys()
+            .copied()
+            .filter(|&(column, row)| {
+                let (x, y) = (column as f64 * size, row as f64 * size);
+                rect.x < x + size && rect.x + rect.width > x && rect.y < y + size && rect.y + rect.height > y
+            })
+            .collect();
+        for key in &dropped {
+            self.tiles.remove(key);
+        }
+        dropped.sort_unstable();
+        dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+}
+
+/// Copy an RGBA image into a Cairo ARGB32 surface, which is premultiplied
+/// and stored as native-endian words
+pub fn to_cairo_surface(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Result<ImageSurface, String> {
+    let (width, height) = image.dimensions();
+    let mut surface = ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)
+        .map_err(|e| format!("Failed to create a {}x{} surface: {}", width, height, e))?;
+    let stride = surface.stride() as usize;
+    {
+        let mut data = surface.data().map_err(|e| format!("Failed to write surface: {}", e))?;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let alpha = pixel[3] as u32;
+            let premultiply = |c: u8| (c as u32 * alpha + 127) / 255;
+            let word = (alpha << 24) | (premultiply(pixel[0]) << 16) | (premultiply(pixel[1]) << 8) | premultiply(pixel[2]);
+            let i = y as usize * stride + x as usize * 4;
+            data[i..i + 4].copy_from_slice(&word.to_ne_bytes());
+        }
+    }
+    surface.mark_dirty();
+    Ok(surface)
+}
+
+/// Copy a Cairo ARGB32 surface back into a straight-alpha RGBA image
+pub fn from_cairo_surface(surface: &mut ImageSurface) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    surface.flush();
+    let (width, height) = (surface.width() as u32, surface.height() as u32);
+    let stride = surface.stride() as usize;
+    let data = surface.data().map_err(|e| format!("Failed to read surface: {}", e))?;
+    Ok(ImageBuffer::from_fn(width, height, |x, y| {
+        let i = y as usize * stride + x as usize * 4;
+        let word = u32::from_ne_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let alpha = word >> 24;
+        let unpremultiply = |c: u32| if alpha == 0 { 0 } else { ((c * 255 + alpha / 2) / alpha).min(255) as u8 };
+        Rgba([unpremultiply((word >> 16) & 0xff), unpremultiply((word >> 8) & 0xff), unpremultiply(word & 0xff), alpha as u8])
+    }))
+}
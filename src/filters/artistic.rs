@@ -1,8 +1,10 @@
 use image::{DynamicImage, Rgba, GenericImageView, ImageBuffer};
+use crate::core::blend::{blend_pixel, LayerBlendMode};
 use crate::filters::Filter;
 use imageproc::noise::{gaussian_noise_mut, salt_and_pepper_noise_mut};
 use rand::distributions::Uniform;
-use rand::{Rng, thread_rng};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng, thread_rng};
 
 /// Applies a pixelation (mosaic) effect to the image
 pub struct PixelateFilter {
@@ -354,4 +356,406 @@ impl Filter for NoiseFilter {
             description: self.description.clone(),
         })
     }
-} 
\ No newline at end of file
+} 
+
+/// Shape of the random values `AddNoise` draws
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseDistribution {
+    Uniform,
+    Gaussian,
+}
+
+/// Adds seeded random noise, so the same settings always give the same
+/// grain.
+///
+/// `amount` is the strength, 0.0 - 1.0. Uniform noise is drawn from
+/// `±amount` of the full channel range; Gaussian noise has the same
+/// variance, so switching distribution changes the look but not the
+/// strength. Alpha is left alone.
+#[derive(Clone)]
+pub struct AddNoise {
+    pub amount: f32,
+    pub distribution: NoiseDistribution,
+    /// Add the same value to red, green and blue instead of noise per channel
+    pub monochrome: bool,
+    pub seed: u64,
+    name: String,
+    description: String,
+}
+
+impl AddNoise {
+    pub fn new(amount: f32, distribution: NoiseDistribution) -> Self {
+        Self {
+            amount,
+            distribution,
+            monochrome: false,
+            seed: 0,
+            name: "Add Noise".to_string(),
+            description: "Adds random grain to the image".to_string(),
+        }
+    }
+
+    pub fn with_monochrome(mut self, monochrome: bool) -> Self {
+        self.monochrome = monochrome;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// One noise value in channel units
+    fn sample(&self, rng: &mut StdRng) -> f32 {
+        let spread = self.amount.clamp(0.0, 1.0) * 255.0;
+        match self.distribution {
+            NoiseDistribution::Uniform => rng.gen_range(-1.0f32..=1.0) * spread,
+            NoiseDistribution::Gaussian => {
+                // Box-Muller; a uniform on ±spread has deviation spread / √3
+                let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+                let u2: f32 = rng.gen();
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+                normal * spread / 3.0f32.sqrt()
+            },
+        }
+    }
+}
+
+impl Filter for AddNoise {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        for pixel in output.pixels_mut() {
+            let shared = if self.monochrome { Some(self.sample(&mut rng)) } else { None };
+            for c in 0..3 {
+                let noise = match shared {
+                    Some(noise) => noise,
+                    None => self.sample(&mut rng),
+                };
+                pixel[c] = (pixel[c] as f32 + noise).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// Generates a tangent-space normal map, reading the image's luminance as a
+/// height field
+///
+/// The surface slope comes from a Sobel gradient and the normal is packed
+/// into RGB the usual way (`(n + 1) / 2`), so flat areas come out as
+/// (128, 128, 255). Green points up the image (the OpenGL convention).
+#[derive(Clone)]
+pub struct NormalMap {
+    /// Height scale; larger values make slopes steeper
+    pub strength: f32,
+    name: String,
+    description: String,
+}
+
+impl NormalMap {
+    pub fn new(strength: f32) -> Self {
+        Self {
+            strength: strength.max(0.0),
+            name: "Normal Map".to_string(),
+            description: "Generates a tangent-space normal map from image brightness".to_string(),
+        }
+    }
+}
+
+impl Filter for NormalMap {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+        
+        let heights: Vec<f32> = image.pixels()
+            .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0)
+            .collect();
+        let height_at = |x: i64, y: i64| {
+            let x = x.clamp(0, width as i64 - 1) as u32;
+            let y = y.clamp(0, height as i64 - 1) as u32;
+            heights[(y * width + x) as usize]
+        };
+        let encode = |n: f32| ((n * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+        
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            // Sobel, divided by 8 to give height change per pixel
+            let dx = (height_at(x + 1, y - 1) + 2.0 * height_at(x + 1, y) + height_at(x + 1, y + 1)
+                - height_at(x - 1, y - 1) - 2.0 * height_at(x - 1, y) - height_at(x - 1, y + 1)) / 8.0;
+            let dy = (height_at(x - 1, y + 1) + 2.0 * height_at(x, y + 1) + height_at(x + 1, y + 1)
+                - height_at(x - 1, y - 1) - 2.0 * height_at(x, y - 1) - height_at(x + 1, y - 1)) / 8.0;
+            
+            // Image y runs down while the normal's y runs up
+            let (nx, ny, nz) = (-dx * self.strength, dy * self.strength, 1.0);
+            let length = (nx * nx + ny * ny + nz * nz).sqrt();
+            
+            let alpha = image.get_pixel(x as u32, y as u32)[3];
+            Rgba([encode(nx / length), encode(ny / length), encode(nz / length), alpha])
+        })
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        1
+    }
+}
+
+/// Sobel edge detection: how steeply the brightness changes at each pixel.
+///
+/// The image is reduced to luminance and the Sobel gradient is scaled so a
+/// hard black-to-white step comes out at 255. The result is opaque. In
+/// color mode the horizontal and vertical gradient strengths go to red and
+/// green separately, which shows the direction of each edge.
+#[derive(Clone)]
+pub struct SobelEdgeDetect {
+    /// Write |Gx| to red and |Gy| to green instead of the magnitude to gray
+    pub color: bool,
+    name: String,
+    description: String,
+}
+
+impl SobelEdgeDetect {
+    /// Largest Sobel response of one direction, at a full-range step edge
+    const FULL_SCALE: f32 = 4.0 * 255.0;
+    
+    pub fn new() -> Self {
+        Self {
+            color: false,
+            name: "Edge Detect".to_string(),
+            description: "Highlights edges using the Sobel gradient magnitude".to_string(),
+        }
+    }
+    
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl Default for SobelEdgeDetect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filter for SobelEdgeDetect {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+        
+        let gray = DynamicImage::ImageRgba8(image.clone()).to_luma8();
+        let gx = imageproc::gradients::horizontal_sobel(&gray);
+        let gy = imageproc::gradients::vertical_sobel(&gray);
+        let scale = |value: f32| (value / Self::FULL_SCALE * 255.0).round().clamp(0.0, 255.0) as u8;
+        
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let (dx, dy) = (gx.get_pixel(x, y)[0] as f32, gy.get_pixel(x, y)[0] as f32);
+            if self.color {
+                Rgba([scale(dx.abs()), scale(dy.abs()), 0, 255])
+            } else {
+                let magnitude = scale((dx * dx + dy * dy).sqrt());
+                Rgba([magnitude, magnitude, magnitude, 255])
+            }
+        })
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        1
+    }
+}
+
+/// Relief effect: the image's luminance is read as a height field and lit
+/// from one side, giving a gray surface where edges stand out as light and
+/// dark ridges.
+///
+/// `angle` is the direction the light comes from, in degrees
+/// counter-clockwise from the right (135 is the usual top-left light).
+/// Flat areas come out as middle gray (128); alpha is kept.
+#[derive(Clone)]
+pub struct Emboss {
+    pub angle: f32,
+    /// Height scale, 0.0 - 10.0; larger values give taller, harsher ridges
+    pub depth: f32,
+    name: String,
+    description: String,
+}
+
+impl Emboss {
+    pub fn new(angle: f32, depth: f32) -> Self {
+        Self {
+            angle,
+            depth: depth.clamp(0.0, 10.0),
+            name: "Emboss".to_string(),
+            description: "Turns edges into a gray relief lit from one direction".to_string(),
+        }
+    }
+}
+
+impl Default for Emboss {
+    fn default() -> Self {
+        Self::new(135.0, 3.0)
+    }
+}
+
+impl Filter for Emboss {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+        
+        let heights: Vec<f32> = image.pixels()
+            .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+            .collect();
+        let height_at = |x: i64, y: i64| {
+            let x = x.clamp(0, width as i64 - 1) as u32;
+            let y = y.clamp(0, height as i64 - 1) as u32;
+            heights[(y * width + x) as usize]
+        };
+        
+        // Towards the light, with y flipped to run down the image
+        let radians = self.angle.to_radians();
+        let (light_x, light_y) = (radians.cos(), -radians.sin());
+        
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            let dx = (height_at(x + 1, y - 1) + 2.0 * height_at(x + 1, y) + height_at(x + 1, y + 1)
+                - height_at(x - 1, y - 1) - 2.0 * height_at(x - 1, y) - height_at(x - 1, y + 1)) / 8.0;
+            let dy = (height_at(x - 1, y + 1) + 2.0 * height_at(x, y + 1) + height_at(x + 1, y + 1)
+                - height_at(x - 1, y - 1) - 2.0 * height_at(x, y - 1) - height_at(x + 1, y - 1)) / 8.0;
+            
+            // A slope climbing towards the light faces away from it
+            let shade = 128.0 - self.depth * (dx * light_x + dy * light_y);
+            let value = shade.round().clamp(0.0, 255.0) as u8;
+            Rgba([value, value, value, image.get_pixel(x as u32, y as u32)[3]])
+        })
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        1
+    }
+}
+
+/// Pencil drawing look, made the way it is done by hand in a layer stack:
+/// a grayscale copy is color-dodged with a blurred negative of itself.
+/// Flat areas dodge to white and only edges, where the blur lags behind,
+/// stay dark as strokes. `radius` sets how broad the strokes are.
+#[derive(Clone)]
+pub struct PencilSketch {
+    /// Sigma of the blur applied to the negative
+    pub radius: f32,
+    name: String,
+    description: String,
+}
+
+impl PencilSketch {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius: radius.max(0.1),
+            name: "Pencil Sketch".to_string(),
+            description: "Redraws the image as pencil strokes on white paper".to_string(),
+        }
+    }
+}
+
+impl Default for PencilSketch {
+    fn default() -> Self {
+        Self::new(4.0)
+    }
+}
+
+impl Filter for PencilSketch {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+        
+        let gray = DynamicImage::ImageRgba8(image.clone()).to_luma8();
+        let mut negative = gray.clone();
+        image::imageops::invert(&mut negative);
+        let blurred = imageproc::filter::gaussian_blur_f32(&negative, self.radius);
+        
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let base = gray.get_pixel(x, y)[0];
+            let dodge = blurred.get_pixel(x, y)[0];
+            let value = blend_pixel(
+                Rgba([base, base, base, 255]),
+                Rgba([dodge, dodge, dodge, 255]),
+                LayerBlendMode::ColorDodge,
+                1.0,
+            )[0];
+            Rgba([value, value, value, image.get_pixel(x, y)[3]])
+        })
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        (3.0 * self.radius).ceil() as u32 + 1
+    }
+}
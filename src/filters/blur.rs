@@ -1,38 +1,72 @@
-use image::{DynamicImage, Rgba, GenericImageView, ImageBuffer, Luma};
-use imageproc::filter::{gaussian_blur_f32, box_filter};
+use image::{DynamicImage, Rgba, GenericImageView, ImageBuffer};
+use imageproc::filter::gaussian_blur_f32;
 use std::f32::consts::PI;
-use crate::filters::Filter;
+use crate::core::Rect;
+use crate::filters::{Filter, Interpolation, apply_with_halo, sample_pixel};
+use crate::filters::color::{srgb_to_linear, linear_to_srgb};
 use log::{debug, info, trace, warn};
 
-/// Gaussian blur filter
+/// Smallest standard deviation the blur runs with; anything lower is
+/// treated as no blur at all
+const MIN_SIGMA: f32 = 0.01;
+
+/// Gaussian blur filter.
+///
+/// The strength is stored as the Gaussian's standard deviation. Most users
+/// think in terms of a radius instead; `from_radius` takes the radius at
+/// which the kernel has all but vanished, three standard deviations out.
 #[derive(Clone)]
 pub struct GaussianBlur {
-    /// The radius of the blur (standard deviation)
-    pub radius: f32,
+    /// Standard deviation of the kernel in pixels
+    pub sigma: f32,
     name: String,
     description: String,
 }
 
 impl GaussianBlur {
-    /// Create a new Gaussian blur filter with the specified radius
-    pub fn new(radius: f32) -> Self {
-        let radius = radius.max(0.1); // Ensure minimum radius
-        info!("Creating new Gaussian blur filter with radius {}", radius);
+    /// Same as `from_sigma`
+    pub fn new(sigma: f32) -> Self {
+        Self::from_sigma(sigma)
+    }
+    
+    /// Blur with a kernel of standard deviation `sigma`
+    pub fn from_sigma(sigma: f32) -> Self {
+        let sigma = if sigma.is_finite() { sigma.max(0.0) } else { 0.0 };
+        info!("Creating new Gaussian blur filter with sigma {}", sigma);
         Self {
-            radius,
+            sigma,
             name: "Gaussian Blur".to_string(),
             description: "Applies a Gaussian blur to the image".to_string(),
         }
     }
+    
+    /// Blur reaching `radius` pixels, i.e. a sigma of a third of that
+    pub fn from_radius(radius: f32) -> Self {
+        Self::from_sigma(radius / 3.0)
+    }
+    
+    /// The radius `from_radius` would take for this blur
+    pub fn radius(&self) -> f32 {
+        self.sigma * 3.0
+    }
 }
 
 impl Filter for GaussianBlur {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        debug!("Applying Gaussian blur with radius {} to {}x{} image", 
-               self.radius, image.width(), image.height());
+        if !(self.sigma >= MIN_SIGMA) {
+            trace!("Gaussian blur sigma {} is below {}; leaving image unchanged", self.sigma, MIN_SIGMA);
+            return image.clone();
+        }
+        debug!("Applying Gaussian blur with sigma {} to {}x{} image", 
+               self.sigma, image.width(), image.height());
+        
+        #[cfg(feature = "gpu-wgpu")]
+        if let Some(result) = crate::filters::gpu::gaussian_blur(image, self.sigma) {
+            return result;
+        }
         
         let start_time = std::time::Instant::now();
-        let result = gaussian_blur_f32(image, self.radius);
+        let result = gaussian_blur_f32(image, self.sigma);
         let duration = start_time.elapsed();
         
         debug!("Gaussian blur completed in {:.2?}", duration);
@@ -51,13 +85,29 @@ impl Filter for GaussianBlur {
         trace!("Cloning Gaussian blur filter");
         Box::new(self.clone())
     }
+    
+    fn support_radius(&self) -> u32 {
+        // The kernel is truncated well inside 3 sigma, so this covers it
+        (3.0 * self.sigma).ceil() as u32 + 1
+    }
+    
+    fn apply_region(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, region: Rect) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        apply_with_halo(image, &region, self.support_radius(), |padded| self.apply(padded))
+    }
 }
 
-/// Box blur filter
+/// Box blur filter.
+///
+/// Every pixel becomes the plain average of the (2r+1)x(2r+1) window around
+/// it, clipped at the image edges. The window is moved along each row and
+/// then each column keeping a running sum, so the cost per pixel is the same
+/// whatever the radius.
 #[derive(Clone)]
 pub struct BoxBlur {
     /// The radius of the blur
     pub radius: u32,
+    /// Keep each pixel's alpha as it is instead of blurring it with the color
+    pub preserve_alpha: bool,
     name: String,
     description: String,
 }
@@ -69,40 +119,187 @@ impl BoxBlur {
         info!("Creating new Box blur filter with radius {}", radius);
         Self {
             radius,
+            preserve_alpha: false,
             name: "Box Blur".to_string(),
             description: "Applies a box blur to the image".to_string(),
         }
     }
+    
+    /// Leave the alpha channel untouched
+    pub fn with_preserve_alpha(mut self, preserve_alpha: bool) -> Self {
+        self.preserve_alpha = preserve_alpha;
+        self
+    }
+}
+
+/// Sums of each `radius` window along a line of `len` samples spaced
+/// `stride` apart, written with the same spacing. Each step adds the sample
+/// entering the window and drops the one leaving it.
+fn running_window_sums(input: &[u64], output: &mut [u64], start: usize, len: usize, stride: usize, radius: usize) {
+    let mut sum: u64 = (0..len.min(radius + 1)).map(|i| input[start + i * stride]).sum();
+    for i in 0..len {
+        output[start + i * stride] = sum;
+        if i + radius + 1 < len {
+            sum += input[start + (i + radius + 1) * stride];
+        }
+        if i >= radius {
+            sum -= input[start + (i - radius) * stride];
+        }
+    }
 }
 
 impl Filter for BoxBlur {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        debug!("Applying Box blur with radius {} to {}x{} image",
+               self.radius, image.width(), image.height());
+        
+        let start_time = std::time::Instant::now();
         let (width, height) = image.dimensions();
-        let mut output = ImageBuffer::new(width, height);
+        let (w, h) = (width as usize, height as usize);
+        let r = self.radius as usize;
+        let channels = if self.preserve_alpha { 3 } else { 4 };
         
-        // Convert to grayscale for blur
-        let mut gray = ImageBuffer::new(width, height);
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel(x, y);
-                let gray_val = ((pixel[0] as f32 + pixel[1] as f32 + pixel[2] as f32) / 3.0) as u8;
-                gray.put_pixel(x, y, Luma([gray_val]));
+        let mut result = image.clone();
+        let mut plane = vec![0u64; w * h];
+        let mut rows = vec![0u64; w * h];
+        let mut sums = vec![0u64; w * h];
+        for c in 0..channels {
+            for (value, pixel) in plane.iter_mut().zip(image.pixels()) {
+                *value = pixel[c] as u64;
+            }
+            for y in 0..h {
+                running_window_sums(&plane, &mut rows, y * w, w, 1, r);
             }
+            for x in 0..w {
+                running_window_sums(&rows, &mut sums, x, h, w, r);
+            }
+            
+            for y in 0..h {
+                let rows_covered = ((y + r + 1).min(h) - y.saturating_sub(r)) as u64;
+                for x in 0..w {
+                    let count = ((x + r + 1).min(w) - x.saturating_sub(r)) as u64 * rows_covered;
+                    let average = (sums[y * w + x] + count / 2) / count;
+                    result.get_pixel_mut(x as u32, y as u32)[c] = average as u8;
+                }
+            }
+        }
+        
+        let duration = start_time.elapsed();
+        debug!("Box blur completed in {:.2?}", duration);
+        result
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        trace!("Cloning Box blur filter");
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        self.radius
+    }
+    
+    fn apply_region(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, region: Rect) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        apply_with_halo(image, &region, self.support_radius(), |padded| self.apply(padded))
+    }
+}
+
+/// Average blur computed in linear light.
+///
+/// Unlike `BoxBlur`, which averages the gamma-encoded values directly, each
+/// window is decoded to linear light first, so a fine black/white pattern
+/// averages to a perceptually correct mid-grey instead of a too-dark one.
+#[derive(Clone)]
+pub struct AverageBlur {
+    /// The radius of the averaging window
+    pub radius: u32,
+    name: String,
+    description: String,
+}
+
+impl AverageBlur {
+    /// Create a new average blur filter with the specified radius
+    pub fn new(radius: u32) -> Self {
+        let radius = radius.max(1); // Ensure minimum radius
+        info!("Creating new Average blur filter with radius {}", radius);
+        Self {
+            radius,
+            name: "Average Blur".to_string(),
+            description: "Averages each pixel's neighbourhood in linear light".to_string(),
         }
+    }
+}
+
+impl Filter for AverageBlur {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        debug!("Applying Average blur with radius {} to {}x{} image",
+               self.radius, image.width(), image.height());
         
-        // Apply box blur to grayscale image
-        let blurred = box_filter(&gray, self.radius, self.radius);
+        let start_time = std::time::Instant::now();
+        let (width, height) = image.dimensions();
+        let (w, h) = (width as usize, height as usize);
+        let r = self.radius as usize;
         
-        // Convert back to RGBA, preserving original alpha channel
-        for y in 0..height {
-            for x in 0..width {
-                let gray_val = blurred.get_pixel(x, y)[0];
-                let alpha = image.get_pixel(x, y)[3];
-                output.put_pixel(x, y, Rgba([gray_val, gray_val, gray_val, alpha]));
+        let decode: Vec<f32> = (0..=255u8).map(srgb_to_linear).collect();
+        
+        // Summed-area tables of alpha-weighted linear color and of alpha,
+        // so transparent pixels don't pull the color towards black
+        let stride = w + 1;
+        let mut tables = vec![vec![0.0f64; stride * (h + 1)]; 4];
+        for y in 0..h {
+            let mut row = [0.0f64; 4];
+            for x in 0..w {
+                let pixel = image.get_pixel(x as u32, y as u32);
+                let alpha = pixel[3] as f64 / 255.0;
+                for c in 0..3 {
+                    row[c] += decode[pixel[c] as usize] as f64 * alpha;
+                }
+                row[3] += alpha;
+                for c in 0..4 {
+                    tables[c][(y + 1) * stride + x + 1] = tables[c][y * stride + x + 1] + row[c];
+                }
+            }
+        }
+        
+        let mut result = ImageBuffer::new(width, height);
+        for y in 0..h {
+            let y0 = y.saturating_sub(r);
+            let y1 = (y + r + 1).min(h);
+            for x in 0..w {
+                let x0 = x.saturating_sub(r);
+                let x1 = (x + r + 1).min(w);
+                let count = ((x1 - x0) * (y1 - y0)) as f64;
+                
+                let mut sums = [0.0f64; 4];
+                for c in 0..4 {
+                    let t = &tables[c];
+                    sums[c] = t[y1 * stride + x1] - t[y0 * stride + x1] - t[y1 * stride + x0] + t[y0 * stride + x0];
+                }
+                
+                let pixel = if sums[3] > 0.0 {
+                    Rgba([
+                        linear_to_srgb((sums[0] / sums[3]) as f32),
+                        linear_to_srgb((sums[1] / sums[3]) as f32),
+                        linear_to_srgb((sums[2] / sums[3]) as f32),
+                        (sums[3] / count * 255.0).round().clamp(0.0, 255.0) as u8,
+                    ])
+                } else {
+                    Rgba([0, 0, 0, 0])
+                };
+                result.put_pixel(x as u32, y as u32, pixel);
             }
         }
         
-        output
+        let duration = start_time.elapsed();
+        debug!("Average blur completed in {:.2?}", duration);
+        result
     }
     
     fn name(&self) -> &str {
@@ -114,9 +311,158 @@ impl Filter for BoxBlur {
     }
     
     fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
-        trace!("Cloning Box blur filter");
+        trace!("Cloning Average blur filter");
         Box::new(self.clone())
     }
+    
+    fn support_radius(&self) -> u32 {
+        self.radius
+    }
+    
+    fn apply_region(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, region: Rect) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        apply_with_halo(image, &region, self.support_radius(), |padded| self.apply(padded))
+    }
+}
+
+/// Edge-preserving bilateral filter for noise reduction.
+///
+/// Each pixel becomes a weighted average of its neighbours, where the weight
+/// falls off both with distance (`spatial_sigma`) and with color difference
+/// (`range_sigma`). Noise within a flat area averages away, but pixels on the
+/// other side of an edge differ too much in color to contribute.
+#[derive(Clone)]
+pub struct BilateralFilter {
+    /// Standard deviation of the spatial falloff, in pixels
+    pub spatial_sigma: f32,
+    /// Standard deviation of the color falloff, in 0-255 units
+    pub range_sigma: f32,
+    name: String,
+    description: String,
+}
+
+impl BilateralFilter {
+    /// Create a new bilateral filter with the given spatial and range sigmas
+    pub fn new(spatial_sigma: f32, range_sigma: f32) -> Self {
+        let spatial_sigma = spatial_sigma.max(0.1);
+        let range_sigma = range_sigma.max(0.1);
+        info!("Creating new Bilateral filter with spatial sigma {}, range sigma {}",
+              spatial_sigma, range_sigma);
+        Self {
+            spatial_sigma,
+            range_sigma,
+            name: "Reduce Noise".to_string(),
+            description: "Smooths noise while keeping edges sharp".to_string(),
+        }
+    }
+    
+    /// Window radius; the spatial weight is negligible beyond 2 sigma
+    fn radius(&self) -> u32 {
+        (2.0 * self.spatial_sigma).ceil() as u32
+    }
+}
+
+impl Filter for BilateralFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        debug!("Applying Bilateral filter (spatial {}, range {}) to {}x{} image",
+               self.spatial_sigma, self.range_sigma, image.width(), image.height());
+        
+        let start_time = std::time::Instant::now();
+        let (width, height) = image.dimensions();
+        let r = self.radius() as i64;
+        let size = (2 * r + 1) as usize;
+        
+        let spatial_denominator = 2.0 * self.spatial_sigma * self.spatial_sigma;
+        let mut spatial = vec![0.0f32; size * size];
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let index = ((dy + r) as usize) * size + (dx + r) as usize;
+                spatial[index] = (-((dx * dx + dy * dy) as f32) / spatial_denominator).exp();
+            }
+        }
+        
+        // Range weights indexed by squared color distance (summed over RGB)
+        let range_denominator = 2.0 * self.range_sigma * self.range_sigma;
+        let range: Vec<f32> = (0..=3 * 255 * 255)
+            .map(|d2| (-(d2 as f32) / range_denominator).exp())
+            .collect();
+        
+        let mut result = ImageBuffer::new(width, height);
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let center = image.get_pixel(x as u32, y as u32);
+                let mut sums = [0.0f32; 3];
+                let mut total = 0.0f32;
+                
+                for dy in -r..=r {
+                    let ny = y + dy;
+                    if ny < 0 || ny >= height as i64 {
+                        continue;
+                    }
+                    for dx in -r..=r {
+                        let nx = x + dx;
+                        if nx < 0 || nx >= width as i64 {
+                            continue;
+                        }
+                        let neighbour = image.get_pixel(nx as u32, ny as u32);
+                        if neighbour[3] == 0 {
+                            continue;
+                        }
+                        
+                        let mut d2 = 0usize;
+                        for c in 0..3 {
+                            let d = neighbour[c] as i32 - center[c] as i32;
+                            d2 += (d * d) as usize;
+                        }
+                        let weight = spatial[((dy + r) as usize) * size + (dx + r) as usize]
+                            * range[d2]
+                            * (neighbour[3] as f32 / 255.0);
+                        
+                        for c in 0..3 {
+                            sums[c] += neighbour[c] as f32 * weight;
+                        }
+                        total += weight;
+                    }
+                }
+                
+                let pixel = if total > 0.0 {
+                    Rgba([
+                        (sums[0] / total).round().clamp(0.0, 255.0) as u8,
+                        (sums[1] / total).round().clamp(0.0, 255.0) as u8,
+                        (sums[2] / total).round().clamp(0.0, 255.0) as u8,
+                        center[3],
+                    ])
+                } else {
+                    *center
+                };
+                result.put_pixel(x as u32, y as u32, pixel);
+            }
+        }
+        
+        let duration = start_time.elapsed();
+        debug!("Bilateral filter completed in {:.2?}", duration);
+        result
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        trace!("Cloning Bilateral filter");
+        Box::new(self.clone())
+    }
+    
+    fn support_radius(&self) -> u32 {
+        self.radius()
+    }
+    
+    fn apply_region(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, region: Rect) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        apply_with_halo(image, &region, self.support_radius(), |padded| self.apply(padded))
+    }
 }
 
 /// Motion blur filter
@@ -254,137 +600,115 @@ impl Filter for MotionBlur {
     }
 }
 
-/// Radial blur filter
+/// How `RadialBlur` smears each pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadialBlurMode {
+    /// Along the circle through the pixel, as if the image spun
+    Spin,
+    /// Along the line to the center, as if the camera zoomed in
+    Zoom,
+}
+
+/// Radial blur filter.
+///
+/// Spin averages samples along an arc around the center, so circles
+/// centered on it stay sharp while anything crossing them blurs. Zoom
+/// averages along the ray from the center, streaking outwards. Either way
+/// the blur grows with the distance from the center, which stays sharp.
 #[derive(Clone)]
 pub struct RadialBlur {
-    /// The center x-coordinate of the blur
-    pub center_x: f32,
-    /// The center y-coordinate of the blur
-    pub center_y: f32,
-    /// The amount of blur
+    /// Center as a fraction of the image width and height
+    pub center: (f32, f32),
+    /// Strength, 0.0 - 1.0: up to a 60° arc for spin, or up to half the
+    /// distance to the center for zoom
     pub amount: f32,
+    pub mode: RadialBlurMode,
     name: String,
     description: String,
 }
 
 impl RadialBlur {
+    /// Most samples taken along one arc or ray
+    const MAX_SAMPLES: usize = 64;
+    
     /// Create a new radial blur filter
-    pub fn new(center_x: f32, center_y: f32, amount: f32) -> Self {
+    pub fn new(center: (f32, f32), amount: f32, mode: RadialBlurMode) -> Self {
         let amount = amount.max(0.0).min(1.0); // Clamp amount to 0.0-1.0
-        info!("Creating new Radial blur filter at position ({}, {}) with amount {}", 
-              center_x, center_y, amount);
+        info!("Creating new Radial blur filter at {:?} with amount {} ({:?})", center, amount, mode);
         
         Self {
-            center_x,
-            center_y,
+            center,
             amount,
+            mode,
             name: "Radial Blur".to_string(),
-            description: "Applies a radial blur effect to the image".to_string(),
+            description: "Blurs in circles around a point or in rays away from it".to_string(),
         }
     }
 }
 
 impl Filter for RadialBlur {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        debug!("Applying Radial blur at ({}, {}) with amount {} to {}x{} image", 
-               self.center_x, self.center_y, self.amount, image.width(), image.height());
+        debug!("Applying Radial blur at {:?} with amount {} ({:?}) to {}x{} image", 
+               self.center, self.amount, self.mode, image.width(), image.height());
         
-        let start_time = std::time::Instant::now();
-        
-        let width = image.width();
-        let height = image.height();
-        let mut result = ImageBuffer::new(width, height);
-        
-        // Normalize center coordinates to 0.0-1.0
-        let center_x = self.center_x.clamp(0.0, 1.0) * width as f32;
-        let center_y = self.center_y.clamp(0.0, 1.0) * height as f32;
-        trace!("Normalized center coordinates: ({}, {})", center_x, center_y);
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 || self.amount <= 0.0 {
+            return image.clone();
+        }
         
-        let num_samples = 10; // Number of samples to take
-        let max_offset = self.amount * 20.0; // Maximum offset for sampling
-        trace!("Using {} samples with max offset {:.2}", num_samples, max_offset);
+        let start_time = std::time::Instant::now();
+        let center_x = self.center.0.clamp(0.0, 1.0) * width as f32;
+        let center_y = self.center.1.clamp(0.0, 1.0) * height as f32;
+        let spin = self.amount * PI / 3.0;
+        let zoom = self.amount * 0.5;
+        let (max_x, max_y) = ((width - 1) as f32, (height - 1) as f32);
         
-        // For each pixel in the output image
-        for y in 0..height {
-            for x in 0..width {
-                let mut r_sum = 0.0;
-                let mut g_sum = 0.0;
-                let mut b_sum = 0.0;
-                let mut a_sum = 0.0;
-                let mut weight_sum = 0.0;
-                
-                // Calculate vector from center to current pixel
-                let dx = x as f32 - center_x;
-                let dy = y as f32 - center_y;
-                
-                // Normalize the vector
-                let length = (dx * dx + dy * dy).sqrt();
-                let norm_dx = if length > 0.0 { dx / length } else { 0.0 };
-                let norm_dy = if length > 0.0 { dy / length } else { 0.0 };
-                
-                // Sample along the radial path
-                for i in 0..=num_samples {
-                    let t = (i as f32 / num_samples as f32) * max_offset;
-                    let sample_x = x as f32 - norm_dx * t;
-                    let sample_y = y as f32 - norm_dy * t;
-                    
-                    // Only sample within image bounds
-                    if sample_x >= 0.0 && sample_x < width as f32 &&
-                       sample_y >= 0.0 && sample_y < height as f32 {
-                        // Bilinear interpolation
-                        let x0 = sample_x.floor() as u32;
-                        let y0 = sample_y.floor() as u32;
-                        let x1 = (x0 + 1).min(width - 1);
-                        let y1 = (y0 + 1).min(height - 1);
-                        
-                        let dx = sample_x - x0 as f32;
-                        let dy = sample_y - y0 as f32;
-                        
-                        let w00 = (1.0 - dx) * (1.0 - dy);
-                        let w01 = (1.0 - dx) * dy;
-                        let w10 = dx * (1.0 - dy);
-                        let w11 = dx * dy;
-                        
-                        let p00 = image.get_pixel(x0, y0);
-                        let p01 = image.get_pixel(x0, y1);
-                        let p10 = image.get_pixel(x1, y0);
-                        let p11 = image.get_pixel(x1, y1);
-                        
-                        let r = p00[0] as f32 * w00 + p01[0] as f32 * w01 + 
-                               p10[0] as f32 * w10 + p11[0] as f32 * w11;
-                        let g = p00[1] as f32 * w00 + p01[1] as f32 * w01 + 
-                               p10[1] as f32 * w10 + p11[1] as f32 * w11;
-                        let b = p00[2] as f32 * w00 + p01[2] as f32 * w01 + 
-                               p10[2] as f32 * w10 + p11[2] as f32 * w11;
-                        let a = p00[3] as f32 * w00 + p01[3] as f32 * w01 + 
-                               p10[3] as f32 * w10 + p11[3] as f32 * w11;
-                        
-                        // Weight decreases with distance from original point
-                        let weight = 1.0 - (i as f32 / num_samples as f32) * 0.5;
-                        
-                        r_sum += r * weight;
-                        g_sum += g * weight;
-                        b_sum += b * weight;
-                        a_sum += a * weight;
-                        weight_sum += weight;
-                    }
-                }
-                
-                // Normalize and set the result
-                if weight_sum > 0.0 {
-                    let r = (r_sum / weight_sum).round().clamp(0.0, 255.0) as u8;
-                    let g = (g_sum / weight_sum).round().clamp(0.0, 255.0) as u8;
-                    let b = (b_sum / weight_sum).round().clamp(0.0, 255.0) as u8;
-                    let a = (a_sum / weight_sum).round().clamp(0.0, 255.0) as u8;
-                    
-                    result.put_pixel(x, y, Rgba([r, g, b, a]));
-                } else {
-                    // If no samples were taken, copy the original pixel
-                    trace!("No samples for pixel ({}, {}), using original", x, y);
-                    result.put_pixel(x, y, *image.get_pixel(x, y));
-                }
+        let result = ImageBuffer::from_fn(width, height, |x, y| {
+            // Work from pixel centers so a center on a pixel leaves it alone
+            let dx = x as f32 + 0.5 - center_x;
+            let dy = y as f32 + 0.5 - center_y;
+            let distance = (dx * dx + dy * dy).sqrt();
+            
+            // Length of the path in pixels decides how many samples it needs
+            let path = match self.mode {
+                RadialBlurMode::Spin => distance * spin,
+                RadialBlurMode::Zoom => distance * zoom,
+            };
+            let samples = (path.ceil() as usize + 1).min(Self::MAX_SAMPLES);
+            if samples < 2 {
+                return *image.get_pixel(x, y);
             }
-        }
+            
+            let mut sum = [0.0f32; 4];
+            for i in 0..samples {
+                // Spread evenly over the path, centered on the pixel
+                let t = i as f32 / (samples - 1) as f32 - 0.5;
+                let (sx, sy) = match self.mode {
+                    RadialBlurMode::Spin => {
+                        let (sin, cos) = (t * spin).sin_cos();
+                        (center_x + dx * cos - dy * sin, center_y + dx * sin + dy * cos)
+                    },
+                    RadialBlurMode::Zoom => {
+                        let scale = 1.0 + t * zoom;
+                        (center_x + dx * scale, center_y + dy * scale)
+                    },
+                };
+                // Clamp to the edge so the border doesn't fade out
+                let pixel = sample_pixel(image, (sx - 0.5).clamp(0.0, max_x), (sy - 0.5).clamp(0.0, max_y), Interpolation::Bilinear);
+                let alpha = pixel[3] as f32;
+                sum[0] += pixel[0] as f32 * alpha;
+                sum[1] += pixel[1] as f32 * alpha;
+                sum[2] += pixel[2] as f32 * alpha;
+                sum[3] += alpha;
+            }
+            
+            if sum[3] <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            let channel = |c: f32| (c / sum[3]).round().clamp(0.0, 255.0) as u8;
+            let alpha = (sum[3] / samples as f32).round().clamp(0.0, 255.0) as u8;
+            Rgba([channel(sum[0]), channel(sum[1]), channel(sum[2]), alpha])
+        });
         
         let duration = start_time.elapsed();
         debug!("Radial blur completed in {:.2?}", duration);
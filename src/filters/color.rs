@@ -1,6 +1,40 @@
-use image::{DynamicImage, Rgba, GenericImageView, ImageBuffer};
+use image::{DynamicImage, Rgba, GenericImageView, ImageBuffer, Luma};
+use imageproc::contrast::threshold;
+use imageproc::filter::gaussian_blur_f32;
+use serde::{Deserialize, Serialize};
+use crate::core::Color;
 use crate::filters::Filter;
 
+/// Decode an sRGB channel value (0.0 - 1.0) to linear light
+pub fn srgb_decode(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear-light value as an sRGB channel value, both clamped to
+/// 0.0 - 1.0
+pub fn srgb_encode(value: f32) -> f32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decode an 8-bit sRGB channel value to linear light (0.0 - 1.0)
+pub fn srgb_to_linear(value: u8) -> f32 {
+    srgb_decode(value as f32 / 255.0)
+}
+
+/// Encode a linear-light value (0.0 - 1.0) as an 8-bit sRGB channel value
+pub fn linear_to_srgb(value: f32) -> u8 {
+    (srgb_encode(value) * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
 /// Color filters for adjusting brightness, contrast, and other color attributes
 pub struct BrightnessFilter {
     pub amount: f32,
@@ -283,4 +317,1507 @@ impl Filter for InvertFilter {
             description: self.description.clone(),
         })
     }
-}
\ No newline at end of file
+}
+
+/// Input and output ranges for one channel of a Levels adjustment
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelsChannel {
+    /// Input value mapped to black
+    pub input_black: u8,
+    /// Input value mapped to white
+    pub input_white: u8,
+    /// Midtone gamma; above 1.0 brightens, below darkens
+    pub gamma: f32,
+    pub output_black: u8,
+    pub output_white: u8,
+}
+
+impl Default for LevelsChannel {
+    fn default() -> Self {
+        Self {
+            input_black: 0,
+            input_white: 255,
+            gamma: 1.0,
+            output_black: 0,
+            output_white: 255,
+        }
+    }
+}
+
+impl LevelsChannel {
+    pub fn new(input_black: u8, input_white: u8, gamma: f32, output_black: u8, output_white: u8) -> Self {
+        Self { input_black, input_white, gamma, output_black, output_white }
+    }
+
+    /// Lookup table mapping every input value through this channel's levels
+    pub fn lut(&self) -> [u8; 256] {
+        let black = self.input_black as f32;
+        // Keep at least one step between the input points so nothing divides by zero
+        let range = (self.input_white as f32 - black).max(1.0);
+        let inverse_gamma = 1.0 / self.gamma.max(0.01);
+        let out_black = self.output_black as f32;
+        let out_range = self.output_white as f32 - out_black;
+
+        let mut lut = [0u8; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            let t = ((value as f32 - black) / range).clamp(0.0, 1.0).powf(inverse_gamma);
+            *entry = (out_black + t * out_range).round().clamp(0.0, 255.0) as u8;
+        }
+        lut
+    }
+}
+
+/// Levels: remap each channel's input range onto an output range with a
+/// midtone gamma. The red, green and blue levels run first, then the
+/// composite levels are applied to all three.
+pub struct LevelsFilter {
+    pub composite: LevelsChannel,
+    pub red: LevelsChannel,
+    pub green: LevelsChannel,
+    pub blue: LevelsChannel,
+    name: String,
+    description: String,
+}
+
+impl LevelsFilter {
+    pub fn new(composite: LevelsChannel, red: LevelsChannel, green: LevelsChannel, blue: LevelsChannel) -> Self {
+        Self {
+            composite,
+            red,
+            green,
+            blue,
+            name: "Levels".to_string(),
+            description: "Remaps input black, white and midtones per channel".to_string(),
+        }
+    }
+
+    /// Combined per-channel then composite lookup tables for red, green and blue
+    pub fn luts(&self) -> [[u8; 256]; 3] {
+        let composite = self.composite.lut();
+        let mut luts = [self.red.lut(), self.green.lut(), self.blue.lut()];
+        for lut in luts.iter_mut() {
+            for entry in lut.iter_mut() {
+                *entry = composite[*entry as usize];
+            }
+        }
+        luts
+    }
+}
+
+impl Filter for LevelsFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let luts = self.luts();
+        let mut output = image.clone();
+
+        for pixel in output.pixels_mut() {
+            for c in 0..3 {
+                pixel[c] = luts[c][pixel[c] as usize];
+            }
+        }
+
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.composite, self.red, self.green, self.blue))
+    }
+}
+
+/// Color Balance slider positions for one tonal range, each -100 to 100
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorBalanceTones {
+    /// Negative towards cyan, positive towards red
+    pub cyan_red: f32,
+    /// Negative towards magenta, positive towards green
+    pub magenta_green: f32,
+    /// Negative towards yellow, positive towards blue
+    pub yellow_blue: f32,
+}
+
+impl ColorBalanceTones {
+    pub fn new(cyan_red: f32, magenta_green: f32, yellow_blue: f32) -> Self {
+        Self { cyan_red, magenta_green, yellow_blue }
+    }
+}
+
+/// Shift the color of shadows, midtones and highlights separately.
+///
+/// Each pixel takes a mix of the three ranges' sliders weighted by its
+/// lightness, so a highlight push fades out through the midtones and never
+/// reaches the darkest pixels.
+pub struct ColorBalanceFilter {
+    pub shadows: ColorBalanceTones,
+    pub midtones: ColorBalanceTones,
+    pub highlights: ColorBalanceTones,
+    /// Restore each pixel's original luminance after the shift
+    pub preserve_luminosity: bool,
+    name: String,
+    description: String,
+}
+
+impl ColorBalanceFilter {
+    /// Channel shift, as a fraction of full range, for a slider at 100
+    const MAX_SHIFT: f32 = 0.4;
+
+    pub fn new(shadows: ColorBalanceTones, midtones: ColorBalanceTones, highlights: ColorBalanceTones, preserve_luminosity: bool) -> Self {
+        Self {
+            shadows,
+            midtones,
+            highlights,
+            preserve_luminosity,
+            name: "Color Balance".to_string(),
+            description: "Shifts colors in the shadows, midtones and highlights".to_string(),
+        }
+    }
+
+    /// Shadow, midtone and highlight weights for a lightness in 0.0 - 1.0
+    pub fn tonal_weights(lightness: f32) -> [f32; 3] {
+        let l = lightness.clamp(0.0, 1.0);
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let shadows = smooth((1.0 - 2.0 * l).clamp(0.0, 1.0));
+        let highlights = smooth((2.0 * l - 1.0).clamp(0.0, 1.0));
+        [shadows, 1.0 - shadows - highlights, highlights]
+    }
+
+    fn balance_pixel(&self, pixel: Rgba<u8>) -> Rgba<u8> {
+        let rgb = [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0];
+        let luma = |c: &[f32; 3]| 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+        let weights = Self::tonal_weights(luma(&rgb));
+
+        let mut shift = [0.0f32; 3];
+        for (weight, tones) in weights.iter().zip([self.shadows, self.midtones, self.highlights].iter()) {
+            shift[0] += weight * tones.cyan_red.clamp(-100.0, 100.0);
+            shift[1] += weight * tones.magenta_green.clamp(-100.0, 100.0);
+            shift[2] += weight * tones.yellow_blue.clamp(-100.0, 100.0);
+        }
+
+        let mut out = [0.0f32; 3];
+        for c in 0..3 {
+            out[c] = (rgb[c] + shift[c] / 100.0 * Self::MAX_SHIFT).clamp(0.0, 1.0);
+        }
+        if self.preserve_luminosity {
+            let correction = luma(&rgb) - luma(&out);
+            for value in out.iter_mut() {
+                *value = (*value + correction).clamp(0.0, 1.0);
+            }
+        }
+
+        Rgba([
+            (out[0] * 255.0).round() as u8,
+            (out[1] * 255.0).round() as u8,
+            (out[2] * 255.0).round() as u8,
+            pixel[3],
+        ])
+    }
+}
+
+impl Filter for ColorBalanceFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            *pixel = self.balance_pixel(*pixel);
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.shadows, self.midtones, self.highlights, self.preserve_luminosity))
+    }
+}
+
+/// Haze removal based on the dark channel prior.
+///
+/// Haze-free outdoor images almost always have some channel close to zero in
+/// any local patch. Where that doesn't hold, the excess is attributed to
+/// atmospheric light, which is estimated and then subtracted back out.
+pub struct Dehaze {
+    /// Strength of the haze removal, from 0.0 (none) to 1.0 (full)
+    pub amount: f32,
+    /// Radius of the square patch used to compute the dark channel
+    pub patch_radius: u32,
+    name: String,
+    description: String,
+}
+
+impl Dehaze {
+    /// Lower bound on the transmission estimate so dense haze doesn't blow up noise
+    const MIN_TRANSMISSION: f32 = 0.1;
+
+    pub fn new(amount: f32) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        Self {
+            amount,
+            patch_radius: 7,
+            name: "Haze Removal".to_string(),
+            description: format!("Removes atmospheric haze with strength {:.2}", amount),
+        }
+    }
+
+    /// Per-pixel minimum over RGB followed by a min filter over the patch.
+    /// `scale` divides each channel first, which is how the normalized dark
+    /// channel `dark(I / A)` is computed for the transmission map.
+    fn dark_channel(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, scale: [f32; 3]) -> Vec<f32> {
+        let (width, height) = image.dimensions();
+        let (w, h) = (width as usize, height as usize);
+        let r = self.patch_radius as usize;
+
+        let mut channel_min = vec![0.0f32; w * h];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let mut m = f32::MAX;
+            for c in 0..3 {
+                m = m.min(pixel[c] as f32 / 255.0 / scale[c]);
+            }
+            channel_min[y as usize * w + x as usize] = m;
+        }
+
+        // The square min filter is separable: rows first, then columns
+        let mut rows = vec![0.0f32; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let x0 = x.saturating_sub(r);
+                let x1 = (x + r).min(w - 1);
+                rows[y * w + x] = channel_min[y * w + x0..=y * w + x1]
+                    .iter()
+                    .fold(f32::MAX, |a, &b| a.min(b));
+            }
+        }
+
+        let mut dark = vec![0.0f32; w * h];
+        for y in 0..h {
+            let y0 = y.saturating_sub(r);
+            let y1 = (y + r).min(h - 1);
+            for x in 0..w {
+                dark[y * w + x] = (y0..=y1).fold(f32::MAX, |a, yy| a.min(rows[yy * w + x]));
+            }
+        }
+
+        dark
+    }
+
+    /// Takes the haziest 0.1% of pixels (highest dark channel values) and
+    /// uses the brightest of them as the atmospheric light, so a white object
+    /// alone can't be mistaken for the haze color.
+    fn atmospheric_light(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, dark: &[f32]) -> [f32; 3] {
+        let width = image.width() as usize;
+        let mut sorted = dark.to_vec();
+        sorted.sort_unstable_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        let count = (dark.len() / 1000).max(1);
+        let threshold = sorted[count - 1];
+
+        let mut light = [0.0f32; 3];
+        let mut best_intensity = -1.0f32;
+        for (i, _) in dark.iter().enumerate().filter(|(_, &d)| d >= threshold) {
+            let pixel = image.get_pixel((i % width) as u32, (i / width) as u32);
+            let intensity = pixel[0] as f32 + pixel[1] as f32 + pixel[2] as f32;
+            if intensity > best_intensity {
+                best_intensity = intensity;
+                for c in 0..3 {
+                    // Keep A away from zero so the normalization stays finite
+                    light[c] = (pixel[c] as f32 / 255.0).max(1.0 / 255.0);
+                }
+            }
+        }
+        light
+    }
+}
+
+impl Filter for Dehaze {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 || self.amount <= 0.0 {
+            return image.clone();
+        }
+
+        let dark = self.dark_channel(image, [1.0, 1.0, 1.0]);
+        let light = Self::atmospheric_light(image, &dark);
+        let normalized_dark = self.dark_channel(image, light);
+
+        let mut output = image.clone();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let i = y as usize * width as usize + x as usize;
+            let transmission = (1.0 - self.amount * normalized_dark[i]).max(Self::MIN_TRANSMISSION);
+
+            let mut rgb = [0u8; 3];
+            for c in 0..3 {
+                let intensity = pixel[c] as f32 / 255.0;
+                let radiance = (intensity - light[c]) / transmission + light[c];
+                rgb[c] = (radiance * 255.0).clamp(0.0, 255.0) as u8;
+            }
+            output.put_pixel(x, y, Rgba([rgb[0], rgb[1], rgb[2], pixel[3]]));
+        }
+
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self {
+            amount: self.amount,
+            patch_radius: self.patch_radius,
+            name: self.name.clone(),
+            description: self.description.clone(),
+        })
+    }
+}
+
+/// Shadows/Highlights tone adjustment.
+///
+/// A blurred luminance mask decides how much of each correction a pixel
+/// receives, so dark regions are lifted and bright regions pulled down
+/// without flattening local contrast the way a global curve would.
+#[derive(PartialEq)]
+pub struct ShadowsHighlights {
+    /// Shadow lift, -1.0 to 1.0 (positive brightens dark regions)
+    pub shadows: f32,
+    /// Highlight recovery, -1.0 to 1.0 (positive darkens bright regions)
+    pub highlights: f32,
+    /// Blur radius of the luminance mask in pixels
+    pub radius: f32,
+    name: String,
+    description: String,
+}
+
+impl ShadowsHighlights {
+    pub fn new(shadows: f32, highlights: f32, radius: f32) -> Self {
+        let shadows = shadows.clamp(-1.0, 1.0);
+        let highlights = highlights.clamp(-1.0, 1.0);
+        let radius = radius.max(0.1);
+        Self {
+            shadows,
+            highlights,
+            radius,
+            name: "Shadows & Highlights".to_string(),
+            description: format!("Shadows {:+.2}, highlights {:+.2}, radius {:.1}", shadows, highlights, radius),
+        }
+    }
+}
+
+impl std::fmt::Debug for ShadowsHighlights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShadowsHighlights")
+            .field("shadows", &self.shadows)
+            .field("highlights", &self.highlights)
+            .field("radius", &self.radius)
+            .finish()
+    }
+}
+
+impl Filter for ShadowsHighlights {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (width, height) = image.dimensions();
+        let mut output = image.clone();
+        
+        // Build the luminance mask and soften it so corrections follow regions, not pixels
+        let mut luminance = ImageBuffer::new(width, height);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let l = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+            luminance.put_pixel(x, y, Luma([l.clamp(0.0, 255.0) as u8]));
+        }
+        let mask = gaussian_blur_f32(&luminance, self.radius);
+        
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let m = mask.get_pixel(x, y)[0] as f32 / 255.0;
+            let shadow_weight = (1.0 - m) * (1.0 - m);
+            let highlight_weight = m * m;
+            
+            let mut rgb = [0u8; 3];
+            for c in 0..3 {
+                let v = pixel[c] as f32 / 255.0;
+                let adjusted = v
+                    + self.shadows * shadow_weight * (1.0 - v)
+                    - self.highlights * highlight_weight * v;
+                rgb[c] = (adjusted * 255.0).clamp(0.0, 255.0) as u8;
+            }
+            output.put_pixel(x, y, Rgba([rgb[0], rgb[1], rgb[2], pixel[3]]));
+        }
+        
+        output
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self {
+            shadows: self.shadows,
+            highlights: self.highlights,
+            radius: self.radius,
+            name: self.name.clone(),
+            description: self.description.clone(),
+        })
+    }
+}
+
+/// Color grading through a 3D lookup table loaded from an Adobe/Resolve
+/// `.cube` file.
+///
+/// Lattice points are sampled with trilinear interpolation, so small LUTs
+/// (17³ or 33³ are common) still grade smoothly.
+pub struct LutFilter {
+    /// Number of lattice points along each axis
+    pub size: usize,
+    /// Input value mapped to the first lattice point of each axis
+    pub domain_min: [f32; 3],
+    /// Input value mapped to the last lattice point of each axis
+    pub domain_max: [f32; 3],
+    /// Output colors, red index varying fastest as in the file
+    table: Vec<[f32; 3]>,
+    name: String,
+    description: String,
+}
+
+impl LutFilter {
+    /// Load a 3D LUT from a `.cube` file
+    pub fn from_cube(path: &std::path::Path) -> Result<LutFilter, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read LUT file {}: {}", path.display(), e))?;
+        let mut lut = Self::parse_cube(&contents)?;
+        if lut.name.is_empty() {
+            if let Some(stem) = path.file_stem() {
+                lut.name = stem.to_string_lossy().to_string();
+            }
+        }
+        Ok(lut)
+    }
+
+    /// Parse the text of a `.cube` file
+    pub fn parse_cube(contents: &str) -> Result<LutFilter, String> {
+        let mut title = String::new();
+        let mut size: Option<usize> = None;
+        let mut domain_min = [0.0f32; 3];
+        let mut domain_max = [1.0f32; 3];
+        let mut table = Vec::new();
+
+        let parse_triplet = |fields: &[&str], line_no: usize| -> Result<[f32; 3], String> {
+            if fields.len() != 3 {
+                return Err(format!("Line {}: expected 3 values, found {}", line_no, fields.len()));
+            }
+            let mut values = [0.0f32; 3];
+            for (value, field) in values.iter_mut().zip(fields) {
+                *value = field.parse::<f32>()
+                    .map_err(|_| format!("Line {}: invalid number '{}'", line_no, field))?;
+            }
+            Ok(values)
+        };
+
+        for (i, raw) in contents.lines().enumerate() {
+            let line_no = i + 1;
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                "TITLE" => {
+                    title = line["TITLE".len()..].trim().trim_matches('"').to_string();
+                },
+                "LUT_3D_SIZE" => {
+                    let n = fields.get(1)
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .ok_or_else(|| format!("Line {}: invalid LUT_3D_SIZE", line_no))?;
+                    if !(2..=256).contains(&n) {
+                        return Err(format!("Line {}: LUT_3D_SIZE {} out of range (2-256)", line_no, n));
+                    }
+                    size = Some(n);
+                },
+                "LUT_1D_SIZE" => {
+                    return Err("1D LUTs are not supported".to_string());
+                },
+                "DOMAIN_MIN" => domain_min = parse_triplet(&fields[1..], line_no)?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(&fields[1..], line_no)?,
+                keyword if keyword.chars().next().map_or(false, |c| c.is_ascii_alphabetic()) => {
+                    // Unknown keywords (e.g. LUT_3D_INPUT_RANGE from other tools) are ignored
+                },
+                _ => {
+                    if size.is_none() {
+                        return Err(format!("Line {}: table data before LUT_3D_SIZE", line_no));
+                    }
+                    table.push(parse_triplet(&fields, line_no)?);
+                },
+            }
+        }
+
+        let size = size.ok_or_else(|| "Missing LUT_3D_SIZE".to_string())?;
+        if table.len() != size * size * size {
+            return Err(format!("Expected {} table entries for a {}³ LUT, found {}",
+                               size * size * size, size, table.len()));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err("DOMAIN_MAX must be greater than DOMAIN_MIN".to_string());
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+            description: format!("Applies a {}³ 3D color lookup table", size),
+            name: title,
+        })
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[(b * self.size + g) * self.size + r]
+    }
+
+    /// Look up a normalized RGB color with trilinear interpolation
+    fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let max_index = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut frac = [0.0f32; 3];
+        for c in 0..3 {
+            let t = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            let pos = t.clamp(0.0, 1.0) * max_index;
+            // Keep the upper neighbour in range when we land exactly on the last point
+            let i = (pos.floor() as usize).min(self.size - 2);
+            base[c] = i;
+            frac[c] = pos - i as f32;
+        }
+
+        let mut out = [0.0f32; 3];
+        for corner in 0..8 {
+            let dr = corner & 1;
+            let dg = (corner >> 1) & 1;
+            let db = (corner >> 2) & 1;
+            let weight = (if dr == 1 { frac[0] } else { 1.0 - frac[0] })
+                * (if dg == 1 { frac[1] } else { 1.0 - frac[1] })
+                * (if db == 1 { frac[2] } else { 1.0 - frac[2] });
+            if weight == 0.0 {
+                continue;
+            }
+            let value = self.entry(base[0] + dr, base[1] + dg, base[2] + db);
+            for c in 0..3 {
+                out[c] += value[c] * weight;
+            }
+        }
+        out
+    }
+}
+
+impl Filter for LutFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let rgb = [
+                pixel[0] as f32 / 255.0,
+                pixel[1] as f32 / 255.0,
+                pixel[2] as f32 / 255.0,
+            ];
+            let graded = self.lookup(rgb);
+            output.put_pixel(x, y, Rgba([
+                (graded[0] * 255.0).round().clamp(0.0, 255.0) as u8,
+                (graded[1] * 255.0).round().clamp(0.0, 255.0) as u8,
+                (graded[2] * 255.0).round().clamp(0.0, 255.0) as u8,
+                pixel[3],
+            ]));
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        if self.name.is_empty() { "Color Lookup" } else { &self.name }
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self {
+            size: self.size,
+            domain_min: self.domain_min,
+            domain_max: self.domain_max,
+            table: self.table.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+        })
+    }
+}
+
+// D65 reference white used for the Lab conversions
+const WHITE_X: f32 = 0.95047;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.08883;
+
+fn lab_f(t: f32) -> f32 {
+    if t > 0.008856 {
+        t.cbrt()
+    } else {
+        7.787 * t + 16.0 / 116.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    let t3 = t * t * t;
+    if t3 > 0.008856 {
+        t3
+    } else {
+        (t - 16.0 / 116.0) / 7.787
+    }
+}
+
+/// Convert linear-light RGB to CIE L*a*b* (D65)
+pub fn linear_rgb_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE_X;
+    let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE_Y;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE_Z;
+
+    let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Convert CIE L*a*b* (D65) to linear-light RGB. Out-of-gamut colors come
+/// back with channels outside 0.0 - 1.0; clipping is left to the caller.
+pub fn lab_to_linear_rgb(lab: [f32; 3]) -> [f32; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let fx = fy + lab[1] / 500.0;
+    let fz = fy - lab[2] / 200.0;
+
+    let x = lab_f_inv(fx) * WHITE_X;
+    let y = lab_f_inv(fy) * WHITE_Y;
+    let z = lab_f_inv(fz) * WHITE_Z;
+
+    [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ]
+}
+
+/// Convert an 8-bit sRGB color to CIE L*a*b* (D65)
+pub fn rgb_to_lab(rgb: [u8; 3]) -> [f32; 3] {
+    linear_rgb_to_lab([srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])])
+}
+
+/// Convert CIE L*a*b* (D65) back to 8-bit sRGB, clipping out-of-gamut colors
+pub fn lab_to_rgb(lab: [f32; 3]) -> [u8; 3] {
+    let [r, g, b] = lab_to_linear_rgb(lab);
+    [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)]
+}
+
+/// The four shades of green on the original Game Boy screen
+pub const GAME_BOY_PALETTE: [[u8; 3]; 4] = [
+    [0x0F, 0x38, 0x0F], [0x30, 0x62, 0x30], [0x8B, 0xAC, 0x0F], [0x9B, 0xBC, 0x0F],
+];
+
+/// The full 16-color CGA text-mode palette
+pub const CGA_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0xAA], [0x00, 0xAA, 0x00], [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00], [0xAA, 0x00, 0xAA], [0xAA, 0x55, 0x00], [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55], [0x55, 0x55, 0xFF], [0x55, 0xFF, 0x55], [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55], [0xFF, 0x55, 0xFF], [0xFF, 0xFF, 0x55], [0xFF, 0xFF, 0xFF],
+];
+
+/// The distinct colors of the NES PPU palette (the repeated blacks dropped)
+pub const NES_PALETTE: [[u8; 3]; 55] = [
+    [0x7C, 0x7C, 0x7C], [0x00, 0x00, 0xFC], [0x00, 0x00, 0xBC], [0x44, 0x28, 0xBC],
+    [0x94, 0x00, 0x84], [0xA8, 0x00, 0x20], [0xA8, 0x10, 0x00], [0x88, 0x14, 0x00],
+    [0x50, 0x30, 0x00], [0x00, 0x78, 0x00], [0x00, 0x68, 0x00], [0x00, 0x58, 0x00],
+    [0x00, 0x40, 0x58], [0x00, 0x00, 0x00],
+    [0xBC, 0xBC, 0xBC], [0x00, 0x78, 0xF8], [0x00, 0x58, 0xF8], [0x68, 0x44, 0xFC],
+    [0xD8, 0x00, 0xCC], [0xE4, 0x00, 0x58], [0xF8, 0x38, 0x00], [0xE4, 0x5C, 0x10],
+    [0xAC, 0x7C, 0x00], [0x00, 0xB8, 0x00], [0x00, 0xA8, 0x00], [0x00, 0xA8, 0x44],
+    [0x00, 0x88, 0x88],
+    [0xF8, 0xF8, 0xF8], [0x3C, 0xBC, 0xFC], [0x68, 0x88, 0xFC], [0x98, 0x78, 0xF8],
+    [0xF8, 0x78, 0xF8], [0xF8, 0x58, 0x98], [0xF8, 0x78, 0x58], [0xFC, 0xA0, 0x44],
+    [0xF8, 0xB8, 0x00], [0xB8, 0xF8, 0x18], [0x58, 0xD8, 0x54], [0x58, 0xF8, 0x98],
+    [0x00, 0xE8, 0xD8], [0x78, 0x78, 0x78],
+    [0xFC, 0xFC, 0xFC], [0xA4, 0xE4, 0xFC], [0xB8, 0xB8, 0xF8], [0xD8, 0xB8, 0xF8],
+    [0xF8, 0xB8, 0xF8], [0xF8, 0xA4, 0xC0], [0xF0, 0xD0, 0xB0], [0xFC, 0xE0, 0xA8],
+    [0xF8, 0xD8, 0x78], [0xD8, 0xF8, 0x78], [0xB8, 0xF8, 0xB8], [0xB8, 0xF8, 0xD8],
+    [0x00, 0xFC, 0xFC], [0xF8, 0xD8, 0xF8],
+];
+
+/// Reduce an image to a fixed palette, choosing the perceptually nearest
+/// entry (in Lab) for each pixel, with optional Floyd–Steinberg dithering.
+/// Alpha is kept as it is.
+pub struct PaletteFilter {
+    pub dither: bool,
+    palette: Vec<[u8; 3]>,
+    palette_lab: Vec<[f32; 3]>,
+    name: String,
+    description: String,
+}
+
+impl PaletteFilter {
+    pub fn new(palette: Vec<Color>, dither: bool) -> Self {
+        let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let rgb = palette.iter().map(|c| [channel(c.r), channel(c.g), channel(c.b)]).collect();
+        Self::from_rgb(rgb, dither)
+    }
+
+    /// Build from 8-bit RGB entries, as in the built-in palette tables
+    pub fn from_rgb(palette: Vec<[u8; 3]>, dither: bool) -> Self {
+        let palette_lab = palette.iter().map(|&rgb| rgb_to_lab(rgb)).collect();
+        Self {
+            dither,
+            palette,
+            palette_lab,
+            name: "Reduce to Palette".to_string(),
+            description: "Maps every pixel to the nearest color of a fixed palette".to_string(),
+        }
+    }
+
+    pub fn game_boy(dither: bool) -> Self {
+        Self::from_rgb(GAME_BOY_PALETTE.to_vec(), dither)
+    }
+
+    pub fn cga(dither: bool) -> Self {
+        Self::from_rgb(CGA_PALETTE.to_vec(), dither)
+    }
+
+    pub fn nes(dither: bool) -> Self {
+        Self::from_rgb(NES_PALETTE.to_vec(), dither)
+    }
+
+    /// The palette entries as 8-bit RGB
+    pub fn palette(&self) -> &[[u8; 3]] {
+        &self.palette
+    }
+
+    /// The palette entry closest to `rgb` in Lab
+    pub fn nearest(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let lab = rgb_to_lab(rgb);
+        let distance = |other: &[f32; 3]| {
+            (0..3).map(|c| (lab[c] - other[c]) * (lab[c] - other[c])).sum::<f32>()
+        };
+        let index = (0..self.palette.len())
+            .min_by(|&a, &b| distance(&self.palette_lab[a]).total_cmp(&distance(&self.palette_lab[b])))
+            .unwrap_or(0);
+        self.palette[index]
+    }
+}
+
+impl Filter for PaletteFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        if self.palette.is_empty() {
+            return output;
+        }
+
+        if !self.dither {
+            let mut cache = std::collections::HashMap::new();
+            for pixel in output.pixels_mut() {
+                let rgb = [pixel[0], pixel[1], pixel[2]];
+                let mapped = *cache.entry(rgb).or_insert_with(|| self.nearest(rgb));
+                *pixel = Rgba([mapped[0], mapped[1], mapped[2], pixel[3]]);
+            }
+            return output;
+        }
+
+        // Floyd–Steinberg: push each pixel's quantization error onto the
+        // unvisited neighbours, 7/16 right and 3/16, 5/16, 1/16 below
+        let (width, height) = image.dimensions();
+        let mut values: Vec<[f32; 3]> = image.pixels()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect();
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let i = y * width as usize + x;
+                let current = values[i];
+                let rgb = [
+                    current[0].round().clamp(0.0, 255.0) as u8,
+                    current[1].round().clamp(0.0, 255.0) as u8,
+                    current[2].round().clamp(0.0, 255.0) as u8,
+                ];
+                let mapped = self.nearest(rgb);
+                let alpha = output.get_pixel(x as u32, y as u32)[3];
+                output.put_pixel(x as u32, y as u32, Rgba([mapped[0], mapped[1], mapped[2], alpha]));
+
+                let error = [
+                    current[0] - mapped[0] as f32,
+                    current[1] - mapped[1] as f32,
+                    current[2] - mapped[2] as f32,
+                ];
+                let mut spread = |dx: i64, dy: i64, weight: f32| {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx >= 0 && nx < width as i64 && ny < height as i64 {
+                        let target = &mut values[ny as usize * width as usize + nx as usize];
+                        for c in 0..3 {
+                            target[c] += error[c] * weight;
+                        }
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::from_rgb(self.palette.clone(), self.dither))
+    }
+}
+
+/// Posterize: quantize colors into `levels` evenly spaced bands.
+///
+/// Per channel, red, green and blue are each snapped to the bands on their
+/// own. Otherwise only the luminance is banded and every channel shifts
+/// with it, which keeps the colors and posterizes just the tones.
+pub struct PosterizeFilter {
+    /// Number of output values per channel, 2 - 255
+    pub levels: u8,
+    pub per_channel: bool,
+    name: String,
+    description: String,
+}
+
+impl PosterizeFilter {
+    pub fn new(levels: u8, per_channel: bool) -> Self {
+        let levels = levels.max(2);
+        Self {
+            levels,
+            per_channel,
+            name: format!("Posterize ({} levels)", levels),
+            description: format!("Reduces each channel to {} levels", levels),
+        }
+    }
+
+    /// Input value to its band's output value
+    pub fn lut(&self) -> [u8; 256] {
+        let levels = self.levels.max(2) as u32;
+        let mut lut = [0u8; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            let band = (value as u32 * levels / 256).min(levels - 1);
+            *entry = ((band * 255) as f32 / (levels - 1) as f32).round() as u8;
+        }
+        lut
+    }
+}
+
+impl Filter for PosterizeFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let lut = self.lut();
+        let mut output = image.clone();
+
+        for pixel in output.pixels_mut() {
+            if self.per_channel {
+                for c in 0..3 {
+                    pixel[c] = lut[pixel[c] as usize];
+                }
+            } else {
+                let luma = (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round();
+                let shift = lut[luma.clamp(0.0, 255.0) as usize] as f32 - luma;
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as f32 + shift).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.levels, self.per_channel))
+    }
+}
+
+/// Approximate linear RGB of a black body at `kelvin` (Tanner Helland's fit)
+fn blackbody_rgb(kelvin: f32) -> [f32; 3] {
+    let t = (kelvin / 100.0).clamp(10.0, 400.0);
+    let red = if t <= 66.0 { 255.0 } else { 329.698_73 * (t - 60.0).powf(-0.133_204_76) };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_846)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    [red.clamp(1.0, 255.0), green.clamp(1.0, 255.0), blue.clamp(1.0, 255.0)]
+}
+
+/// Channel multipliers (green normalized to 1) that neutralize light of
+/// `kelvin` relative to daylight (5500 K), for linear RGB. `tint` runs from
+/// -100 to 100; positive values compensate a green cast.
+pub fn temperature_multipliers(kelvin: f32, tint: f32) -> [f32; 3] {
+    let light = blackbody_rgb(kelvin);
+    let daylight = blackbody_rgb(5500.0);
+    let mut multipliers = [0.0f32; 3];
+    for c in 0..3 {
+        multipliers[c] = daylight[c] / light[c];
+    }
+    multipliers[1] *= 1.0 - (tint / 200.0).clamp(-0.5, 0.5);
+    
+    let green = multipliers[1];
+    [multipliers[0] / green, 1.0, multipliers[2] / green]
+}
+
+/// White balance for already developed images, with the same temperature
+/// and tint controls as RAW development.
+///
+/// `temperature` is the color of the light the photo should be corrected
+/// for: above 5500 K the image gets warmer, below it cooler. The channel
+/// multipliers are applied in linear light.
+pub struct WhiteBalanceFilter {
+    /// Kelvin
+    pub temperature: f32,
+    /// -100 - 100
+    pub tint: f32,
+    name: String,
+    description: String,
+}
+
+impl WhiteBalanceFilter {
+    pub fn new(temperature: f32, tint: f32) -> Self {
+        Self {
+            temperature,
+            tint,
+            name: format!("White Balance ({:.0} K)", temperature),
+            description: "Corrects the color temperature and tint of the image".to_string(),
+        }
+    }
+
+    /// Lookup tables for red, green and blue
+    pub fn luts(&self) -> [[u8; 256]; 3] {
+        let multipliers = temperature_multipliers(self.temperature, self.tint);
+        let mut luts = [[0u8; 256]; 3];
+        for (c, lut) in luts.iter_mut().enumerate() {
+            for (value, entry) in lut.iter_mut().enumerate() {
+                *entry = linear_to_srgb(srgb_to_linear(value as u8) * multipliers[c]);
+            }
+        }
+        luts
+    }
+}
+
+impl Filter for WhiteBalanceFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let luts = self.luts();
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            for c in 0..3 {
+                pixel[c] = luts[c][pixel[c] as usize];
+            }
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.temperature, self.tint))
+    }
+}
+
+/// Channel mixer: each output channel is a weighted sum of the input red,
+/// green and blue plus a constant.
+///
+/// Row `i` of `matrix` holds the weights of output channel `i` (1.0 is
+/// 100%), and `offsets` are added in channel units, -255 - 255. In
+/// monochrome mode the first row gives a gray value used for all three
+/// channels, for custom black and white conversions.
+pub struct ChannelMixerFilter {
+    pub matrix: [[f32; 3]; 3],
+    pub offsets: [f32; 3],
+    pub monochrome: bool,
+    name: String,
+    description: String,
+}
+
+impl ChannelMixerFilter {
+    pub fn new(matrix: [[f32; 3]; 3], offsets: [f32; 3], monochrome: bool) -> Self {
+        Self {
+            matrix,
+            offsets,
+            monochrome,
+            name: "Channel Mixer".to_string(),
+            description: "Mixes each output channel from the input red, green and blue".to_string(),
+        }
+    }
+
+    /// Every channel passed through unchanged
+    pub fn identity() -> Self {
+        Self::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], [0.0; 3], false)
+    }
+
+    pub fn mix_pixel(&self, pixel: Rgba<u8>) -> Rgba<u8> {
+        let input = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        let mix = |row: usize| {
+            let weights = self.matrix[row];
+            let value = weights[0] * input[0] + weights[1] * input[1] + weights[2] * input[2] + self.offsets[row];
+            value.round().clamp(0.0, 255.0) as u8
+        };
+        if self.monochrome {
+            let gray = mix(0);
+            Rgba([gray, gray, gray, pixel[3]])
+        } else {
+            Rgba([mix(0), mix(1), mix(2), pixel[3]])
+        }
+    }
+}
+
+impl Filter for ChannelMixerFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            *pixel = self.mix_pixel(*pixel);
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.matrix, self.offsets, self.monochrome))
+    }
+}
+
+/// Hue of typical skin tones in degrees, and how far either side of it
+/// vibrance backs off
+const SKIN_HUE: f32 = 25.0;
+const SKIN_HUE_RANGE: f32 = 25.0;
+
+/// Vibrance: a saturation boost that favours muted colors.
+///
+/// Each pixel's chroma is scaled around its luminance by an amount that
+/// shrinks as the pixel gets more saturated and near skin-tone hues, and
+/// is capped so no channel clips. Dull areas come alive while faces and
+/// already vivid colors stay put. Negative amounts desaturate.
+pub struct VibranceFilter {
+    /// -1.0 - 1.0
+    pub amount: f32,
+    name: String,
+    description: String,
+}
+
+impl VibranceFilter {
+    pub fn new(amount: f32) -> Self {
+        Self {
+            amount,
+            name: format!("Vibrance ({:+.2})", amount),
+            description: "Boosts the saturation of muted colors, sparing skin tones".to_string(),
+        }
+    }
+
+    pub fn adjust_pixel(&self, pixel: Rgba<u8>) -> Rgba<u8> {
+        let amount = self.amount.clamp(-1.0, 1.0);
+        let [r, g, b, a] = pixel.0.map(|c| c as f32);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        if amount == 0.0 || max == min {
+            return pixel;
+        }
+
+        let saturation = (max - min) / max;
+        let mut scale = amount * (1.0 - saturation);
+        if amount > 0.0 {
+            let hue = rgb_to_hsl(pixel[0], pixel[1], pixel[2]).0 * 360.0;
+            let skin = (1.0 - (hue - SKIN_HUE).abs() / SKIN_HUE_RANGE).max(0.0);
+            scale *= 1.0 - 0.75 * skin;
+        }
+
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        // Largest boost that keeps every channel in range, so the hue holds
+        for c in [r, g, b] {
+            let limit = if c > luma {
+                (255.0 - luma) / (c - luma) - 1.0
+            } else if c < luma {
+                luma / (luma - c) - 1.0
+            } else {
+                continue;
+            };
+            scale = scale.min(limit.max(0.0));
+        }
+
+        let channel = |c: f32| (luma + (c - luma) * (1.0 + scale)).round().clamp(0.0, 255.0) as u8;
+        Rgba([channel(r), channel(g), channel(b), a as u8])
+    }
+}
+
+impl Filter for VibranceFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            *pixel = self.adjust_pixel(*pixel);
+        }
+        output
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.amount))
+    }
+}
+
+/// Threshold: pixels whose luminance is above `threshold` turn white and
+/// the rest black
+pub struct ThresholdFilter {
+    pub threshold: u8,
+    /// Keep each pixel's alpha; otherwise the result is opaque
+    pub keep_alpha: bool,
+    name: String,
+    description: String,
+}
+
+impl ThresholdFilter {
+    pub fn new(threshold: u8, keep_alpha: bool) -> Self {
+        Self {
+            threshold,
+            keep_alpha,
+            name: format!("Threshold ({})", threshold),
+            description: format!("Turns pixels brighter than {} white and the rest black", threshold),
+        }
+    }
+}
+
+impl Filter for ThresholdFilter {
+    fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let luminance: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let p = image.get_pixel(x, y);
+            Luma([(0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32).round() as u8])
+        });
+        let binary = threshold(&luminance, self.threshold);
+
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let v = binary.get_pixel(x, y)[0];
+            let alpha = if self.keep_alpha { image.get_pixel(x, y)[3] } else { 255 };
+            Rgba([v, v, v, alpha])
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn box_clone(&self) -> Box<dyn Filter + Send + Sync> {
+        Box::new(Self::new(self.threshold, self.keep_alpha))
+    }
+}
+
+/// Per-channel mean and standard deviation of the Lab values of the
+/// non-transparent pixels in `image`
+fn lab_statistics(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ([f32; 3], [f32; 3]) {
+    let mut sum = [0.0f64; 3];
+    let mut sum_sq = [0.0f64; 3];
+    let mut count = 0.0f64;
+
+    for pixel in image.pixels().filter(|p| p[3] > 0) {
+        let lab = rgb_to_lab([pixel[0], pixel[1], pixel[2]]);
+        for c in 0..3 {
+            sum[c] += lab[c] as f64;
+            sum_sq[c] += (lab[c] as f64) * (lab[c] as f64);
+        }
+        count += 1.0;
+    }
+
+    let mut mean = [0.0f32; 3];
+    let mut std_dev = [0.0f32; 3];
+    if count > 0.0 {
+        for c in 0..3 {
+            let m = sum[c] / count;
+            mean[c] = m as f32;
+            std_dev[c] = (sum_sq[c] / count - m * m).max(0.0).sqrt() as f32;
+        }
+    }
+    (mean, std_dev)
+}
+
+/// Transfer the overall color of `reference` onto `source` (Reinhard et al.).
+///
+/// Each Lab channel of the source is shifted and scaled so its mean and
+/// standard deviation match the reference's. Alpha is left untouched.
+pub fn match_color(
+    source: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    reference: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (source_mean, source_std) = lab_statistics(source);
+    let (reference_mean, reference_std) = lab_statistics(reference);
+
+    // A flat source channel can't be stretched; only shift it
+    let mut scale = [1.0f32; 3];
+    for c in 0..3 {
+        if source_std[c] > 1e-3 {
+            scale[c] = reference_std[c] / source_std[c];
+        }
+    }
+
+    let mut output = source.clone();
+    for pixel in output.pixels_mut() {
+        let lab = rgb_to_lab([pixel[0], pixel[1], pixel[2]]);
+        let mut matched = [0.0f32; 3];
+        for c in 0..3 {
+            matched[c] = (lab[c] - source_mean[c]) * scale[c] + reference_mean[c];
+        }
+        matched[0] = matched[0].clamp(0.0, 100.0);
+        let rgb = lab_to_rgb(matched);
+        *pixel = Rgba([rgb[0], rgb[1], rgb[2], pixel[3]]);
+    }
+    output
+}
+
+/// Curve used to compress high dynamic range values into displayable ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    /// L / (1 + L): simple, never clips, keeps mid-tones
+    Reinhard,
+    /// Filmic shoulder and toe (Hable), with a bit more contrast
+    Filmic,
+    /// Adaptive logarithmic mapping (Drago et al.) for very wide ranges
+    Drago,
+}
+
+/// Hable's filmic curve, before white-point normalization
+fn filmic_curve(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// Compress a 16-bit linear-light HDR image into 8-bit sRGB.
+///
+/// The input is first scaled so its log-average luminance lands on middle
+/// grey (0.18), then by `2^exposure` stops, and the luminance is mapped
+/// through `operator`. Colors keep their ratios; alpha is carried over.
+pub fn tone_map(
+    image: &ImageBuffer<Rgba<u16>, Vec<u16>>,
+    operator: ToneMapOperator,
+    exposure: f32,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let luminance = |p: &Rgba<u16>| {
+        (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32) / 65535.0
+    };
+
+    // Log-average (geometric mean) luminance is the scene's "key"
+    let mut log_sum = 0.0f64;
+    let mut max_luminance = 0.0f32;
+    for pixel in image.pixels() {
+        let l = luminance(pixel);
+        log_sum += (1e-6 + l as f64).ln();
+        max_luminance = max_luminance.max(l);
+    }
+    let count = (image.width() as f64 * image.height() as f64).max(1.0);
+    let log_average = (log_sum / count).exp() as f32;
+    let scale = 0.18 / log_average.max(1e-6) * 2.0f32.powf(exposure);
+    let max_scaled = (max_luminance * scale).max(1e-6);
+
+    let map = |l: f32| -> f32 {
+        match operator {
+            ToneMapOperator::Reinhard => l / (1.0 + l),
+            ToneMapOperator::Filmic => {
+                const WHITE: f32 = 11.2;
+                filmic_curve(2.0 * l) / filmic_curve(WHITE)
+            },
+            ToneMapOperator::Drago => {
+                const BIAS: f32 = 0.85;
+                let exponent = BIAS.ln() / 0.5f32.ln();
+                let denominator = (2.0 + 8.0 * (l / max_scaled).powf(exponent)).ln();
+                (l + 1.0).ln() / denominator / (max_scaled + 1.0).log10()
+            },
+        }
+    };
+
+    let mut output = ImageBuffer::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let l = luminance(pixel) * scale;
+        let ratio = if l > 0.0 { map(l) / l } else { 0.0 };
+
+        let channel = |c: usize| linear_to_srgb(pixel[c] as f32 / 65535.0 * scale * ratio);
+        output.put_pixel(x, y, Rgba([channel(0), channel(1), channel(2), (pixel[3] >> 8) as u8]));
+    }
+    output
+}
+
+/// Convert 8-bit RGB to HSL, each component in 0.0 - 1.0
+pub fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    rgb_to_hsl_f32(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+/// Convert RGB in 0.0 - 1.0 to HSL, each component in 0.0 - 1.0
+pub fn rgb_to_hsl_f32(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    
+    let mut h = 0.0;
+    let mut s = 0.0;
+    let l = (max + min) / 2.0;
+    
+    if max != min {
+        let d = max - min;
+        s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+        
+        h = if max == r {
+            (g - b) / d + (if g < b { 6.0 } else { 0.0 })
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        };
+        
+        h /= 6.0;
+    }
+    
+    (h, s, l)
+}
+
+/// Convert HSL (each component in 0.0 - 1.0) back to 8-bit RGB
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let (r, g, b) = hsl_to_rgb_f32(h, s, l);
+    ((r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8)
+}
+
+/// Convert HSL (each component in 0.0 - 1.0) back to RGB in 0.0 - 1.0
+pub fn hsl_to_rgb_f32(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let hue_to_rgb = |p: f32, q: f32, mut t: f32| -> f32 {
+        if t < 0.0 { t += 1.0; }
+        if t > 1.0 { t -= 1.0; }
+        
+        if t < 1.0 / 6.0 {
+            return p + (q - p) * 6.0 * t;
+        }
+        if t < 1.0 / 2.0 {
+            return q;
+        }
+        if t < 2.0 / 3.0 {
+            return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
+        }
+        
+        p
+    };
+    
+    if s == 0.0 {
+        // Achromatic (gray)
+        return (l, l, l);
+    }
+    
+    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
+    let p = 2.0 * l - q;
+    
+    (
+        hue_to_rgb(p, q, h + 1.0 / 3.0),
+        hue_to_rgb(p, q, h),
+        hue_to_rgb(p, q, h - 1.0 / 3.0),
+    )
+}
+
+/// Push a value in [0, 1] towards 1 (positive `delta`) or 0 (negative
+/// `delta`), with `delta` in [-1, 1]
+pub fn adjust_value(value: f32, delta: f32) -> f32 {
+    if delta > 0.0 {
+        value + (1.0 - value) * delta
+    } else {
+        value + value * delta
+    }
+}
+
+/// How much a hue (0.0 - 1.0) belongs to each of the six color ranges:
+/// reds, yellows, greens, cyans, blues and magentas.
+///
+/// Ranges are centered 60° apart and fall off linearly to the next center,
+/// so neighbouring ranges overlap and the weights always sum to 1.
+pub fn hue_band_weights(hue: f32) -> [f32; 6] {
+    let position = hue.rem_euclid(1.0) * 6.0;
+    let lower = position.floor() as usize % 6;
+    let t = position - position.floor();
+    let mut weights = [0.0f32; 6];
+    weights[lower] = 1.0 - t;
+    weights[(lower + 1) % 6] += t;
+    weights
+}
+
+/// Apply a global (hue°, saturation%, lightness%) shift plus per-range
+/// deltas to one pixel.
+///
+/// `ranges` holds the (hue°, saturation%, lightness%) tuples for reds,
+/// yellows, greens, cyans, blues and magentas. Each pixel gets a blend of
+/// the ranges its hue falls between, scaled by its saturation so greys,
+/// whose hue is meaningless, are left to the global adjustment.
+pub fn adjust_hsl_pixel(pixel: Rgba<u8>, global: (f32, f32, f32), ranges: &[(f32, f32, f32); 6]) -> Rgba<u8> {
+    let (h, s, l) = rgb_to_hsl(pixel[0], pixel[1], pixel[2]);
+    
+    let weights = hue_band_weights(h);
+    let mut local = (0.0f32, 0.0f32, 0.0f32);
+    for (weight, range) in weights.iter().zip(ranges.iter()) {
+        let w = weight * s;
+        local.0 += range.0 * w;
+        local.1 += range.1 * w;
+        local.2 += range.2 * w;
+    }
+    
+    let h = (h + (global.0 + local.0) / 360.0).rem_euclid(1.0);
+    let s = adjust_value(adjust_value(s, global.1 / 100.0), (local.1 / 100.0).clamp(-1.0, 1.0));
+    let l = adjust_value(adjust_value(l, global.2 / 100.0), (local.2 / 100.0).clamp(-1.0, 1.0));
+    
+    let (r, g, b) = hsl_to_rgb(h, s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+    Rgba([r, g, b, pixel[3]])
+}
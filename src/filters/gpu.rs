@@ -0,0 +1,219 @@
+// GPU implementations of filters, built with the `gpu-wgpu` feature.
+//
+// `init` opens a wgpu device once for the whole process; until it has
+// succeeded every function here returns None and the callers fall back to
+// their CPU code. Images travel as storage buffers of packed RGBA8 words,
+// which every wgpu backend supports, and are unpacked in the shaders.
+
+use std::sync::mpsc;
+use image::{ImageBuffer, Rgba};
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use wgpu::util::DeviceExt;
+
+/// Images smaller than this many pixels aren't worth the upload and readback
+pub const GPU_MIN_PIXELS: u32 = 128 * 128;
+
+/// Side of the square compute workgroup; must match the shader
+const WORKGROUP_SIZE: u32 = 16;
+
+/// One pass of a separable blur, along x (`direction` 0) or y (1).
+///
+/// Pixels past the edge repeat the edge pixel, and each pass is rounded
+/// back to 8 bits, both as the CPU blur does.
+const BLUR_SHADER: &str = r#"
+struct Params {
+    width: u32,
+    height: u32,
+    radius: u32,
+    direction: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> weights: array<f32>;
+@group(0) @binding(2) var<storage, read> source: array<u32>;
+@group(0) @binding(3) var<storage, read_write> destination: array<u32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let radius = i32(params.radius);
+    var sum = vec4<f32>(0.0);
+    for (var i = -radius; i <= radius; i = i + 1) {
+        var x = i32(id.x);
+        var y = i32(id.y);
+        if (params.direction == 0u) {
+            x = clamp(x + i, 0, i32(params.width) - 1);
+        } else {
+            y = clamp(y + i, 0, i32(params.height) - 1);
+        }
+        let pixel = unpack4x8unorm(source[u32(y) * params.width + u32(x)]);
+        sum = sum + pixel * weights[u32(i + radius)];
+    }
+    destination[id.y * params.width + id.x] = pack4x8unorm(sum);
+}
+"#;
+
+struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    blur_pipeline: wgpu::ComputePipeline,
+}
+
+static CONTEXT: OnceCell<GpuContext> = OnceCell::new();
+
+/// Open the first available GPU. Calling it again after a success is a
+/// no-op.
+pub fn init() -> Result<(), String> {
+    if CONTEXT.get().is_some() {
+        return Ok(());
+    }
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))
+    .ok_or_else(|| "No GPU adapter available".to_string())?;
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Rust Photo filters"),
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+        },
+        None,
+    ))
+    .map_err(|e| format!("Failed to open GPU device: {}", e))?;
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Separable blur"),
+        source: wgpu::ShaderSource::Wgsl(BLUR_SHADER.into()),
+    });
+    let blur_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Separable blur"),
+        layout: None,
+        module: &shader,
+        entry_point: "main",
+    });
+
+    info!("GPU filters running on {}", adapter.get_info().name);
+    // A racing init may have won; its context is just as good
+    let _ = CONTEXT.set(GpuContext { device, queue, blur_pipeline });
+    Ok(())
+}
+
+/// Whether `init` has succeeded
+pub fn is_available() -> bool {
+    CONTEXT.get().is_some()
+}
+
+/// The normalized kernel imageproc's `gaussian_blur_f32` uses, reaching
+/// out to twice the standard deviation
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (2.0 * sigma).ceil() as i32;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|x| (-((x * x) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+/// Gaussian blur with standard deviation `sigma` on the GPU.
+///
+/// Returns None when the GPU isn't initialized, the image is smaller than
+/// `GPU_MIN_PIXELS` or too large for the device, or anything fails on the
+/// way; the caller should then blur on the CPU.
+pub fn gaussian_blur(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, sigma: f32) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let context = CONTEXT.get()?;
+    let (width, height) = image.dimensions();
+    if width.saturating_mul(height) < GPU_MIN_PIXELS {
+        return None;
+    }
+
+    let limits = context.device.limits();
+    let size = image.as_raw().len() as u64;
+    let groups = (width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE));
+    if size > limits.max_storage_buffer_binding_size as u64
+        || groups.0 > limits.max_compute_workgroups_per_dimension
+        || groups.1 > limits.max_compute_workgroups_per_dimension {
+        debug!("{}x{} image is too large for the GPU blur", width, height);
+        return None;
+    }
+
+    let kernel = gaussian_kernel(sigma);
+    let radius = (kernel.len() / 2) as u32;
+    let device = &context.device;
+    let storage = |label: &str, contents: &[u8], usage: wgpu::BufferUsages| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+    };
+    let params = |direction: u32| -> Vec<u8> {
+        [width, height, radius, direction].iter().flat_map(|v| v.to_le_bytes()).collect()
+    };
+
+    let weights: Vec<u8> = kernel.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let weights = storage("Blur weights", &weights, wgpu::BufferUsages::STORAGE);
+    let horizontal = storage("Blur x params", &params(0), wgpu::BufferUsages::UNIFORM);
+    let vertical = storage("Blur y params", &params(1), wgpu::BufferUsages::UNIFORM);
+    let source = storage("Blur source", image.as_raw(), wgpu::BufferUsages::STORAGE);
+    let buffer = |label: &str, usage: wgpu::BufferUsages| {
+        device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size, usage, mapped_at_creation: false })
+    };
+    let intermediate = buffer("Blur intermediate", wgpu::BufferUsages::STORAGE);
+    let output = buffer("Blur output", wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+    let readback = buffer("Blur readback", wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+
+    let layout = context.blur_pipeline.get_bind_group_layout(0);
+    let bind_group = |params: &wgpu::Buffer, from: &wgpu::Buffer, to: &wgpu::Buffer| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blur pass"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: weights.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: from.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: to.as_entire_binding() },
+            ],
+        })
+    };
+    let passes = [
+        bind_group(&horizontal, &source, &intermediate),
+        bind_group(&vertical, &intermediate, &output),
+    ];
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Gaussian blur") });
+    for group in &passes {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Blur pass") });
+        pass.set_pipeline(&context.blur_pipeline);
+        pass.set_bind_group(0, group, &[]);
+        pass.dispatch_workgroups(groups.0, groups.1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+    context.queue.submit(Some(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    match receiver.recv() {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => {
+            warn!("Failed to read back GPU blur: {}", e);
+            return None;
+        },
+        Err(_) => {
+            warn!("GPU blur readback was dropped");
+            return None;
+        },
+    }
+    let pixels = slice.get_mapped_range().to_vec();
+    readback.unmap();
+
+    debug!("Blurred {}x{} image on the GPU with sigma {}", width, height, sigma);
+    ImageBuffer::from_raw(width, height, pixels)
+}
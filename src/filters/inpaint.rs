@@ -0,0 +1,189 @@
+// Filling masked areas with texture synthesized from their surroundings.
+//
+// This is a simplified PatchMatch: hole pixels are filled from the edge of
+// the hole inwards, each one copying the center of the known patch that
+// best matches what is already known around it. Candidates come from the
+// matches of neighbouring pixels (shifted by one, so a good match spreads
+// and whole runs of texture get copied coherently), a few random samples,
+// and a shrinking random search around the best so far.
+
+use image::{GrayImage, ImageBuffer, Rgba};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use log::debug;
+
+/// Random candidates tried for every hole pixel
+const RANDOM_SAMPLES: usize = 24;
+
+/// Pixels whose whole patch is known, so they can be copied into the hole
+struct SourceField {
+    width: i64,
+    height: i64,
+    /// Whether a full patch around the pixel is known and inside the image
+    valid: Vec<bool>,
+    list: Vec<(i64, i64)>,
+}
+
+impl SourceField {
+    fn is_valid(&self, x: i64, y: i64) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height && self.valid[(y * self.width + x) as usize]
+    }
+}
+
+/// Fill the pixels of `image` where `mask` is at least 128 with texture
+/// taken from the rest of the image.
+///
+/// Patches are `2 * patch_radius + 1` pixels square; a radius around the
+/// size of the texture's grain works best. Sources are looked for near the
+/// hole, within a margin as wide as the hole itself. Fails when `mask`
+/// doesn't match the image or nothing around the hole is usable.
+pub fn inpaint(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    mask: &GrayImage,
+    patch_radius: u32,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    if mask.dimensions() != image.dimensions() {
+        return Err(format!(
+            "Fill mask is {}x{} but the image is {}x{}",
+            mask.width(), mask.height(), image.width(), image.height()
+        ));
+    }
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let index = |x: i64, y: i64| (y * width + x) as usize;
+    let r = patch_radius.max(1) as i64;
+
+    let mut known: Vec<bool> = mask.pixels().map(|p| p[0] < 128).collect();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, -1, -1);
+    for y in 0..height {
+        for x in 0..width {
+            if !known[index(x, y)] {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if max_x < 0 {
+        return Ok(image.clone());
+    }
+
+    // Sources: fully known patches in a window around the hole
+    let margin = (max_x - min_x).max(max_y - min_y) + 1 + 2 * r;
+    let mut sources = SourceField { width, height, valid: vec![false; known.len()], list: Vec::new() };
+    for y in (min_y - margin).max(r)..=(max_y + margin).min(height - 1 - r) {
+        for x in (min_x - margin).max(r)..=(max_x + margin).min(width - 1 - r) {
+            let patch_known = (-r..=r).all(|dy| (-r..=r).all(|dx| known[index(x + dx, y + dy)]));
+            if patch_known {
+                sources.valid[index(x, y)] = true;
+                sources.list.push((x, y));
+            }
+        }
+    }
+    if sources.list.is_empty() {
+        return Err("Not enough of the image around the area to fill from".to_string());
+    }
+    debug!("Inpainting {}x{} area from {} source patches",
+           max_x - min_x + 1, max_y - min_y + 1, sources.list.len());
+
+    let mut result = image.clone();
+    let mut matched: Vec<Option<(i64, i64)>> = vec![None; known.len()];
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+
+    // Mean squared difference over the pixels known around the target
+    let distance = |result: &ImageBuffer<Rgba<u8>, Vec<u8>>, known: &[bool], target: (i64, i64), source: (i64, i64)| {
+        let mut total = 0u64;
+        let mut count = 0u64;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let (tx, ty) = (target.0 + dx, target.1 + dy);
+                if tx < 0 || ty < 0 || tx >= width || ty >= height || !known[index(tx, ty)] {
+                    continue;
+                }
+                let a = result.get_pixel(tx as u32, ty as u32);
+                let b = result.get_pixel((source.0 + dx) as u32, (source.1 + dy) as u32);
+                for c in 0..4 {
+                    let d = a[c] as i64 - b[c] as i64;
+                    total += (d * d) as u64;
+                }
+                count += 1;
+            }
+        }
+        if count == 0 { f64::MAX } else { total as f64 / count as f64 }
+    };
+
+    // Peel the hole from the outside in: each pass fills the hole pixels
+    // that touch a known one
+    let neighbours = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+    let mut remaining: Vec<(i64, i64)> = (min_y..=max_y)
+        .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+        .filter(|&(x, y)| !known[index(x, y)])
+        .collect();
+    while !remaining.is_empty() {
+        let (front, rest): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|&(x, y)| {
+            neighbours.iter().any(|&(dx, dy)| {
+                let (nx, ny) = (x + dx, y + dy);
+                nx >= 0 && ny >= 0 && nx < width && ny < height && known[index(nx, ny)]
+            })
+        });
+        if front.is_empty() {
+            // Only possible if the whole image is masked, ruled out above
+            break;
+        }
+
+        for (x, y) in front {
+            let mut candidates: Vec<(i64, i64)> = Vec::with_capacity(RANDOM_SAMPLES + 8);
+            for &(dx, dy) in &neighbours {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                if let Some((sx, sy)) = matched[index(nx, ny)] {
+                    candidates.push((sx - dx, sy - dy));
+                }
+            }
+            for _ in 0..RANDOM_SAMPLES {
+                candidates.push(sources.list[rng.gen_range(0..sources.list.len())]);
+            }
+
+            let mut best = None;
+            let mut best_distance = f64::MAX;
+            for candidate in candidates {
+                if !sources.is_valid(candidate.0, candidate.1) {
+                    continue;
+                }
+                let d = distance(&result, &known, (x, y), candidate);
+                if d < best_distance {
+                    best = Some(candidate);
+                    best_distance = d;
+                }
+            }
+
+            // Random search in windows halving around the best match
+            let mut source = best.unwrap_or(sources.list[0]);
+            let mut window = margin;
+            while window >= 1 {
+                let candidate = (
+                    source.0 + rng.gen_range(-window..=window),
+                    source.1 + rng.gen_range(-window..=window),
+                );
+                if sources.is_valid(candidate.0, candidate.1) {
+                    let d = distance(&result, &known, (x, y), candidate);
+                    if d < best_distance {
+                        source = candidate;
+                        best_distance = d;
+                    }
+                }
+                window /= 2;
+            }
+
+            let pixel = *image.get_pixel(source.0 as u32, source.1 as u32);
+            result.put_pixel(x as u32, y as u32, pixel);
+            matched[index(x, y)] = Some(source);
+            known[index(x, y)] = true;
+        }
+        remaining = rest;
+    }
+
+    Ok(result)
+}
@@ -1,24 +1,30 @@
 // Filters module: Contains image processing filters
 
 use image::{DynamicImage, ImageBuffer, Rgba};
-use imageproc::contrast::threshold;
 use imageproc::filter::gaussian_blur_f32;
 use imageproc::gradients::sobel_gradients;
 use imageproc::map::map_colors;
 use imageproc::pixelops::weighted_sum;
 use log::{debug, error, info, trace, warn};
+use crate::core::{Rect, Selection};
 
 pub mod blur;
 pub mod sharpen;
 pub mod color;
 pub mod artistic;
 pub mod distort;
+pub mod transform;
+pub mod inpaint;
+#[cfg(feature = "gpu-wgpu")]
+pub mod gpu;
 
 pub use blur::*;
 pub use sharpen::*;
 pub use color::*;
 pub use artistic::*;
 pub use distort::*;
+pub use transform::*;
+pub use inpaint::*;
 
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -37,6 +43,65 @@ pub trait Filter {
     
     /// Clone the filter into a boxed trait object
     fn box_clone(&self) -> Box<dyn Filter + Send + Sync>;
+    
+    /// How far, in pixels, the filter reads around each output pixel.
+    ///
+    /// Work split into strips or tiles is padded by this much so the pieces
+    /// join without seams. Per-pixel filters keep the default of 0.
+    fn support_radius(&self) -> u32 {
+        0
+    }
+    
+    /// Apply the filter and return only the pixels inside `region`.
+    ///
+    /// The default filters the whole image and crops. Neighbourhood filters
+    /// override this to process just the region plus their kernel halo, which
+    /// keeps previews of a zoomed-in viewport cheap.
+    fn apply_region(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, region: Rect) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (x, y, width, height) = region_bounds(&region, image.width(), image.height());
+        image::imageops::crop_imm(&self.apply(image), x, y, width, height).to_image()
+    }
+}
+
+/// Whole-pixel bounds `(x, y, width, height)` covering `region`, clipped to
+/// an image of the given size. Fractional edges are rounded outwards.
+pub fn region_bounds(region: &Rect, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let x0 = region.x.floor().clamp(0.0, width as f64) as u32;
+    let y0 = region.y.floor().clamp(0.0, height as f64) as u32;
+    let x1 = (region.x + region.width.max(0.0)).ceil().clamp(x0 as f64, width as f64) as u32;
+    let y1 = (region.y + region.height.max(0.0)).ceil().clamp(y0 as f64, height as f64) as u32;
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+/// Run `process` on `region` grown by `halo` pixels on every side, then crop
+/// the result back to `region`.
+///
+/// As long as `halo` covers the filter's reach, the pixels inside the region
+/// come out identical to filtering the whole image.
+pub fn apply_with_halo<F>(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    region: &Rect,
+    halo: u32,
+    process: F,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>>
+where
+    F: FnOnce(&ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>>,
+{
+    let (width, height) = image.dimensions();
+    let (x, y, w, h) = region_bounds(region, width, height);
+    if w == 0 || h == 0 {
+        return ImageBuffer::new(w, h);
+    }
+    
+    let padded_x = x.saturating_sub(halo);
+    let padded_y = y.saturating_sub(halo);
+    let padded_w = (x + w).saturating_add(halo).min(width) - padded_x;
+    let padded_h = (y + h).saturating_add(halo).min(height) - padded_y;
+    trace!("Processing region {}x{} at ({}, {}) with {}px halo", w, h, x, y, halo);
+    
+    let padded = image::imageops::crop_imm(image, padded_x, padded_y, padded_w, padded_h).to_image();
+    let processed = process(&padded);
+    image::imageops::crop_imm(&processed, x - padded_x, y - padded_y, w, h).to_image()
 }
 
 /// Trait for filters that can be applied with a specified intensity
@@ -99,6 +164,89 @@ pub fn apply_filter_to_region(
     result
 }
 
+/// Applies a filter inside a selection.
+///
+/// The selection mask is the blend weight: fully selected pixels take the
+/// filtered result, unselected ones keep the original, and the soft edge of
+/// a feathered selection fades between the two. Only the bounding box of
+/// the selected pixels is filtered, through `Filter::apply_region`, so the
+/// filter still reads the unselected pixels around it as it would when
+/// filtering the whole image.
+pub fn apply_filter_masked(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    filter: &dyn Filter,
+    mask: &Selection,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    info!("Applying filter '{}' inside selection", filter.name());
+    
+    let width = image.width().min(mask.mask.width());
+    let height = image.height().min(mask.mask.height());
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if mask.mask.get_pixel(x, y)[0] > 0 {
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x + 1);
+                bottom = bottom.max(y + 1);
+            }
+        }
+    }
+    
+    let mut result = image.clone();
+    if right <= left || bottom <= top {
+        debug!("Selection is empty, nothing to filter");
+        return result;
+    }
+    
+    let (region_width, region_height) = (right - left, bottom - top);
+    let region = Rect {
+        x: left as f64,
+        y: top as f64,
+        width: region_width as f64,
+        height: region_height as f64,
+    };
+    let filtered = filter.apply_region(image, region);
+    let original = image::imageops::crop_imm(image, left, top, region_width, region_height).to_image();
+    let weights = image::imageops::crop_imm(&mask.mask, left, top, region_width, region_height).to_image();
+    let blended = blend_with_mask(&original, &filtered, &weights);
+    image::imageops::replace(&mut result, &blended, left as i64, top as i64);
+    
+    debug!("Filter '{}' applied inside selection", filter.name());
+    result
+}
+
+/// Mix a filtered image back over its input according to a mask.
+///
+/// The mask's first channel is the weight: white keeps the `filtered` pixel,
+/// black keeps the `original`, and grey values blend linearly. Pixels the
+/// mask doesn't cover take the filtered result, matching a reveal-all mask.
+pub fn blend_with_mask(
+    original: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    filtered: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    mask: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    debug!("Blending filtered {}x{} image through {}x{} mask",
+           original.width(), original.height(), mask.width(), mask.height());
+    
+    let mut result = filtered.clone();
+    for (x, y, pixel) in result.enumerate_pixels_mut() {
+        if x >= mask.width() || y >= mask.height() {
+            continue;
+        }
+        let weight = mask.get_pixel(x, y)[0] as f32 / 255.0;
+        if weight >= 1.0 {
+            continue;
+        }
+        let before = original.get_pixel(x, y);
+        for c in 0..4 {
+            let mixed = before[c] as f32 + (pixel[c] as f32 - before[c] as f32) * weight;
+            pixel[c] = mixed.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    result
+}
+
 /// Extract a region from an image
 fn extract_region(
     image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
@@ -128,7 +276,11 @@ fn extract_region(
     region
 }
 
-/// Helper function to apply a filter in parallel using multiple threads
+/// Helper function to apply a filter in parallel using multiple threads.
+///
+/// The image is split into horizontal strips, each padded above and below
+/// by the filter's `support_radius` so neighbourhood filters see the same
+/// pixels they would on the whole image; the padding is dropped on merge.
 pub fn apply_filter_parallel<F>(
     image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
     filter_factory: F,
@@ -137,19 +289,33 @@ pub fn apply_filter_parallel<F>(
 where
     F: Fn() -> Box<dyn Filter + Send + Sync>,
 {
-    info!("Applying filter in parallel using {} threads", num_threads);
+    let halo = filter_factory().support_radius();
+    apply_filter_parallel_with_halo(image, filter_factory, num_threads, halo)
+}
+
+/// `apply_filter_parallel` with an explicit strip overlap of `halo` rows
+pub fn apply_filter_parallel_with_halo<F>(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    filter_factory: F,
+    num_threads: usize,
+    halo: u32,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>>
+where
+    F: Fn() -> Box<dyn Filter + Send + Sync>,
+{
+    info!("Applying filter in parallel using {} threads ({}px halo)", num_threads, halo);
     
-    if num_threads <= 1 {
+    let width = image.width();
+    let height = image.height();
+    if num_threads <= 1 || height < num_threads as u32 {
         // Single-threaded case
-        debug!("Using single-threaded execution due to thread count <= 1");
+        debug!("Using single-threaded execution for {} threads on {} rows", num_threads, height);
         return filter_factory().apply(image);
     }
     
-    let width = image.width();
-    let height = image.height();
     debug!("Processing image of size {}x{}", width, height);
     
-    let mut result = ImageBuffer::new(width, height);
+    let result = ImageBuffer::new(width, height);
     
     // Split the image into horizontal strips
     let strip_height = height / num_threads as u32;
@@ -170,26 +336,30 @@ where
             end_y += remainder;
         }
         
-        debug!("Thread {} processing strip y={}..{}", i, start_y, end_y);
+        // Rows actually handed to the filter, including the overlap
+        let padded_start = start_y.saturating_sub(halo);
+        let padded_end = end_y.saturating_add(halo).min(height);
+        debug!("Thread {} processing strip y={}..{} (padded {}..{})", i, start_y, end_y, padded_start, padded_end);
         
         let image_clone = image.clone();
         let result_clone = Arc::clone(&result_arc);
         let filter = filter_factory();
         
         let handle = thread::spawn(move || {
-            // Extract the strip
-            let strip = extract_region(&image_clone, 0, start_y, width, end_y - start_y);
+            // Extract the padded strip
+            let strip = extract_region(&image_clone, 0, padded_start, width, padded_end - padded_start);
             
             // Apply the filter to the strip
             debug!("Thread {} applying filter to strip", i);
             let filtered_strip = filter.apply(&strip);
             
-            // Put the filtered strip back into the result
+            // Put the filtered rows back into the result, skipping the halo
             debug!("Thread {} merging results", i);
+            let skip = start_y - padded_start;
             let mut result = result_clone.lock().unwrap();
             for y in 0..(end_y - start_y) {
                 for x in 0..width {
-                    let pixel = filtered_strip.get_pixel(x, y);
+                    let pixel = filtered_strip.get_pixel(x, skip + y);
                     result.put_pixel(x, start_y + y, *pixel);
                 }
             }
@@ -327,12 +497,27 @@ impl ImageFilter {
                 image.blur(self.settings.radius)
             },
             FilterType::Sharpen => {
-                // Apply sharpening filter
-                image.clone()
+                // Unsharp mask; strength is the amount, threshold a 0-1 fraction
+                let threshold = (self.settings.threshold.clamp(0.0, 1.0) * 255.0) as u8;
+                let filter = UnsharpMask::new(self.settings.radius, self.settings.strength, threshold);
+                DynamicImage::ImageRgba8(filter.apply(&image.to_rgba8()))
             },
             FilterType::EdgeDetect => {
-                // Apply edge detection
-                image.clone()
+                DynamicImage::ImageRgba8(SobelEdgeDetect::new().apply(&image.to_rgba8()))
+            },
+            FilterType::Emboss => {
+                // Light from the top left; strength is the relief depth
+                let emboss = Emboss::new(135.0, self.settings.strength);
+                DynamicImage::ImageRgba8(emboss.apply(&image.to_rgba8()))
+            },
+            FilterType::Noise => {
+                let noise = AddNoise::new(self.settings.strength.clamp(0.0, 1.0), NoiseDistribution::Gaussian);
+                DynamicImage::ImageRgba8(noise.apply(&image.to_rgba8()))
+            },
+            FilterType::Threshold => {
+                // The threshold setting is a 0-1 fraction of full brightness
+                let level = (self.settings.threshold.clamp(0.0, 1.0) * 255.0).round() as u8;
+                DynamicImage::ImageRgba8(ThresholdFilter::new(level, true).apply(&image.to_rgba8()))
             },
             // ... other filter implementations
             _ => image.clone(),
@@ -471,12 +656,8 @@ impl ImageFilter {
     }
 
     pub fn apply_sharpen(&self, img: &DynamicImage) -> DynamicImage {
-        let kernel = [
-            -1.0, -1.0, -1.0,
-            -1.0,  9.0, -1.0,
-            -1.0, -1.0, -1.0
-        ];
-        image::DynamicImage::ImageRgb8(imageproc::filter::filter3x3(&img.to_rgb8(), &kernel))
+        let filter = SharpenFilter::new(self.settings.strength, self.settings.radius);
+        DynamicImage::ImageRgba8(filter.apply(&img.to_rgba8()))
     }
 
     pub fn apply_noise_reduction(&self, image: &DynamicImage) -> DynamicImage {
@@ -485,110 +666,173 @@ impl ImageFilter {
             return image.clone();
         }
         
-        // Convert to grayscale and apply gaussian blur
+        // Edge-preserving smoothing; the range sigma keeps real detail intact
         let img = image.to_rgba8();
-        let denoised = gaussian_blur_f32(&img, sigma);
+        let denoised = BilateralFilter::new(sigma, 25.0).apply(&img);
         DynamicImage::ImageRgba8(denoised)
     }
 }
 
-fn apply_brightness(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+fn brightness_pixel(pixel: Rgba<u8>, amount: f32) -> Rgba<u8> {
+    let r = (pixel[0] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
+    let g = (pixel[1] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
+    let b = (pixel[2] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
+    Rgba([r, g, b, pixel[3]])
+}
+
+fn contrast_pixel(pixel: Rgba<u8>, amount: f32) -> Rgba<u8> {
+    let r = ((pixel[0] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
+    let g = ((pixel[1] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
+    let b = ((pixel[2] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
+    Rgba([r, g, b, pixel[3]])
+}
+
+fn saturation_pixel(pixel: Rgba<u8>, amount: f32) -> Rgba<u8> {
+    let gray = (pixel[0] as f32 + pixel[1] as f32 + pixel[2] as f32) / 3.0;
+    let r = (gray + (pixel[0] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
+    let g = (gray + (pixel[1] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
+    let b = (gray + (pixel[2] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
+    Rgba([r, g, b, pixel[3]])
+}
+
+/// Color matrix rotating hue by `hue` degrees
+fn hue_matrix(hue: f32) -> [f32; 9] {
+    let angle = hue * std::f32::consts::PI / 180.0;
+    let cos_h = angle.cos();
+    let sin_h = angle.sin();
+    
+    [
+        0.213 + cos_h * 0.787 - sin_h * 0.213,
+        0.213 - cos_h * 0.213 + sin_h * 0.143,
+        0.213 - cos_h * 0.213 - sin_h * 0.787,
+        
+        0.715 - cos_h * 0.715 - sin_h * 0.715,
+        0.715 + cos_h * 0.285 + sin_h * 0.140,
+        0.715 - cos_h * 0.715 + sin_h * 0.715,
+        
+        0.072 - cos_h * 0.072 + sin_h * 0.928,
+        0.072 - cos_h * 0.072 - sin_h * 0.283,
+        0.072 + cos_h * 0.928 + sin_h * 0.072
+    ]
+}
+
+fn hue_pixel(pixel: Rgba<u8>, matrix: &[f32; 9]) -> Rgba<u8> {
+    let r = pixel[0] as f32 / 255.0;
+    let g = pixel[1] as f32 / 255.0;
+    let b = pixel[2] as f32 / 255.0;
+    
+    let new_r = (matrix[0] * r + matrix[1] * g + matrix[2] * b) * 255.0;
+    let new_g = (matrix[3] * r + matrix[4] * g + matrix[5] * b) * 255.0;
+    let new_b = (matrix[6] * r + matrix[7] * g + matrix[8] * b) * 255.0;
+    
+    Rgba([
+        new_r.clamp(0.0, 255.0) as u8,
+        new_g.clamp(0.0, 255.0) as u8,
+        new_b.clamp(0.0, 255.0) as u8,
+        pixel[3]
+    ])
+}
+
+// Serial references for the Rayon versions below
+#[cfg(test)]
+pub(crate) fn apply_brightness(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let (width, height) = image.dimensions();
     let mut output = image.clone();
     
     for y in 0..height {
         for x in 0..width {
-            let pixel = image.get_pixel(x, y);
-            let r = (pixel[0] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
-            let g = (pixel[1] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
-            let b = (pixel[2] as f32 + amount * 255.0).clamp(0.0, 255.0) as u8;
-            output.put_pixel(x, y, Rgba([r, g, b, pixel[3]]));
+            output.put_pixel(x, y, brightness_pixel(*image.get_pixel(x, y), amount));
         }
     }
     
     output
 }
 
-fn apply_contrast(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+#[cfg(test)]
+pub(crate) fn apply_contrast(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let (width, height) = image.dimensions();
     let mut output = image.clone();
     
     for y in 0..height {
         for x in 0..width {
-            let pixel = image.get_pixel(x, y);
-            let r = ((pixel[0] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
-            let g = ((pixel[1] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
-            let b = ((pixel[2] as f32 - 128.0) * amount + 128.0).clamp(0.0, 255.0) as u8;
-            output.put_pixel(x, y, Rgba([r, g, b, pixel[3]]));
+            output.put_pixel(x, y, contrast_pixel(*image.get_pixel(x, y), amount));
         }
     }
     
     output
 }
 
-fn apply_saturation(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+#[cfg(test)]
+pub(crate) fn apply_saturation(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let (width, height) = image.dimensions();
     let mut output = image.clone();
     
     for y in 0..height {
         for x in 0..width {
-            let pixel = image.get_pixel(x, y);
-            let gray = (pixel[0] as f32 + pixel[1] as f32 + pixel[2] as f32) / 3.0;
-            let r = (gray + (pixel[0] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
-            let g = (gray + (pixel[1] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
-            let b = (gray + (pixel[2] as f32 - gray) * amount).clamp(0.0, 255.0) as u8;
-            output.put_pixel(x, y, Rgba([r, g, b, pixel[3]]));
+            output.put_pixel(x, y, saturation_pixel(*image.get_pixel(x, y), amount));
         }
     }
     
     output
 }
 
-fn apply_hue(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, hue: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+#[cfg(test)]
+pub(crate) fn apply_hue(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, hue: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let (width, height) = image.dimensions();
     let mut output = image.clone();
-    
-    let angle = hue * std::f32::consts::PI / 180.0;
-    let cos_h = angle.cos();
-    let sin_h = angle.sin();
+    let matrix = hue_matrix(hue);
     
     for y in 0..height {
         for x in 0..width {
-            let pixel = image.get_pixel(x, y);
-            let r = pixel[0] as f32 / 255.0;
-            let g = pixel[1] as f32 / 255.0;
-            let b = pixel[2] as f32 / 255.0;
-            
-            let matrix = [
-                0.213 + cos_h * 0.787 - sin_h * 0.213,
-                0.213 - cos_h * 0.213 + sin_h * 0.143,
-                0.213 - cos_h * 0.213 - sin_h * 0.787,
-                
-                0.715 - cos_h * 0.715 - sin_h * 0.715,
-                0.715 + cos_h * 0.285 + sin_h * 0.140,
-                0.715 - cos_h * 0.715 + sin_h * 0.715,
-                
-                0.072 - cos_h * 0.072 + sin_h * 0.928,
-                0.072 - cos_h * 0.072 - sin_h * 0.283,
-                0.072 + cos_h * 0.928 + sin_h * 0.072
-            ];
-            
-            let new_r = (matrix[0] * r + matrix[1] * g + matrix[2] * b) * 255.0;
-            let new_g = (matrix[3] * r + matrix[4] * g + matrix[5] * b) * 255.0;
-            let new_b = (matrix[6] * r + matrix[7] * g + matrix[8] * b) * 255.0;
-            
-            output.put_pixel(x, y, Rgba([
-                new_r.clamp(0.0, 255.0) as u8,
-                new_g.clamp(0.0, 255.0) as u8,
-                new_b.clamp(0.0, 255.0) as u8,
-                pixel[3]
-            ]));
+            output.put_pixel(x, y, hue_pixel(*image.get_pixel(x, y), &matrix));
         }
     }
     
     output
 }
 
+/// Run `adjust` on every pixel of `image`, one row per Rayon task
+fn map_pixels_rayon<F>(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, adjust: F) -> ImageBuffer<Rgba<u8>, Vec<u8>>
+where
+    F: Fn(Rgba<u8>) -> Rgba<u8> + Send + Sync,
+{
+    let mut output = image.clone();
+    let row_len = image.width() as usize * 4;
+    if row_len == 0 {
+        return output;
+    }
+    
+    output.par_chunks_mut(row_len).for_each(|row| {
+        for pixel in row.chunks_exact_mut(4) {
+            let adjusted = adjust(Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+            pixel.copy_from_slice(&adjusted.0);
+        }
+    });
+    
+    output
+}
+
+/// Parallel `apply_brightness`; the output is identical to the serial one
+pub fn apply_brightness_rayon(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    map_pixels_rayon(image, |pixel| brightness_pixel(pixel, amount))
+}
+
+/// Parallel `apply_contrast`
+pub fn apply_contrast_rayon(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    map_pixels_rayon(image, |pixel| contrast_pixel(pixel, amount))
+}
+
+/// Parallel `apply_saturation`
+pub fn apply_saturation_rayon(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    map_pixels_rayon(image, |pixel| saturation_pixel(pixel, amount))
+}
+
+/// Parallel `apply_hue`
+pub fn apply_hue_rayon(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, hue: f32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let matrix = hue_matrix(hue);
+    map_pixels_rayon(image, |pixel| hue_pixel(pixel, &matrix))
+}
+
 fn apply_invert(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let (width, height) = image.dimensions();
     let mut output = image.clone();
@@ -626,7 +870,7 @@ impl BrightnessFilter {
 
 impl Filter for BrightnessFilter {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        apply_brightness(image, self.amount)
+        apply_brightness_rayon(image, self.amount)
     }
     
     fn name(&self) -> &str {
@@ -664,7 +908,7 @@ impl ContrastFilter {
 
 impl Filter for ContrastFilter {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        apply_contrast(image, self.amount)
+        apply_contrast_rayon(image, self.amount)
     }
     
     fn name(&self) -> &str {
@@ -702,7 +946,7 @@ impl SaturationFilter {
 
 impl Filter for SaturationFilter {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        apply_saturation(image, self.amount)
+        apply_saturation_rayon(image, self.amount)
     }
     
     fn name(&self) -> &str {
@@ -740,7 +984,7 @@ impl HueFilter {
 
 impl Filter for HueFilter {
     fn apply(&self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        apply_hue(image, self.amount)
+        apply_hue_rayon(image, self.amount)
     }
     
     fn name(&self) -> &str {
@@ -760,6 +1004,7 @@ impl Filter for HueFilter {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct InvertFilter {
     name: String,
     description: String,
@@ -0,0 +1,412 @@
+// Geometric transforms: resampling and rotation of whole images
+
+use image::{GrayImage, ImageBuffer, Luma, Rgba};
+use log::debug;
+
+/// Resampling method used when pixels don't land on the source grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    NearestNeighbor,
+    Bilinear,
+    Bicubic,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Bilinear
+    }
+}
+
+/// Filter used when an image is scaled to a new size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleFilter {
+    /// Blocky, but keeps hard pixel edges for pixel art
+    Nearest,
+    Bilinear,
+    Bicubic,
+    /// Sharpest downscaling with the least aliasing, for photos
+    Lanczos3,
+}
+
+impl ResampleFilter {
+    pub fn to_filter_type(self) -> image::imageops::FilterType {
+        match self {
+            ResampleFilter::Nearest => image::imageops::FilterType::Nearest,
+            ResampleFilter::Bilinear => image::imageops::FilterType::Triangle,
+            ResampleFilter::Bicubic => image::imageops::FilterType::CatmullRom,
+            ResampleFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Scale `image` to `width` x `height`.
+///
+/// The filters widen with the scale factor when shrinking, so every
+/// source pixel contributes and fine detail averages out instead of
+/// aliasing.
+pub fn resample_image(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    width: u32,
+    height: u32,
+    filter: ResampleFilter,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    debug!("Resampling {}x{} image to {}x{} ({:?})", image.width(), image.height(), width, height, filter);
+    image::imageops::resize(image, width.max(1), height.max(1), filter.to_filter_type())
+}
+
+/// Fetch a pixel as premultiplied floats, treating anything outside the
+/// image as fully transparent so edges fade out instead of smearing.
+fn premultiplied(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: i64, y: i64) -> [f32; 4] {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return [0.0; 4];
+    }
+    let p = image.get_pixel(x as u32, y as u32);
+    let a = p[3] as f32 / 255.0;
+    [p[0] as f32 * a, p[1] as f32 * a, p[2] as f32 * a, p[3] as f32]
+}
+
+fn unpremultiply(acc: [f32; 4]) -> Rgba<u8> {
+    let alpha = acc[3].clamp(0.0, 255.0);
+    if alpha < 0.5 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let a = alpha / 255.0;
+    Rgba([
+        (acc[0] / a).round().clamp(0.0, 255.0) as u8,
+        (acc[1] / a).round().clamp(0.0, 255.0) as u8,
+        (acc[2] / a).round().clamp(0.0, 255.0) as u8,
+        alpha.round() as u8,
+    ])
+}
+
+/// Catmull-Rom weight for a tap at distance `t` from the sample point
+fn cubic_weight(t: f32) -> f32 {
+    let t = t.abs();
+    if t < 1.0 {
+        1.5 * t * t * t - 2.5 * t * t + 1.0
+    } else if t < 2.0 {
+        -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0
+    } else {
+        0.0
+    }
+}
+
+/// Sample `image` at a fractional position, where integer coordinates are
+/// pixel centers. Samples outside the image come back transparent.
+pub fn sample_pixel(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: f32, y: f32, interpolation: Interpolation) -> Rgba<u8> {
+    match interpolation {
+        Interpolation::NearestNeighbor => {
+            let p = premultiplied(image, x.round() as i64, y.round() as i64);
+            unpremultiply(p)
+        },
+        Interpolation::Bilinear => {
+            let x0 = x.floor();
+            let y0 = y.floor();
+            let fx = x - x0;
+            let fy = y - y0;
+            let (x0, y0) = (x0 as i64, y0 as i64);
+
+            let p00 = premultiplied(image, x0, y0);
+            let p10 = premultiplied(image, x0 + 1, y0);
+            let p01 = premultiplied(image, x0, y0 + 1);
+            let p11 = premultiplied(image, x0 + 1, y0 + 1);
+
+            let mut acc = [0.0f32; 4];
+            for c in 0..4 {
+                let top = p00[c] * (1.0 - fx) + p10[c] * fx;
+                let bottom = p01[c] * (1.0 - fx) + p11[c] * fx;
+                acc[c] = top * (1.0 - fy) + bottom * fy;
+            }
+            unpremultiply(acc)
+        },
+        Interpolation::Bicubic => {
+            let x0 = x.floor();
+            let y0 = y.floor();
+            let fx = x - x0;
+            let fy = y - y0;
+            let (x0, y0) = (x0 as i64, y0 as i64);
+
+            let mut acc = [0.0f32; 4];
+            for j in -1..=2i64 {
+                let wy = cubic_weight(j as f32 - fy);
+                for i in -1..=2i64 {
+                    let w = wy * cubic_weight(i as f32 - fx);
+                    let p = premultiplied(image, x0 + i, y0 + j);
+                    for c in 0..4 {
+                        acc[c] += p[c] * w;
+                    }
+                }
+            }
+            // Catmull-Rom overshoots; keep color within the (premultiplied) alpha
+            acc[3] = acc[3].clamp(0.0, 255.0);
+            for c in 0..3 {
+                acc[c] = acc[c].clamp(0.0, acc[3]);
+            }
+            unpremultiply(acc)
+        },
+    }
+}
+
+/// Rotate an image by `degrees` (clockwise) around its center.
+///
+/// With `expand` the output grows to the rotated bounding box and the
+/// uncovered corners are transparent; otherwise the output keeps the
+/// original size and whatever rotates out of frame is clipped.
+pub fn rotate_image(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    degrees: f32,
+    interpolation: Interpolation,
+    expand: bool,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let radians = degrees.to_radians();
+    let (sin, cos) = radians.sin_cos();
+
+    let (out_width, out_height) = if expand {
+        // Shave off float noise so e.g. 90° doesn't gain an extra pixel
+        let w = (width as f32 * cos.abs() + height as f32 * sin.abs() - 1e-3).ceil().max(1.0);
+        let h = (width as f32 * sin.abs() + height as f32 * cos.abs() - 1e-3).ceil().max(1.0);
+        (w as u32, h as u32)
+    } else {
+        (width, height)
+    };
+
+    debug!("Rotating {}x{} image by {}° into {}x{} ({:?})",
+           width, height, degrees, out_width, out_height, interpolation);
+
+    let src_cx = width as f32 / 2.0;
+    let src_cy = height as f32 / 2.0;
+    let dst_cx = out_width as f32 / 2.0;
+    let dst_cy = out_height as f32 / 2.0;
+
+    ImageBuffer::from_fn(out_width, out_height, |x, y| {
+        // Inverse-map the output pixel center back into the source
+        let dx = x as f32 + 0.5 - dst_cx;
+        let dy = y as f32 + 0.5 - dst_cy;
+        let src_x = dx * cos + dy * sin + src_cx - 0.5;
+        let src_y = -dx * sin + dy * cos + src_cy - 0.5;
+        sample_pixel(image, src_x, src_y, interpolation)
+    })
+}
+
+/// A projective (homography) mapping between two planes, stored as a
+/// row-major 3x3 matrix with the bottom-right entry normalized to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerspectiveTransform {
+    pub matrix: [[f64; 3]; 3],
+}
+
+impl PerspectiveTransform {
+    pub fn identity() -> Self {
+        Self {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// Build the transform that maps each point of `from` onto the matching
+    /// point of `to`. Returns None when three of the points are collinear.
+    pub fn from_quads(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<Self> {
+        // Two equations per correspondence for the eight unknowns h0..h7:
+        //   u = (h0 x + h1 y + h2) / (h6 x + h7 y + 1)
+        //   v = (h3 x + h4 y + h5) / (h6 x + h7 y + 1)
+        let mut system = [[0.0f64; 9]; 8];
+        for (i, (&(x, y), &(u, v))) in from.iter().zip(to.iter()).enumerate() {
+            system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+
+        // Gaussian elimination with partial pivoting
+        for col in 0..8 {
+            let pivot = (col..8)
+                .max_by(|&a, &b| system[a][col].abs().total_cmp(&system[b][col].abs()))
+                .unwrap();
+            if system[pivot][col].abs() < 1e-10 {
+                return None;
+            }
+            system.swap(col, pivot);
+
+            for row in 0..8 {
+                if row != col {
+                    let factor = system[row][col] / system[col][col];
+                    for k in col..9 {
+                        system[row][k] -= factor * system[col][k];
+                    }
+                }
+            }
+        }
+
+        let h: Vec<f64> = (0..8).map(|i| system[i][8] / system[i][i]).collect();
+        Some(Self {
+            matrix: [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]],
+        })
+    }
+
+    /// Map a point through the transform
+    pub fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = &self.matrix;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        (
+            (m[0][0] * x + m[0][1] * y + m[0][2]) / w,
+            (m[1][0] * x + m[1][1] * y + m[1][2]) / w,
+        )
+    }
+
+    /// The inverse mapping, or None if the transform is degenerate
+    pub fn inverse(&self) -> Option<Self> {
+        let m = &self.matrix;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+        let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let adjugate = [
+            [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+            [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+            [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+        ];
+        let scale = adjugate[2][2] / det;
+        if scale.abs() < 1e-12 {
+            return None;
+        }
+
+        let mut matrix = [[0.0; 3]; 3];
+        for r in 0..3 {
+            for c in 0..3 {
+                matrix[r][c] = adjugate[r][c] / det / scale;
+            }
+        }
+        Some(Self { matrix })
+    }
+}
+
+/// Warp the quadrilateral `corners` of `image` (top-left, top-right,
+/// bottom-right, bottom-left) onto an upright `width` x `height` rectangle.
+pub fn warp_perspective(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    corners: [(f64, f64); 4],
+    width: u32,
+    height: u32,
+    interpolation: Interpolation,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    if width == 0 || height == 0 {
+        return Err("Output size must be at least 1x1".to_string());
+    }
+
+    // Corners are pixel positions; map output pixel centers onto them
+    let target = [
+        (0.0, 0.0),
+        (width as f64, 0.0),
+        (width as f64, height as f64),
+        (0.0, height as f64),
+    ];
+    let to_source = PerspectiveTransform::from_quads(target, corners)
+        .ok_or_else(|| "Perspective corners must form a quadrilateral".to_string())?;
+
+    debug!("Warping quad {:?} into {}x{} ({:?})", corners, width, height, interpolation);
+
+    Ok(ImageBuffer::from_fn(width, height, |x, y| {
+        let (sx, sy) = to_source.map(x as f64 + 0.5, y as f64 + 0.5);
+        sample_pixel(image, (sx - 0.5) as f32, (sy - 0.5) as f32, interpolation)
+    }))
+}
+
+/// A layer mask as an opaque gray RGBA image, so it can go through the same
+/// transforms as the layer's pixels
+pub fn mask_to_rgba(mask: &GrayImage) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(mask.width(), mask.height(), |x, y| {
+        let v = mask.get_pixel(x, y)[0];
+        Rgba([v, v, v, 255])
+    })
+}
+
+/// Turn a transformed `mask_to_rgba` image back into a mask. Areas the
+/// transform left transparent, outside the old mask, read as hidden.
+pub fn mask_from_rgba(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y);
+        Luma([(p[0] as u32 * p[3] as u32 / 255) as u8])
+    })
+}
+
+/// Find the angle of the dominant near-horizontal line in `image` with a
+/// Hough transform over its Sobel edges.
+///
+/// Only lines within `max_angle` degrees of horizontal are considered. The
+/// result is in degrees, positive when the line falls towards the right
+/// (a clockwise tilt on screen), so rotating by its negative levels the
+/// line. Returns None when no line stands out.
+pub fn detect_dominant_angle(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, max_angle: f64) -> Option<f64> {
+    const STEPS_PER_DEGREE: f64 = 10.0;
+    const MAX_SIDE: u32 = 512;
+
+    // Scaling is uniform so angles survive; it keeps the vote count sane
+    let (width, height) = image.dimensions();
+    let gray = image::imageops::grayscale(image);
+    let gray = if width.max(height) > MAX_SIDE {
+        let scale = MAX_SIDE as f64 / width.max(height) as f64;
+        let w = ((width as f64 * scale).round() as u32).max(1);
+        let h = ((height as f64 * scale).round() as u32).max(1);
+        image::imageops::resize(&gray, w, h, image::imageops::FilterType::Triangle)
+    } else {
+        gray
+    };
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let gx = imageproc::gradients::horizontal_sobel(&gray);
+    let gy = imageproc::gradients::vertical_sobel(&gray);
+    let magnitude = |x: u32, y: u32| {
+        let (dx, dy) = (gx.get_pixel(x, y)[0] as f64, gy.get_pixel(x, y)[0] as f64);
+        (dx * dx + dy * dy).sqrt()
+    };
+    let strongest = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| magnitude(x, y))
+        .fold(0.0, f64::max);
+    if strongest <= 0.0 {
+        return None;
+    }
+
+    // Edge pixels belonging to near-horizontal lines have mostly vertical gradients
+    let threshold = strongest * 0.25;
+    let edges: Vec<(f64, f64)> = (1..height - 1)
+        .flat_map(|y| (1..width - 1).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            let (dx, dy) = (gx.get_pixel(x, y)[0] as f64, gy.get_pixel(x, y)[0] as f64);
+            magnitude(x, y) >= threshold && dy.abs() > dx.abs()
+        })
+        .map(|(x, y)| (x as f64, y as f64))
+        .collect();
+
+    let steps = (max_angle.abs() * STEPS_PER_DEGREE).round() as i64;
+    let diagonal = ((width * width + height * height) as f64).sqrt().ceil() as i64;
+    let bins = (2 * diagonal + 1) as usize;
+    let mut best = (0usize, 0.0f64);
+
+    for step in -steps..=steps {
+        let angle = step as f64 / STEPS_PER_DEGREE;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut accumulator = vec![0usize; bins];
+        for &(x, y) in &edges {
+            // Distance along the line's normal; constant for every point on it
+            let rho = (y * cos - x * sin).round() as i64 + diagonal;
+            accumulator[rho as usize] += 1;
+        }
+        let votes = accumulator.into_iter().max().unwrap_or(0);
+        if votes > best.0 {
+            best = (votes, angle);
+        }
+    }
+
+    // A real line should collect a good share of the image's width in votes
+    if best.0 < (width as usize / 4).max(8) {
+        debug!("No dominant line found ({} votes)", best.0);
+        return None;
+    }
+    debug!("Dominant line at {:.1}° with {} votes", best.1, best.0);
+    Some(best.1)
+}
@@ -77,7 +77,7 @@ fn init_vulkan() -> Result<(), String> {
 fn init_wgpu() -> Result<(), String> {
     #[cfg(feature = "gpu-wgpu")]
     {
-        // WebGPU initialization would go here
+        filters::gpu::init()?;
     }
     
     Ok(())
@@ -14,6 +14,7 @@ mod filters;
 mod tools;
 mod ui;
 mod vector;
+mod raw;
 
 #[cfg(feature = "gpu-cuda")]
 fn init_gpu() {
@@ -34,6 +35,10 @@ fn main() {
     
     // Initialize GPU if available
     init_gpu();
+    #[cfg(feature = "gpu-wgpu")]
+    if let Err(e) = filters::gpu::init() {
+        warn!("GPU filters unavailable, using the CPU: {}", e);
+    }
     
     // Create GTK application
     let app = Application::builder()
@@ -159,4 +164,3313 @@ mod tests {
         assert!((screen_point.x - screen_point2.x).abs() < 0.001);
         assert!((screen_point.y - screen_point2.y).abs() < 0.001);
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_dehaze_increases_contrast() {
+        use crate::filters::Dehaze;
+        
+        // Simulate haze: a checker pattern squeezed into a bright, narrow band
+        let width = 64;
+        let height = 64;
+        let mut image = ImageBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if (x / 8 + y / 8) % 2 == 0 { 190u8 } else { 225u8 };
+                image.put_pixel(x, y, Rgba([v, v.saturating_sub(10), v.saturating_sub(20), 255]));
+            }
+        }
+        
+        let brightness = |img: &ImageBuffer<Rgba<u8>, Vec<u8>>| -> (f32, f32) {
+            let values: Vec<f32> = img.pixels()
+                .map(|p| (p[0] as f32 + p[1] as f32 + p[2] as f32) / 3.0)
+                .collect();
+            let min = values.iter().cloned().fold(f32::MAX, f32::min);
+            let max = values.iter().cloned().fold(f32::MIN, f32::max);
+            (min, max)
+        };
+        
+        let (in_min, in_max) = brightness(&image);
+        let result = Dehaze::new(0.95).apply(&image);
+        let (out_min, out_max) = brightness(&result);
+        
+        assert_eq!(result.dimensions(), image.dimensions());
+        assert!(out_max - out_min > in_max - in_min);
+        assert!(out_min < in_min);
+    }
+    
+    #[test]
+    fn test_find_layers_by_name() {
+        use crate::core::LayerManager;
+        
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::new(10, 10, "Background".to_string()));
+        manager.add_layer(Layer::new(10, 10, "Sky Highlights".to_string()));
+        let inner = Layer::new_group(10, 10, "Clouds".to_string(), vec![
+            Layer::new(10, 10, "Text".to_string()),
+            Layer::new(10, 10, "sky shadows".to_string()),
+        ]);
+        manager.add_layer(Layer::new_group(10, 10, "Weather".to_string(), vec![
+            Layer::new(10, 10, "Sky Tint".to_string()),
+            inner,
+        ]));
+        
+        assert_eq!(manager.find_by_name("sky", false), vec![vec![1], vec![2, 0], vec![2, 1, 1]]);
+        assert_eq!(manager.find_by_name("sky", true), vec![vec![2, 1, 1]]);
+        assert!(manager.find_by_name("missing", false).is_empty());
+        
+        // Paths lead back to the layers, two groups deep included
+        assert_eq!(manager.get_layer_by_path(&[2, 1, 1]).unwrap().name, "sky shadows");
+        assert_eq!(manager.get_layer_by_path(&[2, 1]).unwrap().name, "Clouds");
+        assert!(manager.get_layer_by_path(&[1, 0]).is_none());
+        assert!(manager.get_layer_by_path(&[]).is_none());
+        
+        // Groups composite their children like the top-level stack does
+        let red = image::ImageBuffer::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let mut nested = LayerManager::new();
+        nested.add_layer(Layer::new_group(10, 10, "Outer".to_string(), vec![
+            Layer::new_group(10, 10, "Inner".to_string(), vec![Layer::from_image(red, "Red".to_string())]),
+        ]));
+        nested.get_layer_mut(0).unwrap().opacity = 0.5;
+        let pixel = *nested.flatten().get_pixel(4, 4);
+        assert_eq!((pixel[0], pixel[1], pixel[2]), (255, 0, 0));
+        assert!((127..=128).contains(&pixel[3]));
+    }
+    
+    #[test]
+    fn test_shadows_highlights_lifts_shadows_only() {
+        use crate::core::LayerManager;
+        use crate::filters::ShadowsHighlights;
+        
+        // Left half dark, right half bright
+        let mut image = ImageBuffer::new(80, 20);
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            let v = if x < 40 { 30u8 } else { 230u8 };
+            *pixel = Rgba([v, v, v, 255]);
+        }
+        
+        let result = ShadowsHighlights::new(0.6, 0.0, 3.0).apply(&image);
+        
+        let dark = result.get_pixel(5, 10)[0];
+        let bright = result.get_pixel(75, 10)[0];
+        assert!(dark > 60, "shadows should be lifted, got {}", dark);
+        assert!((bright as i32 - 230).abs() <= 5, "highlights should barely move, got {}", bright);
+        
+        // As an adjustment layer it corrects the composite below it
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image, "Photo".to_string()));
+        manager.add_layer(Layer::new_adjustment(80, 20, "Shadows".to_string(), Box::new(ShadowsHighlights::new(0.6, 0.0, 3.0))));
+        assert_eq!(manager.flatten(), result);
+    }
+    
+    #[test]
+    fn test_rotate_image_expand() {
+        use crate::filters::{rotate_image, Interpolation};
+        
+        let image = ImageBuffer::from_pixel(10, 10, Rgba([200u8, 50, 50, 255]));
+        let rotated = rotate_image(&image, 45.0, Interpolation::Bilinear, true);
+        
+        assert!(rotated.width() > 10 && rotated.height() > 10);
+        
+        let (w, h) = rotated.dimensions();
+        for &(x, y) in &[(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)] {
+            assert_eq!(rotated.get_pixel(x, y)[3], 0, "corner ({}, {}) should be transparent", x, y);
+        }
+        assert_eq!(rotated.get_pixel(w / 2, h / 2)[3], 255);
+        assert_eq!(rotated.get_pixel(w / 2, 1)[3], 255, "top tip of the diamond should be opaque");
+    }
+    
+    #[test]
+    fn test_gradient_along_path_midpoint() {
+        use crate::vector::{VectorPath, Color, Point as VectorPoint};
+        
+        // An L-shaped path: its arc-length midpoint is the corner
+        let mut path = VectorPath::new();
+        path.move_to(0.0, 0.0).line_to(100.0, 0.0).line_to(100.0, 100.0);
+        
+        let stops = vec![
+            (0.0, Color::new(1.0, 0.0, 0.0, 1.0)),
+            (0.5, Color::new(0.0, 1.0, 0.0, 1.0)),
+            (1.0, Color::new(0.0, 0.0, 1.0, 1.0)),
+        ];
+        let gradient = path.gradient_along(&stops);
+        
+        let midpoint = gradient.point_at(0.5).unwrap();
+        assert!((midpoint.x - 100.0).abs() < 1e-6 && midpoint.y.abs() < 1e-6);
+        
+        let color = gradient.color_near(&VectorPoint::new(100.0, 0.0));
+        assert!((color.g - 1.0).abs() < 1e-6);
+        assert!(color.r.abs() < 1e-6 && color.b.abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_refine_edges_soft_ramp() {
+        use crate::core::Selection;
+        
+        // A bright object whose left edge fades in over ten pixels
+        let image = ImageBuffer::from_fn(60, 20, |x, _| {
+            let v = if x < 20 { 0.0 } else if x >= 30 { 255.0 } else { (x - 20) as f32 * 25.5 };
+            Rgba([v as u8, v as u8, v as u8, 255])
+        });
+        
+        let mut selection = Selection::rectangle(25.0, 0.0, 35, 20, 60, 20);
+        selection.refine_edges(&image, 6, 0.5);
+        
+        let alpha = |x: u32| selection.mask.get_pixel(x, 10)[0];
+        for x in 22..=27 {
+            assert!(alpha(x) > 25 && alpha(x) < 230, "alpha at {} should be partial, got {}", x, alpha(x));
+        }
+        for x in 18..30 {
+            assert!(alpha(x) <= alpha(x + 1), "alpha should ramp up across the edge");
+        }
+    }
+    
+    #[test]
+    fn test_merge_visible_stamps_composite() {
+        use crate::core::LayerManager;
+        
+        let mut manager = LayerManager::new();
+        let mut bottom = Layer::new(8, 8, "Bottom".to_string());
+        bottom.image = ImageBuffer::from_pixel(8, 8, Rgba([200, 20, 20, 255]));
+        let mut middle = Layer::new(8, 8, "Middle".to_string());
+        middle.image = ImageBuffer::from_pixel(8, 8, Rgba([20, 200, 20, 128]));
+        let mut top = Layer::new(8, 8, "Top".to_string());
+        top.image = ImageBuffer::from_pixel(4, 4, Rgba([20, 20, 200, 255]));
+        top.opacity = 0.5;
+        manager.add_layer(bottom);
+        manager.add_layer(middle);
+        manager.add_layer(top);
+        
+        let composite = manager.flatten();
+        let index = manager.merge_visible().unwrap();
+        
+        assert_eq!(index, 3);
+        assert_eq!(manager.layer_count(), 4);
+        assert_eq!(manager.get_layer(index).unwrap().image, composite);
+        
+        // Hide the stamp, then do a true merge of the three originals
+        manager.get_layer_mut(index).unwrap().visible = false;
+        let index = manager.merge_visible_with(false).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(manager.layer_count(), 2);
+        assert_eq!(manager.get_layer(index).unwrap().image, composite);
+    }
+    
+    #[test]
+    fn test_shift_drag_adds_to_selection() {
+        use crate::tools::{SelectionTool, Modifiers};
+        
+        let mut tool = SelectionTool::new();
+        tool.set_active(true);
+        tool.set_canvas_size(100, 100);
+        
+        tool.mouse_down(10.0, 10.0, 1, Modifiers::none());
+        tool.mouse_move(30.0, 30.0);
+        tool.mouse_up(30.0, 30.0, 1);
+        
+        // As GTK reports it while Shift is held
+        let shift = Modifiers::from_state(gtk4::gdk::ModifierType::SHIFT_MASK);
+        assert_eq!(shift, Modifiers { shift: true, ..Modifiers::none() });
+        tool.mouse_down(50.0, 50.0, 1, shift);
+        tool.mouse_move(70.0, 70.0);
+        tool.mouse_up(70.0, 70.0, 1);
+        
+        let mask = &tool.get_selection().unwrap().mask;
+        assert_eq!(mask.get_pixel(20, 20)[0], 255);
+        assert_eq!(mask.get_pixel(60, 60)[0], 255);
+        assert_eq!(mask.get_pixel(40, 40)[0], 0);
+    }
+    
+    #[test]
+    fn test_text_fit_to_box() {
+        use crate::vector::TextShape;
+        
+        let mut text = TextShape::default();
+        text.set_text("The quick brown fox jumps over the lazy dog again and again".to_string());
+        let default_size = text.style.font_size;
+        
+        text.fit_to_box(60.0, 30.0);
+        
+        assert!(text.style.font_size < default_size);
+        assert!(text.style.font_size >= TextShape::MIN_FIT_FONT_SIZE);
+        
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+        let context = cairo::Context::new(&surface).unwrap();
+        let (width, height) = text.measure_wrapped(&context, text.style.font_size, Some(60.0));
+        assert!(width <= 60.0 && height <= 30.0);
+    }
+    
+    #[test]
+    fn test_average_blur_is_gamma_correct() {
+        use crate::filters::AverageBlur;
+        
+        let checkerboard = ImageBuffer::from_fn(16, 16, |x, y| {
+            let v = if (x + y) % 2 == 0 { 0u8 } else { 255u8 };
+            Rgba([v, v, v, 255])
+        });
+        
+        // Odd windows hold one more black than white; a wide window keeps that small
+        let result = AverageBlur::new(5).apply(&checkerboard);
+        let value = result.get_pixel(8, 8)[0];
+        assert!((value as i32 - 188).abs() <= 6, "expected ~188, got {}", value);
+        assert_eq!(result.get_pixel(8, 8)[3], 255);
+    }
+    
+    #[test]
+    fn test_rotate_layer_undo_restores_pixels() {
+        let mut document = Document::new(6, 4);
+        let original = ImageBuffer::from_fn(6, 4, |x, y| Rgba([(x * 40) as u8, (y * 60) as u8, 7, 255]));
+        document.layer_manager.get_layer_mut(0).unwrap().image = original.clone();
+        
+        document.rotate_layer(0, 90.0).unwrap();
+        let rotated = document.layer_manager.get_layer(0).unwrap();
+        assert_eq!(rotated.image.dimensions(), (4, 6));
+        assert_eq!((rotated.width, rotated.height), (4, 6));
+        
+        assert!(document.undo());
+        let restored = document.layer_manager.get_layer(0).unwrap();
+        assert_eq!(restored.image, original);
+        assert_eq!((restored.width, restored.height), (6, 4));
+        assert_eq!((restored.x_offset, restored.y_offset), (0, 0));
+        
+        assert!(document.redo());
+        assert_eq!(document.layer_manager.get_layer(0).unwrap().image.dimensions(), (4, 6));
+    }
+    
+    #[test]
+    fn test_scale_layer_undo() {
+        let mut document = Document::new(10, 10);
+        let before = document.layer_manager.get_layer(0).unwrap().clone();
+        
+        document.scale_layer(0, 2.0, 0.5).unwrap();
+        assert_eq!(document.layer_manager.get_layer(0).unwrap().image.dimensions(), (20, 5));
+        
+        assert!(document.undo());
+        assert_eq!(document.layer_manager.get_layer(0).unwrap(), &before);
+        assert!(document.scale_layer(0, 0.0, 1.0).is_err());
+    }
+    
+    #[test]
+    fn test_selection_grow_by_color() {
+        use crate::core::Selection;
+        
+        // A slightly noisy red region on the left, blue on the right
+        let image = ImageBuffer::from_fn(40, 20, |x, y| {
+            if x < 25 {
+                Rgba([200 + ((x + y) % 3) as u8 * 4, 30, 30, 255])
+            } else {
+                Rgba([30, 30, 200, 255])
+            }
+        });
+        
+        let mut selection = Selection::rectangle(5.0, 5.0, 5, 5, 40, 20);
+        selection.grow_by_color(&image, 16);
+        
+        for y in 0..20 {
+            for x in 0..40 {
+                let expected = if x < 25 { 255 } else { 0 };
+                assert_eq!(selection.mask.get_pixel(x, y)[0], expected, "pixel ({}, {})", x, y);
+            }
+        }
+        assert_eq!(selection.width, 25);
+        assert_eq!(selection.height, 20);
+    }
+    
+    #[test]
+    fn test_point_rect_conversions_round_trip() {
+        use crate::core::selection::Rect as CoreRect;
+        use crate::vector::{Point as VectorPoint, Rect as VectorRect};
+        
+        let core_point = Point::new(12.5, -3.25);
+        let vector_point: VectorPoint = core_point.into();
+        assert_eq!(vector_point, VectorPoint::new(12.5, -3.25));
+        assert_eq!(Point::from(vector_point), core_point);
+        
+        let core_rect = CoreRect { x: 4.0, y: 8.0, width: 100.0, height: 50.5 };
+        let vector_rect = VectorRect::from(core_rect);
+        assert_eq!(vector_rect, VectorRect::new(4.0, 8.0, 100.0, 50.5));
+        let back: CoreRect = vector_rect.into();
+        assert_eq!(back, core_rect);
+    }
+    
+    #[test]
+    fn test_rect_serde_round_trip() {
+        use crate::vector::Rect as VectorRect;
+        
+        let rect = VectorRect::new(1.0, 2.0, 30.0, 40.0);
+        let json = serde_json::to_string(&rect).unwrap();
+        assert_eq!(json, r#"{"x":1.0,"y":2.0,"width":30.0,"height":40.0}"#);
+        let parsed: VectorRect = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, rect);
+        
+        // The core rect shares the same field layout, so the JSON is interchangeable
+        let core: crate::core::selection::Rect = serde_json::from_str(&json).unwrap();
+        assert_eq!(VectorRect::from(core), rect);
+    }
+    
+    #[test]
+    fn test_lut_filter_from_cube() {
+        use crate::filters::LutFilter;
+        use std::io::Write;
+        
+        let image = ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16) as u8, (y * 16) as u8, ((x + y) * 8) as u8, 255])
+        });
+        
+        // Writes a 2x2x2 cube whose entries come from `f(r, g, b)`, red fastest
+        let write_cube = |f: &dyn Fn(f32, f32, f32) -> [f32; 3]| {
+            let mut file = tempfile::Builder::new().suffix(".cube").tempfile().unwrap();
+            writeln!(file, "# test LUT\nTITLE \"Test\"\nLUT_3D_SIZE 2").unwrap();
+            for b in 0..2 {
+                for g in 0..2 {
+                    for r in 0..2 {
+                        let v = f(r as f32, g as f32, b as f32);
+                        writeln!(file, "{} {} {}", v[0], v[1], v[2]).unwrap();
+                    }
+                }
+            }
+            file
+        };
+        
+        let identity = write_cube(&|r, g, b| [r, g, b]);
+        let lut = LutFilter::from_cube(identity.path()).unwrap();
+        assert_eq!(lut.name(), "Test");
+        assert_eq!(lut.apply(&image), image);
+        
+        let swap = write_cube(&|r, g, b| [b, g, r]);
+        let swapped = LutFilter::from_cube(swap.path()).unwrap().apply(&image);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let out = swapped.get_pixel(x, y);
+            assert_eq!([out[0], out[1], out[2], out[3]], [pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+        
+        // Truncated table
+        assert!(LutFilter::parse_cube("LUT_3D_SIZE 2\n0 0 0\n1 0 0\n").is_err());
+        assert!(LutFilter::parse_cube("0 0 0\n").is_err());
+    }
+    
+    #[test]
+    fn test_gaussian_apply_region_matches_crop() {
+        use crate::core::Rect;
+        
+        let image = ImageBuffer::from_fn(80, 60, |x, y| {
+            Rgba([((x * 37 + y * 11) % 256) as u8, ((x * y) % 256) as u8, (y * 4) as u8, 255])
+        });
+        let blur = GaussianBlur::new(2.5);
+        let full = blur.apply(&image);
+        
+        // One region in the interior and one touching the image edge
+        for region in [Rect { x: 20.0, y: 15.0, width: 25.0, height: 18.0 },
+                       Rect { x: 60.0, y: 0.0, width: 30.0, height: 10.0 }] {
+            let partial = blur.apply_region(&image, region);
+            let clipped_width = (region.width as u32).min(80 - region.x as u32);
+            assert_eq!(partial.dimensions(), (clipped_width, region.height as u32));
+            let expected = image::imageops::crop_imm(&full, region.x as u32, region.y as u32,
+                                                     partial.width(), partial.height()).to_image();
+            assert_eq!(partial, expected);
+        }
+    }
+    
+    #[test]
+    fn test_history_thumbnails_evict_oldest() {
+        let mut document = Document::new(200, 100);
+        document.history.set_thumbnail_size(32);
+        // A 32x16 RGBA thumbnail is 2048 bytes; leave room for exactly two
+        document.history.set_thumbnail_budget(2 * 32 * 16 * 4);
+        
+        for _ in 0..3 {
+            document.rotate_layer(0, 180.0).unwrap();
+        }
+        
+        let states = document.history.undo_states();
+        assert_eq!(states.len(), 3);
+        assert!(states[0].thumbnail().is_none());
+        for state in &states[1..] {
+            assert_eq!(state.thumbnail().unwrap().dimensions(), (32, 16));
+        }
+        assert_eq!(document.history.thumbnail_memory(), 2 * 32 * 16 * 4);
+    }
+    
+    #[test]
+    fn test_brush_shift_click_draws_line() {
+        use crate::tools::{BrushTool, Modifiers, ToolImpl, ToolManager, ToolType};
+        use gtk4::gdk::ModifierType;
+        
+        let white = ImageBuffer::from_pixel(60, 40, Rgba([255, 255, 255, 255]));
+        let mut canvas = Canvas::from_image(white);
+        let mut brush = BrushTool::new();
+        brush.size = 2.0;
+        brush.hardness = 1.0;
+        
+        // First click stamps a dot, the shift-click joins it with a line
+        brush.on_mouse_down(&mut canvas, 10.0, 10.0);
+        brush.on_mouse_up(&mut canvas, 10.0, 10.0);
+        brush.set_constrain(true);
+        brush.on_mouse_down(&mut canvas, 45.0, 30.0);
+        brush.on_mouse_up(&mut canvas, 45.0, 30.0);
+        
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        for i in 0..=100 {
+            let t = i as f64 / 100.0;
+            let x = (10.0 + 35.0 * t) as u32;
+            let y = (10.0 + 20.0 * t) as u32;
+            assert_eq!(image.get_pixel(x, y)[0], 0, "gap in line at ({}, {})", x, y);
+        }
+        // Far from the line stays untouched
+        assert_eq!(image.get_pixel(45, 5)[0], 255);
+        
+        // Constrained drags snap to 45° increments
+        let snapped = BrushTool::constrain_to_45(crate::vector::Point::new(0.0, 0.0), crate::vector::Point::new(10.0, 1.0));
+        assert!((snapped.x - 10.0).abs() < 1e-9 && snapped.y.abs() < 1e-9);
+        
+        // The tool manager turns constraining on while GTK reports Shift held
+        let mut tools = ToolManager::new();
+        tools.set_active_tool(ToolType::Brush);
+        tools.set_modifiers(Modifiers::from_state(ModifierType::SHIFT_MASK));
+        tools.mouse_down(5.0, 5.0, 1, &mut canvas);
+        assert!(tools.brush_tool.constrain);
+        tools.mouse_up(5.0, 5.0, 1, &mut canvas);
+        tools.set_modifiers(Modifiers::from_state(ModifierType::empty()));
+        tools.mouse_down(5.0, 5.0, 1, &mut canvas);
+        assert!(!tools.brush_tool.constrain);
+    }
+    
+    #[test]
+    fn test_count_unique_colors() {
+        use crate::core::UniqueColorResult;
+        
+        let palette = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 255, 0, 255]),
+            Rgba([0, 0, 255, 255]),
+            Rgba([20, 20, 20, 255]),
+        ];
+        let mut document = Document::new(16, 16);
+        document.layer_manager.get_layer_mut(0).unwrap().image =
+            ImageBuffer::from_fn(16, 16, |x, y| palette[((x / 4 + y / 4) % 4) as usize]);
+        assert_eq!(document.count_unique_colors(256), UniqueColorResult::Exact(4));
+        
+        let mut gradient = Document::new(256, 256);
+        gradient.layer_manager.get_layer_mut(0).unwrap().image =
+            ImageBuffer::from_fn(256, 256, |x, y| Rgba([x as u8, y as u8, 128, 255]));
+        assert_eq!(gradient.count_unique_colors(256), UniqueColorResult::OverMax);
+    }
+    
+    #[test]
+    fn test_nearest_display_interpolation_at_zoom() {
+        use crate::core::canvas::InterpolationMode;
+        
+        // Two texels, black then white
+        let image = ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        });
+        let mut canvas = Canvas::from_image(image);
+        canvas.set_zoom(8.0);
+        assert_eq!(canvas.display_filter(), cairo::Filter::Good);
+        
+        // Renders at 8x and returns the red value just left of the texel boundary
+        let sample_near_edge = |canvas: &Canvas| -> u8 {
+            let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 16, 8).unwrap();
+            {
+                let context = cairo::Context::new(&surface).unwrap();
+                canvas.render_to_cairo_context(&context);
+            }
+            surface.flush();
+            let stride = surface.stride() as usize;
+            let data = surface.data().unwrap();
+            // ARGB32 is stored as native-endian u32, so red is byte 2 on little endian
+            data[4 * stride + 6 * 4 + 2]
+        };
+        
+        assert!(sample_near_edge(&canvas) > 0, "smooth scaling should blend across the edge");
+        
+        canvas.display_interpolation = InterpolationMode::Nearest;
+        assert_eq!(canvas.display_filter(), cairo::Filter::Nearest);
+        assert_eq!(sample_near_edge(&canvas), 0);
+        
+        // Below the threshold nearest is not used even when requested
+        canvas.set_zoom(1.0);
+        assert_eq!(canvas.display_filter(), cairo::Filter::Good);
+    }
+    
+    #[test]
+    fn test_expand_canvas_keeps_content_in_place() {
+        use crate::core::DocumentBackground;
+        
+        let mut document = Document::new(100, 100);
+        {
+            let layer = document.layer_manager.get_layer_mut(0).unwrap();
+            layer.image = ImageBuffer::from_pixel(100, 100, Rgba([0, 0, 255, 255]));
+            layer.image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        }
+        
+        document.expand_canvas(10, 10, 10, 10, DocumentBackground::White);
+        assert_eq!((document.width, document.height), (120, 120));
+        
+        let flattened = document.layer_manager.flatten();
+        assert_eq!(flattened.dimensions(), (120, 120));
+        assert_eq!(*flattened.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+        assert_eq!(*flattened.get_pixel(109, 109), Rgba([0, 0, 255, 255]));
+        assert_eq!(*flattened.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*flattened.get_pixel(119, 60), Rgba([255, 255, 255, 255]));
+    }
+    
+    #[test]
+    fn test_export_slices_along_guides() {
+        use crate::core::{DocumentFormat, GuideOrientation};
+        
+        let mut document = Document::new(100, 80);
+        document.add_guide(GuideOrientation::Vertical, 30);
+        document.add_guide(GuideOrientation::Horizontal, 50);
+        
+        let dir = tempfile::tempdir().unwrap();
+        let paths = document.export_slices(dir.path(), DocumentFormat::PNG).unwrap();
+        assert_eq!(paths.len(), 4);
+        
+        let expected = [("slice_0_0.png", (30, 50)), ("slice_0_1.png", (70, 50)),
+                        ("slice_1_0.png", (30, 30)), ("slice_1_1.png", (70, 30))];
+        for (name, size) in expected {
+            let slice = image::open(dir.path().join(name)).unwrap().to_rgba8();
+            assert_eq!(slice.dimensions(), size, "{}", name);
+        }
+        
+        // A guide on the canvas edge doesn't make a valid grid
+        document.add_guide(GuideOrientation::Vertical, 100);
+        assert!(document.export_slices(dir.path(), DocumentFormat::PNG).is_err());
+    }
+    
+    #[test]
+    fn test_color_harmonies() {
+        use crate::vector::shape::Color;
+        
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+        
+        let cyan = red.complementary();
+        assert!(close(cyan.r, 0.0) && close(cyan.g, 1.0) && close(cyan.b, 1.0));
+        assert!(close(cyan.to_hsl().0, 180.0));
+        
+        let [first, second] = red.triadic();
+        let (h1, s1, l1) = first.to_hsl();
+        let (h2, _, _) = second.to_hsl();
+        assert!(close(h1, 120.0) && close(h2, 240.0));
+        assert!(close(h2 - h1, 120.0));
+        assert!(close(s1, 1.0) && close(l1, 0.5));
+        
+        let hues: Vec<f64> = red.split_complementary().iter().map(|c| c.to_hsl().0).collect();
+        assert!(close(hues[0], 150.0) && close(hues[1], 210.0));
+        
+        let analogous = red.analogous(3, 60.0);
+        assert_eq!(analogous.len(), 3);
+        assert!(close(analogous[0].to_hsl().0, 330.0));
+        assert_eq!(analogous[1], red);
+        assert!(close(analogous[2].to_hsl().0, 30.0));
+    }
+    
+    #[test]
+    fn test_masked_invert_adjustment() {
+        use crate::core::LayerManager;
+        use crate::filters::InvertFilter;
+        use image::{GrayImage, Luma};
+        
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(ImageBuffer::from_pixel(20, 10, Rgba([200, 100, 50, 255])), "Photo".to_string()));
+        let mut invert = Layer::new_adjustment(20, 10, "Invert".to_string(), Box::new(InvertFilter::new()));
+        // Left half black (protected), right half white (adjusted)
+        invert.mask = Some(GrayImage::from_fn(20, 10, |x, _| Luma([if x < 10 { 0 } else { 255 }])));
+        manager.add_layer(invert);
+        
+        let result = manager.flatten();
+        for (x, _, pixel) in result.enumerate_pixels() {
+            if x < 10 {
+                assert_eq!(*pixel, Rgba([200, 100, 50, 255]));
+            } else {
+                assert_eq!(*pixel, Rgba([55, 155, 205, 255]));
+            }
+        }
+        
+        // The layer-level call agrees with flattening, and plain layers
+        // have no adjustment to apply
+        let photo = manager.get_layer(0).unwrap();
+        assert_eq!(manager.get_layer(1).unwrap().apply_adjustment(&photo.image), Some(result));
+        assert!(photo.apply_adjustment(&photo.image).is_none());
+        
+        // Half opacity meets the two halfway
+        manager.get_layer_mut(1).unwrap().opacity = 0.5;
+        let half = *manager.flatten().get_pixel(15, 5);
+        assert!(half.0[..3].iter().all(|&c| (127..=128).contains(&c)), "{:?}", half);
+    }
+    
+    #[test]
+    fn test_spot_heal_removes_dark_spot() {
+        use crate::tools::{SpotHealTool, ToolImpl};
+        
+        let gradient = |x: u32, y: u32| Rgba([(x * 2) as u8, (y * 2) as u8, 100, 255]);
+        let mut image = ImageBuffer::from_fn(80, 80, gradient);
+        for y in 36..=44 {
+            for x in 36..=44 {
+                if (x as i32 - 40).pow(2) + (y as i32 - 40).pow(2) <= 16 {
+                    image.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+                }
+            }
+        }
+        
+        let mut canvas = Canvas::from_image(image);
+        let mut tool = SpotHealTool::new();
+        tool.radius = 6.0;
+        assert!(tool.on_mouse_down(&mut canvas, 40.0, 40.0));
+        
+        let healed = &canvas.layer_manager.get_active_layer().unwrap().image;
+        for y in 34..=46 {
+            for x in 34..=46 {
+                let expected = gradient(x, y);
+                let actual = healed.get_pixel(x, y);
+                for c in 0..3 {
+                    let diff = (actual[c] as i32 - expected[c] as i32).abs();
+                    assert!(diff <= 2, "pixel ({}, {}) is {:?}, expected {:?}", x, y, actual, expected);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_metadata_written_on_export() {
+        use crate::core::metadata::read_text_fields;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut document = Document::new(16, 16);
+        document.set_title("Harbour at dusk");
+        document.set_author(Some("Jo Example".to_string()));
+        document.set_copyright(Some("© 2026 Jo Example".to_string()));
+        document.add_keyword("harbour");
+        document.add_keyword("boats");
+        document.add_keyword("Harbour");
+        document.add_keyword("sea, calm");
+        assert_eq!(document.keywords(), ["harbour", "boats", "sea, calm"]);
+        
+        for name in ["export.png", "export.jpg"] {
+            let path = dir.path().join(name);
+            document.save(&path).unwrap();
+            
+            let fields = read_text_fields(&path).unwrap();
+            let field = |key: &str| fields.get(key).cloned().unwrap_or_default();
+            assert_eq!(field("Author"), ["Jo Example"], "{}", name);
+            assert_eq!(field("Copyright"), ["© 2026 Jo Example"], "{}", name);
+            assert_eq!(field("Title"), ["Harbour at dusk"], "{}", name);
+            // A comma inside a keyword doesn't split it
+            assert_eq!(field("Keywords"), ["harbour", "boats", "sea, calm"], "{}", name);
+            
+            // The file must still decode as an image
+            assert_eq!(image::open(&path).unwrap().to_rgba8().dimensions(), (16, 16));
+        }
+    }
+    
+    #[test]
+    fn test_match_color_moves_toward_reference() {
+        use crate::filters::{match_color, lab_to_rgb, rgb_to_lab};
+        
+        let means = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            let mut sum = [0.0f64; 3];
+            for p in image.pixels() {
+                for c in 0..3 {
+                    sum[c] += p[c] as f64;
+                }
+            }
+            let n = image.pixels().len() as f64;
+            [sum[0] / n, sum[1] / n, sum[2] / n]
+        };
+        
+        // Round trip through Lab is close to lossless
+        let back = lab_to_rgb(rgb_to_lab([200, 120, 40]));
+        assert!(back.iter().zip([200u8, 120, 40]).all(|(a, b)| (*a as i32 - b as i32).abs() <= 1));
+        
+        let source = ImageBuffer::from_fn(32, 32, |x, y| {
+            let v = (96 + (x + y) * 2) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let reference = ImageBuffer::from_fn(32, 32, |x, y| {
+            let v = ((x * 3 + y) % 40) as u8;
+            Rgba([190 + v / 2, 130 + v, 70 + v / 2, 255])
+        });
+        
+        let before = means(&source);
+        let target = means(&reference);
+        let after = means(&match_color(&source, &reference));
+        
+        for c in 0..3 {
+            assert!((after[c] - target[c]).abs() < (before[c] - target[c]).abs(),
+                    "channel {} moved from {:.1} to {:.1}, target {:.1}", c, before[c], after[c], target[c]);
+        }
+        assert!(after[0] > after[2], "result should be warm");
+    }
+    
+    #[test]
+    fn test_perspective_crop_straightens_trapezoid() {
+        use crate::filters::PerspectiveTransform;
+        use crate::tools::PerspectiveCropTool;
+        use crate::vector::Point as VectorPoint;
+        
+        // 8px checkerboard that we photograph "in perspective"
+        let pattern = |u: u32, v: u32| if (u / 8 + v / 8) % 2 == 0 { 0u8 } else { 255u8 };
+        let trapezoid = [(30.0, 10.0), (70.0, 10.0), (90.0, 90.0), (10.0, 90.0)];
+        let rectangle = [(0.0, 0.0), (32.0, 0.0), (32.0, 32.0), (0.0, 32.0)];
+        let to_pattern = PerspectiveTransform::from_quads(trapezoid, rectangle).unwrap();
+        
+        // The transform and its inverse agree
+        let back = to_pattern.inverse().unwrap().map(16.0, 16.0);
+        let (u, v) = to_pattern.map(back.0, back.1);
+        assert!((u - 16.0).abs() < 1e-6 && (v - 16.0).abs() < 1e-6);
+        
+        let photo = ImageBuffer::from_fn(100, 100, |x, y| {
+            let (u, v) = to_pattern.map(x as f64 + 0.5, y as f64 + 0.5);
+            if u >= 0.0 && v >= 0.0 && u < 32.0 && v < 32.0 {
+                let value = pattern(u as u32, v as u32);
+                Rgba([value, value, value, 255])
+            } else {
+                Rgba([128, 0, 0, 255])
+            }
+        });
+        let mut canvas = Canvas::from_image(photo);
+        
+        let mut tool = PerspectiveCropTool::new();
+        tool.set_corners(trapezoid.map(|(x, y)| VectorPoint::new(x, y)));
+        tool.set_output_size(Some((32, 32)));
+        tool.apply(&mut canvas).unwrap();
+        
+        assert_eq!((canvas.width, canvas.height), (32, 32));
+        let result = &canvas.get_active_layer().unwrap().image;
+        assert_eq!(result.dimensions(), (32, 32));
+        
+        // Away from block borders the straightened image is the checkerboard
+        for y in (0..32).filter(|y| (2..6).contains(&(y % 8))) {
+            for x in (0..32).filter(|x| (2..6).contains(&(x % 8))) {
+                let p = result.get_pixel(x, y);
+                let expected = pattern(x, y) as i32;
+                assert!((p[0] as i32 - expected).abs() < 40 && p[1] == p[0],
+                        "pixel ({}, {}) = {:?}, expected {}", x, y, p, expected);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_bilateral_filter_preserves_edges() {
+        use crate::filters::BilateralFilter;
+        
+        // Dark left half, bright right half, with deterministic noise
+        let mut seed = 12345u32;
+        let noisy = ImageBuffer::from_fn(40, 20, |x, _y| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let noise = ((seed >> 16) % 41) as i32 - 20;
+            let base = if x < 20 { 60 } else { 190 };
+            let v = (base + noise).clamp(0, 255) as u8;
+            Rgba([v, v, v, 255])
+        });
+        
+        let flat_deviation = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            let values: Vec<f64> = (4..16).flat_map(|y| (4..14).map(move |x| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y)[0] as f64)
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64).sqrt()
+        };
+        let edge_contrast = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            let column = |x: u32| (0..20).map(|y| image.get_pixel(x, y)[0] as f64).sum::<f64>() / 20.0;
+            column(20) - column(19)
+        };
+        
+        let bilateral = BilateralFilter::new(2.0, 40.0).apply(&noisy);
+        let gaussian = GaussianBlur::new(2.0).apply(&noisy);
+        
+        assert!(flat_deviation(&bilateral) < flat_deviation(&noisy) * 0.6,
+                "noise {} -> {}", flat_deviation(&noisy), flat_deviation(&bilateral));
+        assert!(edge_contrast(&bilateral) > 110.0, "edge contrast {}", edge_contrast(&bilateral));
+        assert!(edge_contrast(&gaussian) < edge_contrast(&bilateral) * 0.5);
+    }
+    
+    #[test]
+    fn test_convert_layers_to_smart_object() {
+        use crate::core::LayerManager;
+        
+        let bottom = ImageBuffer::from_pixel(8, 8, Rgba([200, 40, 40, 255]));
+        let top = ImageBuffer::from_fn(8, 8, |x, _| {
+            if x < 4 { Rgba([0, 0, 255, 128]) } else { Rgba([0, 0, 0, 0]) }
+        });
+        
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(bottom.clone(), "Bottom".to_string()));
+        manager.add_layer(Layer::from_image(top.clone(), "Top".to_string()));
+        manager.add_layer(Layer::new(8, 8, "Above".to_string()));
+        let expected = manager.flatten();
+        
+        assert!(manager.convert_to_smart_object(&[0, 0]).is_err());
+        assert!(manager.convert_to_smart_object(&[1, 5]).is_err());
+        
+        let index = manager.convert_to_smart_object(&[1, 0]).unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(manager.layer_count(), 2);
+        assert_eq!(manager.get_active_layer_index(), 0);
+        assert_eq!(manager.get_layer(1).unwrap().name, "Above");
+        
+        // The smart object shows the composite of what went into it
+        let smart = manager.get_layer(0).unwrap();
+        assert!(smart.smart_object.is_some());
+        assert_eq!(smart.image, expected);
+        
+        // ...and the originals are still there to edit
+        let inner = manager.edit_smart_object(0).unwrap();
+        assert_eq!(inner.len(), 2);
+        assert_eq!(inner[0].name, "Bottom");
+        assert_eq!(inner[0].image, bottom);
+        assert_eq!(inner[1].image, top);
+        
+        inner[1].visible = false;
+        manager.refresh_smart_object(0).unwrap();
+        assert_eq!(manager.get_layer(0).unwrap().image, bottom);
+        
+        assert!(manager.edit_smart_object(1).is_err());
+    }
+    
+    #[test]
+    fn test_reinhard_tone_map_preserves_order() {
+        use crate::filters::{tone_map, ToneMapOperator};
+        
+        // Sixteen doublings of brightness: far more range than 8 bits can hold
+        let hdr: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(16, 1, |x, _| {
+            let v = (1u32 << x).min(65535) as u16;
+            Rgba([v, v, v, 65535])
+        });
+        
+        let sdr = tone_map(&hdr, ToneMapOperator::Reinhard, 0.0);
+        let values: Vec<u8> = (0..16).map(|x| sdr.get_pixel(x, 0)[0]).collect();
+        
+        assert!(values.windows(2).all(|w| w[0] <= w[1]), "brightness order changed: {:?}", values);
+        // The highlights compress instead of clipping to white
+        assert!(values[15] < 255, "brightest value clipped: {:?}", values);
+        assert!(values[14] < values[15], "top stops merged: {:?}", values);
+        assert!(values[0] < values[8] && values[8] < values[15]);
+        assert!(sdr.pixels().all(|p| p[3] == 255 && p[0] == p[1] && p[1] == p[2]));
+    }
+    
+    #[test]
+    fn test_unsharp_mask_threshold() {
+        use crate::filters::UnsharpMask;
+        
+        let filter = UnsharpMask::new(1.5, 1.0, 4);
+        
+        // A hard edge gets steeper: overshoot on both sides
+        let edge = ImageBuffer::from_fn(16, 4, |x, _| {
+            let v = if x < 8 { 50 } else { 200 };
+            Rgba([v, v, v, 255])
+        });
+        let sharpened = filter.apply(&edge);
+        let before = edge.get_pixel(8, 2)[0] as i32 - edge.get_pixel(7, 2)[0] as i32;
+        let after = sharpened.get_pixel(8, 2)[0] as i32 - sharpened.get_pixel(7, 2)[0] as i32;
+        assert!(after > before, "edge step {} -> {}", before, after);
+        assert!(sharpened.get_pixel(7, 2)[0] < 50 && sharpened.get_pixel(8, 2)[0] > 200);
+        assert_eq!(sharpened.get_pixel(0, 2), edge.get_pixel(0, 2));
+        
+        // A gentle gradient stays under the threshold and is left alone
+        let gradient = ImageBuffer::from_fn(32, 4, |x, _| {
+            let v = 100 + (x / 4) as u8;
+            Rgba([v, v, v, 255])
+        });
+        assert_eq!(filter.apply(&gradient), gradient);
+    }
+    
+    #[test]
+    fn test_replace_color_blue_to_red() {
+        use crate::core::{Color, Document};
+        
+        // Top rows: a gradient of blues; bottom rows: green
+        let image = ImageBuffer::from_fn(20, 4, |x, y| {
+            if y < 2 {
+                Rgba([0, 0, 150 + (x * 5) as u8, 255])
+            } else {
+                Rgba([0, 200, 0, 255])
+            }
+        });
+        let luma = |p: &Rgba<u8>| 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32;
+        
+        let mut document = Document::new(20, 4);
+        let index = document.add_layer(Layer::from_image(image.clone(), "Photo".to_string()));
+        document.layer_manager.set_active_layer(index);
+        
+        let blue = Color::rgb(0.0, 0.0, 0.8);
+        let red = Color::rgb(0.8, 0.0, 0.0);
+        let mut plain = document.clone();
+        plain.replace_color(blue, red, 0.5, false).unwrap();
+        document.replace_color(blue, red, 0.5, true).unwrap();
+        
+        for result in [&plain, &document] {
+            let pixels = &result.layer_manager.get_layer(index).unwrap().image;
+            for x in 0..20 {
+                let p = pixels.get_pixel(x, 0);
+                assert!(p[0] > p[2], "pixel {} still blue: {:?}", x, p);
+                // Unrelated colors are untouched
+                assert_eq!(pixels.get_pixel(x, 3), image.get_pixel(x, 3));
+            }
+        }
+        
+        let preserved = &document.layer_manager.get_layer(index).unwrap().image;
+        for x in 0..20 {
+            let diff = (luma(preserved.get_pixel(x, 0)) - luma(image.get_pixel(x, 0))).abs();
+            assert!(diff <= 1.5, "luminance drifted by {} at {}", diff, x);
+        }
+        let shifted = &plain.layer_manager.get_layer(index).unwrap().image;
+        assert!(luma(shifted.get_pixel(10, 0)) > luma(image.get_pixel(10, 0)) + 5.0);
+    }
+    
+    #[test]
+    fn test_path_offset_square() {
+        use crate::vector::path::Path;
+        use crate::vector::PathNodeType;
+        use cairo::LineJoin;
+        
+        let mut square = Path::new();
+        for (x, y) in [(10.0, 10.0), (30.0, 10.0), (30.0, 30.0), (10.0, 30.0)] {
+            square.add_point(x, y, PathNodeType::Point);
+        }
+        square.set_closed(true);
+        
+        for join in [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel] {
+            let grown = square.offset(5.0, join).get_bounds();
+            assert!((grown.x - 5.0).abs() < 1e-6 && (grown.y - 5.0).abs() < 1e-6);
+            assert!((grown.width - 30.0).abs() < 1e-6 && (grown.height - 30.0).abs() < 1e-6,
+                    "{:?} bounds {:?}", join, grown);
+        }
+        
+        // Miter corners land exactly on the grown square
+        assert_eq!(square.offset(5.0, LineJoin::Miter).node_count(), 4);
+        
+        // The same outset regardless of winding, and negative insets
+        let mut reversed = square.clone();
+        reversed.nodes.reverse();
+        let bounds = reversed.offset(5.0, LineJoin::Miter).get_bounds();
+        assert!((bounds.width - 30.0).abs() < 1e-6);
+        let inset = square.offset(-5.0, LineJoin::Miter).get_bounds();
+        assert!((inset.x - 15.0).abs() < 1e-6 && (inset.width - 10.0).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_hsl_range_adjustment_targets_reds() {
+        use crate::core::adjustment::{HSLAdjustment, HSLRanges};
+        use crate::core::LayerManager;
+        use crate::filters::{adjust_hsl_pixel, hue_band_weights, rgb_to_hsl};
+        
+        // Bands overlap smoothly: orange is half red, half yellow
+        let orange = hue_band_weights(30.0 / 360.0);
+        assert!((orange[0] - 0.5).abs() < 1e-5 && (orange[1] - 0.5).abs() < 1e-5);
+        assert!((hue_band_weights(0.73).iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        
+        let mut ranges = [(0.0, 0.0, 0.0); 6];
+        ranges[0] = (0.0, 60.0, 0.0); // reds: saturation up
+        
+        let red = Rgba([180, 60, 60, 255]);
+        let blue = Rgba([60, 60, 180, 255]);
+        let grey = Rgba([120, 120, 120, 255]);
+        
+        let new_red = adjust_hsl_pixel(red, (0.0, 0.0, 0.0), &ranges);
+        assert_ne!(new_red, red);
+        assert!(rgb_to_hsl(new_red[0], new_red[1], new_red[2]).1 > rgb_to_hsl(180, 60, 60).1);
+        assert_eq!(adjust_hsl_pixel(blue, (0.0, 0.0, 0.0), &ranges), blue);
+        assert_eq!(adjust_hsl_pixel(grey, (0.0, 0.0, 0.0), &ranges), grey);
+        
+        // The same through an HSL adjustment layer over the three colors
+        let mut manager = LayerManager::new();
+        let colors = [red, blue, grey];
+        manager.add_layer(Layer::from_image(ImageBuffer::from_fn(3, 1, |x, _| colors[x as usize]), "Swatches".to_string()));
+        let adjustment = HSLAdjustment {
+            ranges: HSLRanges { reds: (0.0, 60.0, 0.0), ..HSLRanges::default() },
+            ..HSLAdjustment::default()
+        };
+        manager.add_layer(Layer::new_adjustment(3, 1, "Reds".to_string(), Box::new(adjustment)));
+        let flat = manager.flatten();
+        assert_eq!(*flat.get_pixel(0, 0), new_red);
+        assert_eq!(*flat.get_pixel(1, 0), blue);
+        assert_eq!(*flat.get_pixel(2, 0), grey);
+    }
+    
+    #[test]
+    fn test_extract_and_apply_channel() {
+        use crate::core::{Channel, Document};
+        use image::GrayImage;
+        
+        let image = ImageBuffer::from_fn(6, 5, |x, y| Rgba([(x * 40) as u8, (y * 50) as u8, 7, 255]));
+        let mut document = Document::new(6, 5);
+        document.add_layer(Layer::from_image(image.clone(), "Photo".to_string()));
+        
+        let red = document.extract_channel(Channel::Red);
+        assert_eq!(red.dimensions(), (6, 5));
+        assert!(red.enumerate_pixels().all(|(x, y, p)| p[0] == image.get_pixel(x, y)[0]));
+        
+        document.apply_channel(Channel::Blue, &red).unwrap();
+        let blue = document.extract_channel(Channel::Blue);
+        assert_eq!(blue, red);
+        
+        // The other channels are untouched
+        assert_eq!(document.extract_channel(Channel::Red), red);
+        let result = &document.layer_manager.get_active_layer().unwrap().image;
+        assert!(result.enumerate_pixels().all(|(x, y, p)| p[1] == image.get_pixel(x, y)[1] && p[3] == 255));
+        
+        assert!(document.apply_channel(Channel::Green, &GrayImage::new(2, 2)).is_err());
+    }
+    
+    #[test]
+    fn test_conical_gradient_renders_by_angle() {
+        use crate::vector::shape::build_conical_pattern;
+        use crate::vector::{Color as VectorColor, Point as VectorPoint};
+        
+        let stops = [
+            (0.0, VectorColor::new(1.0, 0.0, 0.0, 1.0)),
+            (0.25, VectorColor::new(0.0, 1.0, 0.0, 1.0)),
+            (0.5, VectorColor::new(0.0, 0.0, 1.0, 1.0)),
+            (0.75, VectorColor::new(1.0, 1.0, 0.0, 1.0)),
+        ];
+        
+        let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 100, 100).unwrap();
+        {
+            let context = cairo::Context::new(&surface).unwrap();
+            // A full turn added to the start angle must not change anything
+            let pattern = build_conical_pattern(VectorPoint::new(50.0, 50.0), 2.0 * std::f64::consts::PI, &stops);
+            context.set_source(&pattern).unwrap();
+            context.paint().unwrap();
+        }
+        surface.flush();
+        let stride = surface.stride() as usize;
+        let data = surface.data().unwrap();
+        // ARGB32 is stored as B, G, R, A in memory on little-endian machines
+        let rgb = |x: usize, y: usize| {
+            let i = y * stride + x * 4;
+            [data[i + 2], data[i + 1], data[i]]
+        };
+        let close = |actual: [u8; 3], expected: [u8; 3]| {
+            actual.iter().zip(expected.iter()).all(|(a, e)| (*a as i32 - *e as i32).abs() <= 12)
+        };
+        
+        // Clockwise from the +x axis in y-down coordinates
+        assert!(close(rgb(90, 50), [255, 0, 0]), "0°: {:?}", rgb(90, 50));
+        assert!(close(rgb(50, 90), [0, 255, 0]), "90°: {:?}", rgb(50, 90));
+        assert!(close(rgb(10, 50), [0, 0, 255]), "180°: {:?}", rgb(10, 50));
+        assert!(close(rgb(50, 10), [255, 255, 0]), "270°: {:?}", rgb(50, 10));
+        // Halfway between the last stop and the wrap back to the first
+        assert!(close(rgb(80, 20), [255, 128, 0]), "315°: {:?}", rgb(80, 20));
+    }
+    
+    #[test]
+    fn test_raw_bilinear_demosaic_rggb() {
+        use crate::raw::{CfaPattern, RawImage, RawProcessingParams, RawProcessor, WhiteBalance};
+        use crate::filters::linear_to_srgb;
+        
+        // 4x4 RGGB mosaic, white level 1000:
+        //   R G R G
+        //   G B G B
+        //   R G R G
+        //   G B G B
+        let data: Vec<u16> = vec![
+            100, 400, 200, 400,
+            300, 500, 500, 700,
+            300, 600, 400, 400,
+            400, 900, 500, 900,
+        ];
+        let path = std::env::temp_dir().join("rust_photo_test_rggb.rpraw");
+        let mut bytes = b"RPHORAW1".to_vec();
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(b"RGGB");
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&1000u16.to_le_bytes());
+        for value in &data {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(&path, &bytes).unwrap();
+        
+        let processor = RawProcessor::new();
+        let raw = processor.load_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((raw.width(), raw.height(), raw.cfa()), (4, 4, CfaPattern::RGGB));
+        
+        let mut params = RawProcessingParams::default();
+        params.white_balance = WhiteBalance::AsShot;
+        params.exposure_compensation = 0.0;
+        let developed = processor.process_image(&raw, &params).to_rgb8();
+        let expect = |r: f32, g: f32, b: f32| [linear_to_srgb(r / 1000.0), linear_to_srgb(g / 1000.0), linear_to_srgb(b / 1000.0)];
+        let close = |a: [u8; 3], b: [u8; 3]| (0..3).all(|c| (a[c] as i32 - b[c] as i32).abs() <= 1);
+        
+        // (1, 1) is a blue site: red from the four diagonals, green from the four sides
+        assert!(close(developed.get_pixel(1, 1).0, expect((100.0 + 200.0 + 300.0 + 400.0) / 4.0, (400.0 + 300.0 + 500.0 + 600.0) / 4.0, 500.0)));
+        // (2, 2) is a red site: blue from the diagonals, green from the sides
+        assert!(close(developed.get_pixel(2, 2).0, expect(400.0, (500.0 + 600.0 + 400.0 + 500.0) / 4.0, (500.0 + 700.0 + 900.0 + 900.0) / 4.0)));
+        // (2, 1) is a green site on a blue row: red above/below, blue left/right
+        assert!(close(developed.get_pixel(2, 1).0, expect((200.0 + 400.0) / 2.0, 500.0, (500.0 + 700.0) / 2.0)));
+        
+        // A stop of exposure doubles the linear values before demosaicing
+        params.exposure_compensation = 1.0;
+        let brighter = processor.process_image(&raw, &params).to_rgb8();
+        assert!(close(brighter.get_pixel(2, 2).0, expect(800.0, 1000.0, 1000.0)));
+    }
+    
+    #[test]
+    fn test_smart_sharpen_bounds_edge_overshoot() {
+        use crate::filters::SmartSharpen;
+        
+        // Vertical step edge from 50 to 200
+        let image = ImageBuffer::from_fn(20, 8, |x, _| {
+            if x < 10 { Rgba([50, 50, 50, 255]) } else { Rgba([200, 200, 200, 255]) }
+        });
+        let sharpen = SmartSharpen::new(3.0, 1.5, 0.5);
+        let result = sharpen.apply(&image);
+        
+        // The edge gets steeper...
+        assert!(result.get_pixel(9, 4)[0] < 50, "dark side: {}", result.get_pixel(9, 4)[0]);
+        assert!(result.get_pixel(10, 4)[0] > 200, "light side: {}", result.get_pixel(10, 4)[0]);
+        
+        // ...but never overshoots the local range by more than the allowed slack
+        let slack = (150.0 * SmartSharpen::MAX_OVERSHOOT).ceil() as i32;
+        for pixel in result.pixels() {
+            let value = pixel[0] as i32;
+            assert!(value >= 50 - slack && value <= 200 + slack, "overshoot to {}", value);
+            assert_eq!(pixel[3], 255);
+        }
+        
+        // Flat areas away from the edge are untouched
+        assert_eq!(result.get_pixel(0, 4)[0], 50);
+        assert_eq!(result.get_pixel(19, 4)[0], 200);
+    }
+    
+    #[test]
+    fn test_native_document_round_trip() {
+        use crate::core::{BlendMode, DocumentFormat, Guide, GuideOrientation, SmartObject};
+        use crate::core::adjustment::{AdjustmentSettings, PosterizeAdjustment};
+        use crate::core::linked::LinkedTransform;
+        use crate::filters::ShadowsHighlights;
+        use image::{GrayImage, Luma};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = Document::new(8, 6);
+        doc.set_title("Round trip");
+        doc.guides.push(Guide { orientation: GuideOrientation::Vertical, position: 3 });
+        
+        let mut top = Layer::from_image(
+            ImageBuffer::from_fn(8, 6, |x, y| Rgba([x as u8 * 30, y as u8 * 40, 90, 200])),
+            "Top".to_string(),
+        );
+        top.opacity = 0.75;
+        top.blend_mode = BlendMode::Multiply;
+        top.x_offset = 2;
+        top.mask = Some(GrayImage::from_fn(8, 6, |x, _| if x < 4 { Luma([0]) } else { Luma([255]) }));
+        doc.add_layer(top);
+        
+        // A group holding an embedded smart object and a linked one
+        let inner = Layer::from_image(ImageBuffer::from_pixel(8, 6, Rgba([10, 200, 30, 255])), "Inner".to_string());
+        let mut embedded = Layer::from_image(inner.image.clone(), "Embedded".to_string());
+        embedded.smart_object = Some(SmartObject { layers: vec![inner], width: 8, height: 6, linked: None });
+        let transform = LinkedTransform { x: 1, y: 2, rotation: 90.0, ..Default::default() };
+        let linked = Layer::new_linked(8, 6, "Linked".to_string(), dir.path().join("missing.png"), transform);
+        let lift = Layer::new_adjustment(8, 6, "Lift".to_string(), Box::new(ShadowsHighlights::new(0.4, 0.1, 2.0)));
+        let mut group = Layer::new_group(8, 6, "Group".to_string(), vec![embedded, linked, lift]);
+        group.opacity = 0.5;
+        doc.add_layer(group);
+        let posterize = PosterizeAdjustment { levels: 3, ..Default::default() };
+        doc.add_layer(Layer::new_adjustment(8, 6, "Posterize".to_string(), Box::new(posterize.clone())));
+        doc.layer_manager.set_active_layer(0);
+        
+        let path = dir.path().join("round_trip.rpho");
+        doc.save(&path).unwrap();
+        let reloaded = Document::open(&path).unwrap();
+        
+        assert_eq!(reloaded.format, DocumentFormat::Native);
+        assert_eq!((reloaded.width, reloaded.height), (8, 6));
+        assert_eq!(reloaded.guides, doc.guides);
+        assert_eq!(reloaded.title(), "Round trip");
+        assert_eq!(reloaded.layer_manager.layer_count(), 4);
+        assert_eq!(reloaded.layer_manager.get_active_layer_index(), 0);
+        // Names, ids, pixels, opacity, blend mode, offsets, the mask, the
+        // group, both smart objects and the adjustments all survive
+        assert_eq!(reloaded.layer_manager, doc.layer_manager);
+        let adjustment = reloaded.layer_manager.get_layer(3).unwrap().adjustment.as_ref().unwrap();
+        assert_eq!(adjustment.settings(), AdjustmentSettings::Posterize(posterize));
+        let nested = reloaded.layer_manager.get_layer_by_path(&[2, 0]).unwrap();
+        assert_eq!(nested.smart_object.as_ref().unwrap().layers[0].name, "Inner");
+        let link = reloaded.layer_manager.get_layer_by_path(&[2, 1]).unwrap().smart_object.as_ref().unwrap();
+        assert_eq!(link.linked.as_ref().unwrap().transform, transform);
+        
+        // The mask hides the left half of the top layer when compositing
+        let mut without_group = reloaded.layer_manager.clone();
+        without_group.get_layer_mut(2).unwrap().visible = false;
+        without_group.get_layer_mut(3).unwrap().visible = false;
+        let flat = without_group.flatten();
+        assert_eq!(flat.get_pixel(3, 0), doc.layer_manager.get_layer(0).unwrap().image.get_pixel(3, 0));
+        assert!(flat.get_pixel(7, 0)[3] > 0);
+        assert_eq!(reloaded.layer_manager.flatten(), doc.layer_manager.flatten());
+    }
+    
+    #[test]
+    fn test_digit_key_sets_layer_opacity() {
+        use crate::tools::{ToolManager, ToolType};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        
+        let document = Rc::new(RefCell::new(Document::new(4, 4)));
+        let mut canvas = Canvas::new(4, 4);
+        canvas.set_document(Some(document.clone()));
+        let mut tools = ToolManager::new();
+        
+        canvas.composite_tile((0, 0));
+        tools.key_press("5", &mut canvas);
+        assert_eq!(canvas.get_active_layer().unwrap().opacity, 0.5);
+        assert!(canvas.cached_tiles().is_empty());
+        assert_eq!(document.borrow().layer_manager.get_active_layer().unwrap().opacity, 0.5);
+        
+        // 0 means fully opaque
+        tools.key_press("KP_0", &mut canvas);
+        assert_eq!(canvas.get_active_layer().unwrap().opacity, 1.0);
+        
+        // Each change is its own undo step
+        assert!(document.borrow_mut().undo());
+        assert_eq!(document.borrow().layer_manager.get_active_layer().unwrap().opacity, 0.5);
+        assert!(document.borrow_mut().undo());
+        assert_eq!(document.borrow().layer_manager.get_active_layer().unwrap().opacity, 1.0);
+        
+        // Text tools take digits as input instead
+        tools.set_active_tool(ToolType::Text);
+        tools.key_press("3", &mut canvas);
+        assert_eq!(canvas.get_active_layer().unwrap().opacity, 1.0);
+    }
+    
+    #[test]
+    fn test_layer_trim_keeps_canvas_position() {
+        let mut layer = Layer::new(100, 80, "Square".to_string());
+        layer.set_offset(5, -3);
+        for y in 30..40 {
+            for x in 20..26 {
+                layer.set_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        
+        assert_eq!(layer.content_bounds(), Some((20, 30, 6, 10)));
+        assert!(layer.trim());
+        assert_eq!(layer.image.dimensions(), (6, 10));
+        assert_eq!((layer.width, layer.height), (6, 10));
+        // The square's top-left pixel stays at (25, 27) on the canvas
+        assert_eq!((layer.x_offset, layer.y_offset), (25, 27));
+        assert_eq!(layer.get_pixel(0, 0), Some(Rgba([255, 0, 0, 255])));
+        
+        let mut empty = Layer::new(10, 10, "Empty".to_string());
+        assert!(!empty.trim());
+        assert_eq!((empty.width, empty.height), (0, 0));
+    }
+    
+    #[test]
+    fn test_parallel_blur_has_no_strip_seams() {
+        use crate::filters::{apply_filter_parallel, apply_filter_parallel_with_halo};
+        
+        // High-frequency content so any seam shows up
+        let image = ImageBuffer::from_fn(32, 40, |x, y| {
+            let v = if (x / 3 + y / 2) % 2 == 0 { 240 } else { 20 };
+            Rgba([v, (x * 7) as u8, (y * 5) as u8, 255])
+        });
+        let blur = GaussianBlur::new(2.0);
+        let single = blur.apply(&image);
+        let parallel = apply_filter_parallel(&image, || Box::new(GaussianBlur::new(2.0)), 4);
+        
+        let max_diff = single.pixels().zip(parallel.pixels())
+            .flat_map(|(a, b)| (0..4).map(move |c| (a[c] as i32 - b[c] as i32).abs()))
+            .max()
+            .unwrap();
+        assert!(max_diff <= 1, "parallel result differs by {}", max_diff);
+        
+        // Without the overlap the strips visibly disagree at their edges
+        let seamed = apply_filter_parallel_with_halo(&image, || Box::new(GaussianBlur::new(2.0)), 4, 0);
+        let seam_diff = single.pixels().zip(seamed.pixels())
+            .flat_map(|(a, b)| (0..4).map(move |c| (a[c] as i32 - b[c] as i32).abs()))
+            .max()
+            .unwrap();
+        assert!(seam_diff > 1);
+    }
+    
+    #[test]
+    fn test_normal_map_follows_ramp() {
+        use crate::filters::NormalMap;
+        
+        // Brightness rising left to right, with a flat block on the right
+        let image = ImageBuffer::from_fn(32, 8, |x, _| {
+            let v = if x < 20 { (x * 10) as u8 } else { 200 };
+            Rgba([v, v, v, 255])
+        });
+        let normals = NormalMap::new(4.0).apply(&image);
+        
+        // On the slope the normal leans left, against the rising height, by
+        // the same amount everywhere
+        let slope = *normals.get_pixel(5, 4);
+        assert!(slope[0] < 128, "normal should lean against the slope: {:?}", slope);
+        assert!((slope[1] as i32 - 128).abs() <= 1);
+        for x in 2..18 {
+            for y in 0..8 {
+                assert_eq!(normals.get_pixel(x, y).0, slope.0, "at ({}, {})", x, y);
+            }
+        }
+        
+        // Flat areas point straight out of the surface
+        assert_eq!(normals.get_pixel(28, 4).0, [128, 128, 255, 255]);
+    }
+    
+    #[test]
+    fn test_combine_overlapping_rectangles() {
+        use crate::vector::{combine_shapes, PathOperation, ShapeType, VectorObject};
+        use crate::vector::shape::VectorShape;
+        
+        let a = VectorShape::new_rectangle(0.0, 0.0, 10.0, 10.0, 0.0);
+        let b = VectorShape::new_rectangle(5.0, 5.0, 10.0, 10.0, 0.0);
+        let area = |shape: &VectorShape| match &shape.shape_type {
+            ShapeType::Custom { path } => {
+                let n = path.nodes.len();
+                (0..n).map(|i| {
+                    let (p, q) = (path.nodes[i].point.position, path.nodes[(i + 1) % n].point.position);
+                    p.x * q.y - q.x * p.y
+                }).sum::<f64>().abs() / 2.0
+            },
+            _ => panic!("boolean result should be a custom path"),
+        };
+        let bounds = |shape: &VectorShape| {
+            let r = shape.get_bounds();
+            (r.x.round(), r.y.round(), r.width.round(), r.height.round())
+        };
+        
+        let union = combine_shapes(&a, &b, PathOperation::Union);
+        assert_eq!(bounds(&union), (0.0, 0.0, 15.0, 15.0));
+        assert!((area(&union) - 175.0).abs() < 1e-6);
+        
+        let intersect = combine_shapes(&a, &b, PathOperation::Intersect);
+        assert_eq!(bounds(&intersect), (5.0, 5.0, 5.0, 5.0));
+        assert!((area(&intersect) - 25.0).abs() < 1e-6);
+        
+        // Subtract cuts the overlapping quarter out of the first rectangle
+        let subtract = combine_shapes(&a, &b, PathOperation::Subtract);
+        assert_eq!(bounds(&subtract), (0.0, 0.0, 10.0, 10.0));
+        assert!((area(&subtract) - 75.0).abs() < 1e-6);
+        if let ShapeType::Custom { path } = &subtract.shape_type {
+            assert!(path.nodes.iter().all(|node| {
+                let p = node.point.position;
+                !(p.x > 5.0 + 1e-9 && p.y > 5.0 + 1e-9)
+            }));
+        }
+    }
+    
+    #[test]
+    fn test_levels_remaps_mid_gray() {
+        use crate::core::adjustment::LevelsAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::{Filter, LevelsChannel, LevelsFilter};
+        use image::{ImageBuffer, Rgba};
+        
+        let composite = LevelsChannel::new(50, 200, 1.0, 0, 255);
+        let identity = LevelsChannel::default();
+        let levels = LevelsFilter::new(composite, identity, identity, identity);
+        
+        let lut = composite.lut();
+        assert_eq!(lut[50], 0);
+        assert_eq!(lut[200], 255);
+        assert_eq!(lut[20], 0);
+        assert_eq!(lut[230], 255);
+        
+        // (125 - 50) / (200 - 50) = 0.5 of full range
+        let image = ImageBuffer::from_pixel(2, 2, Rgba([125u8, 125, 125, 180]));
+        let output = levels.apply(&image);
+        assert_eq!(*output.get_pixel(1, 1), Rgba([128, 128, 128, 180]));
+        
+        // A Levels adjustment layer does the same to the layers below it
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image.clone(), "Gray".to_string()));
+        let adjustment = LevelsAdjustment { composite, ..LevelsAdjustment::default() };
+        manager.add_layer(Layer::new_adjustment(2, 2, "Levels".to_string(), Box::new(adjustment)));
+        assert_eq!(*manager.flatten().get_pixel(1, 1), Rgba([128, 128, 128, 180]));
+        
+        // A gamma above 1 lifts the midtones
+        let brighter = LevelsChannel::new(50, 200, 2.0, 0, 255).lut();
+        assert!(brighter[125] > 170);
+    }
+    
+    #[test]
+    fn test_blend_frames_cross_dissolves() {
+        use crate::core::document::Document;
+        use crate::core::layer::Layer;
+        use image::{ImageBuffer, Rgba};
+        
+        let mut document = Document::new(4, 3);
+        let black = document.add_layer(Layer::from_image(ImageBuffer::from_pixel(4, 3, Rgba([0, 0, 0, 255])), "Frame 1".to_string()));
+        let white = document.add_layer(Layer::from_image(ImageBuffer::from_pixel(4, 3, Rgba([255, 255, 255, 255])), "Frame 2".to_string()));
+        
+        let middle = document.blend_frames(black, white, 0.5).to_rgba8();
+        assert_eq!(middle.dimensions(), (4, 3));
+        for pixel in middle.pixels() {
+            assert!(pixel[0] == 127 || pixel[0] == 128);
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[1], pixel[2]);
+            assert_eq!(pixel[3], 255);
+        }
+        
+        assert_eq!(*document.blend_frames(black, white, 0.0).to_rgba8().get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*document.blend_frames(black, white, 1.0).to_rgba8().get_pixel(3, 2), Rgba([255, 255, 255, 255]));
+    }
+    
+    #[test]
+    fn test_color_balance_highlights_toward_blue() {
+        use crate::core::adjustment::ColorBalanceAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::{ColorBalanceFilter, ColorBalanceTones, Filter};
+        use image::{ImageBuffer, Rgba};
+        
+        let neutral = ColorBalanceTones::default();
+        let balance = ColorBalanceFilter::new(neutral, neutral, ColorBalanceTones::new(0.0, 0.0, 100.0), false);
+        
+        let mut image = ImageBuffer::from_pixel(2, 1, Rgba([20u8, 20, 20, 255]));
+        image.put_pixel(1, 0, Rgba([210, 210, 210, 255]));
+        let output = balance.apply(&image);
+        
+        // Shadows are outside the highlight range entirely
+        assert_eq!(*output.get_pixel(0, 0), Rgba([20, 20, 20, 255]));
+        let bright = output.get_pixel(1, 0);
+        assert!(bright[2] > 240);
+        assert_eq!(bright[0], 210);
+        assert_eq!(bright[1], 210);
+        
+        // Preserving luminosity pulls red and green down to compensate
+        let preserving = ColorBalanceFilter::new(neutral, neutral, ColorBalanceTones::new(0.0, 0.0, 100.0), true);
+        let bright = *preserving.apply(&image).get_pixel(1, 0);
+        assert!(bright[2] > bright[0]);
+        assert!(bright[0] < 210);
+        
+        // A Color Balance adjustment layer gives the filter's result
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image.clone(), "Tones".to_string()));
+        let adjustment = ColorBalanceAdjustment {
+            highlights: ColorBalanceTones::new(0.0, 0.0, 100.0),
+            ..ColorBalanceAdjustment::default()
+        };
+        manager.add_layer(Layer::new_adjustment(2, 1, "Balance".to_string(), Box::new(adjustment)));
+        assert_eq!(manager.flatten(), preserving.apply(&image));
+    }
+    
+    #[test]
+    fn test_palette_reduction_uses_only_palette_colors() {
+        use crate::core::adjustment::PaletteAdjustment;
+        use crate::core::{Color, LayerManager};
+        use crate::filters::{Filter, PaletteFilter};
+        use image::{ImageBuffer, Rgba};
+        
+        let palette = vec![
+            Color::black(),
+            Color::rgb(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0),
+            Color::rgb(2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0),
+            Color::white(),
+        ];
+        let allowed = [[0u8, 0, 0], [85, 85, 85], [170, 170, 170], [255, 255, 255]];
+        let gradient = ImageBuffer::from_fn(64, 8, |x, _| {
+            let v = (x * 4) as u8;
+            Rgba([v, v, v, 255])
+        });
+        
+        for dither in [false, true] {
+            let output = PaletteFilter::new(palette.clone(), dither).apply(&gradient);
+            for pixel in output.pixels() {
+                assert!(allowed.contains(&[pixel[0], pixel[1], pixel[2]]));
+                assert_eq!(pixel[3], 255);
+            }
+            assert_eq!(*output.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+            assert_eq!(*output.get_pixel(63, 7), Rgba([255, 255, 255, 255]));
+        }
+        
+        assert_eq!(PaletteFilter::game_boy(false).palette().len(), 4);
+        
+        // As an adjustment layer the gradient below is reduced the same way
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(gradient.clone(), "Gradient".to_string()));
+        let adjustment = PaletteAdjustment::new(palette.clone(), true);
+        manager.add_layer(Layer::new_adjustment(64, 8, "Palette".to_string(), Box::new(adjustment)));
+        assert_eq!(manager.flatten(), PaletteFilter::new(palette, true).apply(&gradient));
+    }
+    
+    #[test]
+    fn test_detect_horizon_finds_tilted_line() {
+        use crate::core::document::Document;
+        use crate::core::layer::Layer;
+        use image::{ImageBuffer, Rgba};
+        
+        // A thick dark line falling 5° to the right across a white image
+        let slope = 5.0f64.to_radians().tan();
+        let image = ImageBuffer::from_fn(240, 160, |x, y| {
+            let center = 80.0 + (x as f64 - 120.0) * slope;
+            if (y as f64 - center).abs() < 3.0 {
+                Rgba([20u8, 20, 20, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut document = Document::new(240, 160);
+        document.add_layer(Layer::from_image(image, "Photo".to_string()));
+        
+        let angle = document.detect_horizon().expect("line should be detected");
+        assert!((angle - 5.0).abs() < 1.0, "detected {}", angle);
+        
+        // A blank document has nothing to level
+        assert_eq!(Document::new(64, 64).detect_horizon(), None);
+    }
+    
+    #[test]
+    fn test_export_with_options() {
+        use crate::core::document::Document;
+        use crate::core::export::{ExportError, ExportOptions};
+        use crate::core::layer::Layer;
+        use image::{ImageBuffer, Rgba};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut document = Document::new(20, 10);
+        // Half transparent, half opaque red
+        let image = ImageBuffer::from_fn(20, 10, |x, _| {
+            if x < 10 { Rgba([0u8, 0, 0, 0]) } else { Rgba([255, 0, 0, 255]) }
+        });
+        document.add_layer(Layer::from_image(image, "Content".to_string()));
+        
+        let png = dir.path().join("export.png");
+        document.export(&png, ExportOptions::png()).unwrap();
+        let decoded = image::open(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (20, 10));
+        assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+        
+        // JPEG composites the transparent half over the background
+        let jpeg = dir.path().join("export.jpg");
+        let mut options = ExportOptions::jpeg(95);
+        options.background = Rgba([0, 0, 255, 255]);
+        options.resize = Some((40, 20));
+        document.export(&jpeg, options).unwrap();
+        let decoded = image::open(&jpeg).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (40, 20));
+        let corner = decoded.get_pixel(2, 10);
+        assert!(corner[2] > 200 && corner[0] < 60, "{:?}", corner);
+        
+        let mut bad = ExportOptions::png();
+        bad.resize = Some((0, 5));
+        assert!(matches!(document.export(dir.path().join("bad.png"), bad), Err(ExportError::InvalidOptions(_))));
+    }
+    
+    #[test]
+    fn test_liquify_freeze_mask_protects_pixels() {
+        use crate::tools::LiquifyTool;
+        use crate::vector::Point;
+        use image::{GrayImage, ImageBuffer, Luma, Rgba};
+        
+        // Horizontal ramp so any sideways displacement changes the color
+        let image = ImageBuffer::from_fn(40, 20, |x, _| Rgba([(x * 6) as u8, 0, 0, 255]));
+        let frozen = GrayImage::from_fn(40, 20, |x, _| Luma([if x < 20 { 255 } else { 0 }]));
+        
+        let mut liquify = LiquifyTool::new();
+        liquify.radius = 8.0;
+        liquify.strength = 0.5;
+        liquify.set_freeze_mask(frozen);
+        liquify.begin_stroke(&image);
+        liquify.push(Point::new(16.0, 10.0), Point::new(22.0, 10.0));
+        let warped = liquify.render().unwrap();
+        
+        for y in 0..20 {
+            for x in 0..20 {
+                assert_eq!(warped.get_pixel(x, y), image.get_pixel(x, y), "frozen pixel ({}, {}) moved", x, y);
+            }
+        }
+        assert_ne!(warped.get_pixel(21, 10), image.get_pixel(21, 10));
+        assert_ne!(warped.get_pixel(23, 10), image.get_pixel(23, 10));
+        
+        // Without the mask the same stroke reaches the left half too
+        liquify.clear_freeze_mask();
+        liquify.begin_stroke(&image);
+        liquify.push(Point::new(16.0, 10.0), Point::new(22.0, 10.0));
+        assert_ne!(liquify.render().unwrap().get_pixel(18, 10), image.get_pixel(18, 10));
+    }
+    
+    #[test]
+    fn test_canvas_resize_interpolates() {
+        use crate::core::Canvas;
+        use crate::filters::ResampleFilter;
+        use image::{ImageBuffer, Rgba};
+        
+        let checkerboard = ImageBuffer::from_fn(16, 16, |x, y| {
+            if (x + y) % 2 == 0 { Rgba([0u8, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        });
+        
+        let mut canvas = Canvas::from_image(checkerboard.clone());
+        canvas.resize(8, 8, ResampleFilter::Bilinear);
+        assert_eq!((canvas.width, canvas.height), (8, 8));
+        let layer = canvas.get_active_layer().unwrap();
+        assert_eq!(layer.image.dimensions(), (8, 8));
+        assert!(layer.image.pixels().any(|p| p[0] > 64 && p[0] < 192), "no intermediate grays");
+        
+        // Nearest neighbour only ever picks original pixels
+        let mut canvas = Canvas::from_image(checkerboard);
+        canvas.resize(8, 8, ResampleFilter::Nearest);
+        let layer = canvas.get_active_layer().unwrap();
+        assert!(layer.image.pixels().all(|p| p[0] == 0 || p[0] == 255));
+    }
+    
+    #[test]
+    fn test_gradient_lab_midpoint_is_more_saturated() {
+        use crate::vector::{Color, Gradient, GradientInterpolation, GradientSegment};
+        
+        let mut gradient = Gradient::default();
+        gradient.stops = vec![(0.0, Color::new(0.0, 0.0, 1.0, 1.0)), (1.0, Color::new(1.0, 1.0, 0.0, 1.0))];
+        
+        let rgb_mid = gradient.color_at(0.5);
+        assert!(rgb_mid.to_hsl().1 < 0.01, "RGB blue-yellow should pass through gray");
+        
+        gradient.set_segment(0, GradientSegment::new(GradientInterpolation::Lab, 0.5));
+        let lab_mid = gradient.color_at(0.5);
+        assert!(lab_mid.to_hsl().1 > rgb_mid.to_hsl().1 + 0.1, "{:?}", lab_mid);
+        assert_eq!(gradient.color_at(0.0), gradient.stops[0].1);
+        
+        // Cairo gets extra stops for the Lab segment
+        assert!(gradient.render_stops().len() > 2);
+        
+        // Moving the midpoint towards the first stop reaches the even blend sooner
+        gradient.set_segment(0, GradientSegment::new(GradientInterpolation::Rgb, 0.25));
+        let biased = gradient.color_at(0.25);
+        assert!((biased.r - 0.5).abs() < 1e-6 && (biased.b - 0.5).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_feather_is_fast_and_smooth() {
+        use crate::core::selection::Selection;
+        use std::time::{Duration, Instant};
+        
+        let mut selection = Selection::rectangle(0.0, 0.0, 500, 1000, 1000, 1000);
+        let start = Instant::now();
+        selection.feather(20.0);
+        // The old 2D kernel took minutes at this size; unoptimized builds
+        // of the separable version finish in a few seconds at most
+        assert!(start.elapsed() < Duration::from_secs(20), "feather took {:?}", start.elapsed());
+        
+        let row: Vec<u8> = (0..1000).map(|x| selection.mask.get_pixel(x, 500)[0]).collect();
+        assert_eq!(row[400], 255);
+        assert_eq!(row[600], 0);
+        assert!(row[499] > 100 && row[500] < 155);
+        assert!(row.windows(2).all(|pair| pair[0] >= pair[1]), "edge should fall off monotonically");
+        // Soft, not a hard step
+        assert!((470..530).filter(|&x| row[x] > 0 && row[x] < 255).count() > 30);
+    }
+    
+    #[test]
+    fn test_export_for_web_fits_budget() {
+        use crate::core::document::Document;
+        use crate::core::export::ExportFormat;
+        use crate::core::layer::Layer;
+        use image::{ImageBuffer, Rgba};
+        
+        // Noisy detail so quality makes a real difference to the size
+        let image = ImageBuffer::from_fn(128, 128, |x, y| {
+            let v = ((x * 7919 + y * 104729) ^ (x * y)) as u8;
+            Rgba([v, v.wrapping_mul(3), v.wrapping_add(x as u8), 255])
+        });
+        let mut document = Document::new(128, 128);
+        document.add_layer(Layer::from_image(image, "Detail".to_string()));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("web.jpg");
+        let budget = 12_000;
+        let quality = document.export_for_web(&path, budget, ExportFormat::JPEG).unwrap();
+        assert!(quality >= 1 && quality < 100);
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert!(size <= budget, "{} bytes at quality {}", size, quality);
+        assert_eq!(image::open(&path).unwrap().to_rgb8().dimensions(), (128, 128));
+        
+        // Impossible budgets are reported instead of overshooting
+        assert!(document.export_for_web(dir.path().join("tiny.jpg"), 200, ExportFormat::JPEG).is_err());
+    }
+    
+    #[test]
+    fn test_magic_wand_contours_trace_ring() {
+        use crate::core::selection::{Selection, SelectionShape};
+        use image::Rgba;
+        
+        // A ring: selected between radius 6 and 14 around (20, 20)
+        let mut selection = Selection::new(40, 40);
+        selection.shape = SelectionShape::MagicWand;
+        for (x, y, pixel) in selection.mask.enumerate_pixels_mut() {
+            let distance = ((x as f64 - 20.0).powi(2) + (y as f64 - 20.0).powi(2)).sqrt();
+            let value = if (6.0..=14.0).contains(&distance) { 255 } else { 0 };
+            *pixel = Rgba([value, value, value, 255]);
+        }
+        
+        let contours = selection.contours();
+        assert_eq!(contours.len(), 2, "expected an outer and an inner contour");
+        
+        let radii: Vec<f64> = contours.iter().map(|contour| {
+            assert!(contour.len() > 20);
+            for &(x, y) in contour {
+                // Every point sits on a selected pixel next to an unselected one
+                let (px, py) = (x.floor() as u32, y.floor() as u32);
+                assert_eq!(selection.mask.get_pixel(px, py)[0], 255);
+                let on_edge = [(-1i32, 0i32), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)].iter()
+                    .any(|&(dx, dy)| selection.mask.get_pixel((px as i32 + dx) as u32, (py as i32 + dy) as u32)[0] == 0);
+                assert!(on_edge, "({}, {}) is inside the selection", x, y);
+            }
+            contour.iter().map(|&(x, y)| ((x - 20.5).powi(2) + (y - 20.5).powi(2)).sqrt()).sum::<f64>() / contour.len() as f64
+        }).collect();
+        
+        let (outer, inner) = if radii[0] > radii[1] { (radii[0], radii[1]) } else { (radii[1], radii[0]) };
+        assert!(outer > 12.0 && outer < 15.0, "outer radius {}", outer);
+        assert!(inner > 5.0 && inner < 8.0, "inner radius {}", inner);
+    }
+    
+    #[test]
+    fn test_box_blur_matches_naive_average() {
+        // A single white pixel spreads evenly over its 3x3 neighbourhood
+        let mut dot = ImageBuffer::from_pixel(5, 5, Rgba([0u8, 0, 0, 255]));
+        dot.put_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        let blurred = BoxBlur::new(1).apply(&dot);
+        for y in 0..5 {
+            for x in 0..5 {
+                let expected = if (1..=3).contains(&x) && (1..=3).contains(&y) { 28 } else { 0 };
+                assert_eq!(blurred.get_pixel(x, y)[0], expected, "at ({}, {})", x, y);
+                assert_eq!(blurred.get_pixel(x, y)[3], 255);
+            }
+        }
+        
+        // Against a direct window average, clipped at the edges
+        let image = ImageBuffer::from_fn(23, 17, |x, y| {
+            Rgba([(x * 37 % 256) as u8, (y * 53 % 256) as u8, ((x * y) % 256) as u8, ((x + y) * 11 % 256) as u8])
+        });
+        for radius in [1u32, 3, 8, 30] {
+            let fast = BoxBlur::new(radius).apply(&image);
+            for y in 0..17i64 {
+                for x in 0..23i64 {
+                    let r = radius as i64;
+                    let mut sums = [0u64; 4];
+                    let mut count = 0u64;
+                    for wy in (y - r).max(0)..=(y + r).min(16) {
+                        for wx in (x - r).max(0)..=(x + r).min(22) {
+                            let pixel = image.get_pixel(wx as u32, wy as u32);
+                            for c in 0..4 {
+                                sums[c] += pixel[c] as u64;
+                            }
+                            count += 1;
+                        }
+                    }
+                    let expected = sums.map(|s| ((s + count / 2) / count) as u8);
+                    assert_eq!(fast.get_pixel(x as u32, y as u32).0, expected, "radius {} at ({}, {})", radius, x, y);
+                }
+            }
+        }
+        
+        let kept = BoxBlur::new(2).with_preserve_alpha(true).apply(&image);
+        assert!(kept.pixels().zip(image.pixels()).all(|(a, b)| a[3] == b[3]));
+    }
+    
+    #[test]
+    fn test_content_aware_fill_matches_surrounding_texture() {
+        use crate::core::document::Document;
+        use crate::core::layer::Layer;
+        use crate::core::selection::Selection;
+        use image::{ImageBuffer, Rgba};
+        
+        // Diagonal stripes with some grain, and a dark blob to remove
+        let texture = |x: u32, y: u32| {
+            let stripe = if (x + y) / 4 % 2 == 0 { 90 } else { 170 };
+            let grain = ((x * 7919 + y * 104729) % 23) as u8;
+            stripe + grain
+        };
+        let inside_blob = |x: u32, y: u32| (x as f64 - 32.0).powi(2) + (y as f64 - 32.0).powi(2) <= 36.0;
+        let image = ImageBuffer::from_fn(64, 64, |x, y| {
+            let v = if inside_blob(x, y) { 10 } else { texture(x, y) };
+            Rgba([v, v, v, 255])
+        });
+        let mut document = Document::new(64, 64);
+        document.add_layer(Layer::from_image(image, "Photo".to_string()));
+        
+        let mut selection = Selection::new(64, 64);
+        let in_hole = |x: u32, y: u32| (x as f64 - 32.0).powi(2) + (y as f64 - 32.0).powi(2) <= 64.0;
+        for (x, y, pixel) in selection.mask.enumerate_pixels_mut() {
+            if in_hole(x, y) {
+                *pixel = Rgba([255, 255, 255, 255]);
+            }
+        }
+        document.content_aware_fill(&selection).unwrap();
+        
+        let stats = |values: &[f64]| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            (mean, variance.sqrt())
+        };
+        let layer = document.layer_manager.get_active_layer().unwrap();
+        let mut filled = Vec::new();
+        let mut surrounding = Vec::new();
+        for y in 16..48 {
+            for x in 16..48 {
+                let value = layer.image.get_pixel(x, y)[0] as f64;
+                if in_hole(x, y) {
+                    filled.push(value);
+                } else {
+                    surrounding.push(value);
+                }
+            }
+        }
+        
+        assert!(filled.iter().all(|&v| v >= 90.0), "the dark blob is gone");
+        let (filled_mean, filled_deviation) = stats(&filled);
+        let (around_mean, around_deviation) = stats(&surrounding);
+        assert!((filled_mean - around_mean).abs() < 20.0, "mean {} vs {}", filled_mean, around_mean);
+        // Stripes carried through, not a flat patch
+        assert!(filled_deviation > around_deviation * 0.6, "deviation {} vs {}", filled_deviation, around_deviation);
+        
+        assert!(document.undo());
+        let restored = document.layer_manager.get_active_layer().unwrap();
+        assert_eq!(restored.image.get_pixel(32, 32)[0], 10);
+        
+        assert!(document.content_aware_fill(&Selection::new(64, 64)).is_err());
+    }
+    
+    #[test]
+    fn test_split_and_merge_rgb_channels() {
+        use crate::core::document::{ChannelMode, Document};
+        use crate::core::layer::Layer;
+        use image::{ImageBuffer, Rgba};
+        
+        let image = ImageBuffer::from_fn(16, 12, |x, y| {
+            Rgba([(x * 16) as u8, (y * 20) as u8, ((x * y) % 256) as u8, 255])
+        });
+        let mut document = Document::new(16, 12);
+        document.add_layer(Layer::from_image(image.clone(), "Photo".to_string()));
+        let layers_before = document.layer_manager.layer_count();
+        
+        let indices = document.split_channels(ChannelMode::Rgb).unwrap();
+        assert_eq!(indices.len(), 3);
+        assert_eq!(document.layer_manager.layer_count(), layers_before + 2);
+        for (c, (&index, name)) in indices.iter().zip(["R", "G", "B"]).enumerate() {
+            let layer = document.layer_manager.get_layer(index).unwrap();
+            assert_eq!(layer.name, name);
+            for (x, y, pixel) in layer.image.enumerate_pixels() {
+                let expected = image.get_pixel(x, y)[c];
+                assert_eq!(pixel.0, [expected, expected, expected, 255]);
+            }
+        }
+        
+        let merged = document.merge_channels(ChannelMode::Rgb, &indices).unwrap();
+        assert_eq!(document.layer_manager.layer_count(), layers_before);
+        assert_eq!(document.layer_manager.get_layer(merged).unwrap().image, image);
+        
+        // The wrong number of channel layers is refused
+        assert!(document.merge_channels(ChannelMode::Cmyk, &[0, 1]).is_err());
+    }
+    
+    #[test]
+    fn test_brush_spacing_leaves_no_gaps() {
+        use crate::tools::{BrushTool, ToolImpl};
+        
+        let white = ImageBuffer::from_pixel(120, 20, Rgba([255, 255, 255, 255]));
+        let mut canvas = Canvas::from_image(white);
+        let mut brush = BrushTool::new();
+        brush.size = 3.0;
+        brush.hardness = 1.0;
+        brush.spacing = 0.25;
+        assert!((brush.dab_spacing() - 1.5).abs() < 1e-9);
+        
+        // One fast drag across the whole line
+        brush.on_mouse_down(&mut canvas, 0.0, 10.0);
+        brush.on_mouse_drag(&mut canvas, 100.0, 10.0);
+        brush.on_mouse_up(&mut canvas, 100.0, 10.0);
+        
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        for x in 0..=100 {
+            assert_eq!(image.get_pixel(x, 10)[0], 0, "gap at x = {}", x);
+        }
+        assert_eq!(image.get_pixel(110, 10)[0], 255);
+        
+        // A soft dab fades from full strength at the center to nothing at the rim
+        let mut canvas = Canvas::from_image(ImageBuffer::from_pixel(40, 40, Rgba([255, 255, 255, 255])));
+        brush.size = 10.0;
+        brush.hardness = 0.0;
+        brush.on_mouse_down(&mut canvas, 20.0, 20.0);
+        brush.on_mouse_up(&mut canvas, 20.0, 20.0);
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        let row: Vec<u8> = (20..31).map(|x| image.get_pixel(x, 20)[0]).collect();
+        assert_eq!(row[0], 0);
+        assert!(row.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(row[5] > 50 && row[5] < 205);
+        assert_eq!(row[10], 255);
+        
+        // With the buildup limit, scribbling over the same spot in one
+        // stroke stays at the brush opacity
+        let mut canvas = Canvas::from_image(ImageBuffer::from_pixel(40, 40, Rgba([255, 255, 255, 255])));
+        brush.hardness = 1.0;
+        brush.opacity = 0.5;
+        brush.limit_buildup = true;
+        brush.on_mouse_down(&mut canvas, 15.0, 20.0);
+        for _ in 0..5 {
+            brush.on_mouse_drag(&mut canvas, 25.0, 20.0);
+            brush.on_mouse_drag(&mut canvas, 15.0, 20.0);
+        }
+        brush.on_mouse_up(&mut canvas, 15.0, 20.0);
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        assert_eq!(image.get_pixel(20, 20)[0], 128);
+    }
+    
+    #[test]
+    fn test_history_goto_jumps_to_state() {
+        let mut document = Document::new(8, 8);
+        for opacity in [0.9, 0.8, 0.7, 0.6, 0.5] {
+            document.set_layer_opacity(0, opacity).unwrap();
+        }
+        let opacity = |document: &Document| document.layer_manager.get_layer(0).unwrap().opacity;
+        assert_eq!(document.history.state_count(), 5);
+        
+        assert!(document.goto_history(2));
+        assert_eq!(document.history.current_state(), 2);
+        assert!((opacity(&document) - 0.8).abs() < 1e-9);
+        
+        // Forwards again through the undone commands
+        assert!(document.goto_history(4));
+        assert!((opacity(&document) - 0.6).abs() < 1e-9);
+        
+        assert!(document.goto_history(0));
+        assert!((opacity(&document) - 1.0).abs() < 1e-9);
+        assert!(!document.goto_history(6));
+        assert_eq!(document.history.state_count(), 5);
+    }
+    
+    #[test]
+    fn test_clone_tool_copies_from_source_offset() {
+        use crate::tools::{CloneTool, ToolImpl};
+        
+        let original = ImageBuffer::from_fn(80, 40, |x, y| {
+            Rgba([(x * 3) as u8, (y * 6) as u8, ((x * y) % 256) as u8, 255])
+        });
+        let mut canvas = Canvas::from_image(original.clone());
+        let mut tool = CloneTool::new();
+        tool.size = 4.0;
+        tool.hardness = 1.0;
+        
+        // Alt-click sets the source, then a stroke 40 pixels to the right
+        tool.set_sampling(true);
+        tool.on_mouse_down(&mut canvas, 10.0, 20.0);
+        tool.set_sampling(false);
+        tool.on_mouse_down(&mut canvas, 50.0, 20.0);
+        tool.on_mouse_drag(&mut canvas, 60.0, 20.0);
+        tool.on_mouse_up(&mut canvas, 60.0, 20.0);
+        assert_eq!(tool.source_offset(), Some((-40, 0)));
+        
+        let image = canvas.layer_manager.get_active_layer().unwrap().image.clone();
+        for x in 50..=60 {
+            for y in 18..=22 {
+                assert_eq!(image.get_pixel(x, y), original.get_pixel(x - 40, y), "at ({}, {})", x, y);
+            }
+        }
+        assert_eq!(image.get_pixel(70, 20), original.get_pixel(70, 20));
+        
+        // Aligned: the next stroke keeps the same offset
+        tool.on_mouse_down(&mut canvas, 50.0, 30.0);
+        tool.on_mouse_up(&mut canvas, 50.0, 30.0);
+        let image = canvas.layer_manager.get_active_layer().unwrap().image.clone();
+        assert_eq!(image.get_pixel(50, 30), original.get_pixel(10, 30));
+        
+        // Non-aligned: every stroke starts sampling at the source again
+        tool.aligned = false;
+        tool.on_mouse_down(&mut canvas, 70.0, 8.0);
+        tool.on_mouse_up(&mut canvas, 70.0, 8.0);
+        let image = canvas.layer_manager.get_active_layer().unwrap().image.clone();
+        assert_eq!(image.get_pixel(70, 8), original.get_pixel(10, 20));
+        
+        // Sources past the edge are skipped rather than wrapped
+        let mut canvas = Canvas::from_image(original.clone());
+        tool.set_source(2.0, 20.0);
+        tool.on_mouse_down(&mut canvas, 40.0, 20.0);
+        tool.on_mouse_up(&mut canvas, 40.0, 20.0);
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        assert_eq!(image.get_pixel(37, 20), original.get_pixel(37, 20));
+        assert_eq!(image.get_pixel(41, 20), original.get_pixel(3, 20));
+    }
+    
+    #[test]
+    fn test_heal_tool_fills_hole_from_texture() {
+        use crate::tools::{HealTool, ToolImpl};
+        
+        // Fine repeating texture with a transparent hole punched in it
+        let texture = |x: u32, y: u32| Rgba([100 + ((x * 3 + y * 5) % 7) as u8 * 5, 80, 60 + (y % 3) as u8 * 10, 255]);
+        let mut image = ImageBuffer::from_fn(48, 48, texture);
+        for y in 21..=27 {
+            for x in 21..=27 {
+                image.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+            }
+        }
+        let mut canvas = Canvas::from_image(image);
+        
+        let mut tool = HealTool::new();
+        tool.settings.radius = 6.0;
+        assert!(tool.settings.content_aware);
+        tool.on_mouse_down(&mut canvas, 24.0, 24.0);
+        tool.on_mouse_drag(&mut canvas, 24.5, 24.0);
+        assert!(tool.on_mouse_up(&mut canvas, 24.5, 24.0));
+        
+        let healed = &canvas.layer_manager.get_active_layer().unwrap().image;
+        for y in 21..=27 {
+            for x in 21..=27 {
+                let pixel = healed.get_pixel(x, y);
+                assert_eq!(pixel[3], 255, "hole left transparent at ({}, {})", x, y);
+                assert!(pixel[0] >= 95 && pixel[0] <= 135, "red {} at ({}, {})", pixel[0], x, y);
+                assert!((pixel[1] as i32 - 80).abs() <= 5);
+                assert!(pixel[2] >= 55 && pixel[2] <= 85);
+            }
+        }
+        // Far from the stroke nothing changes
+        assert_eq!(*healed.get_pixel(2, 2), texture(2, 2));
+    }
+    
+    #[test]
+    fn test_merge_down_composites_into_lower_layer() {
+        use crate::core::LayerManager;
+        use image::GrayImage;
+        
+        let mut manager = LayerManager::new();
+        let mut bottom = Layer::new(8, 8, "Bottom".to_string());
+        bottom.image = ImageBuffer::from_pixel(8, 8, Rgba([200, 20, 20, 255]));
+        let mut top = Layer::new(4, 4, "Top".to_string());
+        top.image = ImageBuffer::from_pixel(4, 4, Rgba([20, 20, 200, 255]));
+        top.set_offset(2, 2);
+        top.opacity = 0.5;
+        // The mask hides the left half of the upper layer
+        top.mask = Some(GrayImage::from_fn(4, 4, |x, _| image::Luma([if x < 2 { 0 } else { 255 }])));
+        manager.add_layer(bottom);
+        manager.add_layer(top);
+        
+        let composite = manager.flatten();
+        assert!(manager.merge_down(0).is_err());
+        let index = manager.merge_down(1).unwrap();
+        
+        assert_eq!(index, 0);
+        assert_eq!(manager.layer_count(), 1);
+        assert_eq!(manager.get_active_layer_index(), 0);
+        let merged = manager.get_layer(0).unwrap();
+        assert_eq!(merged.name, "Bottom");
+        assert_eq!(merged.image, composite);
+        // Masked off: still red. Unmasked: half blue
+        let hidden = merged.image.get_pixel(2, 3);
+        assert!(hidden[0] >= 199 && hidden[2] <= 21);
+        let shown = merged.image.get_pixel(5, 3);
+        assert!(shown[0] > 100 && shown[0] < 120 && shown[2] > 100 && shown[2] < 120);
+        
+        // A layer hanging over the edge grows the merged layer to cover it
+        let mut overhang = Layer::new(4, 4, "Overhang".to_string());
+        overhang.image = ImageBuffer::from_pixel(4, 4, Rgba([0, 255, 0, 255]));
+        overhang.set_offset(6, -2);
+        manager.add_layer(overhang);
+        manager.merge_down(1).unwrap();
+        let merged = manager.get_layer(0).unwrap();
+        assert_eq!((merged.width, merged.height, merged.x_offset, merged.y_offset), (10, 10, 0, -2));
+        assert_eq!(*merged.image.get_pixel(9, 0), Rgba([0, 255, 0, 255]));
+        assert_eq!(*merged.image.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        
+        // An adjustment layer has no pixels to merge into; nothing changes
+        manager.add_layer(Layer::new_adjustment(8, 8, "Invert".to_string(), Box::new(crate::filters::InvertFilter::new())));
+        manager.add_layer(Layer::new(8, 8, "Above".to_string()));
+        let before = manager.clone();
+        assert!(manager.merge_down(2).is_err());
+        assert_eq!(manager, before);
+    }
+    
+    #[test]
+    fn test_duplicate_layer_gets_fresh_ids() {
+        use crate::core::LayerManager;
+        use std::collections::HashSet;
+        
+        let mut manager = LayerManager::new();
+        let mut first = Layer::new(4, 4, "First".to_string());
+        first.image = ImageBuffer::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        manager.add_layer(first);
+        manager.add_layer(Layer::new(4, 4, "Second".to_string()));
+        manager.add_layer(Layer::new(4, 4, "Above".to_string()));
+        let group = manager.convert_to_smart_object(&[0, 1]).unwrap();
+        manager.get_layer_mut(group).unwrap().name = "Group".to_string();
+        
+        assert_eq!(manager.duplicate_layer(7), None);
+        let copy = manager.duplicate_layer(group).unwrap();
+        assert_eq!(copy, group + 1);
+        assert_eq!(manager.get_active_layer_index(), copy);
+        assert_eq!(manager.layer_count(), 3);
+        assert_eq!(manager.get_layer(copy + 1).unwrap().name, "Above");
+        
+        let original = manager.get_layer(group).unwrap();
+        let duplicate = manager.get_layer(copy).unwrap();
+        assert_eq!(duplicate.name, "Group copy");
+        assert_eq!(duplicate.image, original.image);
+        
+        let children = |layer: &Layer| layer.smart_object.as_ref().unwrap().layers.clone();
+        assert_eq!(children(duplicate).len(), 2);
+        assert_eq!(children(duplicate)[0].image, children(original)[0].image);
+        
+        let mut ids = HashSet::new();
+        for layer in [original, duplicate] {
+            ids.insert(layer.id.clone());
+            for child in children(layer) {
+                ids.insert(child.id);
+            }
+        }
+        assert_eq!(ids.len(), 6, "every layer and child needs its own id");
+    }
+    
+    #[test]
+    fn test_filter_undo_restores_changed_region() {
+        use crate::core::history::LayerPixelsCommand;
+        use crate::filters::BrightnessFilter;
+        
+        // White stays white under a brightness boost, so only the gray block changes
+        let original = ImageBuffer::from_fn(32, 24, |x, y| {
+            if (10..14).contains(&x) && (5..8).contains(&y) {
+                Rgba([100, 120, 140, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut document = Document::new(32, 24);
+        document.layer_manager.get_layer_mut(0).unwrap().image = original.clone();
+        
+        let filter = BrightnessFilter::new(0.2);
+        let brightened = filter.apply(&original);
+        let delta = LayerPixelsCommand::from_diff("Brightness", "id".to_string(), &original, &brightened).unwrap();
+        assert_eq!(delta.region(), (10, 5, 4, 3));
+        assert_eq!(delta.memory_size(), 2 * 4 * 3 * 4);
+        assert!(LayerPixelsCommand::from_diff("None", "id".to_string(), &original, &original).is_none());
+        
+        document.apply_filter(&filter).unwrap();
+        assert_eq!(document.layer_manager.get_layer(0).unwrap().image, brightened);
+        assert!(document.undo());
+        assert_eq!(document.layer_manager.get_layer(0).unwrap().image, original);
+        assert!(document.redo());
+        assert_eq!(document.layer_manager.get_layer(0).unwrap().image, brightened);
+        
+        // The oldest steps fall off past the undo limit
+        document.set_undo_limit(2);
+        for _ in 0..3 {
+            document.apply_filter(&BrightnessFilter::new(-0.1)).unwrap();
+        }
+        assert_eq!(document.history.state_count(), 2);
+    }
+    
+    #[test]
+    fn test_transform_decompose_recovers_components() {
+        use crate::vector::{Point as VectorPoint, Transform};
+        
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let composed = Transform::translation(12.0, -7.0)
+            .multiply(&Transform::rotation(30.0))
+            .multiply(&Transform::scale(2.0, 0.5));
+        let (translation, rotation, scale, skew) = composed.decompose();
+        assert!(close(translation.x, 12.0) && close(translation.y, -7.0));
+        assert!(close(rotation, 30.0));
+        assert!(close(scale.0, 2.0) && close(scale.1, 0.5));
+        assert!(close(skew.0, 0.0) && close(skew.1, 0.0));
+        
+        // A shear survives the round trip too
+        let sheared = Transform::from_components(VectorPoint::new(3.0, 4.0), -45.0, (1.5, 3.0), (0.3, 0.0));
+        let (translation, rotation, scale, skew) = sheared.decompose();
+        assert!(close(translation.x, 3.0) && close(translation.y, 4.0));
+        assert!(close(rotation, -45.0));
+        assert!(close(scale.0, 1.5) && close(scale.1, 3.0));
+        assert!(close(skew.0, 0.3) && close(skew.1, 0.0));
+        
+        let moved = Transform::skew(std::f64::consts::FRAC_PI_4, 0.0).apply_to_point(&VectorPoint::new(0.0, 2.0));
+        assert!(close(moved.x, 2.0) && close(moved.y, 2.0));
+    }
+    
+    #[test]
+    fn test_gaussian_blur_sigma_and_radius() {
+        let blur = GaussianBlur::from_radius(3.0);
+        assert!((blur.sigma - 1.0).abs() < 1e-6);
+        assert!((blur.radius() - 3.0).abs() < 1e-6);
+        assert_eq!(GaussianBlur::new(2.5).sigma, GaussianBlur::from_sigma(2.5).sigma);
+        
+        let image = ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgba([if (x + y) % 2 == 0 { 255 } else { 0 }, (x * 16) as u8, (y * 16) as u8, 255])
+        });
+        assert_eq!(GaussianBlur::from_sigma(0.0).apply(&image), image);
+        assert_eq!(GaussianBlur::from_sigma(-1.0).apply(&image), image);
+        assert_ne!(blur.apply(&image), image);
+    }
+    
+    #[test]
+    fn test_histogram_of_half_black_half_white() {
+        use crate::core::HistogramChannel;
+        use crate::core::selection::Selection;
+        
+        let mut document = Document::new(20, 10);
+        document.layer_manager.get_layer_mut(0).unwrap().image = ImageBuffer::from_fn(20, 10, |x, _| {
+            if x < 10 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        });
+        
+        for channel in [HistogramChannel::Red, HistogramChannel::Green, HistogramChannel::Blue, HistogramChannel::Luminance] {
+            let histogram = document.compute_histogram(channel);
+            assert_eq!(histogram[0], 100, "{:?}", channel);
+            assert_eq!(histogram[255], 100, "{:?}", channel);
+            assert_eq!(histogram.iter().sum::<u32>(), 200);
+        }
+        assert_eq!(document.compute_histogram(HistogramChannel::Alpha)[255], 200);
+        
+        // Only the 6x10 strip on the white side is counted
+        let selection = Selection::rectangle(14.0, 0.0, 6, 10, 20, 10);
+        let histogram = document.compute_selection_histogram(HistogramChannel::Luminance, &selection);
+        assert_eq!(histogram[0], 0);
+        assert_eq!(histogram[255], 60);
+    }
+    
+    #[test]
+    fn test_pattern_fill_tiles_image() {
+        use crate::vector::{FillStyle, PatternFill, Point as VectorPoint};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checker.png");
+        let checker = ImageBuffer::from_fn(2, 2, |x, y| {
+            if (x + y) % 2 == 0 { Rgba([255u8, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) }
+        });
+        checker.save(&path).unwrap();
+        
+        let render = |fill: FillStyle| {
+            let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 64, 64).unwrap();
+            {
+                let context = cairo::Context::new(&surface).unwrap();
+                fill.apply(&context);
+                context.rectangle(0.0, 0.0, 64.0, 64.0);
+                context.fill().unwrap();
+            }
+            surface.flush();
+            let stride = surface.stride() as usize;
+            let data = surface.data().unwrap().to_vec();
+            move |x: usize, y: usize| {
+                let i = y * stride + x * 4;
+                [data[i + 2], data[i + 1], data[i], data[i + 3]]
+            }
+        };
+        
+        // Each image pixel becomes an 8x8 cell. With the half-pixel offset
+        // the pixel centers sampled below land on cell centers, which keeps
+        // the pattern's filtering out of the comparison.
+        let pattern = PatternFill::new(path.to_str().unwrap())
+            .with_scale(8.0)
+            .with_offset(VectorPoint::new(3.5, 3.5));
+        let pixel = render(FillStyle::Pattern(pattern));
+        let close = |actual: [u8; 4], expected: [u8; 4]| {
+            actual.iter().zip(expected.iter()).all(|(a, e)| (*a as i32 - *e as i32).abs() <= 2)
+        };
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        assert!(close(pixel(7, 7), red), "{:?}", pixel(7, 7));
+        assert!(close(pixel(15, 7), blue), "{:?}", pixel(15, 7));
+        assert!(close(pixel(7, 15), blue), "{:?}", pixel(7, 15));
+        assert!(close(pixel(23, 7), red), "{:?}", pixel(23, 7));
+        assert!(close(pixel(55, 55), red), "{:?}", pixel(55, 55));
+        // Left of the offset the tiling wraps around to the blue column
+        assert!(pixel(0, 7)[2] > 200 && pixel(0, 7)[0] < 55, "{:?}", pixel(0, 7));
+        
+        // A missing file fills with nothing instead of failing
+        let missing = render(FillStyle::Pattern(PatternFill::new("/nonexistent/pattern.png")));
+        assert_eq!(missing(10, 10)[3], 0);
+    }
+    
+    #[test]
+    fn test_selection_path_round_trip() {
+        use crate::core::selection::Selection;
+        
+        let original = Selection::rectangle(10.0, 8.0, 20, 12, 40, 30);
+        let path = original.to_path();
+        assert!(!path.is_empty());
+        let restored = Selection::from_path(&path, 40, 30);
+        
+        // Away from the edge the masks must agree exactly; on it, to within a pixel
+        let selected = |selection: &Selection, x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < 40 && y < 30 && selection.mask.get_pixel(x as u32, y as u32)[0] >= 128
+        };
+        for y in 0..30 {
+            for x in 0..40 {
+                if selected(&original, x, y) == selected(&restored, x, y) {
+                    continue;
+                }
+                let near_edge = (-1..=1).any(|dy| (-1..=1).any(|dx| {
+                    selected(&original, x + dx, y + dy) != selected(&original, x, y)
+                }));
+                assert!(near_edge, "({}, {}) changed away from the selection edge", x, y);
+            }
+        }
+        let bounds = (restored.x, restored.y, restored.width, restored.height);
+        assert!((bounds.0 - 10.0).abs() <= 1.0 && (bounds.1 - 8.0).abs() <= 1.0, "{:?}", bounds);
+        assert!((bounds.2 as i32 - 20).abs() <= 1 && (bounds.3 as i32 - 12).abs() <= 1, "{:?}", bounds);
+        
+        // An empty path selects nothing
+        let empty = Selection::from_path(&crate::vector::VectorPath::new(), 40, 30);
+        assert!(empty.mask.pixels().all(|p| p[0] == 0));
+    }
+    
+    #[test]
+    fn test_ellipse_and_lasso_masks_are_antialiased() {
+        use crate::core::selection::Selection;
+        
+        let ellipse = Selection::ellipse(2.0, 2.0, 12, 8, 16, 12);
+        let value = |selection: &Selection, x: u32, y: u32| selection.mask.get_pixel(x, y)[0];
+        assert_eq!(value(&ellipse, 8, 6), 255);
+        assert_eq!(value(&ellipse, 0, 0), 0);
+        // The ends of the long axis sit right on the edge
+        for (x, y) in [(14, 6), (2, 6), (8, 2), (8, 10)] {
+            let v = value(&ellipse, x, y);
+            assert!(v > 0 && v < 255, "({}, {}) = {}", x, y, v);
+        }
+        let partial = ellipse.mask.pixels().filter(|p| p[0] > 0 && p[0] < 255).count();
+        assert!(partial >= 16, "only {} edge pixels are partial", partial);
+        
+        // A square whose sides run through the middle of pixels 3 and 7
+        let square = vec![Point::new(3.0, 3.0), Point::new(7.0, 3.0), Point::new(7.0, 7.0), Point::new(3.0, 7.0)];
+        let lasso = Selection::lasso(square, 12, 12);
+        assert_eq!(value(&lasso, 5, 5), 255);
+        assert_eq!(value(&lasso, 1, 5), 0);
+        assert_eq!(value(&lasso, 3, 5), 128);
+        assert_eq!(value(&lasso, 7, 5), 128);
+        assert_eq!(value(&lasso, 3, 3), 64);
+    }
+    
+    #[test]
+    fn test_color_space_round_trips() {
+        use crate::core::Color;
+        
+        let close = |a: Color, b: Color, epsilon: f32| {
+            (a.r - b.r).abs() < epsilon
+                && (a.g - b.g).abs() < epsilon
+                && (a.b - b.b).abs() < epsilon
+                && (a.a - b.a).abs() < epsilon
+        };
+        let colors = [
+            Color::rgb(1.0, 0.0, 0.0),
+            Color::rgb(0.2, 0.6, 0.3),
+            Color::rgb(0.9, 0.8, 0.1),
+            Color::rgb(0.05, 0.1, 0.95),
+            Color::new(0.7, 0.2, 0.65, 0.4),
+            Color::new(0.5, 0.5, 0.5, 0.0),
+            Color::black(),
+            Color::white(),
+        ];
+        for color in colors {
+            let (h, s, l) = color.to_hsl();
+            assert!(close(Color::from_hsl(h, s, l, color.a), color, 1e-5), "HSL {:?}", color);
+            let (h, s, v) = color.to_hsv();
+            assert!(close(Color::from_hsv(h, s, v, color.a), color, 1e-5), "HSV {:?}", color);
+            let (l, a, b) = color.to_lab();
+            assert!(close(Color::from_lab(l, a, b, color.a), color, 1e-3), "Lab {:?}", color);
+        }
+        
+        // Known values with hue in degrees, and grays with no hue or saturation
+        let (h, s, l) = Color::rgb(1.0, 0.0, 0.0).to_hsl();
+        assert!(h.abs() < 1e-6 && (s - 1.0).abs() < 1e-6 && (l - 0.5).abs() < 1e-6);
+        assert!((Color::rgb(0.0, 0.0, 1.0).to_hsl().0 - 240.0).abs() < 1e-3);
+        assert!(close(Color::from_hsv(120.0, 1.0, 1.0, 1.0), Color::rgb(0.0, 1.0, 0.0), 1e-5));
+        assert_eq!(Color::rgb(0.5, 0.5, 0.5).to_hsv(), (0.0, 0.0, 0.5));
+        let (l, a, b) = Color::white().to_lab();
+        assert!((l - 100.0).abs() < 0.1 && a.abs() < 0.1 && b.abs() < 0.1);
+        
+        // Far outside the sRGB gamut still gives a valid color
+        let clipped = Color::from_lab(50.0, 120.0, -120.0, 1.0);
+        for channel in [clipped.r, clipped.g, clipped.b] {
+            assert!((0.0..=1.0).contains(&channel));
+        }
+    }
+    
+    #[cfg(feature = "gpu-wgpu")]
+    #[test]
+    fn test_gpu_gaussian_blur_matches_cpu() {
+        use crate::filters::gpu;
+        
+        if let Err(e) = gpu::init() {
+            eprintln!("Skipping GPU blur comparison: {}", e);
+            return;
+        }
+        let image = ImageBuffer::from_fn(256, 256, |x, y| {
+            let checker = if (x / 8 + y / 8) % 2 == 0 { 230 } else { 20 };
+            Rgba([checker, (x % 256) as u8, (y % 256) as u8, 255 - (x / 2) as u8])
+        });
+        
+        let gpu_result = gpu::gaussian_blur(&image, 2.0).expect("GPU blur failed");
+        let cpu_result = imageproc::filter::gaussian_blur_f32(&image, 2.0);
+        let worst = gpu_result.as_raw().iter().zip(cpu_result.as_raw())
+            .map(|(a, b)| (*a as i32 - *b as i32).abs())
+            .max()
+            .unwrap();
+        assert!(worst <= 2, "GPU and CPU blur differ by {}", worst);
+        
+        // Tiny images stay on the CPU
+        let tiny = ImageBuffer::from_pixel(8, 8, Rgba([1u8, 2, 3, 4]));
+        assert!(gpu::gaussian_blur(&tiny, 2.0).is_none());
+    }
+    
+    #[test]
+    fn test_sobel_edge_detect_lights_up_vertical_edge() {
+        use crate::filters::SobelEdgeDetect;
+        
+        // Dark on the left, light from column 10 on
+        let image = ImageBuffer::from_fn(20, 12, |x, _| {
+            if x < 10 { Rgba([20u8, 20, 20, 128]) } else { Rgba([220, 220, 220, 128]) }
+        });
+        let edges = SobelEdgeDetect::new().apply(&image);
+        for y in 0..12 {
+            for x in [9, 10] {
+                assert!(edges.get_pixel(x, y)[0] > 150, "({}, {}) = {:?}", x, y, edges.get_pixel(x, y));
+            }
+            for x in [0, 4, 7, 12, 15, 19] {
+                assert!(edges.get_pixel(x, y)[0] < 5, "({}, {}) = {:?}", x, y, edges.get_pixel(x, y));
+            }
+        }
+        assert!(edges.pixels().all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+        
+        // In color mode a vertical edge is all horizontal gradient
+        let colored = SobelEdgeDetect::new().with_color(true).apply(&image);
+        let edge = colored.get_pixel(10, 6);
+        assert!(edge[0] > 150 && edge[1] == 0, "{:?}", edge);
+    }
+    
+
+    #[test]
+    fn test_tile_cache_invalidates_only_overlapping_tiles() {
+        use crate::core::Rect;
+
+        // 600x600 is three columns and three rows of 256 pixel tiles
+        let mut canvas = Canvas::new(600, 600);
+        let keys: Vec<_> = (0..3).flat_map(|row| (0..3).map(move |column| (column, row))).collect();
+        for &key in &keys {
+            canvas.composite_tile(key);
+        }
+        assert_eq!(canvas.cached_tiles(), keys);
+
+        let edit = Rect { x: 300.0, y: 20.0, width: 10.0, height: 10.0 };
+        if let Some(layer) = canvas.get_active_layer_mut() {
+            for y in 20..30 {
+                for x in 300..310 {
+                    layer.image.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+                }
+            }
+        }
+        assert_eq!(canvas.invalidate_region(edit), vec![(1, 0)]);
+        assert_eq!(canvas.cached_tiles().len(), 8);
+        assert!(!canvas.cached_tiles().contains(&(1, 0)));
+
+        // The tile is composited again with the edit; (1, 0) starts at x 256
+        let tile = canvas.composite_tile((1, 0));
+        assert_eq!(*tile.get_pixel(300 - 256, 20), Rgba([255, 0, 0, 255]));
+        assert_eq!(canvas.cached_tiles(), keys);
+
+        // An edit across a corner touches all four tiles around it
+        let corner = Rect { x: 250.0, y: 250.0, width: 10.0, height: 10.0 };
+        assert_eq!(canvas.invalidate_region(corner), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+
+        // Switching documents drops every tile of the old one
+        canvas.composite_tile((2, 2));
+        let document = std::rc::Rc::new(std::cell::RefCell::new(Document::new(600, 600)));
+        canvas.set_document(Some(document));
+        assert!(canvas.cached_tiles().is_empty());
+    }
+    
+
+    #[test]
+    fn test_point_and_rect_helpers() {
+        use crate::vector::{Point as VPoint, Rect as VRect};
+
+        let a = VPoint::new(3.0, 4.0);
+        let b = VPoint::new(1.0, -2.0);
+        assert_eq!(a.add(&b), VPoint::new(4.0, 2.0));
+        assert_eq!(a.sub(&b), VPoint::new(2.0, 6.0));
+        assert_eq!(a.scale(0.5), VPoint::new(1.5, 2.0));
+        assert_eq!(a.dot(&b), -5.0);
+        assert_eq!(a.normalize(), VPoint::new(0.6, 0.8));
+        assert!((a.normalize().distance(&VPoint::new(0.0, 0.0)) - 1.0).abs() < 1e-12);
+        // The difference of two points has the length `distance` reports
+        assert_eq!(a.sub(&b).distance(&VPoint::new(0.0, 0.0)), a.distance(&b));
+        // A zero-length vector normalizes to zero, not NaN
+        assert_eq!(VPoint::new(0.0, 0.0).normalize(), VPoint::new(0.0, 0.0));
+
+        let rect = VRect::new(10.0, 20.0, 30.0, 40.0);
+        assert_eq!(rect.inset(5.0), VRect::new(15.0, 25.0, 20.0, 30.0));
+        assert_eq!(rect.inset(-5.0), VRect::new(5.0, 15.0, 40.0, 50.0));
+        // Insetting past the middle collapses onto the center
+        assert_eq!(rect.inset(20.0), VRect::new(25.0, 40.0, 0.0, 0.0));
+        assert_eq!(rect.offset(-10.0, 5.0), VRect::new(0.0, 25.0, 30.0, 40.0));
+
+        let bounds = VRect::new(0.0, 0.0, 32.0, 50.0);
+        assert_eq!(rect.clamp_to(&bounds), VRect::new(10.0, 20.0, 22.0, 30.0));
+        assert_eq!(rect.offset(-20.0, -30.0).clamp_to(&bounds), VRect::new(0.0, 0.0, 20.0, 30.0));
+        let outside = rect.offset(100.0, 0.0).clamp_to(&bounds);
+        assert_eq!((outside.width, outside.height), (0.0, 30.0));
+    }
+    
+
+    #[test]
+    fn test_open_jpeg_applies_exif_orientation() {
+        use image::codecs::jpeg::JpegEncoder;
+        use image::ImageEncoder;
+
+        // Stored sideways: left half red, right half blue
+        let stored: ImageBuffer<image::Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(32, 16, |x, _| {
+            if x < 16 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) }
+        });
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 95)
+            .write_image(stored.as_raw(), 32, 16, image::ColorType::Rgb8)
+            .unwrap();
+
+        // An APP1 segment with a one-entry IFD: Orientation (0x0112), SHORT, 6
+        let tiff: [u8; 26] = [
+            b'I', b'I', 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00,
+            0x01, 0x00,
+            0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut app1 = vec![0xFF, 0xE1, 0x00, (2 + 6 + tiff.len()) as u8];
+        app1.extend_from_slice(b"Exif\0\0");
+        app1.extend_from_slice(&tiff);
+        jpeg.splice(2..2, app1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sideways.jpg");
+        std::fs::write(&path, &jpeg).unwrap();
+
+        let document = Document::open(&path).unwrap();
+        assert_eq!(document.metadata.exif.as_ref().map(|exif| exif.orientation), Some(6));
+
+        // Turned 90° clockwise: the red half is now on top
+        let image = document.get_image().unwrap();
+        assert_eq!(image.dimensions(), (16, 32));
+        let top = image.get_pixel(8, 8);
+        let bottom = image.get_pixel(8, 24);
+        assert!(top[0] > 200 && top[2] < 60, "{:?}", top);
+        assert!(bottom[2] > 200 && bottom[0] < 60, "{:?}", bottom);
+    }
+    
+
+    #[test]
+    fn test_brush_stabilization_smooths_jitter() {
+        use crate::tools::{BrushTool, ToolImpl};
+
+        // A shaky horizontal line: y jumps 6 pixels either side of 30
+        let input: Vec<(f64, f64)> = (0..30)
+            .map(|i| (10.0 + 4.0 * i as f64, if i % 2 == 0 { 24.0 } else { 36.0 }))
+            .collect();
+        let variance = |values: &[f64]| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+        };
+
+        let mut canvas = Canvas::from_image(ImageBuffer::from_pixel(140, 60, Rgba([255, 255, 255, 255])));
+        let mut brush = BrushTool::new();
+        brush.size = 1.0;
+        brush.hardness = 1.0;
+        brush.stabilization = 0.8;
+        brush.on_mouse_down(&mut canvas, 10.0, 30.0);
+        for &(x, y) in &input {
+            brush.on_mouse_drag(&mut canvas, x, y);
+        }
+        brush.on_mouse_up(&mut canvas, 130.0, 50.0);
+
+        // Center of the painted line in each column it crosses
+        let image = &canvas.layer_manager.get_active_layer().unwrap().image;
+        let centers: Vec<f64> = (20..100)
+            .filter_map(|x| {
+                let (mut total, mut weight) = (0.0, 0.0);
+                for y in 0..image.height() {
+                    let ink = 255.0 - image.get_pixel(x, y)[0] as f64;
+                    total += ink * y as f64;
+                    weight += ink;
+                }
+                (weight > 0.0).then(|| total / weight)
+            })
+            .collect();
+        assert!(centers.len() > 60);
+        let input_ys: Vec<f64> = input.iter().map(|&(_, y)| y).collect();
+        assert!(variance(&centers) < variance(&input_ys) / 4.0,
+                "{} vs {}", variance(&centers), variance(&input_ys));
+
+        // Releasing flushes the lag: the stroke reaches the release point
+        assert_eq!(image.get_pixel(130, 50)[0], 0);
+    }
+    
+
+    #[test]
+    fn test_applying_half_black_mask_clears_alpha() {
+        use image::{GrayImage, Luma};
+        
+        let mut layer = Layer::from_image(ImageBuffer::from_pixel(20, 10, Rgba([200, 100, 50, 255])), "Masked".to_string());
+        assert!(layer.apply_mask().is_err());
+        assert!(!layer.invert_mask());
+        
+        // Left half black (hidden), right half white (shown)
+        let half_black = GrayImage::from_fn(20, 10, |x, _| Luma([if x < 10 { 0 } else { 255 }]));
+        layer.mask = Some(half_black.clone());
+        layer.apply_mask().unwrap();
+        assert!(layer.mask.is_none());
+        for (x, _, pixel) in layer.image.enumerate_pixels() {
+            if x < 10 {
+                assert_eq!(pixel[3], 0);
+            } else {
+                assert_eq!(*pixel, Rgba([200, 100, 50, 255]));
+            }
+        }
+        
+        // Inverting swaps the halves; deleting leaves the pixels alone
+        let mut layer = Layer::from_image(ImageBuffer::from_pixel(20, 10, Rgba([10, 20, 30, 200])), "Inverted".to_string());
+        layer.mask = Some(half_black.clone());
+        assert!(layer.invert_mask());
+        assert_eq!(layer.mask.as_ref().unwrap().get_pixel(3, 3)[0], 255);
+        assert_eq!(layer.mask.as_ref().unwrap().get_pixel(15, 3)[0], 0);
+        assert_eq!(layer.delete_mask().map(|mask| mask.get_pixel(15, 3)[0]), Some(0));
+        assert!(layer.mask.is_none());
+        assert!(layer.image.pixels().all(|pixel| pixel[3] == 200));
+        
+        // A gray mask scales alpha
+        layer.mask = Some(GrayImage::from_pixel(20, 10, Luma([128])));
+        layer.apply_mask().unwrap();
+        assert_eq!(layer.image.get_pixel(3, 3)[3], 100);
+    }
+    
+    
+    #[test]
+    fn test_arc_to_bezier_degenerate_cases() {
+        use crate::vector::arc_to_bezier;
+
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        // A zero radius is a straight line: one cubic with collinear controls
+        let flat = arc_to_bezier(0.0, 0.0, 0.0, 10.0, 0.0, false, true, 30.0, 60.0);
+        assert_eq!(flat.len(), 6);
+        assert!(flat.iter().all(|v| v.is_finite()));
+        assert!(close(flat[0], 10.0) && close(flat[1], 20.0));
+        assert!(close(flat[2], 20.0) && close(flat[3], 40.0));
+        assert_eq!((flat[4], flat[5]), (30.0, 60.0));
+        for radii in [(-5.0, 5.0), (f64::NAN, 5.0)] {
+            let line = arc_to_bezier(0.0, 0.0, radii.0, radii.1, 0.0, false, true, 30.0, 60.0);
+            assert_eq!(line, flat, "{:?}", radii);
+        }
+
+        // Coincident endpoints draw nothing
+        assert!(arc_to_bezier(5.0, 5.0, 10.0, 10.0, 0.0, true, true, 5.0, 5.0).is_empty());
+
+        // A regular half circle still ends where it should
+        let half = arc_to_bezier(0.0, 0.0, 10.0, 10.0, 0.0, false, true, 20.0, 0.0);
+        assert_eq!(half.len(), 12);
+        assert!(half.iter().all(|v| v.is_finite()));
+        assert!(close(half[10], 20.0) && close(half[11], 0.0));
+        assert!(close(half[4], 10.0) && close(half[5].abs(), 10.0));
+    }
+    
+
+    #[test]
+    fn test_posterize_two_levels() {
+        use crate::core::adjustment::PosterizeAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::PosterizeFilter;
+
+        let image = ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 180])
+        });
+        let posterized = PosterizeFilter::new(2, true).apply(&image);
+        for (input, output) in image.pixels().zip(posterized.pixels()) {
+            for c in 0..3 {
+                assert!(output[c] == 0 || output[c] == 255, "{:?}", output);
+                assert_eq!(output[c] == 255, input[c] >= 128);
+            }
+            assert_eq!(output[3], 180);
+        }
+
+        // A Posterize adjustment layer bands the layers below the same way
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image.clone(), "Gradient".to_string()));
+        let adjustment = PosterizeAdjustment { levels: 2, per_channel: true };
+        manager.add_layer(Layer::new_adjustment(64, 64, "Posterize".to_string(), Box::new(adjustment)));
+        assert_eq!(manager.flatten(), posterized);
+
+        // Evenly spaced bands
+        let lut = PosterizeFilter::new(4, true).lut();
+        assert_eq!([lut[0], lut[63], lut[64], lut[128], lut[255]], [0, 0, 85, 170, 255]);
+    }
+    
+
+    #[test]
+    fn test_threshold_splits_at_cutoff() {
+        use crate::core::adjustment::ThresholdAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::ThresholdFilter;
+
+        // A grey ramp, so each column's luminance is its x coordinate
+        let ramp = ImageBuffer::from_fn(256, 4, |x, _| Rgba([x as u8, x as u8, x as u8, 200]));
+        let result = ThresholdFilter::new(100, true).apply(&ramp);
+        for (x, _, pixel) in result.enumerate_pixels() {
+            let expected = if x > 100 { 255 } else { 0 };
+            assert_eq!(*pixel, Rgba([expected, expected, expected, 200]), "x = {}", x);
+        }
+
+        // A Threshold adjustment layer splits the layers below the same way
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(ramp.clone(), "Ramp".to_string()));
+        let adjustment = ThresholdAdjustment { threshold: 100, keep_alpha: true };
+        manager.add_layer(Layer::new_adjustment(256, 4, "Threshold".to_string(), Box::new(adjustment)));
+        assert_eq!(manager.flatten(), result);
+
+        // Colors go by luminance, and alpha can be dropped
+        let colors = ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgba([0, 255, 0, 10]) } else { Rgba([0, 0, 255, 10]) }
+        });
+        let result = ThresholdFilter::new(100, false).apply(&colors);
+        assert_eq!(*result.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*result.get_pixel(1, 0), Rgba([0, 0, 0, 255]));
+    }
+    
+
+    #[test]
+    fn test_vibrance_favours_muted_colors() {
+        use crate::core::adjustment::VibranceAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::{SaturationFilter, VibranceFilter};
+
+        let saturation = |p: &Rgba<u8>| {
+            let max = p[0].max(p[1]).max(p[2]) as f32;
+            let min = p[0].min(p[1]).min(p[2]) as f32;
+            (max - min) / max
+        };
+        let nearly_gray = Rgba([120, 120, 135, 255]);
+        let red = Rgba([230, 30, 30, 255]);
+        let image = ImageBuffer::from_fn(2, 1, |x, _| if x == 0 { nearly_gray } else { red });
+
+        let vibrant = VibranceFilter::new(1.0).apply(&image);
+        let gray_gain = saturation(vibrant.get_pixel(0, 0)) - saturation(&nearly_gray);
+        let red_gain = saturation(vibrant.get_pixel(1, 0)) - saturation(&red);
+        assert!(gray_gain > 0.0 && red_gain >= 0.0);
+        assert!(gray_gain > red_gain, "gray {} vs red {}", gray_gain, red_gain);
+        // Vibrance stops short of clipping the red channel
+        assert!(vibrant.get_pixel(1, 0)[0] < 255);
+
+        // A Vibrance adjustment layer gives the filter's result
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image.clone(), "Colors".to_string()));
+        let adjustment = VibranceAdjustment { amount: 1.0 };
+        manager.add_layer(Layer::new_adjustment(2, 1, "Vibrance".to_string(), Box::new(adjustment)));
+        assert_eq!(manager.flatten(), vibrant);
+
+        // A plain saturation boost pushes the red channel into clipping
+        let saturated = SaturationFilter::new(2.0).apply(&image);
+        assert_eq!(saturated.get_pixel(1, 0)[0], 255);
+
+        // Skin tones are spared compared with another hue of equal saturation
+        let skin = Rgba([200, 150, 120, 255]);
+        let teal = Rgba([120, 200, 185, 255]);
+        let filter = VibranceFilter::new(1.0);
+        assert!(saturation(&filter.adjust_pixel(skin)) - saturation(&skin)
+            < saturation(&filter.adjust_pixel(teal)) - saturation(&teal));
+    }
+    
+
+    #[test]
+    fn test_channel_mixer_swaps_red_and_blue() {
+        use crate::core::adjustment::ChannelMixerAdjustment;
+        use crate::core::LayerManager;
+        use crate::filters::ChannelMixerFilter;
+
+        let swap = ChannelMixerFilter::new([[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]], [0.0; 3], false);
+        let image = ImageBuffer::from_pixel(3, 3, Rgba([255, 0, 0, 128]));
+        for pixel in swap.apply(&image).pixels() {
+            assert_eq!(*pixel, Rgba([0, 0, 255, 128]));
+        }
+
+        // A Channel Mixer adjustment layer swaps the layers below
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::from_image(image.clone(), "Red".to_string()));
+        let adjustment = ChannelMixerAdjustment { matrix: swap.matrix, ..ChannelMixerAdjustment::default() };
+        manager.add_layer(Layer::new_adjustment(3, 3, "Mixer".to_string(), Box::new(adjustment)));
+        assert!(manager.flatten().pixels().all(|pixel| *pixel == Rgba([0, 0, 255, 128])));
+
+        // Monochrome takes the first row for all channels, offsets included
+        let mono = ChannelMixerFilter::new([[0.5, 0.25, 0.25], [0.0; 3], [0.0; 3]], [10.0, 0.0, 0.0], true);
+        assert_eq!(mono.mix_pixel(Rgba([200, 100, 40, 255])), Rgba([145, 145, 145, 255]));
+        assert_eq!(ChannelMixerFilter::identity().mix_pixel(Rgba([1, 2, 3, 4])), Rgba([1, 2, 3, 4]));
+    }
+    
+
+    #[test]
+    fn test_zoom_to_fit_frames_document() {
+        use crate::core::Rect;
+
+        let inside = |p: crate::vector::Point, w: f64, h: f64| {
+            p.x >= -1e-9 && p.y >= -1e-9 && p.x <= w + 1e-9 && p.y <= h + 1e-9
+        };
+        for (width, height) in [(4000, 3000), (100, 50), (300, 900)] {
+            let mut canvas = Canvas::new(width, height);
+            canvas.pan(1234.0, -56.0);
+            canvas.zoom_to_fit(800, 600);
+
+            let corners = [(0.0, 0.0), (width as f64, 0.0), (0.0, height as f64), (width as f64, height as f64)];
+            for (x, y) in corners {
+                let screen = canvas.canvas_to_screen(x, y);
+                assert!(inside(screen, 800.0, 600.0), "{}x{} corner {:?} at {:?}", width, height, (x, y), screen);
+            }
+            // Fitted along one axis and centered along the other
+            let top_left = canvas.canvas_to_screen(0.0, 0.0);
+            let bottom_right = canvas.canvas_to_screen(width as f64, height as f64);
+            assert!((top_left.x + bottom_right.x - 800.0).abs() < 1e-9);
+            assert!((top_left.y + bottom_right.y - 600.0).abs() < 1e-9);
+            assert!((bottom_right.x - top_left.x - 800.0).abs() < 1e-9 || (bottom_right.y - top_left.y - 600.0).abs() < 1e-9);
+            // The view center maps back to the document center
+            let center = canvas.screen_to_canvas(400.0, 300.0);
+            assert!((center.x - width as f64 / 2.0).abs() < 1e-9 && (center.y - height as f64 / 2.0).abs() < 1e-9);
+        }
+
+        let mut canvas = Canvas::new(1000, 1000);
+        canvas.zoom_to_rect(Rect { x: 100.0, y: 200.0, width: 200.0, height: 100.0 }, 800, 600);
+        assert_eq!(canvas.zoom, 4.0);
+        let top_left = canvas.canvas_to_screen(100.0, 200.0);
+        assert_eq!((top_left.x, top_left.y), (0.0, 100.0));
+    }
+    
+
+    #[test]
+    fn test_add_noise_is_seeded_and_unbiased() {
+        use crate::filters::{AddNoise, NoiseDistribution};
+
+        let gray = ImageBuffer::from_pixel(64, 64, Rgba([128, 128, 128, 255]));
+        let mean = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            image.pixels().map(|p| p[0] as f64 + p[1] as f64 + p[2] as f64).sum::<f64>() / (image.len() / 4 * 3) as f64
+        };
+
+        for distribution in [NoiseDistribution::Uniform, NoiseDistribution::Gaussian] {
+            let noise = AddNoise::new(0.1, distribution).with_seed(7);
+            let first = noise.apply(&gray);
+            assert_eq!(first, noise.apply(&gray), "{:?} isn't reproducible", distribution);
+            assert_ne!(first, noise.clone().with_seed(8).apply(&gray));
+            assert_ne!(first, gray);
+            assert!((mean(&first) - 128.0).abs() < 1.0, "{:?} mean {}", distribution, mean(&first));
+            assert!(first.pixels().all(|p| p[3] == 255));
+        }
+
+        // Monochrome noise keeps pixels gray
+        let mono = AddNoise::new(0.2, NoiseDistribution::Gaussian).with_monochrome(true).with_seed(3).apply(&gray);
+        assert!(mono.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+        assert!(mono.pixels().any(|p| p[0] != 128));
+    }
+    
+
+    #[test]
+    fn test_white_balance_temperature_warms_gray() {
+        use crate::filters::WhiteBalanceFilter;
+
+        let gray = ImageBuffer::from_pixel(4, 4, Rgba([128, 128, 128, 255]));
+        let at = |kelvin: f32| *WhiteBalanceFilter::new(kelvin, 0.0).apply(&gray).get_pixel(1, 1);
+
+        // Daylight is neutral
+        assert_eq!(at(5500.0), Rgba([128, 128, 128, 255]));
+
+        let warm = at(6500.0);
+        let warmer = at(7500.0);
+        assert!(warm[0] > 128 && warm[2] < 128, "{:?}", warm);
+        assert!(warmer[0] > warm[0] && warmer[2] < warm[2], "{:?} vs {:?}", warmer, warm);
+        assert_eq!(warmer[1], 128);
+
+        let cool = at(3200.0);
+        assert!(cool[0] < 128 && cool[2] > 128, "{:?}", cool);
+
+        // Positive tint takes out green; with green as the reference that
+        // lifts red and blue instead
+        let tinted = *WhiteBalanceFilter::new(5500.0, 50.0).apply(&gray).get_pixel(0, 0);
+        assert!(tinted[1] == 128 && tinted[0] > 128 && tinted[0] == tinted[2], "{:?}", tinted);
+    }
+    
+    
+    #[test]
+    fn test_reorder_layer_keeps_active_layer() {
+        use crate::core::LayerManager;
+        
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::new(10, 10, "Background".to_string()));
+        manager.add_layer(Layer::new(10, 10, "Middle".to_string()));
+        manager.add_layer(Layer::new(10, 10, "Top".to_string()));
+        manager.set_active_layer(1);
+        let active_id = manager.get_active_layer().unwrap().id.clone();
+        
+        manager.reorder(0, 2).unwrap();
+        let names: Vec<&str> = (0..3).map(|i| manager.get_layer(i).unwrap().name.as_str()).collect();
+        assert_eq!(names, vec!["Middle", "Top", "Background"]);
+        assert_eq!(manager.get_active_layer_index(), 0);
+        assert_eq!(manager.get_active_layer().unwrap().id, active_id);
+        
+        assert!(manager.reorder(3, 0).is_err());
+        assert!(manager.reorder(0, 3).is_err());
+    }
+    
+    
+    #[test]
+    fn test_import_svg_rect_and_path() {
+        use crate::vector::DocumentImpl;
+        use crate::vector::{AsAny, FillStyle, VectorObject};
+        
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <defs>
+                <linearGradient id="fade"><stop offset="0" stop-color="#f00"/><stop offset="1" stop-color="blue"/></linearGradient>
+            </defs>
+            <rect x="10" y="20" width="50" height="30" fill="url(#fade)"/>
+            <path d="M 100 10 l 40 0 L 140 60 H 100 Z" fill="#00ff00"/>
+            <text x="0" y="0">ignored</text>
+        </svg>"##;
+        let document = DocumentImpl::from_svg_str(svg).unwrap();
+        assert_eq!((document.width, document.height), (200.0, 100.0));
+        
+        let objects = &document.layers[0].objects;
+        assert_eq!(objects.len(), 2);
+        let rect = objects[0].get_bounds();
+        assert_eq!((rect.x, rect.y, rect.width, rect.height), (10.0, 20.0, 50.0, 30.0));
+        let path = objects[1].get_bounds();
+        assert_eq!((path.x, path.y, path.width, path.height), (100.0, 10.0, 40.0, 50.0));
+        
+        let shape = AsAny::as_any(&*objects[0]).downcast_ref::<crate::vector::ShapeImpl>().unwrap();
+        assert!(matches!(shape.fill, FillStyle::Gradient(_)));
+    }
+    
+    
+    #[test]
+    fn test_blend_pixel_modes() {
+        use crate::core::{blend_pixel, LayerBlendMode};
+        
+        let gray = |v: u8| Rgba([v, v, v, 255]);
+        let blend = |base: u8, over: u8, mode| blend_pixel(gray(base), gray(over), mode, 1.0)[0];
+        
+        // 200 * 100 / 255 = 78.4
+        assert_eq!(blend(200, 100, LayerBlendMode::Multiply), 78);
+        // 255 - 55 * 155 / 255 = 221.6
+        assert_eq!(blend(200, 100, LayerBlendMode::Screen), 222);
+        // Light base screens: 255 - 2 * 55 * 155 / 255 = 188.1
+        assert_eq!(blend(200, 100, LayerBlendMode::Overlay), 188);
+        // Dark base multiplies: 2 * 64 * 100 / 255 = 50.2
+        assert_eq!(blend(64, 100, LayerBlendMode::Overlay), 50);
+        assert_eq!(blend(200, 100, LayerBlendMode::Difference), 100);
+        assert_eq!(blend(100, 200, LayerBlendMode::Difference), 100);
+        // 100 / (1 - 128 / 255) = 100 * 255 / 127 = 200.8
+        assert_eq!(blend(100, 128, LayerBlendMode::ColorDodge), 201);
+        assert_eq!(blend(100, 255, LayerBlendMode::ColorDodge), 255);
+        assert_eq!(blend(0, 200, LayerBlendMode::ColorDodge), 0);
+        
+        // Opacity mixes the blended color with the base
+        assert_eq!(blend_pixel(gray(0), gray(255), LayerBlendMode::Normal, 0.5), gray(128));
+        assert_eq!(blend_pixel(gray(90), gray(255), LayerBlendMode::Screen, 0.0), gray(90));
+        // Over a transparent base the source is left unblended
+        let red = Rgba([200, 0, 0, 255]);
+        assert_eq!(blend_pixel(Rgba([0, 0, 0, 0]), red, LayerBlendMode::Multiply, 1.0), red);
+    }
+    
+    
+    #[test]
+    fn test_crop_rotated_straightens_region() {
+        use crate::core::Rect;
+        
+        // A red bar tilted 30° clockwise through the center of the image
+        let angle: f64 = 30.0;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let image = ImageBuffer::from_fn(100, 100, |x, y| {
+            let (dx, dy) = (x as f64 + 0.5 - 50.0, y as f64 + 0.5 - 50.0);
+            // Distance across the bar, measured in its own frame
+            let across = -dx * sin + dy * cos;
+            if across.abs() < 3.0 { Rgba([255, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        });
+        let mut canvas = Canvas::new(100, 100);
+        canvas.layer_manager.get_layer_mut(0).unwrap().image = image;
+        
+        canvas.crop_rotated(Rect { x: 20.0, y: 30.0, width: 60.0, height: 40.0 }, angle).unwrap();
+        assert_eq!((canvas.width, canvas.height), (60, 40));
+        
+        // The bar now runs straight across the middle row
+        let layer = canvas.layer_manager.get_layer(0).unwrap();
+        assert_eq!((layer.image.width(), layer.image.height()), (60, 40));
+        for x in 5..55 {
+            assert!(layer.image.get_pixel(x, 20)[1] < 60, "bar missing at ({}, 20)", x);
+            assert!(layer.image.get_pixel(x, 8)[1] > 200, "bar leaked to ({}, 8)", x);
+            assert!(layer.image.get_pixel(x, 32)[1] > 200, "bar leaked to ({}, 32)", x);
+        }
+    }
+    
+    
+    #[test]
+    fn test_gradient_tool_fills_layer() {
+        use crate::core::Selection;
+        use crate::tools::GradientTool;
+        use crate::vector::Point as VectorPoint;
+        
+        let mut tool = GradientTool::new();
+        tool.start_point = Some(VectorPoint::new(0.0, 5.0));
+        tool.end_point = Some(VectorPoint::new(99.0, 5.0));
+        
+        let mut layer = Layer::new(100, 10, "Gradient".to_string());
+        assert!(tool.apply(&mut layer));
+        assert_eq!(*layer.image.get_pixel(0, 3), Rgba([0, 0, 0, 255]));
+        assert_eq!(*layer.image.get_pixel(99, 3), Rgba([255, 255, 255, 255]));
+        let middle = layer.image.get_pixel(50, 3)[0];
+        assert!(middle > 120 && middle < 135, "middle is {}", middle);
+        
+        // Outside the selection the layer keeps its pixels
+        let mut layer = Layer::new(100, 10, "Gradient".to_string());
+        let selection = Selection::rectangle(50.0, 0.0, 50, 10, 100, 10);
+        assert!(tool.apply_in_selection(&mut layer, Some(&selection)));
+        assert_eq!(layer.image.get_pixel(10, 3)[3], 0);
+        assert_eq!(*layer.image.get_pixel(99, 3), Rgba([255, 255, 255, 255]));
+    }
+    
+    
+    #[test]
+    fn test_render_text_layer() {
+        use crate::core::LayerManager;
+        use crate::core::text::{TextAlignment, TextLayerData};
+        
+        let data = TextLayerData {
+            text: "Hello".to_string(),
+            font_family: "Sans".to_string(),
+            font_size: 20.0,
+            color: [0, 0, 0, 255],
+            bold: false,
+            italic: false,
+            underline: false,
+            alignment: TextAlignment::Left,
+            letter_spacing: 0.0,
+            line_height: 1.2,
+        };
+        let mut manager = LayerManager::new();
+        let white = image::ImageBuffer::from_pixel(200, 40, image::Rgba([255, 255, 255, 255]));
+        manager.add_layer(Layer::from_image(white, "Background".to_string()));
+        manager.add_layer(Layer::new_text(200, 40, "Title".to_string(), data.clone()));
+        let flat = manager.flatten();
+        assert_eq!(flat.dimensions(), (200, 40));
+        
+        // Glyphs sit on a baseline roughly four fifths of the font size
+        // down, at the left edge; the rest of the background shows through
+        let inked = |x0: u32, x1: u32, y0: u32, y1: u32| {
+            (y0..y1).any(|y| (x0..x1).any(|x| flat.get_pixel(x, y)[0] < 128))
+        };
+        assert!(inked(0, 80, 4, 20));
+        assert!(!inked(120, 200, 0, 40));
+        assert!(!inked(0, 200, 30, 40));
+        
+        // The text stays editable; the stored pixels were never drawn into
+        let text_layer = &manager.get_layers()[1];
+        assert_eq!(text_layer.text.as_ref(), Some(&data));
+        assert!(text_layer.image.pixels().all(|pixel| pixel[3] == 0));
+    }
+    
+    
+    #[test]
+    fn test_parallel_adjustments_match_serial() {
+        use crate::filters::{
+            apply_brightness, apply_brightness_rayon, apply_contrast, apply_contrast_rayon, apply_hue,
+            apply_hue_rayon, apply_saturation, apply_saturation_rayon,
+        };
+        
+        let image = ImageBuffer::from_fn(512, 512, |x, y| {
+            Rgba([(x % 256) as u8, (y % 256) as u8, ((x * 7 + y * 13) % 256) as u8, ((x + y) % 256) as u8])
+        });
+        for amount in [-0.35, 0.0, 0.2, 0.73] {
+            assert_eq!(apply_brightness(&image, amount).as_raw(), apply_brightness_rayon(&image, amount).as_raw());
+            assert_eq!(apply_contrast(&image, amount).as_raw(), apply_contrast_rayon(&image, amount).as_raw());
+            assert_eq!(apply_saturation(&image, amount).as_raw(), apply_saturation_rayon(&image, amount).as_raw());
+        }
+        for hue in [-90.0, 0.0, 45.0, 180.0] {
+            assert_eq!(apply_hue(&image, hue).as_raw(), apply_hue_rayon(&image, hue).as_raw());
+        }
+    }
+    
+    
+    #[test]
+    fn test_sharpen_filter_amount() {
+        use crate::filters::SharpenFilter;
+        
+        // Soft vertical edge from dark to light around x = 20
+        let image = ImageBuffer::from_fn(40, 20, |x, _| {
+            let v = (80.0 + 100.0 / (1.0 + (-(x as f32 - 20.0) / 2.0).exp())).round() as u8;
+            Rgba([v, v, v, 255])
+        });
+        
+        assert_eq!(SharpenFilter::new(0.0, 3.0).apply(&image).as_raw(), image.as_raw());
+        
+        let edge_change = |amount: f32| {
+            let result = SharpenFilter::new(amount, 3.0).apply(&image);
+            (image.get_pixel(23, 10)[0] as i32 - result.get_pixel(23, 10)[0] as i32).abs()
+        };
+        let (low, high) = (edge_change(0.5), edge_change(2.0));
+        assert!(low > 0);
+        assert!(high > low, "amount 2.0 changed the edge by {}, 0.5 by {}", high, low);
+        assert_eq!(SharpenFilter::new(2.0, 3.0).apply(&image).get_pixel(23, 10)[3], 255);
+    }
+    
+    
+    #[test]
+    fn test_render_linked_smart_object() {
+        use crate::core::LayerManager;
+        use crate::core::linked::LinkedTransform;
+        use crate::filters::{resample_image, ResampleFilter};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("linked.png");
+        let source = ImageBuffer::from_fn(8, 6, |x, y| Rgba([(x * 30) as u8, (y * 40) as u8, 200, 255]));
+        source.save(&path).unwrap();
+        
+        let transform = LinkedTransform { x: 3, y: 2, scale_x: 2.0, scale_y: 2.0, ..Default::default() };
+        let mut manager = LayerManager::new();
+        manager.add_layer(Layer::new_linked(24, 20, "Linked".to_string(), path.clone(), transform));
+        let rendered = manager.flatten();
+        assert_eq!(rendered.dimensions(), (24, 20));
+        
+        let expected = resample_image(&source, 16, 12, ResampleFilter::Bilinear);
+        for (x, y, pixel) in rendered.enumerate_pixels() {
+            let inside = (3..19).contains(&x) && (2..14).contains(&y);
+            if inside {
+                assert_eq!(pixel, expected.get_pixel(x - 3, y - 2), "at {}, {}", x, y);
+            } else {
+                assert_eq!(pixel[3], 0, "at {}, {}", x, y);
+            }
+        }
+        
+        // The cached pixels are used even after the file goes away, and
+        // a duplicate shares them
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(manager.flatten(), rendered);
+        assert_eq!(manager.duplicate_layer(0), Some(1));
+        manager.get_layer_mut(0).unwrap().visible = false;
+        assert_eq!(manager.flatten(), rendered);
+        
+        // A broken link shows a visible placeholder instead of nothing
+        let mut broken = LayerManager::new();
+        broken.add_layer(Layer::new_linked(24, 20, "Missing".to_string(), path, transform));
+        assert!(broken.flatten().pixels().all(|p| p[3] > 0));
+    }
+    
+    
+    #[test]
+    fn test_emboss_and_pencil_sketch() {
+        use crate::filters::{Emboss, PencilSketch};
+        
+        let image = ImageBuffer::from_fn(30, 20, |x, _| {
+            if x < 15 { Rgba([60, 90, 40, 255]) } else { Rgba([220, 200, 180, 255]) }
+        });
+        
+        let relief = Emboss::new(180.0, 2.0).apply(&image);
+        for &x in &[2, 8, 22, 27] {
+            let pixel = relief.get_pixel(x, 10);
+            assert!((pixel[0] as i32 - 128).abs() <= 1, "flat area at x = {} is {:?}", x, pixel);
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[1], pixel[2]);
+        }
+        // Light from the left catches the step up
+        assert!(relief.get_pixel(15, 10)[0] > 200);
+        // and from the right the same step falls into shadow
+        assert!(Emboss::new(0.0, 2.0).apply(&image).get_pixel(15, 10)[0] < 60);
+        
+        let sketch = PencilSketch::new(2.0).apply(&image);
+        assert!(sketch.get_pixel(3, 10)[0] >= 250);
+        assert!(sketch.get_pixel(26, 10)[0] >= 250);
+        assert!(sketch.get_pixel(14, 10)[0] < 200);
+    }
+    
+    
+    #[test]
+    fn test_radial_blur_spin_keeps_rings() {
+        use crate::filters::{RadialBlur, RadialBlurMode};
+        
+        // A bright ring around the center, a short spoke crossing the
+        // circles inside it and a marker on the center pixel
+        let image = ImageBuffer::from_fn(41, 41, |x, y| {
+            let (dx, dy) = (x as f32 - 20.0, y as f32 - 20.0);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if x == 20 && y == 20 {
+                Rgba([255, 0, 0, 255])
+            } else if (distance - 14.0).abs() <= 1.5 {
+                Rgba([255, 255, 255, 255])
+            } else if y == 20 && (24..=30).contains(&x) {
+                Rgba([200, 200, 200, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        
+        let spun = RadialBlur::new((0.5, 0.5), 1.0, RadialBlurMode::Spin).apply(&image);
+        assert_eq!(spun.get_pixel(20, 20), image.get_pixel(20, 20));
+        assert!(spun.get_pixel(20, 34)[0] >= 200);
+        assert!(spun.get_pixel(6, 20)[0] >= 200);
+        // The spoke is smeared around its circles
+        assert!(spun.get_pixel(28, 20)[0] < 100);
+        assert!(spun.get_pixel(28, 22)[0] > 0);
+        
+        // Zoom keeps the spoke, which lies along a ray, but spreads the ring
+        let zoomed = RadialBlur::new((0.5, 0.5), 1.0, RadialBlurMode::Zoom).apply(&image);
+        assert_eq!(zoomed.get_pixel(20, 20), image.get_pixel(20, 20));
+        assert!(zoomed.get_pixel(27, 20)[0] > 100);
+        assert!(zoomed.get_pixel(20, 34)[0] < 200);
+    }
+    
+    
+    #[test]
+    fn test_apply_filter_masked_feathered() {
+        use crate::core::Selection;
+        use crate::filters::{apply_filter_masked, InvertFilter};
+        
+        let image = ImageBuffer::from_fn(40, 40, |x, y| Rgba([(x * 5) as u8, (y * 5) as u8, 60, 255]));
+        let mut selection = Selection::ellipse(10.0, 10.0, 20, 20, 40, 40);
+        selection.feather(3.0);
+        
+        let result = apply_filter_masked(&image, &InvertFilter::new(), &selection);
+        let inverted = |p: &Rgba<u8>| Rgba([255 - p[0], 255 - p[1], 255 - p[2], p[3]]);
+        
+        assert_eq!(*result.get_pixel(20, 20), inverted(image.get_pixel(20, 20)));
+        assert_eq!(result.get_pixel(2, 2), image.get_pixel(2, 2));
+        assert_eq!(result.get_pixel(38, 20), image.get_pixel(38, 20));
+        
+        // On the feathered edge the pixel lies strictly between the two
+        let weight = selection.mask.get_pixel(20, 10)[0];
+        assert!(weight > 0 && weight < 255);
+        let (before, after) = (image.get_pixel(20, 10)[2], result.get_pixel(20, 10)[2]);
+        assert!(after > before && after < 255 - before, "edge pixel {} from {}", after, before);
+    }
+    
+    #[test]
+    fn test_flatten_uses_every_blend_mode() {
+        use crate::core::{BlendMode, LayerManager};
+        
+        let mut manager = LayerManager::new();
+        let mut base = Layer::new(4, 4, "Base".to_string());
+        base.image = ImageBuffer::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+        let mut dodge = Layer::new(4, 4, "Dodge".to_string());
+        dodge.image = ImageBuffer::from_pixel(4, 4, Rgba([128, 128, 128, 255]));
+        dodge.blend_mode = BlendMode::ColorDodge;
+        manager.add_layer(base);
+        manager.add_layer(dodge);
+        
+        // 100 / (1 - 128 / 255) = 200.8, not the Normal result of 128
+        assert_eq!(*manager.flatten().get_pixel(1, 1), Rgba([201, 201, 201, 255]));
+        
+        // Half opacity lands halfway between the base and the dodged color
+        manager.get_layer_mut(1).unwrap().opacity = 0.5;
+        let pixel = manager.flatten().get_pixel(1, 1)[0];
+        assert!((150..=151).contains(&pixel), "{}", pixel);
+    }
+    
+    #[test]
+    fn test_masks_follow_rotate_and_crop() {
+        use image::{GrayImage, Luma};
+        
+        let mut doc = Document::new(4, 2);
+        let mut layer = Layer::from_image(ImageBuffer::from_pixel(4, 2, Rgba([255, 0, 0, 255])), "Masked".to_string());
+        // Only column 1 is hidden
+        layer.mask = Some(GrayImage::from_fn(4, 2, |x, _| if x == 1 { Luma([0]) } else { Luma([255]) }));
+        let index = doc.add_layer(layer);
+        
+        // A quarter turn clockwise takes column 1 to row 1
+        doc.rotate_layer(index, 90.0).unwrap();
+        let rotated = doc.layer_manager.get_layer(index).unwrap();
+        assert_eq!(rotated.image.dimensions(), (2, 4));
+        let expected = GrayImage::from_fn(2, 4, |_, y| if y == 1 { Luma([0]) } else { Luma([255]) });
+        assert_eq!(rotated.mask.as_ref(), Some(&expected));
+        
+        // Cropping from row 1 brings the hidden row to the top
+        doc.layer_manager.get_layer_mut(index).unwrap().crop(0, 1, 2, 2);
+        let cropped = doc.layer_manager.get_layer(index).unwrap();
+        let expected = GrayImage::from_fn(2, 2, |_, y| if y == 0 { Luma([0]) } else { Luma([255]) });
+        assert_eq!(cropped.mask.as_ref(), Some(&expected));
+        
+        // Arbitrary angles resample the mask into the grown frame too
+        doc.rotate_layer(index, 30.0).unwrap();
+        let turned = doc.layer_manager.get_layer(index).unwrap();
+        assert_eq!(turned.mask.as_ref().unwrap().dimensions(), turned.image.dimensions());
+    }
+}
@@ -1,14 +1,90 @@
+use std::fs;
 use std::path::Path;
-use image::DynamicImage;
+use image::{DynamicImage, ImageBuffer, Rgb};
+use log::{debug, info, warn};
 use crate::core::document::ColorSpace;
+use crate::filters::{linear_to_srgb, temperature_multipliers};
 
 pub fn init() -> Result<(), String> { Ok(()) }
 
+/// Magic bytes at the start of a raw sensor dump
+const RAW_DUMP_MAGIC: &[u8; 8] = b"RPHORAW1";
+/// Magic + width + height + CFA + black level + white level
+const RAW_DUMP_HEADER_LEN: usize = 24;
+
+/// Layout of the color filter array over the sensor, named by the colors of
+/// the top-left 2x2 block in reading order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfaPattern {
+    RGGB,
+    BGGR,
+    GRBG,
+    GBRG,
+}
+
+impl CfaPattern {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "RGGB" => Some(CfaPattern::RGGB),
+            "BGGR" => Some(CfaPattern::BGGR),
+            "GRBG" => Some(CfaPattern::GRBG),
+            "GBRG" => Some(CfaPattern::GBRG),
+            _ => None,
+        }
+    }
+    
+    /// Color channel (0 = red, 1 = green, 2 = blue) of the photosite at (x, y)
+    pub fn color_at(&self, x: u32, y: u32) -> usize {
+        let block = match self {
+            CfaPattern::RGGB => [0, 1, 1, 2],
+            CfaPattern::BGGR => [2, 1, 1, 0],
+            CfaPattern::GRBG => [1, 0, 2, 1],
+            CfaPattern::GBRG => [1, 2, 0, 1],
+        };
+        block[((y % 2) * 2 + x % 2) as usize]
+    }
+}
+
+/// Undemosaiced sensor data: one sample per photosite
 #[derive(Debug, Clone)]
 pub struct RawImage {
     width: u32,
     height: u32,
-    data: Vec<u8>,
+    cfa: CfaPattern,
+    black_level: u16,
+    white_level: u16,
+    data: Vec<u16>,
+}
+
+impl RawImage {
+    pub fn new(width: u32, height: u32, cfa: CfaPattern, black_level: u16, white_level: u16, data: Vec<u16>) -> Result<Self, String> {
+        if data.len() != width as usize * height as usize {
+            return Err(format!("Expected {} samples for {}x{}, got {}", width as usize * height as usize, width, height, data.len()));
+        }
+        if white_level <= black_level {
+            return Err("White level must be above the black level".to_string());
+        }
+        Ok(Self { width, height, cfa, black_level, white_level, data })
+    }
+    
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    
+    pub fn cfa(&self) -> CfaPattern {
+        self.cfa
+    }
+    
+    /// Sample at (x, y) scaled so black is 0.0 and white is 1.0
+    fn normalized(&self, x: u32, y: u32) -> f32 {
+        let value = self.data[(y * self.width + x) as usize];
+        let range = (self.white_level - self.black_level) as f32;
+        (value.saturating_sub(self.black_level) as f32 / range).min(1.0)
+    }
 }
 
 pub struct RawProcessor;
@@ -18,21 +94,157 @@ impl RawProcessor {
         Self
     }
     
+    /// Load a raw sensor dump.
+    ///
+    /// The format is little-endian: the 8 magic bytes `RPHORAW1`, u32 width,
+    /// u32 height, the 4-character CFA pattern (e.g. `RGGB`), u16 black
+    /// level, u16 white level, then one u16 sample per photosite row by row.
+    /// TIFF-based raws (DNG and most camera formats) aren't read yet.
     pub fn load_file(&self, path: &Path) -> Result<RawImage, String> {
-        // Just a mock implementation for now
-        Ok(RawImage {
-            width: 1000,
-            height: 750,
-            data: vec![0; 1000 * 750 * 3],
-        })
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        
+        if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+            return Err("TIFF-based raw files (DNG, CR2, NEF, ...) are not supported yet".to_string());
+        }
+        if bytes.len() < RAW_DUMP_HEADER_LEN || &bytes[..8] != RAW_DUMP_MAGIC {
+            return Err("Not a raw sensor dump".to_string());
+        }
+        
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        
+        let width = u32_at(8);
+        let height = u32_at(12);
+        let cfa_name = String::from_utf8_lossy(&bytes[16..20]).to_string();
+        let cfa = CfaPattern::from_name(&cfa_name)
+            .ok_or_else(|| format!("Unknown CFA pattern '{}'", cfa_name))?;
+        let black_level = u16_at(20);
+        let white_level = u16_at(22);
+        
+        let expected = RAW_DUMP_HEADER_LEN + width as usize * height as usize * 2;
+        if bytes.len() < expected {
+            return Err(format!("Raw data is truncated: expected {} bytes, got {}", expected, bytes.len()));
+        }
+        
+        let data = bytes[RAW_DUMP_HEADER_LEN..expected]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        
+        info!("Loaded {}x{} {:?} raw from {:?}", width, height, cfa, path);
+        RawImage::new(width, height, cfa, black_level, white_level, data)
     }
     
+    /// Develop a raw image: apply exposure and white balance as per-channel
+    /// multipliers, demosaic, and encode the result as 8-bit sRGB
     pub fn process_image(&self, image: &RawImage, params: &RawProcessingParams) -> DynamicImage {
-        // Mock processing based on params
-        DynamicImage::new_rgb8(image.width, image.height)
+        let balance = white_balance_multipliers(&params.white_balance, image);
+        let exposure = 2.0f32.powf(params.exposure_compensation);
+        debug!("Developing raw with exposure x{:.2}, white balance {:?}", exposure, balance);
+        
+        // Scale every photosite by the multiplier of its own color first
+        let mut scaled = Vec::with_capacity(image.data.len());
+        for y in 0..image.height {
+            for x in 0..image.width {
+                scaled.push(image.normalized(x, y) * exposure * balance[image.cfa.color_at(x, y)]);
+            }
+        }
+        
+        let rgb = match params.demosaic_algorithm {
+            DemosaicAlgorithm::Bilinear => demosaic_bilinear(image, &scaled),
+            other => {
+                warn!("{:?} demosaicing isn't implemented yet, using bilinear", other);
+                demosaic_bilinear(image, &scaled)
+            },
+        };
+        
+        let buffer = ImageBuffer::from_fn(image.width, image.height, |x, y| {
+            let pixel = rgb[(y * image.width + x) as usize];
+            Rgb([linear_to_srgb(pixel[0]), linear_to_srgb(pixel[1]), linear_to_srgb(pixel[2])])
+        });
+        DynamicImage::ImageRgb8(buffer)
     }
 }
 
+/// Fill in the two missing colors of every photosite by averaging the
+/// neighbours in its 3x3 window that carry that color. On a Bayer grid this
+/// is exactly bilinear interpolation: 2 or 4 neighbours depending on site.
+fn demosaic_bilinear(image: &RawImage, samples: &[f32]) -> Vec<[f32; 3]> {
+    let (width, height) = (image.width as i64, image.height as i64);
+    let mut output = vec![[0.0f32; 3]; samples.len()];
+    
+    for y in 0..height {
+        for x in 0..width {
+            let own = image.cfa.color_at(x as u32, y as u32);
+            let mut sums = [0.0f32; 3];
+            let mut counts = [0u32; 3];
+            
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        continue;
+                    }
+                    let color = image.cfa.color_at(nx as u32, ny as u32);
+                    // Greens at the diagonals of a green site are further away
+                    // than its red and blue neighbours; bilinear skips them
+                    if color == own && (dx != 0 || dy != 0) {
+                        continue;
+                    }
+                    sums[color] += samples[(ny * width + nx) as usize];
+                    counts[color] += 1;
+                }
+            }
+            
+            let pixel = &mut output[(y * width + x) as usize];
+            for c in 0..3 {
+                pixel[c] = if c == own {
+                    samples[(y * width + x) as usize]
+                } else if counts[c] > 0 {
+                    sums[c] / counts[c] as f32
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+    
+    output
+}
+
+/// Channel multipliers (green normalized to 1) for a white balance setting
+fn white_balance_multipliers(balance: &WhiteBalance, image: &RawImage) -> [f32; 3] {
+    let (kelvin, tint) = match balance {
+        // The dump carries no as-shot coefficients, so leave the data alone
+        WhiteBalance::AsShot => return [1.0, 1.0, 1.0],
+        WhiteBalance::Auto => {
+            // Gray world: make the average of each color equal
+            let mut sums = [0.0f64; 3];
+            let mut counts = [0u32; 3];
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    let color = image.cfa.color_at(x, y);
+                    sums[color] += image.normalized(x, y) as f64;
+                    counts[color] += 1;
+                }
+            }
+            let mean = |c: usize| if counts[c] > 0 { sums[c] / counts[c] as f64 } else { 0.0 };
+            let green = mean(1);
+            let multiplier = |c: usize| if mean(c) > 0.0 { (green / mean(c)) as f32 } else { 1.0 };
+            return [multiplier(0), 1.0, multiplier(2)];
+        },
+        WhiteBalance::Daylight => (5500.0, 0),
+        WhiteBalance::Cloudy => (6500.0, 0),
+        WhiteBalance::Shade => (7500.0, 0),
+        WhiteBalance::Tungsten => (3200.0, 0),
+        WhiteBalance::Fluorescent => (4000.0, 10),
+        WhiteBalance::Flash => (5500.0, 0),
+        WhiteBalance::Custom { temperature, tint } => (*temperature as f32, *tint),
+    };
+    
+    temperature_multipliers(kelvin, tint as f32)
+}
+
 #[derive(Debug, Clone)]
 pub struct RawProcessingParams {
     pub demosaic_algorithm: DemosaicAlgorithm,
@@ -1,15 +1,56 @@
 use crate::core::Canvas;
+use crate::core::canvas::BrushSettings;
 use crate::vector::Point;
+use image::{ImageBuffer, Rgba};
 use super::ToolImpl;
 
+/// Strength of a round dab `distance` pixels from its center, 0.0 - 1.0.
+/// Full strength out to `hardness * radius`, then a smooth fade to nothing
+/// at the rim.
+pub(crate) fn dab_falloff(distance: f64, radius: f64, hardness: f64) -> f64 {
+    if distance > radius {
+        return 0.0;
+    }
+    let core = radius * hardness.clamp(0.0, 1.0);
+    if distance <= core {
+        return 1.0;
+    }
+    let t = (radius - distance) / (radius - core);
+    t * t * (3.0 - 2.0 * t)
+}
+
 #[derive(Clone)]
 pub struct BrushTool {
+    /// Brush radius in pixels
     pub size: f64,
+    /// Fraction of the radius painted at full strength; beyond it the dab
+    /// fades out smoothly to the edge. 1.0 is a hard-edged disc.
     pub hardness: f64,
     pub opacity: f64,
+    /// Distance between dabs along a stroke, as a fraction of the diameter
+    pub spacing: f64,
+    /// Overlapping dabs within one stroke never paint more than `opacity`,
+    /// rather than building up towards full strength
+    pub limit_buildup: bool,
     pub color: [u8; 4],
     pub last_point: Option<Point>,
     pub active: bool,
+    /// Straight-line mode, normally driven by the Shift key
+    pub constrain: bool,
+    /// Smoothing of freehand strokes, 0.0 - 1.0. The painted line trails
+    /// the pointer, each move closing only part of the gap, so hand jitter
+    /// averages out; 0.0 paints exactly where the pointer goes.
+    pub stabilization: f32,
+    /// Where the current stroke started, used to snap constrained drags
+    stroke_start: Option<Point>,
+    /// Last dab of the previous stroke, so a constrained click can join it
+    last_stamp: Option<Point>,
+    /// Distance travelled since the last dab
+    residual: f64,
+    /// Layer pixels before the stroke, for `limit_buildup`
+    stroke_base: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    /// Strongest dab coverage of each layer pixel so far this stroke
+    stroke_coverage: Vec<f32>,
 }
 
 impl BrushTool {
@@ -18,9 +59,18 @@ impl BrushTool {
             size: 10.0,
             hardness: 0.5,
             opacity: 1.0,
+            spacing: 0.25,
+            limit_buildup: false,
             color: [0, 0, 0, 255],
             last_point: None,
             active: false,
+            constrain: false,
+            stabilization: 0.0,
+            stroke_start: None,
+            last_stamp: None,
+            residual: 0.0,
+            stroke_base: None,
+            stroke_coverage: Vec::new(),
         }
     }
     
@@ -29,95 +79,197 @@ impl BrushTool {
         
         if !active {
             self.last_point = None;
+            self.last_stamp = None;
+            self.end_stroke();
+        }
+    }
+    
+    pub fn set_constrain(&mut self, constrain: bool) {
+        self.constrain = constrain;
+    }
+    
+    /// Take size, hardness, opacity and stabilization from `settings`
+    pub fn apply_settings(&mut self, settings: &BrushSettings) {
+        self.size = settings.size;
+        self.hardness = settings.hardness;
+        self.opacity = settings.opacity;
+        self.stabilization = settings.stabilization;
+    }
+    
+    /// Where the painted line moves to when the pointer goes from the end
+    /// of the line at `last` to `target`: an exponential moving average
+    /// that covers less of the gap the higher the stabilization
+    pub fn stabilize(&self, last: Point, target: Point) -> Point {
+        // Capped so the line always keeps moving towards the pointer
+        let lag = self.stabilization.clamp(0.0, 0.95) as f64;
+        last.lerp(&target, 1.0 - lag)
+    }
+    
+    /// Snap `point` onto the nearest 45° ray from `origin`, keeping the
+    /// distance along that ray
+    pub fn constrain_to_45(origin: Point, point: Point) -> Point {
+        let dx = point.x - origin.x;
+        let dy = point.y - origin.y;
+        let step = std::f64::consts::FRAC_PI_4;
+        let angle = (dy.atan2(dx) / step).round() * step;
+        let length = dx * angle.cos() + dy * angle.sin();
+        Point::new(origin.x + length * angle.cos(), origin.y + length * angle.sin())
+    }
+    
+    /// Gap between dabs along a stroke, in pixels
+    pub fn dab_spacing(&self) -> f64 {
+        (self.spacing * self.size * 2.0).max(0.5)
+    }
+    
+    fn begin_stroke(&mut self, canvas: &Canvas) {
+        self.residual = 0.0;
+        self.stroke_base = None;
+        self.stroke_coverage.clear();
+        if self.limit_buildup {
+            if let Some(layer) = canvas.layer_manager.get_active_layer() {
+                self.stroke_coverage = vec![0.0; (layer.image.width() * layer.image.height()) as usize];
+                self.stroke_base = Some(layer.image.clone());
+            }
+        }
+    }
+    
+    fn end_stroke(&mut self) {
+        self.stroke_base = None;
+        self.stroke_coverage.clear();
+    }
+    
+    /// Paint a single brush dab centered at (x, y)
+    fn stamp(&mut self, canvas: &mut Canvas, x: f64, y: f64) {
+        let layer = match canvas.layer_manager.get_active_layer_mut() {
+            Some(layer) => layer,
+            None => return,
+        };
+        let buffer = &mut layer.image;
+        let (width, height) = buffer.dimensions();
+        let base = self.stroke_base.as_ref().filter(|base| base.dimensions() == (width, height));
+        
+        let radius = self.size.max(0.5);
+        let min_x = (x - radius).floor().max(0.0) as u32;
+        let min_y = (y - radius).floor().max(0.0) as u32;
+        let max_x = ((x + radius).ceil().max(0.0) as u32).min(width.saturating_sub(1));
+        let max_y = ((y + radius).ceil().max(0.0) as u32).min(height.saturating_sub(1));
+        if width == 0 || height == 0 || min_x > max_x || min_y > max_y {
+            return;
+        }
+        
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let strength = dab_falloff(((px as f64 - x).powi(2) + (py as f64 - y).powi(2)).sqrt(), radius, self.hardness);
+                if strength <= 0.0 {
+                    continue;
+                }
+                
+                // Capped strokes repaint from the pre-stroke pixels with the
+                // strongest coverage seen, so overlaps don't add up
+                let (under, alpha) = match base {
+                    Some(base) => {
+                        let coverage = &mut self.stroke_coverage[(py * width + px) as usize];
+                        *coverage = coverage.max(strength as f32);
+                        (base.get_pixel(px, py).0, *coverage as f64 * self.opacity)
+                    },
+                    None => (buffer.get_pixel(px, py).0, strength * self.opacity),
+                };
+                
+                let mut rgba = under;
+                for i in 0..3 {
+                    rgba[i] = ((1.0 - alpha) * under[i] as f64 + alpha * self.color[i] as f64).round() as u8;
+                }
+                buffer.put_pixel(px, py, Rgba(rgba));
+            }
+        }
+    }
+    
+    /// Stamp dabs every `dab_spacing` pixels along the segment from `from`
+    /// to `to`, carrying the leftover distance on to the next segment so
+    /// dabs stay evenly spaced however the mouse events arrive
+    fn stroke_line(&mut self, canvas: &mut Canvas, from: Point, to: Point) {
+        let length = from.distance_to(&to);
+        if length <= 0.0 {
+            return;
+        }
+        
+        let step = self.dab_spacing();
+        let mut travelled = step - self.residual;
+        let mut last_dab = -self.residual;
+        while travelled <= length {
+            let t = travelled / length;
+            self.stamp(canvas, from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+            last_dab = travelled;
+            travelled += step;
         }
+        self.residual = length - last_dab;
     }
 }
 
 impl ToolImpl for BrushTool {
     fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
         let point = Point::new(x, y);
-        self.last_point = Some(point);
         
-        // Draw a single dot at the current position
-        if let Some(layer) = canvas.layer_manager.get_active_layer_mut() {
-            // Draw a circle at the current position
-            // In a real implementation, we'd draw an anti-aliased circle using the brush parameters
-            // For now, just place a simple marker
-            let buffer = &mut layer.image;
-            let size = self.size as i32;
-            let cx = x as i32;
-            let cy = y as i32;
-            
-            // Simple circle drawing
-            for dy in -size..=size {
-                for dx in -size..=size {
-                    let px = cx + dx;
-                    let py = cy + dy;
-                    
-                    // Check if in bounds
-                    if px >= 0 && px < buffer.width() as i32 && 
-                       py >= 0 && py < buffer.height() as i32 {
-                        // Calculate distance from center
-                        let dist = ((dx*dx + dy*dy) as f64).sqrt();
-                        if dist <= self.size {
-                            // Calculate opacity based on distance and hardness
-                            let alpha = if dist < self.size * (1.0 - self.hardness) {
-                                self.opacity
-                            } else {
-                                let t = (self.size - dist) / (self.size * self.hardness);
-                                t * self.opacity
-                            };
-                            
-                            if alpha > 0.0 {
-                                let pixel = buffer.get_pixel(px as u32, py as u32);
-                                let mut rgba = pixel.0;
-                                
-                                // Simple alpha compositing
-                                for i in 0..3 {
-                                    rgba[i] = ((1.0 - alpha) * rgba[i] as f64 + 
-                                              alpha * self.color[i] as f64) as u8;
-                                }
-                                
-                                buffer.put_pixel(px as u32, py as u32, image::Rgba(rgba));
-                            }
-                        }
-                    }
+        self.begin_stroke(canvas);
+        
+        // A constrained click joins the previous stroke with a straight line
+        match self.last_stamp {
+            Some(previous) if self.constrain => {
+                self.stroke_line(canvas, previous, point);
+                if self.residual > 0.0 {
+                    self.stamp(canvas, x, y);
                 }
-            }
+            },
+            _ => self.stamp(canvas, x, y),
         }
+        self.residual = 0.0;
         
+        self.last_point = Some(point);
+        self.stroke_start = Some(point);
+        self.last_stamp = Some(point);
         true
     }
     
     fn on_mouse_drag(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        let mut curr = Point::new(x, y);
+        if self.constrain {
+            if let Some(start) = self.stroke_start {
+                curr = Self::constrain_to_45(start, curr);
+            }
+        }
+        
         if let Some(last) = self.last_point {
-            // Draw a line from last point to current point
-            // In a real implementation, we'd use a line drawing algorithm with proper anti-aliasing
-            // For simplicity, we'll just call on_mouse_down repeatedly along the line
-            
-            let curr = Point::new(x, y);
-            let dist = last.distance_to(&curr);
-            let step_size = self.size / 4.0;  // Make steps smaller than brush size for smooth lines
-            
-            if dist > 0.0 {
-                let steps = (dist / step_size).ceil() as usize;
-                
-                for i in 0..=steps {
-                    let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
-                    let ix = last.x + (curr.x - last.x) * t;
-                    let iy = last.y + (curr.y - last.y) * t;
-                    
-                    self.on_mouse_down(canvas, ix, iy);
-                }
+            curr = self.stabilize(last, curr);
+            if last.distance_to(&curr) > 0.0 {
+                self.stroke_line(canvas, last, curr);
             }
         }
         
-        self.last_point = Some(Point::new(x, y));
+        self.last_point = Some(curr);
+        self.last_stamp = Some(curr);
         true
     }
     
-    fn on_mouse_up(&mut self, _canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
+    fn on_mouse_up(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        // The stabilized line lags behind the pointer; catch up with it so
+        // the stroke ends where the button was released
+        if let (Some(last), Some(start)) = (self.last_point, self.stroke_start) {
+            let mut end = Point::new(x, y);
+            if self.constrain {
+                end = Self::constrain_to_45(start, end);
+            }
+            if self.stabilization > 0.0 && last.distance_to(&end) > 0.0 {
+                self.stroke_line(canvas, last, end);
+                if self.residual > 0.0 {
+                    self.stamp(canvas, end.x, end.y);
+                }
+                self.last_stamp = Some(end);
+            }
+        }
+        
         self.last_point = None;
+        self.stroke_start = None;
+        self.end_stroke();
         true
     }
     
@@ -192,8 +344,8 @@ impl super::Tool for BrushTool {
             context.arc(point.x, point.y, self.size, 0.0, 2.0 * std::f64::consts::PI);
             context.stroke();
             
-            // Draw inner circle showing hardness falloff
-            let inner_radius = self.size * (1.0 - self.hardness);
+            // Draw inner circle showing where the hardness falloff starts
+            let inner_radius = self.size * self.hardness.clamp(0.0, 1.0);
             if inner_radius > 0.0 {
                 context.set_source_rgba(
                     self.color[0] as f64 / 255.0,
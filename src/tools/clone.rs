@@ -1,16 +1,35 @@
 use crate::core::Canvas;
 use crate::vector::Point;
+use image::{ImageBuffer, Rgba};
+use super::brush::dab_falloff;
 use super::ToolImpl;
 
+/// Paint with pixels copied from another part of the layer.
+///
+/// A sampling click (Alt held) sets the source point. The first stroke
+/// after that pairs it with the stroke's start, and pixels are copied from
+/// `source + (current - stroke start)` through a round brush. In aligned
+/// mode the pairing is kept for later strokes, so the source keeps moving
+/// with the brush; otherwise every stroke samples from the source point
+/// again.
 #[derive(Clone)]
 pub struct CloneTool {
     pub size: f64,
     pub hardness: f64,
     pub opacity: f64,
+    /// Keep the source offset between strokes
+    pub aligned: bool,
+    /// Where sampling starts
     pub source_point: Option<Point>,
+    /// Destination paired with `source_point`
     pub destination_point: Option<Point>,
     pub last_point: Option<Point>,
     pub active: bool,
+    /// Clicks set the source instead of painting, normally while Alt is held
+    pub sampling: bool,
+    /// Layer pixels at the start of the stroke, which are what gets copied,
+    /// so a stroke never picks up what it has just painted
+    stroke_source: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
 }
 
 impl CloneTool {
@@ -19,15 +38,24 @@ impl CloneTool {
             size: 20.0,
             hardness: 0.5,
             opacity: 1.0,
+            aligned: true,
             source_point: None,
             destination_point: None,
             last_point: None,
             active: false,
+            sampling: false,
+            stroke_source: None,
         }
     }
     
+    /// Sample from (x, y) starting with the next stroke
     pub fn set_source(&mut self, x: f64, y: f64) {
         self.source_point = Some(Point::new(x, y));
+        self.destination_point = None;
+    }
+    
+    pub fn set_sampling(&mut self, sampling: bool) {
+        self.sampling = sampling;
     }
     
     pub fn set_active(&mut self, active: bool) {
@@ -35,54 +63,58 @@ impl CloneTool {
         
         if !active {
             self.last_point = None;
+            self.stroke_source = None;
             // Note: We don't clear source_point when deactivating
             // as we might want to remember the clone source
         }
     }
+    
+    /// Offset from destination to source pixels for the current stroke
+    pub fn source_offset(&self) -> Option<(i64, i64)> {
+        let (source, destination) = (self.source_point?, self.destination_point?);
+        Some(((source.x - destination.x).round() as i64, (source.y - destination.y).round() as i64))
+    }
 }
 
 impl ToolImpl for CloneTool {
     fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
-        // If Alt key is pressed (would be handled through event flags in real implementation)
-        // set the source point instead of cloning
-        // For now, we'll assume first click sets source, second click starts cloning
-        
-        if self.source_point.is_none() {
+        // Without a source yet, a plain click sets one too
+        if self.sampling || self.source_point.is_none() {
             self.set_source(x, y);
             return true;
         }
         
-        self.destination_point = Some(Point::new(x, y));
+        match canvas.layer_manager.get_active_layer() {
+            Some(layer) => self.stroke_source = Some(layer.image.clone()),
+            None => return false,
+        }
+        if !self.aligned || self.destination_point.is_none() {
+            self.destination_point = Some(Point::new(x, y));
+        }
         self.last_point = Some(Point::new(x, y));
         
-        // Actually clone the pixels
         self.clone_pixels(canvas, x, y);
-        
         true
     }
     
     fn on_mouse_drag(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
-        if self.source_point.is_none() || self.destination_point.is_none() {
+        if self.stroke_source.is_none() {
             return false;
         }
         
         if let Some(last) = self.last_point {
-            // Draw a line from last point to current point
-            // Similar to brush tool
+            // Dab along the segment so fast drags don't leave gaps
             let curr = Point::new(x, y);
             let dist = last.distance_to(&curr);
-            let step_size = self.size / 4.0;
+            let step_size = (self.size / 4.0).max(0.5);
+            let steps = (dist / step_size).ceil() as usize;
             
-            if dist > 0.0 {
-                let steps = (dist / step_size).ceil() as usize;
+            for i in 1..=steps {
+                let t = i as f64 / steps as f64;
+                let ix = last.x + (curr.x - last.x) * t;
+                let iy = last.y + (curr.y - last.y) * t;
                 
-                for i in 0..=steps {
-                    let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
-                    let ix = last.x + (curr.x - last.x) * t;
-                    let iy = last.y + (curr.y - last.y) * t;
-                    
-                    self.clone_pixels(canvas, ix, iy);
-                }
+                self.clone_pixels(canvas, ix, iy);
             }
         }
         
@@ -91,8 +123,10 @@ impl ToolImpl for CloneTool {
     }
     
     fn on_mouse_up(&mut self, _canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
+        let was_cloning = self.stroke_source.is_some();
         self.last_point = None;
-        true
+        self.stroke_source = None;
+        was_cloning
     }
     
     fn get_cursor(&self) -> Option<String> {
@@ -101,74 +135,49 @@ impl ToolImpl for CloneTool {
 }
 
 impl CloneTool {
+    /// Copy one brush dab centered at (x, y) from the source. Source pixels
+    /// outside the layer are skipped, leaving the destination as it was.
     fn clone_pixels(&self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
-        if let (Some(source), Some(dest)) = (self.source_point, self.destination_point) {
-            // Calculate offset between source and destination
-            let offset_x = source.x - dest.x;
-            let offset_y = source.y - dest.y;
-            
-            // Calculate source position corresponding to current position
-            let src_x = x + offset_x;
-            let src_y = y + offset_y;
-            
-            if let Some(layer) = canvas.layer_manager.get_active_layer_mut() {
-                let image = &mut layer.image;
-                let size = self.size as i32;
-                let cx = x as i32;
-                let cy = y as i32;
-                
-                let width = image.width() as i32;
-                let height = image.height() as i32;
+        let ((offset_x, offset_y), source) = match (self.source_offset(), &self.stroke_source) {
+            (Some(offset), Some(source)) => (offset, source),
+            _ => return false,
+        };
+        let layer = match canvas.layer_manager.get_active_layer_mut() {
+            Some(layer) => layer,
+            None => return false,
+        };
+        let image = &mut layer.image;
+        if image.dimensions() != source.dimensions() {
+            return false;
+        }
+        
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let radius = self.size.max(0.5);
+        let min_x = ((x - radius).floor() as i64).max(0);
+        let min_y = ((y - radius).floor() as i64).max(0);
+        let max_x = ((x + radius).ceil() as i64).min(width - 1);
+        let max_y = ((y + radius).ceil() as i64).min(height - 1);
+        
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let (sx, sy) = (px + offset_x, py + offset_y);
+                if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                    continue;
+                }
+                let distance = ((px as f64 - x).powi(2) + (py as f64 - y).powi(2)).sqrt();
+                let alpha = dab_falloff(distance, radius, self.hardness) * self.opacity;
+                if alpha <= 0.0 {
+                    continue;
+                }
                 
-                // Simple circle cloning
-                for dy in -size..=size {
-                    for dx in -size..=size {
-                        let px = cx + dx;
-                        let py = cy + dy;
-                        
-                        // Source pixel
-                        let sx = (src_x as i32) + dx;
-                        let sy = (src_y as i32) + dy;
-                        
-                        // Check if in bounds
-                        if px >= 0 && px < width && py >= 0 && py < height &&
-                           sx >= 0 && sx < width && sy >= 0 && sy < height {
-                            // Calculate distance from center
-                            let dist = ((dx*dx + dy*dy) as f64).sqrt();
-                            if dist <= self.size {
-                                // Calculate opacity based on distance and hardness
-                                let alpha = if dist < self.size * (1.0 - self.hardness) {
-                                    self.opacity
-                                } else {
-                                    let t = (self.size - dist) / (self.size * self.hardness);
-                                    t * self.opacity
-                                };
-                                
-                                if alpha > 0.0 {
-                                    // Get source pixel
-                                    let src_pixel = image.get_pixel(sx as u32, sy as u32);
-                                    // Get destination pixel
-                                    let dst_pixel = image.get_pixel(px as u32, py as u32);
-                                    
-                                    // Blend pixels
-                                    let mut rgba = dst_pixel.0;
-                                    
-                                    // Simple alpha compositing
-                                    for i in 0..4 {  // Include alpha channel
-                                        rgba[i] = ((1.0 - alpha) * rgba[i] as f64 + 
-                                                   alpha * src_pixel.0[i] as f64) as u8;
-                                    }
-                                    
-                                    image.put_pixel(px as u32, py as u32, image::Rgba(rgba));
-                                }
-                            }
-                        }
-                    }
+                let src_pixel = source.get_pixel(sx as u32, sy as u32);
+                let dst_pixel = image.get_pixel_mut(px as u32, py as u32);
+                for i in 0..4 {  // Include alpha channel
+                    dst_pixel[i] = ((1.0 - alpha) * dst_pixel[i] as f64 + alpha * src_pixel[i] as f64).round() as u8;
                 }
             }
-            return true;
         }
-        false
+        true
     }
 }
 
@@ -190,13 +199,7 @@ impl super::Tool for CloneTool {
     }
 
     fn set_active(&mut self, active: bool) {
-        self.active = active;
-        
-        if !active {
-            self.last_point = None;
-            // Note: We don't clear source_point when deactivating
-            // as we might want to remember the clone source
-        }
+        CloneTool::set_active(self, active);
     }
 
     fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
@@ -204,13 +207,9 @@ impl super::Tool for CloneTool {
             return;
         }
         
-        // If Alt key is pressed (would be handled through event flags in real implementation)
-        // set the source point instead of cloning
-        // For now, we'll assume first click sets source, second click starts cloning
-        if self.source_point.is_none() {
+        // Painting needs the canvas and goes through `on_mouse_down`
+        if self.sampling || self.source_point.is_none() {
             self.set_source(x, y);
-        } else {
-            self.destination_point = Some(Point::new(x, y));
         }
         
         self.last_point = Some(Point::new(x, y));
@@ -295,8 +294,8 @@ impl super::Tool for CloneTool {
             context.arc(point.x, point.y, self.size, 0.0, 2.0 * std::f64::consts::PI);
             context.stroke();
             
-            // Draw inner circle showing hardness falloff
-            let inner_radius = self.size * (1.0 - self.hardness);
+            // Draw inner circle showing where the hardness falloff starts
+            let inner_radius = self.size * self.hardness.clamp(0.0, 1.0);
             if inner_radius > 0.0 {
                 context.set_source_rgba(0.2, 0.5, 0.9, 0.2);
                 context.arc(point.x, point.y, inner_radius, 0.0, 2.0 * std::f64::consts::PI);
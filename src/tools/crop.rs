@@ -13,6 +13,8 @@ pub struct CropTool {
     pub end_y: Option<f64>,
     pub dragging: bool,
     pub handle_index: Option<usize>,
+    /// Clockwise turn of the crop frame in degrees, for straightening
+    pub angle: f64,
 }
 
 impl CropTool {
@@ -25,6 +27,7 @@ impl CropTool {
             end_y: None,
             dragging: false,
             handle_index: None,
+            angle: 0.0,
         }
     }
     
@@ -91,8 +94,18 @@ impl CropTool {
         })
     }
     
+    /// Turn the crop frame to `degrees` clockwise, kept within ±180
+    pub fn set_angle(&mut self, degrees: f64) {
+        self.angle = (degrees + 180.0).rem_euclid(360.0) - 180.0;
+    }
+    
+    pub fn is_rotated(&self) -> bool {
+        self.angle.abs() > f64::EPSILON
+    }
+    
     pub fn reset(&mut self) {
         self.clear_selection();
+        self.angle = 0.0;
     }
 }
 
@@ -210,20 +223,21 @@ impl Tool for CropTool {
             let canvas_width = canvas.width as f64;
             let canvas_height = canvas.height as f64;
             
-            // Top
-            context.rectangle(0.0, 0.0, canvas_width, y);
-            context.fill();
-            
-            // Left
-            context.rectangle(0.0, y, x, height);
-            context.fill();
-            
-            // Right
-            context.rectangle(x + width, y, canvas_width - (x + width), height);
-            context.fill();
+            // Everything from here on is drawn in the turned frame
+            let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+            context.translate(cx, cy);
+            context.rotate(self.angle.to_radians());
+            context.translate(-cx, -cy);
             
-            // Bottom
-            context.rectangle(0.0, y + height, canvas_width, canvas_height - (y + height));
+            // Darken all but the crop area: the canvas, with the frame cut out
+            context.save();
+            context.translate(cx, cy);
+            context.rotate(-self.angle.to_radians());
+            context.translate(-cx, -cy);
+            context.rectangle(0.0, 0.0, canvas_width, canvas_height);
+            context.restore();
+            context.rectangle(x, y, width, height);
+            context.set_fill_rule(cairo::FillRule::EvenOdd);
             context.fill();
             
             // Draw crop rectangle border
@@ -2,7 +2,7 @@ use crate::core::Canvas;
 use crate::vector::Point;
 use super::ToolImpl;
 use image::{Rgba, ImageBuffer};
-use crate::core::{Layer};
+use crate::core::{blend_pixel, Layer, LayerBlendMode, Selection};
 use crate::tools::{Tool, ToolType};
 use cairo::Context;
 
@@ -24,6 +24,12 @@ pub struct GradientTool {
     pub color2: Rgba<u8>,
     pub gradient_type: GradientType,
     pub active: bool,
+    /// Color stops as (position 0.0 - 1.0, color); when empty the gradient
+    /// runs from `color1` to `color2`
+    pub stops: Vec<(f64, Rgba<u8>)>,
+    /// How the gradient combines with what the layer already holds
+    pub blend_mode: LayerBlendMode,
+    pub opacity: f32,
 }
 
 impl GradientTool {
@@ -36,6 +42,9 @@ impl GradientTool {
             color2: Rgba([255, 255, 255, 255]),
             gradient_type: GradientType::Linear,
             active: false,
+            stops: Vec::new(),
+            blend_mode: LayerBlendMode::Normal,
+            opacity: 1.0,
         }
     }
     
@@ -48,6 +57,12 @@ impl GradientTool {
         self.gradient_type = gradient_type;
     }
     
+    /// Use `stops` instead of the two colors. They are sorted by position.
+    pub fn set_stops(&mut self, mut stops: Vec<(f64, Rgba<u8>)>) {
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        self.stops = stops;
+    }
+    
     pub fn set_active(&mut self, active: bool) {
         self.active = active;
         
@@ -59,42 +74,73 @@ impl GradientTool {
     }
     
     fn apply_gradient(&self, canvas: &mut Canvas) -> bool {
-        if let (Some(start), Some(end)) = (self.start_point, self.end_point) {
-            if let Some(layer) = canvas.layer_manager.get_active_layer_mut() {
-                let image = &mut layer.image;
-                let width = image.width();
-                let height = image.height();
-                
-                // Create a new buffer for the gradient
-                let mut gradient_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> = 
-                    ImageBuffer::new(width, height);
-                
-                match self.gradient_type {
-                    GradientType::Linear => {
-                        self.apply_linear_gradient(&mut gradient_buffer, start, end);
-                    },
-                    GradientType::Radial => {
-                        self.apply_radial_gradient(&mut gradient_buffer, start, end);
-                    },
-                    GradientType::Angular => {
-                        self.apply_angular_gradient(&mut gradient_buffer, start, end);
-                    },
-                    GradientType::Diamond => {
-                        self.apply_diamond_gradient(&mut gradient_buffer, start, end);
-                    },
-                    GradientType::Reflected => {
-                        self.apply_reflected_gradient(&mut gradient_buffer, start, end);
+        let selection = canvas.selection.as_ref();
+        match canvas.layer_manager.get_active_layer_mut() {
+            Some(layer) => self.apply_in_selection(layer, selection),
+            None => false,
+        }
+    }
+    
+    /// Render the dragged gradient into `layer`, combining it with the
+    /// existing pixels through `blend_mode` at `opacity`.
+    ///
+    /// Returns false when there is no drag to render or it has no length.
+    pub fn apply(&self, layer: &mut Layer) -> bool {
+        self.apply_in_selection(layer, None)
+    }
+    
+    /// Like `apply`, but only inside `selection`, whose mask is in canvas
+    /// coordinates; partly selected pixels get a partial gradient
+    pub fn apply_in_selection(&self, layer: &mut Layer, selection: Option<&Selection>) -> bool {
+        let (start, end) = match (self.start_point, self.end_point) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return false,
+        };
+        if start.distance_to(&end) < f64::EPSILON {
+            return false;
+        }
+        
+        // The drag is in canvas coordinates; render in the layer's own
+        let (ox, oy) = (layer.x_offset as f64, layer.y_offset as f64);
+        let (start, end) = (Point::new(start.x - ox, start.y - oy), Point::new(end.x - ox, end.y - oy));
+        let (width, height) = layer.image.dimensions();
+        let mut gradient_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
+        
+        match self.gradient_type {
+            GradientType::Linear => {
+                self.apply_linear_gradient(&mut gradient_buffer, start, end);
+            },
+            GradientType::Radial => {
+                self.apply_radial_gradient(&mut gradient_buffer, start, end);
+            },
+            GradientType::Angular => {
+                self.apply_angular_gradient(&mut gradient_buffer, start, end);
+            },
+            GradientType::Diamond => {
+                self.apply_diamond_gradient(&mut gradient_buffer, start, end);
+            },
+            GradientType::Reflected => {
+                self.apply_reflected_gradient(&mut gradient_buffer, start, end);
+            }
+        }
+        
+        for (x, y, pixel) in layer.image.enumerate_pixels_mut() {
+            let coverage = match selection {
+                Some(selection) => {
+                    let (cx, cy) = (x as i64 + layer.x_offset as i64, y as i64 + layer.y_offset as i64);
+                    if cx < 0 || cy < 0 || cx >= selection.mask.width() as i64 || cy >= selection.mask.height() as i64 {
+                        continue;
                     }
-                }
-                
-                // Copy the gradient to the layer image
-                *image = gradient_buffer;
-                
-                return true;
+                    selection.mask.get_pixel(cx as u32, cy as u32)[0] as f32 / 255.0
+                },
+                None => 1.0,
+            };
+            if coverage > 0.0 {
+                *pixel = blend_pixel(*pixel, *gradient_buffer.get_pixel(x, y), self.blend_mode, self.opacity * coverage);
             }
         }
         
-        false
+        true
     }
     
     fn apply_linear_gradient(&self, buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, 
@@ -242,13 +288,26 @@ impl GradientTool {
     }
     
     fn interpolate_colors(&self, t: f64) -> Rgba<u8> {
-        // Linear interpolation between color1 and color2 using t
-        let r = ((1.0 - t) * self.color1.0[0] as f64 + t * self.color2.0[0] as f64) as u8;
-        let g = ((1.0 - t) * self.color1.0[1] as f64 + t * self.color2.0[1] as f64) as u8;
-        let b = ((1.0 - t) * self.color1.0[2] as f64 + t * self.color2.0[2] as f64) as u8;
-        let a = ((1.0 - t) * self.color1.0[3] as f64 + t * self.color2.0[3] as f64) as u8;
+        let mix = |from: Rgba<u8>, to: Rgba<u8>, t: f64| {
+            let channel = |c: usize| ((1.0 - t) * from.0[c] as f64 + t * to.0[c] as f64) as u8;
+            Rgba([channel(0), channel(1), channel(2), channel(3)])
+        };
         
-        Rgba([r, g, b, a])
+        let stops = &self.stops;
+        if stops.is_empty() {
+            return mix(self.color1, self.color2, t);
+        }
+        if t <= stops[0].0 {
+            return stops[0].1;
+        }
+        for pair in stops.windows(2) {
+            let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+            if t <= p1 {
+                let span = p1 - p0;
+                return if span <= f64::EPSILON { c1 } else { mix(c0, c1, (t - p0) / span) };
+            }
+        }
+        stops[stops.len() - 1].1
     }
 }
 
@@ -273,7 +332,10 @@ impl ToolImpl for GradientTool {
             self.is_dragging = false;
             
             // Apply the gradient to the active layer
-            return self.apply_gradient(canvas);
+            if self.apply_gradient(canvas) {
+                canvas.invalidate_all();
+                return true;
+            }
         }
         false
     }
@@ -1,6 +1,7 @@
 use crate::core::{Canvas, Layer};
+use crate::filters::inpaint;
 use crate::vector::Point;
-use image::{ImageBuffer, Rgba};
+use image::{GrayImage, ImageBuffer, Luma, Rgba};
 use super::ToolImpl;
 use crate::tools::{Tool, ToolType};
 use cairo::Context;
@@ -10,6 +11,9 @@ pub struct HealSettings {
     pub radius: f64,
     pub hardness: f64,
     pub tolerance: f64,
+    /// Fill the brushed area from its surroundings instead of from a
+    /// chosen source point
+    pub content_aware: bool,
 }
 
 #[derive(Clone)]
@@ -19,9 +23,17 @@ pub struct HealTool {
     pub source_point: Option<Point>,
     pub destination_point: Option<Point>,
     pub last_point: Option<Point>,
+    /// Area brushed so far in a content-aware stroke, in layer pixels
+    stroke_mask: Option<GrayImage>,
 }
 
 impl HealTool {
+    /// Patch radius used to match texture around a content-aware fill
+    const PATCH_RADIUS: u32 = 3;
+    /// Width in pixels of the band outside the area that is cross-faded
+    /// into the fill
+    const BLEND_WIDTH: i64 = 2;
+    
     pub fn new() -> Self {
         Self {
             active: false,
@@ -29,10 +41,12 @@ impl HealTool {
                 radius: 10.0,
                 hardness: 0.5,
                 tolerance: 0.3,
+                content_aware: true,
             },
             source_point: None,
             destination_point: None,
             last_point: None,
+            stroke_mask: None,
         }
     }
     
@@ -43,6 +57,92 @@ impl HealTool {
             self.source_point = None;
             self.destination_point = None;
             self.last_point = None;
+            self.stroke_mask = None;
+        }
+    }
+    
+    /// Replace the pixels where `mask` is set with texture matched from
+    /// around them.
+    ///
+    /// Each unknown pixel copies the center of the best-matching known
+    /// patch nearby (see `filters::inpaint`). The fill is then cross-faded
+    /// over a narrow band just outside the area so it has no hard seam.
+    pub fn heal_region(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, mask: &GrayImage) -> Result<(), String> {
+        if mask.dimensions() != image.dimensions() {
+            return Err("Heal mask doesn't match the layer size".to_string());
+        }
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let in_hole = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && x < width && y < height && mask.get_pixel(x as u32, y as u32)[0] >= 128
+        };
+        
+        // Distance to the area for pixels within the blend band
+        let band = Self::BLEND_WIDTH;
+        let distance = |x: i64, y: i64| -> Option<f64> {
+            (-band..=band)
+                .flat_map(|dy| (-band..=band).map(move |dx| (dx, dy)))
+                .filter(|&(dx, dy)| in_hole(x + dx, y + dy))
+                .map(|(dx, dy)| ((dx * dx + dy * dy) as f64).sqrt())
+                .filter(|&d| d <= band as f64)
+                .min_by(f64::total_cmp)
+        };
+        let grown = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([if distance(x as i64, y as i64).is_some() { 255 } else { 0 }])
+        });
+        
+        let filled = inpaint(image, &grown, Self::PATCH_RADIUS)?;
+        for y in 0..height {
+            for x in 0..width {
+                let weight = match distance(x, y) {
+                    Some(d) => 1.0 - d / (band + 1) as f64,
+                    None => continue,
+                };
+                let new = filled.get_pixel(x as u32, y as u32);
+                let old = image.get_pixel_mut(x as u32, y as u32);
+                for c in 0..4 {
+                    old[c] = (old[c] as f64 * (1.0 - weight) + new[c] as f64 * weight).round() as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Heal the area inside the canvas selection on the active layer
+    pub fn heal_selection(&self, canvas: &mut Canvas) -> Result<(), String> {
+        let selection = canvas.selection.as_ref().ok_or_else(|| "Nothing is selected".to_string())?;
+        let layer = canvas.layer_manager.get_active_layer()
+            .ok_or_else(|| "No active layer".to_string())?;
+        let mask = GrayImage::from_fn(layer.image.width(), layer.image.height(), |x, y| {
+            let (sx, sy) = (x as i64 + layer.x_offset as i64, y as i64 + layer.y_offset as i64);
+            let selected = sx >= 0 && sy >= 0
+                && sx < selection.mask.width() as i64 && sy < selection.mask.height() as i64
+                && selection.mask.get_pixel(sx as u32, sy as u32)[0] >= 128;
+            Luma([if selected { 255 } else { 0 }])
+        });
+        
+        let layer = canvas.layer_manager.get_active_layer_mut()
+            .ok_or_else(|| "No active layer".to_string())?;
+        Self::heal_region(&mut layer.image, &mask)
+    }
+    
+    /// Add the brush disc at (x, y) to the content-aware stroke
+    fn mark_stroke(&mut self, x: f64, y: f64) {
+        let mask = match self.stroke_mask.as_mut() {
+            Some(mask) => mask,
+            None => return,
+        };
+        let radius = self.settings.radius.max(0.5);
+        let (width, height) = (mask.width() as i64, mask.height() as i64);
+        let min_x = ((x - radius).floor() as i64).max(0);
+        let min_y = ((y - radius).floor() as i64).max(0);
+        let max_x = ((x + radius).ceil() as i64).min(width - 1);
+        let max_y = ((y + radius).ceil() as i64).min(height - 1);
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                if (px as f64 - x).powi(2) + (py as f64 - y).powi(2) <= radius * radius {
+                    mask.put_pixel(px as u32, py as u32, Luma([255]));
+                }
+            }
         }
     }
     
@@ -73,13 +173,7 @@ impl Tool for HealTool {
     }
     
     fn set_active(&mut self, active: bool) {
-        self.active = active;
-        
-        if !active {
-            self.source_point = None;
-            self.destination_point = None;
-            self.last_point = None;
-        }
+        HealTool::set_active(self, active);
     }
     
     fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
@@ -88,7 +182,7 @@ impl Tool for HealTool {
         }
         
         // Alt key is often used to define source point (would need event state in real impl)
-        if self.source_point.is_none() {
+        if self.source_point.is_none() && !self.settings.content_aware {
             self.source_point = Some(Point::new(x, y));
         } else {
             self.destination_point = Some(Point::new(x, y));
@@ -187,6 +281,18 @@ impl Tool for HealTool {
 
 impl ToolImpl for HealTool {
     fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        // Content-aware strokes collect the brushed area and fill it on release
+        if self.settings.content_aware {
+            let (width, height) = match canvas.layer_manager.get_active_layer() {
+                Some(layer) => layer.image.dimensions(),
+                None => return false,
+            };
+            self.stroke_mask = Some(GrayImage::new(width, height));
+            self.mark_stroke(x, y);
+            self.last_point = Some(Point::new(x, y));
+            return true;
+        }
+        
         // Similar to clone tool, but we will blend the textures instead of just copying
         if self.source_point.is_none() {
             self.source_point = Some(Point::new(x, y));
@@ -203,6 +309,19 @@ impl ToolImpl for HealTool {
     }
     
     fn on_mouse_drag(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        if self.stroke_mask.is_some() {
+            if let Some(last) = self.last_point {
+                let curr = Point::new(x, y);
+                let steps = (last.distance_to(&curr) / (self.settings.radius / 4.0).max(0.5)).ceil() as usize;
+                for i in 1..=steps {
+                    let t = i as f64 / steps as f64;
+                    self.mark_stroke(last.x + (curr.x - last.x) * t, last.y + (curr.y - last.y) * t);
+                }
+            }
+            self.last_point = Some(Point::new(x, y));
+            return true;
+        }
+        
         if self.source_point.is_none() || self.destination_point.is_none() {
             return false;
         }
@@ -229,8 +348,19 @@ impl ToolImpl for HealTool {
         true
     }
     
-    fn on_mouse_up(&mut self, _canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
+    fn on_mouse_up(&mut self, canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
         self.last_point = None;
+        
+        if let Some(mask) = self.stroke_mask.take() {
+            let layer = match canvas.layer_manager.get_active_layer_mut() {
+                Some(layer) => layer,
+                None => return false,
+            };
+            if let Err(e) = Self::heal_region(&mut layer.image, &mask) {
+                log::warn!("Heal failed: {}", e);
+                return false;
+            }
+        }
         true
     }
     
@@ -0,0 +1,246 @@
+use crate::core::Canvas;
+use crate::vector::Point;
+use crate::filters::{sample_pixel, Interpolation};
+use image::{GrayImage, ImageBuffer, Rgba};
+use log::warn;
+use super::ToolImpl;
+use crate::tools::{Tool, ToolType};
+use cairo::Context;
+
+/// Push pixels around with the brush, like smudging wet paint.
+///
+/// A stroke snapshots the layer and builds up a displacement field: each
+/// output pixel samples the snapshot at its own position plus its
+/// displacement, so repeated dabs keep warping the original pixels rather
+/// than resampling an already warped copy.
+///
+/// Pixels covered by the freeze mask never move, which protects e.g. a face
+/// while the background around it is reshaped.
+#[derive(Clone)]
+pub struct LiquifyTool {
+    pub active: bool,
+    /// Brush radius in pixels
+    pub radius: f64,
+    /// How far pixels follow the brush, 0.0 - 1.0
+    pub strength: f64,
+    pub last_point: Option<Point>,
+    /// Pixels where the mask is non-zero are protected from warping
+    freeze_mask: Option<GrayImage>,
+    /// Layer pixels at the start of the stroke
+    source: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    /// Per-pixel offset into `source`, row-major
+    displacement: Vec<(f32, f32)>,
+}
+
+impl LiquifyTool {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            radius: 30.0,
+            strength: 0.5,
+            last_point: None,
+            freeze_mask: None,
+            source: None,
+            displacement: Vec::new(),
+        }
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+
+        if !active {
+            self.end_stroke();
+            self.last_point = None;
+        }
+    }
+
+    pub fn cursor(&self) -> &'static str {
+        "crosshair"
+    }
+
+    /// Protect the pixels where `mask` is non-zero. The mask must be the
+    /// size of the layer being warped.
+    pub fn set_freeze_mask(&mut self, mask: GrayImage) {
+        self.freeze_mask = Some(mask);
+    }
+
+    pub fn clear_freeze_mask(&mut self) {
+        self.freeze_mask = None;
+    }
+
+    pub fn freeze_mask(&self) -> Option<&GrayImage> {
+        self.freeze_mask.as_ref()
+    }
+
+    fn is_frozen(&self, x: u32, y: u32) -> bool {
+        match &self.freeze_mask {
+            Some(mask) => mask.get_pixel(x, y)[0] > 0,
+            None => false,
+        }
+    }
+
+    /// Start a stroke on `image`, which later `push` calls warp
+    pub fn begin_stroke(&mut self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        if let Some(mask) = &self.freeze_mask {
+            if mask.dimensions() != image.dimensions() {
+                warn!("Freeze mask is {:?} but the layer is {:?}; ignoring it",
+                      mask.dimensions(), image.dimensions());
+                self.freeze_mask = None;
+            }
+        }
+        self.displacement = vec![(0.0, 0.0); (image.width() * image.height()) as usize];
+        self.source = Some(image.clone());
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.source = None;
+        self.displacement.clear();
+    }
+
+    /// Drag the pixels under the brush from `from` towards `to`
+    pub fn push(&mut self, from: Point, to: Point) {
+        let (width, height) = match &self.source {
+            Some(source) => source.dimensions(),
+            None => return,
+        };
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let radius = self.radius.max(1.0);
+        let strength = self.strength.clamp(0.0, 1.0);
+
+        let min_x = (to.x - radius).floor().max(0.0) as u32;
+        let min_y = (to.y - radius).floor().max(0.0) as u32;
+        let max_x = ((to.x + radius).ceil().max(0.0) as u32).min(width.saturating_sub(1));
+        let max_y = ((to.y + radius).ceil().max(0.0) as u32).min(height.saturating_sub(1));
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let distance = ((x as f64 - to.x).powi(2) + (y as f64 - to.y).powi(2)).sqrt();
+                if distance >= radius || self.is_frozen(x, y) {
+                    continue;
+                }
+                let falloff = 1.0 - (distance / radius).powi(2);
+                let weight = strength * falloff * falloff;
+
+                // Sample from behind the brush so content moves with it
+                let offset = &mut self.displacement[(y * width + x) as usize];
+                offset.0 -= (dx * weight) as f32;
+                offset.1 -= (dy * weight) as f32;
+            }
+        }
+    }
+
+    /// The stroke's source warped by the current displacement field
+    pub fn render(&self) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let source = self.source.as_ref()?;
+        let width = source.width();
+        Some(ImageBuffer::from_fn(width, source.height(), |x, y| {
+            let (ox, oy) = self.displacement[(y * width + x) as usize];
+            if ox == 0.0 && oy == 0.0 {
+                *source.get_pixel(x, y)
+            } else {
+                sample_pixel(source, x as f32 + ox, y as f32 + oy, Interpolation::Bilinear)
+            }
+        }))
+    }
+}
+
+impl Tool for LiquifyTool {
+    fn tool_type(&self) -> ToolType {
+        ToolType::Liquify
+    }
+
+    fn cursor(&self) -> &'static str {
+        "crosshair"
+    }
+
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn set_active(&mut self, active: bool) {
+        LiquifyTool::set_active(self, active);
+    }
+
+    fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
+        if button != 1 || !self.active {
+            return;
+        }
+
+        self.last_point = Some(Point::new(x, y));
+    }
+
+    fn mouse_move(&mut self, x: f64, y: f64) {
+        if !self.active {
+            return;
+        }
+
+        if let Some(last) = self.last_point {
+            self.push(last, Point::new(x, y));
+        }
+        self.last_point = Some(Point::new(x, y));
+    }
+
+    fn mouse_up(&mut self, _x: f64, _y: f64, button: u32) {
+        if button == 1 {
+            self.end_stroke();
+        }
+    }
+
+    fn key_press(&mut self, key: &str) {
+        match key {
+            "bracketleft" => self.radius = (self.radius - 2.0).max(1.0),
+            "bracketright" => self.radius += 2.0,
+            "Escape" => self.end_stroke(),
+            _ => {}
+        }
+    }
+
+    fn draw_preview(&self, context: &Context, _canvas: &Canvas) {
+        if let Some(last) = self.last_point {
+            context.save();
+            context.set_source_rgba(1.0, 1.0, 1.0, 0.7);
+            context.set_line_width(1.0);
+            context.arc(last.x, last.y, self.radius, 0.0, 2.0 * std::f64::consts::PI);
+            context.stroke();
+            context.restore();
+        }
+    }
+}
+
+impl ToolImpl for LiquifyTool {
+    fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        match canvas.layer_manager.get_active_layer() {
+            Some(layer) => self.begin_stroke(&layer.image),
+            None => return false,
+        }
+        self.last_point = Some(Point::new(x, y));
+        true
+    }
+
+    fn on_mouse_drag(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        let last = match (self.last_point, &self.source) {
+            (Some(last), Some(_)) => last,
+            _ => return false,
+        };
+        self.push(last, Point::new(x, y));
+        self.last_point = Some(Point::new(x, y));
+
+        match (self.render(), canvas.layer_manager.get_active_layer_mut()) {
+            (Some(warped), Some(layer)) => {
+                layer.image = warped;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn on_mouse_up(&mut self, _canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
+        let was_warping = self.source.is_some();
+        self.end_stroke();
+        was_warping
+    }
+
+    fn get_cursor(&self) -> Option<String> {
+        Some("crosshair".to_string())
+    }
+}
@@ -4,7 +4,10 @@ mod selection;
 mod brush;
 mod clone;
 mod heal;
+mod spot_heal;
+mod liquify;
 mod crop;
+mod perspective_crop;
 mod text;
 mod gradient;
 mod vector_tools;
@@ -13,13 +16,17 @@ pub use selection::{SelectionTool, SelectionType};
 pub use brush::BrushTool;
 pub use clone::CloneTool;
 pub use heal::{HealTool, HealSettings};
+pub use spot_heal::SpotHealTool;
+pub use liquify::LiquifyTool;
 pub use crop::CropTool;
+pub use perspective_crop::PerspectiveCropTool;
 pub use text::TextTool;
 pub use gradient::GradientTool;
 pub use vector_tools::{RectangleTool, EllipseTool, PathTool, TextTool as VectorTextTool};
 pub use crate::core::canvas::BrushSettings;
 
 use cairo::Context;
+use gtk4::gdk::ModifierType;
 use image::{DynamicImage, ImageBuffer, Rgba};
 use crate::core::{Canvas, Layer, LayerManager, Selection, Color};
 use crate::core::Point as CorePoint;
@@ -29,6 +36,29 @@ use crate::vector::{Point as VectorPoint, VectorDocument, Rect};
 use std::str::FromStr;
 use std::cell::RefMut;
 
+/// Keyboard modifiers held while a pointer event happened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub fn none() -> Self {
+        Self::default()
+    }
+    
+    /// The modifiers held in a GTK event's state
+    pub fn from_state(state: ModifierType) -> Self {
+        Self {
+            shift: state.contains(ModifierType::SHIFT_MASK),
+            ctrl: state.contains(ModifierType::CONTROL_MASK),
+            alt: state.contains(ModifierType::ALT_MASK),
+        }
+    }
+}
+
 /// The different types of tools available in the application
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ToolType {
@@ -45,6 +75,8 @@ pub enum ToolType {
     Eraser,
     Clone,
     Heal,
+    SpotHeal,
+    Liquify,
     Fill,
     
     // Vector tools
@@ -55,6 +87,7 @@ pub enum ToolType {
     
     // Other tools
     Crop,
+    PerspectiveCrop,
     Text,
     Gradient,
     ColorPicker,
@@ -75,12 +108,15 @@ impl std::fmt::Display for ToolType {
             ToolType::Eraser => write!(f, "Eraser"),
             ToolType::Clone => write!(f, "Clone"),
             ToolType::Heal => write!(f, "Heal"),
+            ToolType::SpotHeal => write!(f, "SpotHeal"),
+            ToolType::Liquify => write!(f, "Liquify"),
             ToolType::Fill => write!(f, "Fill"),
             ToolType::VectorRectangle => write!(f, "VectorRectangle"),
             ToolType::VectorEllipse => write!(f, "VectorEllipse"),
             ToolType::VectorPath => write!(f, "VectorPath"),
             ToolType::VectorText => write!(f, "VectorText"),
             ToolType::Crop => write!(f, "Crop"),
+            ToolType::PerspectiveCrop => write!(f, "PerspectiveCrop"),
             ToolType::Text => write!(f, "Text"),
             ToolType::Gradient => write!(f, "Gradient"),
             ToolType::ColorPicker => write!(f, "ColorPicker"),
@@ -117,12 +153,15 @@ impl FromStr for ToolType {
             "Eraser" => Ok(ToolType::Eraser),
             "Clone" => Ok(ToolType::Clone),
             "Heal" => Ok(ToolType::Heal),
+            "SpotHeal" => Ok(ToolType::SpotHeal),
+            "Liquify" => Ok(ToolType::Liquify),
             "Fill" => Ok(ToolType::Fill),
             "VectorRectangle" => Ok(ToolType::VectorRectangle),
             "VectorEllipse" => Ok(ToolType::VectorEllipse),
             "VectorPath" => Ok(ToolType::VectorPath),
             "VectorText" => Ok(ToolType::VectorText),
             "Crop" => Ok(ToolType::Crop),
+            "PerspectiveCrop" => Ok(ToolType::PerspectiveCrop),
             "Text" => Ok(ToolType::Text),
             "Gradient" => Ok(ToolType::Gradient),
             "ColorPicker" => Ok(ToolType::ColorPicker),
@@ -173,13 +212,18 @@ pub struct ToolManager {
     pub brush_tool: BrushTool,
     pub clone_tool: CloneTool,
     pub heal_tool: HealTool,
+    pub spot_heal_tool: SpotHealTool,
+    pub liquify_tool: LiquifyTool,
     pub crop_tool: CropTool,
+    pub perspective_crop_tool: PerspectiveCropTool,
     pub text_tool: TextTool,
     pub gradient_tool: GradientTool,
     pub rectangle_tool: RectangleTool,
     pub ellipse_tool: EllipseTool,
     pub path_tool: PathTool,
     pub vector_text_tool: VectorTextTool,
+    /// Modifier keys currently held, forwarded to tools on pointer events
+    pub modifiers: Modifiers,
 }
 
 impl ToolManager {
@@ -190,16 +234,25 @@ impl ToolManager {
             brush_tool: BrushTool::new(),
             clone_tool: CloneTool::new(),
             heal_tool: HealTool::new(),
+            spot_heal_tool: SpotHealTool::new(),
+            liquify_tool: LiquifyTool::new(),
             crop_tool: CropTool::new(),
+            perspective_crop_tool: PerspectiveCropTool::new(),
             text_tool: TextTool::new(),
             gradient_tool: GradientTool::new(),
             rectangle_tool: RectangleTool::new(),
             ellipse_tool: EllipseTool::new(),
             path_tool: PathTool::new(),
             vector_text_tool: VectorTextTool::new(),
+            modifiers: Modifiers::none(),
         }
     }
     
+    /// Update the modifier key state (called from the window's key handlers)
+    pub fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.modifiers = modifiers;
+    }
+    
     pub fn set_active_tool(&mut self, tool_type: ToolType) {
         // Deactivate current tool
         match self.active_tool {
@@ -211,7 +264,10 @@ impl ToolManager {
             ToolType::Brush => self.brush_tool.set_active(false),
             ToolType::Clone => self.clone_tool.set_active(false),
             ToolType::Heal => self.heal_tool.set_active(false),
+            ToolType::SpotHeal => self.spot_heal_tool.set_active(false),
+            ToolType::Liquify => self.liquify_tool.set_active(false),
             ToolType::Crop => self.crop_tool.set_active(false),
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.set_active(false),
             ToolType::Text => self.text_tool.set_active(false),
             ToolType::Gradient => self.gradient_tool.set_active(false),
             
@@ -245,7 +301,10 @@ impl ToolManager {
             ToolType::Brush => self.brush_tool.set_active(true),
             ToolType::Clone => self.clone_tool.set_active(true),
             ToolType::Heal => self.heal_tool.set_active(true),
+            ToolType::SpotHeal => self.spot_heal_tool.set_active(true),
+            ToolType::Liquify => self.liquify_tool.set_active(true),
             ToolType::Crop => self.crop_tool.set_active(true),
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.set_active(true),
             ToolType::Text => self.text_tool.set_active(true),
             ToolType::Gradient => self.gradient_tool.set_active(true),
             
@@ -269,7 +328,10 @@ impl ToolManager {
             ToolType::Brush => self.brush_tool.cursor(),
             ToolType::Clone => self.clone_tool.cursor(),
             ToolType::Heal => self.heal_tool.cursor(),
+            ToolType::SpotHeal => self.spot_heal_tool.cursor(),
+            ToolType::Liquify => self.liquify_tool.cursor(),
             ToolType::Crop => self.crop_tool.cursor(),
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.cursor(),
             ToolType::Text => self.text_tool.cursor(),
             ToolType::Gradient => self.gradient_tool.cursor(),
             
@@ -290,12 +352,43 @@ impl ToolManager {
             ToolType::RectangleSelection |
             ToolType::EllipseSelection |
             ToolType::LassoSelection |
-            ToolType::MagicWandSelection => self.selection_tool.mouse_down(x, y, button),
+            ToolType::MagicWandSelection => {
+                self.selection_tool.set_canvas_size(canvas.width, canvas.height);
+                self.selection_tool.mouse_down(x, y, button, self.modifiers);
+            },
             
-            ToolType::Brush => self.brush_tool.mouse_down(x, y, button),
-            ToolType::Clone => self.clone_tool.mouse_down(x, y, button),
-            ToolType::Heal => self.heal_tool.mouse_down(x, y, button),
+            ToolType::Brush => {
+                self.brush_tool.set_constrain(self.modifiers.shift);
+                self.brush_tool.mouse_down(x, y, button);
+            },
+            ToolType::Clone => {
+                self.clone_tool.set_sampling(self.modifiers.alt);
+                if button == 1 && self.clone_tool.active && self.clone_tool.on_mouse_down(canvas, x, y) {
+                    Self::invalidate_stroke(canvas, None, x, y, self.clone_tool.size);
+                }
+            },
+            ToolType::Heal => {
+                if button == 1 && self.heal_tool.active {
+                    self.heal_tool.on_mouse_down(canvas, x, y);
+                }
+            },
+            ToolType::SpotHeal => {
+                // Spot healing needs no source, so a click heals immediately
+                if button == 1 && self.spot_heal_tool.active && self.spot_heal_tool.on_mouse_down(canvas, x, y) {
+                    Self::invalidate_stroke(canvas, None, x, y, self.spot_heal_tool.radius);
+                }
+            },
+            ToolType::Liquify => {
+                if button == 1 && self.liquify_tool.active {
+                    self.liquify_tool.on_mouse_down(canvas, x, y);
+                }
+            },
             ToolType::Crop => self.crop_tool.mouse_down(x, y, button),
+            ToolType::PerspectiveCrop => {
+                if button == 1 && self.perspective_crop_tool.active {
+                    self.perspective_crop_tool.on_mouse_down(canvas, x, y);
+                }
+            },
             ToolType::Text => self.text_tool.mouse_down(x, y, button),
             ToolType::Gradient => self.gradient_tool.mouse_down(x, y, button),
             
@@ -313,7 +406,7 @@ impl ToolManager {
         }
     }
     
-    pub fn mouse_move(&mut self, x: f64, y: f64, _canvas: &mut Canvas) {
+    pub fn mouse_move(&mut self, x: f64, y: f64, canvas: &mut Canvas) {
         match self.active_tool {
             ToolType::RectangleSelection |
             ToolType::EllipseSelection |
@@ -321,9 +414,33 @@ impl ToolManager {
             ToolType::MagicWandSelection => self.selection_tool.mouse_move(x, y),
             
             ToolType::Brush => self.brush_tool.mouse_move(x, y),
-            ToolType::Clone => self.clone_tool.mouse_move(x, y),
-            ToolType::Heal => self.heal_tool.mouse_move(x, y),
+            ToolType::Clone => {
+                // Hovering between strokes just moves the brush outline
+                let last = self.clone_tool.last_point;
+                if self.clone_tool.on_mouse_drag(canvas, x, y) {
+                    Self::invalidate_stroke(canvas, last, x, y, self.clone_tool.size);
+                } else {
+                    self.clone_tool.mouse_move(x, y);
+                }
+            },
+            ToolType::Heal => {
+                let last = self.heal_tool.last_point;
+                if self.heal_tool.on_mouse_drag(canvas, x, y) {
+                    Self::invalidate_stroke(canvas, last, x, y, self.heal_tool.settings.radius);
+                } else {
+                    self.heal_tool.mouse_move(x, y);
+                }
+            },
+            ToolType::SpotHeal => self.spot_heal_tool.mouse_move(x, y),
+            ToolType::Liquify => {
+                // Warping needs the layer, so it goes through the canvas
+                let last = self.liquify_tool.last_point;
+                if self.liquify_tool.on_mouse_drag(canvas, x, y) {
+                    Self::invalidate_stroke(canvas, last, x, y, self.liquify_tool.radius);
+                }
+            },
             ToolType::Crop => self.crop_tool.mouse_move(x, y),
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.mouse_move(x, y),
             ToolType::Text => self.text_tool.mouse_move(x, y),
             ToolType::Gradient => self.gradient_tool.mouse_move(x, y),
             
@@ -344,34 +461,53 @@ impl ToolManager {
             ToolType::LassoSelection |
             ToolType::MagicWandSelection => {
                 self.selection_tool.mouse_up(x, y, button);
-                // Apply selection to canvas
+                // Apply selection to canvas; the tool has already combined it
+                // with the previous selection according to the modifiers
                 if let Some(selection) = self.selection_tool.get_selection() {
-                    // Convert from CoreSelection to CanvasSelection
-                    let canvas_selection = CanvasSelection::rectangle(
-                        selection.x as f64,
-                        selection.y as f64,
-                        selection.width as u32,
-                        selection.height as u32,
-                        canvas.width,
-                        canvas.height
-                    );
-                    canvas.set_selection(canvas_selection);
+                    canvas.set_selection(selection.clone());
                 }
             },
             
             ToolType::Brush => self.brush_tool.mouse_up(x, y, button),
-            ToolType::Clone => self.clone_tool.mouse_up(x, y, button),
-            ToolType::Heal => self.heal_tool.mouse_up(x, y, button),
+            ToolType::Clone => {
+                if button == 1 {
+                    self.clone_tool.on_mouse_up(canvas, x, y);
+                }
+            },
+            ToolType::Heal => {
+                // A content-aware stroke is filled all at once on release
+                if button == 1 && self.heal_tool.active && self.heal_tool.on_mouse_up(canvas, x, y) {
+                    canvas.invalidate_all();
+                }
+            },
+            ToolType::SpotHeal => self.spot_heal_tool.mouse_up(x, y, button),
+            ToolType::Liquify => {
+                if button == 1 {
+                    self.liquify_tool.on_mouse_up(canvas, x, y);
+                }
+            },
             ToolType::Crop => {
                 self.crop_tool.mouse_up(x, y, button);
                 if self.crop_tool.is_complete() {
                     // Apply crop to canvas
                     if let Some(rect) = self.crop_tool.get_crop_rect() {
-                        canvas.crop(rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32);
+                        if self.crop_tool.is_rotated() {
+                            // A turned frame may reach past the edge; that part comes out transparent
+                            if let Err(e) = canvas.crop_rotated(rect.into(), self.crop_tool.angle) {
+                                log::warn!("Rotated crop failed: {}", e);
+                            }
+                        } else {
+                            // A drag past the edge crops to the edge
+                            let rect = rect.clamp_to(&Rect::new(0.0, 0.0, canvas.width as f64, canvas.height as f64));
+                            if rect.width >= 1.0 && rect.height >= 1.0 {
+                                canvas.crop(rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32);
+                            }
+                        }
                     }
                     self.crop_tool.reset();
                 }
             },
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.mouse_up(x, y, button),
             ToolType::Text => self.text_tool.mouse_up(x, y, button),
             ToolType::Gradient => self.gradient_tool.mouse_up(x, y, button),
             
@@ -400,7 +536,58 @@ impl ToolManager {
         }
     }
     
+    /// Drop the canvas tiles a brush of `radius` touched moving from `last`
+    /// (or just dabbing, without one) to `x`, `y`
+    fn invalidate_stroke(canvas: &Canvas, last: Option<VectorPoint>, x: f64, y: f64, radius: f64) {
+        let (from_x, from_y) = last.map(|p| (p.x, p.y)).unwrap_or((x, y));
+        // One pixel of slack for anti-aliased brush edges
+        let reach = radius.max(0.0) + 1.0;
+        let left = from_x.min(x) - reach;
+        let top = from_y.min(y) - reach;
+        canvas.invalidate_region(crate::core::Rect {
+            x: left,
+            y: top,
+            width: from_x.max(x) + reach - left,
+            height: from_y.max(y) + reach - top,
+        });
+    }
+    
+    /// Opacity chosen by a digit key: 1 is 10%, ..., 9 is 90% and 0 is 100%
+    pub fn opacity_for_key(key: &str) -> Option<f64> {
+        let digit = key.strip_prefix("KP_").unwrap_or(key);
+        match digit.parse::<u8>() {
+            Ok(0) => Some(1.0),
+            Ok(n) if n <= 9 => Some(n as f64 / 10.0),
+            _ => None,
+        }
+    }
+    
+    /// Set the active layer's opacity, through the document's history when
+    /// the canvas has one so the change can be undone
+    fn set_active_layer_opacity(&mut self, opacity: f64, canvas: &mut Canvas) {
+        let index = canvas.layer_manager.get_active_layer_index();
+        if let Some(layer) = canvas.layer_manager.get_layer_mut(index) {
+            layer.set_opacity(opacity);
+        }
+        
+        if let Some(document) = &canvas.document {
+            if let Err(e) = document.borrow_mut().set_layer_opacity(index, opacity) {
+                log::warn!("Failed to set layer opacity: {}", e);
+            }
+        }
+        canvas.invalidate_all();
+    }
+    
     pub fn key_press(&mut self, key: &str, canvas: &mut Canvas) {
+        // Digits set the layer opacity unless a text tool wants them as input
+        let typing = matches!(self.active_tool, ToolType::Text | ToolType::VectorText);
+        if !typing {
+            if let Some(opacity) = Self::opacity_for_key(key) {
+                self.set_active_layer_opacity(opacity, canvas);
+                return;
+            }
+        }
+        
         match self.active_tool {
             ToolType::RectangleSelection |
             ToolType::EllipseSelection |
@@ -410,7 +597,18 @@ impl ToolManager {
             ToolType::Brush => self.brush_tool.key_press(key),
             ToolType::Clone => self.clone_tool.key_press(key),
             ToolType::Heal => self.heal_tool.key_press(key),
+            ToolType::SpotHeal => self.spot_heal_tool.key_press(key),
+            ToolType::Liquify => self.liquify_tool.key_press(key),
             ToolType::Crop => self.crop_tool.key_press(key),
+            ToolType::PerspectiveCrop => {
+                if key == "Return" {
+                    if let Err(e) = self.perspective_crop_tool.apply(canvas) {
+                        log::warn!("Perspective crop failed: {}", e);
+                    }
+                } else {
+                    self.perspective_crop_tool.key_press(key);
+                }
+            },
             ToolType::Text => self.text_tool.key_press(key),
             ToolType::Gradient => self.gradient_tool.key_press(key),
             
@@ -438,7 +636,10 @@ impl ToolManager {
             ToolType::Brush => self.brush_tool.draw_preview(context, canvas),
             ToolType::Clone => self.clone_tool.draw_preview(context, canvas),
             ToolType::Heal => self.heal_tool.draw_preview(context, canvas),
+            ToolType::SpotHeal => self.spot_heal_tool.draw_preview(context, canvas),
+            ToolType::Liquify => self.liquify_tool.draw_preview(context, canvas),
             ToolType::Crop => self.crop_tool.draw_preview(context, canvas),
+            ToolType::PerspectiveCrop => self.perspective_crop_tool.draw_preview(context, canvas),
             ToolType::Text => self.text_tool.draw_preview(context, canvas),
             ToolType::Gradient => self.gradient_tool.draw_preview(context, canvas),
             
@@ -468,13 +669,17 @@ impl Clone for ToolManager {
             brush_tool: self.brush_tool.clone(),
             clone_tool: self.clone_tool.clone(),
             heal_tool: self.heal_tool.clone(),
+            spot_heal_tool: self.spot_heal_tool.clone(),
+            liquify_tool: self.liquify_tool.clone(),
             crop_tool: self.crop_tool.clone(),
+            perspective_crop_tool: self.perspective_crop_tool.clone(),
             text_tool: self.text_tool.clone(),
             gradient_tool: self.gradient_tool.clone(),
             rectangle_tool: self.rectangle_tool.clone(),
             ellipse_tool: self.ellipse_tool.clone(),
             path_tool: self.path_tool.clone(),
             vector_text_tool: self.vector_text_tool.clone(),
+            modifiers: self.modifiers,
         }
     }
 }
@@ -0,0 +1,264 @@
+use crate::core::Canvas;
+use crate::vector::Point;
+use crate::filters::{mask_from_rgba, mask_to_rgba, warp_perspective, Interpolation};
+use super::ToolImpl;
+use crate::tools::{Tool, ToolType};
+use cairo::Context;
+
+/// Crop a skewed quadrilateral out of the image and straighten it.
+///
+/// The four handles start on the canvas corners; the user drags them onto
+/// the edges of a document or whiteboard and confirms with Return, which
+/// warps every layer so the quadrilateral fills an upright rectangle.
+#[derive(Clone)]
+pub struct PerspectiveCropTool {
+    pub active: bool,
+    /// Handles in order top-left, top-right, bottom-right, bottom-left
+    pub corners: Option<[Point; 4]>,
+    /// Output size; when None it is estimated from the quad's edge lengths
+    pub output_size: Option<(u32, u32)>,
+    pub dragging: Option<usize>,
+}
+
+impl PerspectiveCropTool {
+    /// Grab distance for the corner handles, in canvas pixels
+    const HANDLE_RADIUS: f64 = 8.0;
+
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            corners: None,
+            output_size: None,
+            dragging: None,
+        }
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+
+        if !active {
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.corners = None;
+        self.dragging = None;
+    }
+
+    pub fn cursor(&self) -> &'static str {
+        if self.dragging.is_some() {
+            "grabbing"
+        } else {
+            "crosshair"
+        }
+    }
+
+    pub fn set_corners(&mut self, corners: [Point; 4]) {
+        self.corners = Some(corners);
+    }
+
+    pub fn set_output_size(&mut self, size: Option<(u32, u32)>) {
+        self.output_size = size;
+    }
+
+    /// Place the handles on the corners of a `width` x `height` canvas
+    pub fn reset_corners(&mut self, width: u32, height: u32) {
+        let (w, h) = (width as f64, height as f64);
+        self.corners = Some([
+            Point::new(0.0, 0.0),
+            Point::new(w, 0.0),
+            Point::new(w, h),
+            Point::new(0.0, h),
+        ]);
+    }
+
+    /// Index of the handle under (x, y), if any
+    pub fn handle_at(&self, x: f64, y: f64) -> Option<usize> {
+        let corners = self.corners?;
+        let point = Point::new(x, y);
+        (0..4)
+            .filter(|&i| corners[i].distance_to(&point) <= Self::HANDLE_RADIUS)
+            .min_by(|&a, &b| corners[a].distance_to(&point).total_cmp(&corners[b].distance_to(&point)))
+    }
+
+    /// The rectangle the quad will be straightened into: the chosen output
+    /// size, or the longer of each pair of opposite edges
+    pub fn target_size(&self) -> Option<(u32, u32)> {
+        if let Some(size) = self.output_size {
+            return Some(size);
+        }
+
+        let c = self.corners?;
+        let width = c[0].distance_to(&c[1]).max(c[3].distance_to(&c[2])).round();
+        let height = c[0].distance_to(&c[3]).max(c[1].distance_to(&c[2])).round();
+        if width < 1.0 || height < 1.0 {
+            return None;
+        }
+        Some((width as u32, height as u32))
+    }
+
+    /// Warp every layer and mask of `canvas` so the quad fills the target
+    /// rectangle, resizing the canvas to match
+    pub fn apply(&mut self, canvas: &mut Canvas) -> Result<(), String> {
+        let corners = self.corners.ok_or_else(|| "No perspective crop area set".to_string())?;
+        let (width, height) = self.target_size()
+            .ok_or_else(|| "Perspective crop area is empty".to_string())?;
+        let quad = [
+            (corners[0].x, corners[0].y),
+            (corners[1].x, corners[1].y),
+            (corners[2].x, corners[2].y),
+            (corners[3].x, corners[3].y),
+        ];
+
+        // Warp everything first so a bad quad leaves the canvas untouched
+        let mut warped = Vec::with_capacity(canvas.layer_manager.layer_count());
+        for layer in canvas.layer_manager.get_layers() {
+            let offset_quad = quad.map(|(x, y)| (x - layer.x_offset as f64, y - layer.y_offset as f64));
+            let image = warp_perspective(&layer.image, offset_quad, width, height, Interpolation::Bilinear)?;
+            let mask = match &layer.mask {
+                Some(mask) => {
+                    let warped = warp_perspective(&mask_to_rgba(mask), offset_quad, width, height, Interpolation::Bilinear)?;
+                    Some(mask_from_rgba(&warped))
+                },
+                None => None,
+            };
+            warped.push((image, mask));
+        }
+
+        for (index, (image, mask)) in warped.into_iter().enumerate() {
+            let layer = match canvas.layer_manager.get_layer_mut(index) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            layer.image = image;
+            layer.mask = mask;
+            layer.width = width;
+            layer.height = height;
+            layer.x_offset = 0;
+            layer.y_offset = 0;
+        }
+        canvas.width = width;
+        canvas.height = height;
+        canvas.selection = None;
+
+        self.reset();
+        Ok(())
+    }
+}
+
+impl Tool for PerspectiveCropTool {
+    fn tool_type(&self) -> ToolType {
+        ToolType::PerspectiveCrop
+    }
+
+    fn cursor(&self) -> &'static str {
+        PerspectiveCropTool::cursor(self)
+    }
+
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn set_active(&mut self, active: bool) {
+        PerspectiveCropTool::set_active(self, active);
+    }
+
+    fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
+        if button != 1 || !self.active {
+            return;
+        }
+
+        self.dragging = self.handle_at(x, y);
+    }
+
+    fn mouse_move(&mut self, x: f64, y: f64) {
+        if let (Some(index), Some(corners)) = (self.dragging, self.corners.as_mut()) {
+            corners[index] = Point::new(x, y);
+        }
+    }
+
+    fn mouse_up(&mut self, x: f64, y: f64, button: u32) {
+        if button != 1 {
+            return;
+        }
+
+        self.mouse_move(x, y);
+        self.dragging = None;
+    }
+
+    fn key_press(&mut self, key: &str) {
+        // Return is handled by the tool manager, which has the canvas
+        if key == "Escape" {
+            self.reset();
+        }
+    }
+
+    fn draw_preview(&self, context: &Context, _canvas: &Canvas) {
+        let corners = match self.corners {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        context.save();
+        context.set_source_rgba(1.0, 1.0, 1.0, 0.9);
+        context.set_line_width(1.0);
+        context.move_to(corners[0].x, corners[0].y);
+        for corner in &corners[1..] {
+            context.line_to(corner.x, corner.y);
+        }
+        context.close_path();
+        context.stroke();
+
+        // Thirds grid across the quad as a rough preview of the result
+        context.set_source_rgba(1.0, 1.0, 1.0, 0.4);
+        for t in [1.0 / 3.0, 2.0 / 3.0] {
+            let top = corners[0].lerp(&corners[1], t);
+            let bottom = corners[3].lerp(&corners[2], t);
+            context.move_to(top.x, top.y);
+            context.line_to(bottom.x, bottom.y);
+            let left = corners[0].lerp(&corners[3], t);
+            let right = corners[1].lerp(&corners[2], t);
+            context.move_to(left.x, left.y);
+            context.line_to(right.x, right.y);
+        }
+        context.stroke();
+
+        for corner in &corners {
+            context.rectangle(corner.x - 4.0, corner.y - 4.0, 8.0, 8.0);
+        }
+        context.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+        context.fill_preserve();
+        context.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+        context.stroke();
+        context.restore();
+    }
+}
+
+impl ToolImpl for PerspectiveCropTool {
+    fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        if self.corners.is_none() {
+            self.reset_corners(canvas.width, canvas.height);
+        }
+        self.dragging = self.handle_at(x, y);
+        self.dragging.is_some()
+    }
+
+    fn on_mouse_drag(&mut self, _canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        if self.dragging.is_none() {
+            return false;
+        }
+        Tool::mouse_move(self, x, y);
+        true
+    }
+
+    fn on_mouse_up(&mut self, _canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        let was_dragging = self.dragging.is_some();
+        Tool::mouse_up(self, x, y, 1);
+        was_dragging
+    }
+
+    fn get_cursor(&self) -> Option<String> {
+        Some(PerspectiveCropTool::cursor(self).to_string())
+    }
+}
@@ -1,11 +1,12 @@
 use cairo::Context;
 use crate::core::{Canvas, Point, Selection};
+use crate::core::selection::SelectionType as CombineMode;
 use crate::vector::{VectorShape, SelectionState};
 use crate::vector::document::VectorDocument;
 use crate::vector::shape::VectorShape as ShapeImpl;
 use crate::vector::text::TextShape;
 use image::{ImageBuffer, Rgba, GenericImageView};
-use super::ToolImpl;
+use super::{ToolImpl, Modifiers};
 
 /// Types of selection
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -26,6 +27,10 @@ pub struct SelectionTool {
     pub is_selecting: bool,
     pub selection: Option<Selection>,
     pub points: Vec<Point>,
+    /// How the selection being drawn combines with the existing one
+    pub combine_mode: CombineMode,
+    canvas_width: u32,
+    canvas_height: u32,
 }
 
 impl SelectionTool {
@@ -38,6 +43,25 @@ impl SelectionTool {
             is_selecting: false,
             selection: None,
             points: Vec::new(),
+            combine_mode: CombineMode::New,
+            canvas_width: 2000,
+            canvas_height: 2000,
+        }
+    }
+    
+    /// Set the size of the canvas selections are created for
+    pub fn set_canvas_size(&mut self, width: u32, height: u32) {
+        self.canvas_width = width;
+        self.canvas_height = height;
+    }
+    
+    /// Shift adds, Alt subtracts and Shift+Alt intersects, as in most editors
+    pub fn combine_mode_for(modifiers: Modifiers) -> CombineMode {
+        match (modifiers.shift, modifiers.alt) {
+            (true, true) => CombineMode::Intersect,
+            (true, false) => CombineMode::Add,
+            (false, true) => CombineMode::Subtract,
+            (false, false) => CombineMode::New,
         }
     }
     
@@ -66,11 +90,12 @@ impl SelectionTool {
         self.selection.as_ref()
     }
     
-    pub fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
+    pub fn mouse_down(&mut self, x: f64, y: f64, button: u32, modifiers: Modifiers) {
         if !self.is_active || button != 1 {
             return;
         }
         
+        self.combine_mode = Self::combine_mode_for(modifiers);
         self.is_selecting = true;
         self.start_point = Some(Point::new(x, y));
         self.end_point = Some(Point::new(x, y));
@@ -108,9 +133,11 @@ impl SelectionTool {
         self.end_point = Some(Point::new(x, y));
         
         if let (Some(start), Some(end)) = (self.start_point, self.end_point) {
-            // If the selection is too small, clear it
+            // A click without a drag deselects, unless modifying an existing selection
             if (end.x - start.x).abs() < 3.0 && (end.y - start.y).abs() < 3.0 {
-                self.selection = None;
+                if self.combine_mode == CombineMode::New {
+                    self.selection = None;
+                }
                 return;
             }
             
@@ -139,33 +166,30 @@ impl SelectionTool {
         let y = start.y.min(end.y) as u32;
         let width = (start.x - end.x).abs() as u32;
         let height = (start.y - end.y).abs() as u32;
+        let (canvas_width, canvas_height) = (self.canvas_width, self.canvas_height);
         
         // Create selection based on type
-        match self.selection_type {
+        let mut selection = match self.selection_type {
             SelectionType::Rectangle => {
-                // Make a rectangle selection with width and height of the canvas
-                let canvas_width = 2000; // Should be actual canvas width
-                let canvas_height = 2000; // Should be actual canvas height
-                self.selection = Some(Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height));
+                Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height)
             },
             SelectionType::Ellipse => {
-                // Make an ellipse selection with width and height of the canvas
-                let canvas_width = 2000; // Should be actual canvas width
-                let canvas_height = 2000; // Should be actual canvas height
-                self.selection = Some(Selection::ellipse(x.into(), y.into(), width, height, canvas_width, canvas_height));
+                Selection::ellipse(x.into(), y.into(), width, height, canvas_width, canvas_height)
             },
             SelectionType::Lasso => {
                 // Lasso selection is not implemented yet - use rectangle as fallback
-                let canvas_width = 2000; // Should be actual canvas width
-                let canvas_height = 2000; // Should be actual canvas height
-                self.selection = Some(Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height));
+                Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height)
             },
             SelectionType::MagicWand => {
                 // Magic wand is not implemented yet - use rectangle as fallback
-                let canvas_width = 2000; // Should be actual canvas width
-                let canvas_height = 2000; // Should be actual canvas height
-                self.selection = Some(Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height));
+                Selection::rectangle(x.into(), y.into(), width, height, canvas_width, canvas_height)
             },
+        };
+        selection.selection_type = self.combine_mode;
+        
+        match self.selection.as_mut() {
+            Some(existing) if self.combine_mode != CombineMode::New => existing.combine(&selection),
+            _ => self.selection = Some(selection),
         }
     }
     
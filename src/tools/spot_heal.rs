@@ -0,0 +1,289 @@
+use crate::core::Canvas;
+use crate::vector::Point;
+use image::{ImageBuffer, Rgba};
+use super::ToolImpl;
+use crate::tools::{Tool, ToolType};
+use cairo::Context;
+
+/// One-click blemish removal.
+///
+/// Unlike `HealTool` there is no source to pick: the tool looks at patches
+/// around the click, copies the smoothest one over the spot and then blends
+/// it in so its edges match the surrounding pixels.
+#[derive(Clone)]
+pub struct SpotHealTool {
+    pub active: bool,
+    /// Radius of the healed area in pixels
+    pub radius: f64,
+    pub last_point: Option<Point>,
+}
+
+impl SpotHealTool {
+    /// Number of candidate source patches tried around the spot
+    const CANDIDATES: usize = 16;
+    /// Jacobi iterations used to blend the patch into its surroundings
+    const BLEND_ITERATIONS: usize = 400;
+
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            radius: 8.0,
+            last_point: None,
+        }
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+
+        if !active {
+            self.last_point = None;
+        }
+    }
+
+    pub fn cursor(&self) -> &'static str {
+        "crosshair"
+    }
+
+    /// Heal the disc of `radius` around (x, y) in `image`.
+    ///
+    /// Returns false when no candidate patch fits inside the image.
+    pub fn heal_spot(&self, image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: f64, y: f64) -> bool {
+        let r = self.radius.max(1.0).ceil() as i64;
+        let (cx, cy) = (x.round() as i64, y.round() as i64);
+        let (width, height) = (image.width() as i64, image.height() as i64);
+
+        // Offsets inside the disc, plus the one-pixel ring just outside it
+        // that anchors the blend
+        let mut inside = Vec::new();
+        let mut ring = Vec::new();
+        for dy in -(r + 1)..=(r + 1) {
+            for dx in -(r + 1)..=(r + 1) {
+                let d = ((dx * dx + dy * dy) as f64).sqrt();
+                if d <= self.radius {
+                    inside.push((dx, dy));
+                } else if d <= self.radius + 1.5 {
+                    ring.push((dx, dy));
+                }
+            }
+        }
+
+        let in_bounds = |px: i64, py: i64| px >= 0 && py >= 0 && px < width && py < height;
+        let fits = |ox: i64, oy: i64| {
+            inside.iter().chain(ring.iter()).all(|&(dx, dy)| in_bounds(ox + dx, oy + dy))
+        };
+        if !fits(cx, cy) {
+            return false;
+        }
+
+        let source = match self.find_source(image, cx, cy, &inside, &ring, &fits) {
+            Some(source) => source,
+            None => return false,
+        };
+
+        self.blend_patch(image, (cx, cy), source, &inside, &ring);
+        true
+    }
+
+    /// Pick the candidate patch around the spot with the lowest luminance
+    /// variance, i.e. the cleanest texture nearby
+    fn find_source<F>(
+        &self,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        cx: i64,
+        cy: i64,
+        inside: &[(i64, i64)],
+        ring: &[(i64, i64)],
+        fits: &F,
+    ) -> Option<(i64, i64)>
+    where
+        F: Fn(i64, i64) -> bool,
+    {
+        // Far enough that the candidate (and its ring) never overlaps the spot
+        let distance = 2.0 * (self.radius + 2.0);
+        let luminance = |px: i64, py: i64| {
+            let p = image.get_pixel(px as u32, py as u32);
+            0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64
+        };
+
+        let mut best: Option<((i64, i64), f64)> = None;
+        for i in 0..Self::CANDIDATES {
+            let angle = i as f64 / Self::CANDIDATES as f64 * 2.0 * std::f64::consts::PI;
+            let sx = cx + (distance * angle.cos()).round() as i64;
+            let sy = cy + (distance * angle.sin()).round() as i64;
+            if !fits(sx, sy) {
+                continue;
+            }
+
+            let values: Vec<f64> = inside.iter().chain(ring.iter())
+                .map(|&(dx, dy)| luminance(sx + dx, sy + dy))
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+
+            if best.map_or(true, |(_, best_variance)| variance < best_variance) {
+                best = Some(((sx, sy), variance));
+            }
+        }
+
+        best.map(|(source, _)| source)
+    }
+
+    /// Copy the source patch over the spot and remove the seam: the
+    /// difference between destination and source along the ring is spread
+    /// smoothly over the disc (a membrane interpolation), so gradients in the
+    /// surroundings carry straight through the healed area.
+    fn blend_patch(
+        &self,
+        image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        (cx, cy): (i64, i64),
+        (sx, sy): (i64, i64),
+        inside: &[(i64, i64)],
+        ring: &[(i64, i64)],
+    ) {
+        let r = self.radius.max(1.0).ceil() as i64 + 1;
+        let size = (2 * r + 1) as usize;
+        let index = |dx: i64, dy: i64| ((dy + r) as usize) * size + (dx + r) as usize;
+        let pixel = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>, px: i64, py: i64| *image.get_pixel(px as u32, py as u32);
+
+        // Fixed boundary values of the correction, per channel
+        let mut correction = vec![[0.0f64; 4]; size * size];
+        for &(dx, dy) in ring {
+            let dst = pixel(image, cx + dx, cy + dy);
+            let src = pixel(image, sx + dx, sy + dy);
+            for c in 0..4 {
+                correction[index(dx, dy)][c] = dst[c] as f64 - src[c] as f64;
+            }
+        }
+
+        // Pixels whose correction is either fixed (ring) or solved for (disc)
+        let mut known = vec![false; size * size];
+        for &(dx, dy) in ring.iter().chain(inside.iter()) {
+            known[index(dx, dy)] = true;
+        }
+
+        // Start the interior at the mean boundary difference to converge faster
+        let mut mean = [0.0f64; 4];
+        for &(dx, dy) in ring {
+            for c in 0..4 {
+                mean[c] += correction[index(dx, dy)][c] / ring.len() as f64;
+            }
+        }
+        for &(dx, dy) in inside {
+            correction[index(dx, dy)] = mean;
+        }
+
+        for _ in 0..Self::BLEND_ITERATIONS {
+            let previous = correction.clone();
+            for &(dx, dy) in inside {
+                let mut sum = [0.0f64; 4];
+                let mut count = 0.0;
+                for (nx, ny) in [(dx - 1, dy), (dx + 1, dy), (dx, dy - 1), (dx, dy + 1)] {
+                    let i = index(nx, ny);
+                    if known[i] {
+                        for c in 0..4 {
+                            sum[c] += previous[i][c];
+                        }
+                        count += 1.0;
+                    }
+                }
+                if count > 0.0 {
+                    for c in 0..4 {
+                        correction[index(dx, dy)][c] = sum[c] / count;
+                    }
+                }
+            }
+        }
+
+        for &(dx, dy) in inside {
+            let src = pixel(image, sx + dx, sy + dy);
+            let fix = correction[index(dx, dy)];
+            let mut healed = [0u8; 4];
+            for c in 0..4 {
+                healed[c] = (src[c] as f64 + fix[c]).round().clamp(0.0, 255.0) as u8;
+            }
+            image.put_pixel((cx + dx) as u32, (cy + dy) as u32, Rgba(healed));
+        }
+    }
+}
+
+impl Tool for SpotHealTool {
+    fn tool_type(&self) -> ToolType {
+        ToolType::SpotHeal
+    }
+
+    fn cursor(&self) -> &'static str {
+        "crosshair"
+    }
+
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn set_active(&mut self, active: bool) {
+        SpotHealTool::set_active(self, active);
+    }
+
+    fn mouse_down(&mut self, x: f64, y: f64, button: u32) {
+        if button != 1 || !self.active {
+            return;
+        }
+
+        self.last_point = Some(Point::new(x, y));
+    }
+
+    fn mouse_move(&mut self, x: f64, y: f64) {
+        if !self.active {
+            return;
+        }
+
+        self.last_point = Some(Point::new(x, y));
+    }
+
+    fn mouse_up(&mut self, _x: f64, _y: f64, _button: u32) {
+        // Healing happens on click; the last point stays for the preview
+    }
+
+    fn key_press(&mut self, key: &str) {
+        match key {
+            "bracketleft" => self.radius = (self.radius - 1.0).max(1.0),
+            "bracketright" => self.radius += 1.0,
+            "Escape" => self.last_point = None,
+            _ => {}
+        }
+    }
+
+    fn draw_preview(&self, context: &Context, _canvas: &Canvas) {
+        if let Some(last) = self.last_point {
+            context.save();
+            context.set_source_rgba(0.3, 0.6, 1.0, 0.5);
+            context.set_line_width(1.0);
+            context.arc(last.x, last.y, self.radius, 0.0, 2.0 * std::f64::consts::PI);
+            context.stroke();
+            context.restore();
+        }
+    }
+}
+
+impl ToolImpl for SpotHealTool {
+    fn on_mouse_down(&mut self, canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        self.last_point = Some(Point::new(x, y));
+
+        match canvas.layer_manager.get_active_layer_mut() {
+            Some(layer) => self.heal_spot(&mut layer.image, x, y),
+            None => false,
+        }
+    }
+
+    fn on_mouse_drag(&mut self, _canvas: &mut Canvas, x: f64, y: f64) -> bool {
+        self.last_point = Some(Point::new(x, y));
+        false
+    }
+
+    fn on_mouse_up(&mut self, _canvas: &mut Canvas, _x: f64, _y: f64) -> bool {
+        true
+    }
+
+    fn get_cursor(&self) -> Option<String> {
+        Some("crosshair".to_string())
+    }
+}
@@ -1,7 +1,7 @@
 use gtk4::{
     Application, ApplicationWindow as Window, Box as GtkBox, FileChooserAction,
-    FileChooserDialog, HeaderBar, MenuButton, ResponseType, ScrolledWindow,
-    Orientation, PopoverMenu, PopoverMenuBar,
+    EventControllerKey, FileChooserDialog, GestureClick, HeaderBar, MenuButton, ResponseType,
+    ScrolledWindow, Orientation, PopoverMenu, PopoverMenuBar,
 };
 use gtk4::prelude::*;
 use libadwaita as adw;
@@ -13,7 +13,7 @@ use log::{info, error};
 
 use crate::core::document::Document;
 use crate::core::canvas::Canvas;
-use crate::tools::ToolManager;
+use crate::tools::{Modifiers, ToolManager};
 use crate::ui::CanvasWidget;
 use crate::ui::tools_panel::ToolsPanel;
 use crate::ui::layers_panel::LayersPanel;
@@ -61,6 +61,7 @@ impl MainWindow {
         // Create canvas
         let canvas = Rc::new(RefCell::new(Canvas::new(800, 600)));
         let canvas_widget = Rc::new(RefCell::new(CanvasWidget::new()));
+        menu_manager.borrow().connect_history(canvas.clone(), canvas_widget.clone());
 
         // Create tools panel
         let tools_panel = Rc::new(RefCell::new(ToolsPanel::new(tool_manager.clone())));
@@ -98,6 +99,44 @@ impl MainWindow {
         main_box.append(&content_box);
         window.set_child(Some(&main_box));
 
+        // Forward key presses to the active tool (digits set layer opacity)
+        let key_controller = EventControllerKey::new();
+        {
+            let tool_manager = tool_manager.clone();
+            let canvas = canvas.clone();
+            let canvas_widget = canvas_widget.clone();
+            key_controller.connect_key_pressed(move |_, keyval, _, state| {
+                tool_manager.borrow_mut().set_modifiers(Modifiers::from_state(state));
+                if let Some(name) = keyval.name() {
+                    tool_manager.borrow_mut().key_press(&name, &mut canvas.borrow_mut());
+                    canvas_widget.borrow().widget().queue_draw();
+                }
+                gtk4::glib::Propagation::Proceed
+            });
+        }
+        // Track Shift/Ctrl/Alt as they go down and up, so tools see them on
+        // the next pointer event (Shift adds to a selection, Alt subtracts)
+        {
+            let tool_manager = tool_manager.clone();
+            key_controller.connect_modifiers(move |_, state| {
+                tool_manager.borrow_mut().set_modifiers(Modifiers::from_state(state));
+                gtk4::glib::Propagation::Proceed
+            });
+        }
+        window.add_controller(key_controller);
+        
+        // Pointer presses carry the modifier state too, which covers keys
+        // held down before the window had focus
+        let click = GestureClick::new();
+        click.set_button(0);
+        {
+            let tool_manager = tool_manager.clone();
+            click.connect_pressed(move |gesture, _, _, _| {
+                tool_manager.borrow_mut().set_modifiers(Modifiers::from_state(gesture.current_event_state()));
+            });
+        }
+        canvas_widget.borrow().widget().add_controller(click);
+
         Self {
             window,
             document: RefCell::new(None),
@@ -2,6 +2,8 @@ use gtk4::prelude::*;
 use gtk4::{gio, MenuButton, PopoverMenu};
 use std::cell::RefCell;
 use std::rc::Rc;
+use crate::core::canvas::Canvas;
+use crate::ui::CanvasWidget;
 use crate::ui::MainWindow;
 
 /// Creates all menus for the application
@@ -171,20 +173,41 @@ impl MenuManager {
         preferences_section.append(Some("Preferences..."), Some("app.preferences"));
         self.edit_menu.append_section(None, &preferences_section);
         
-        // Add actions
-        self.add_simple_action("undo", |_| {
-            println!("Undo");
-        });
-        
-        self.add_simple_action("redo", |_| {
-            println!("Redo");
-        });
-        
+        // Undo and redo are added by `connect_history` once there is a canvas
         self.add_simple_action("preferences", |window| {
             println!("Opening preferences dialog");
         });
     }
 
+    /// Add the Edit > Undo and Redo actions, stepping through the history
+    /// of the document shown on `canvas` and redrawing `canvas_widget`
+    pub fn connect_history(&self, canvas: Rc<RefCell<Canvas>>, canvas_widget: Rc<RefCell<CanvasWidget>>) {
+        for (name, undo) in [("undo", true), ("redo", false)] {
+            let action = gio::SimpleAction::new(name, None);
+            let canvas = canvas.clone();
+            let canvas_widget = canvas_widget.clone();
+            action.connect_activate(move |_, _| {
+                let document = match canvas.borrow().document.clone() {
+                    Some(document) => document,
+                    None => return,
+                };
+                let changed = if undo {
+                    document.borrow_mut().undo()
+                } else {
+                    document.borrow_mut().redo()
+                };
+                if changed {
+                    // Pick up the restored layers and drop the stale tiles
+                    canvas.borrow_mut().set_document(Some(document.clone()));
+                    let mut widget = canvas_widget.borrow_mut();
+                    widget.set_document(Some(document.borrow().clone()));
+                    widget.widget().queue_draw();
+                }
+            });
+            self.actions.add_action(&action);
+        }
+    }
+
     fn build_text_menu(&mut self) {
         // Basic text operations
         let text_section = gio::Menu::new();
@@ -76,6 +76,8 @@ impl ToolsPanel {
         self.add_tool_button("Eraser", "edit-clear-symbolic", ToolType::Eraser);
         self.add_tool_button("Clone", "edit-copy-symbolic", ToolType::Clone);
         self.add_tool_button("Heal", "applications-science-symbolic", ToolType::Heal);
+        self.add_tool_button("Spot Heal", "edit-clear-symbolic", ToolType::SpotHeal);
+        self.add_tool_button("Liquify", "view-wrapped-symbolic", ToolType::Liquify);
         self.add_tool_button("Fill", "color-fill-symbolic", ToolType::Fill);
 
         // Vector tools
@@ -86,6 +88,7 @@ impl ToolsPanel {
 
         // Other tools
         self.add_tool_button("Crop", "edit-cut-symbolic", ToolType::Crop);
+        self.add_tool_button("Perspective Crop", "object-flip-vertical-symbolic", ToolType::PerspectiveCrop);
         self.add_tool_button("Text", "insert-text-symbolic", ToolType::Text);
         self.add_tool_button("Gradient", "color-gradient-symbolic", ToolType::Gradient);
         self.add_tool_button("Color Picker", "color-select-symbolic", ToolType::ColorPicker);
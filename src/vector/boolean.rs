@@ -0,0 +1,280 @@
+// Boolean operations (union, subtract, intersect, xor) between vector shapes.
+//
+// Both shapes are flattened to polygons and every edge is split where it
+// crosses the other polygon. Each piece then lies wholly inside or outside
+// the other shape, so the result is just a choice of pieces per operation,
+// chained back together into closed loops.
+
+use std::collections::HashMap;
+use log::warn;
+use uuid::Uuid;
+use crate::vector::{Point, PathOperation, Transform};
+use crate::vector::path::{Path, PathNodeType};
+use crate::vector::shape::{ShapeType, VectorShape};
+
+/// Distances below this are treated as zero
+const EPSILON: f64 = 1e-9;
+
+/// Where an edge piece lies relative to the other polygon
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Inside,
+    Outside,
+    /// On a boundary edge running the same way
+    SharedSame,
+    /// On a boundary edge running the opposite way
+    SharedOpposite,
+}
+
+fn signed_area(polygon: &[Point]) -> f64 {
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area / 2.0
+}
+
+/// Drop repeated points and give the polygon a positive signed area
+fn normalize(mut polygon: Vec<Point>) -> Vec<Point> {
+    polygon.dedup_by(|a, b| a.distance(b) < EPSILON);
+    while polygon.len() > 1 && polygon[0].distance(&polygon[polygon.len() - 1]) < EPSILON {
+        polygon.pop();
+    }
+    if signed_area(&polygon) < 0.0 {
+        polygon.reverse();
+    }
+    polygon
+}
+
+fn edges(polygon: &[Point]) -> Vec<(Point, Point)> {
+    (0..polygon.len())
+        .map(|i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+        .collect()
+}
+
+/// Parameters along `a` where it crosses or touches `b`
+fn crossings(a: (Point, Point), b: (Point, Point)) -> Vec<f64> {
+    let r = (a.1.x - a.0.x, a.1.y - a.0.y);
+    let s = (b.1.x - b.0.x, b.1.y - b.0.y);
+    let denominator = r.0 * s.1 - r.1 * s.0;
+    let offset = (b.0.x - a.0.x, b.0.y - a.0.y);
+    let length_sq = r.0 * r.0 + r.1 * r.1;
+    if length_sq < EPSILON {
+        return Vec::new();
+    }
+
+    if denominator.abs() < EPSILON {
+        // Parallel: only collinear overlaps matter, split at b's endpoints
+        if (offset.0 * r.1 - offset.1 * r.0).abs() > EPSILON * length_sq.sqrt().max(1.0) {
+            return Vec::new();
+        }
+        return [b.0, b.1].iter()
+            .map(|p| ((p.x - a.0.x) * r.0 + (p.y - a.0.y) * r.1) / length_sq)
+            .filter(|t| *t > EPSILON && *t < 1.0 - EPSILON)
+            .collect();
+    }
+
+    let t = (offset.0 * s.1 - offset.1 * s.0) / denominator;
+    let u = (offset.0 * r.1 - offset.1 * r.0) / denominator;
+    if t > EPSILON && t < 1.0 - EPSILON && u > -EPSILON && u < 1.0 + EPSILON {
+        vec![t]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Split every edge of `polygon` wherever the `other` polygon crosses it
+fn split_edges(polygon: &[Point], other: &[Point]) -> Vec<(Point, Point)> {
+    let other_edges = edges(other);
+    let mut pieces = Vec::new();
+    for edge in edges(polygon) {
+        let mut ts: Vec<f64> = other_edges.iter().flat_map(|&o| crossings(edge, o)).collect();
+        ts.push(0.0);
+        ts.push(1.0);
+        ts.sort_by(f64::total_cmp);
+        ts.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+
+        let at = |t: f64| Point::new(edge.0.x + (edge.1.x - edge.0.x) * t, edge.0.y + (edge.1.y - edge.0.y) * t);
+        for pair in ts.windows(2) {
+            let (from, to) = (at(pair[0]), at(pair[1]));
+            if from.distance(&to) > EPSILON {
+                pieces.push((from, to));
+            }
+        }
+    }
+    pieces
+}
+
+fn point_on_segment(p: &Point, a: &Point, b: &Point) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < EPSILON {
+        return p.distance(a) < 1e-6;
+    }
+    let cross = ((p.x - a.x) * dy - (p.y - a.y) * dx) / length;
+    let t = ((p.x - a.x) * dx + (p.y - a.y) * dy) / (length * length);
+    cross.abs() < 1e-6 && t > -EPSILON && t < 1.0 + EPSILON
+}
+
+/// Even-odd point in polygon test
+fn contains(polygon: &[Point], p: &Point) -> bool {
+    let mut inside = false;
+    for (a, b) in edges(polygon) {
+        if (a.y > p.y) != (b.y > p.y) {
+            let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+fn classify(piece: (Point, Point), other: &[Point]) -> Side {
+    let middle = Point::new((piece.0.x + piece.1.x) / 2.0, (piece.0.y + piece.1.y) / 2.0);
+    for (a, b) in edges(other) {
+        if point_on_segment(&middle, &a, &b) {
+            let same = (piece.1.x - piece.0.x) * (b.x - a.x) + (piece.1.y - piece.0.y) * (b.y - a.y) > 0.0;
+            return if same { Side::SharedSame } else { Side::SharedOpposite };
+        }
+    }
+    if contains(other, &middle) { Side::Inside } else { Side::Outside }
+}
+
+/// Chain directed edges into closed loops by matching endpoints
+fn chain(pieces: Vec<(Point, Point)>) -> Vec<Vec<Point>> {
+    let key = |p: &Point| ((p.x * 1e6).round() as i64, (p.y * 1e6).round() as i64);
+    let mut starting_at: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, (from, _)) in pieces.iter().enumerate() {
+        starting_at.entry(key(from)).or_default().push(i);
+    }
+
+    let mut used = vec![false; pieces.len()];
+    let mut loops = Vec::new();
+    for start in 0..pieces.len() {
+        if used[start] {
+            continue;
+        }
+        let mut contour = Vec::new();
+        let mut current = start;
+        loop {
+            used[current] = true;
+            contour.push(pieces[current].0);
+            let next = starting_at.get(&key(&pieces[current].1))
+                .and_then(|candidates| candidates.iter().copied().find(|&i| !used[i]));
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        let contour = normalize_loop(contour);
+        if contour.len() >= 3 && signed_area(&contour).abs() > EPSILON {
+            loops.push(contour);
+        }
+    }
+    loops
+}
+
+/// Drop points that lie on a straight line between their neighbours
+fn normalize_loop(points: Vec<Point>) -> Vec<Point> {
+    let n = points.len();
+    if n < 3 {
+        return points;
+    }
+    (0..n)
+        .filter(|&i| {
+            let (prev, p, next) = (&points[(i + n - 1) % n], &points[i], &points[(i + 1) % n]);
+            let cross = (p.x - prev.x) * (next.y - p.y) - (p.y - prev.y) * (next.x - p.x);
+            cross.abs() > EPSILON
+        })
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Loops outlining `op` applied to two normalized polygons. Only Union,
+/// Intersect and Subtract are handled here.
+fn boolean_loops(poly_a: &[Point], poly_b: &[Point], op: PathOperation) -> Vec<Vec<Point>> {
+    // Shared boundary pieces are taken from `a` only, so they appear once
+    let (keep_a, keep_b, reverse_b): (&[Side], &[Side], bool) = match op {
+        PathOperation::Union => (&[Side::Outside, Side::SharedSame], &[Side::Outside], false),
+        PathOperation::Intersect => (&[Side::Inside, Side::SharedSame], &[Side::Inside], false),
+        PathOperation::Subtract => (&[Side::Outside, Side::SharedOpposite], &[Side::Inside], true),
+        _ => return vec![poly_a.to_vec()],
+    };
+
+    let mut pieces: Vec<(Point, Point)> = split_edges(poly_a, poly_b).into_iter()
+        .filter(|&piece| keep_a.contains(&classify(piece, poly_b)))
+        .collect();
+    pieces.extend(split_edges(poly_b, poly_a).into_iter()
+        .filter(|&piece| keep_b.contains(&classify(piece, poly_a)))
+        .map(|piece| if reverse_b { (piece.1, piece.0) } else { piece }));
+    chain(pieces)
+}
+
+/// Combine the outlines of two shapes with a boolean operation.
+///
+/// The result is a `ShapeType::Custom` shape in document coordinates that
+/// takes its fill and stroke from `a`. Curves are flattened to lines first.
+/// When the result has several separate loops (a hole, or two islands) they
+/// are joined into the one path through zero-width bridges; the fill is
+/// right, but a stroke will show the bridges.
+///
+/// `Divide` produces several shapes and isn't supported here; like `None`
+/// it returns `a`'s outline unchanged.
+pub fn combine_shapes(a: &VectorShape, b: &VectorShape, op: PathOperation) -> VectorShape {
+    let poly_a = normalize(a.to_polygon());
+    let poly_b = normalize(b.to_polygon());
+    if poly_a.len() < 3 || poly_b.len() < 3 {
+        warn!("Boolean operation on a degenerate shape");
+        return build_shape(a, op, vec![poly_a]);
+    }
+
+    let loops = match op {
+        PathOperation::Union | PathOperation::Intersect | PathOperation::Subtract => {
+            boolean_loops(&poly_a, &poly_b, op)
+        },
+        PathOperation::XOR => {
+            let mut loops = boolean_loops(&poly_a, &poly_b, PathOperation::Subtract);
+            loops.extend(boolean_loops(&poly_b, &poly_a, PathOperation::Subtract));
+            loops
+        },
+        PathOperation::None | PathOperation::Divide => {
+            if op == PathOperation::Divide {
+                warn!("Divide isn't supported by combine_shapes; returning the first shape");
+            }
+            vec![poly_a]
+        },
+    };
+
+    build_shape(a, op, loops)
+}
+
+fn build_shape(a: &VectorShape, op: PathOperation, loops: Vec<Vec<Point>>) -> VectorShape {
+    let mut path = Path::new();
+    // Every loop after the first hangs off the first loop's start point: out
+    // along a bridge, round the loop, and back along the same bridge
+    if let Some(anchor) = loops.first().and_then(|l| l.first()).copied() {
+        for (i, contour) in loops.iter().enumerate() {
+            for point in contour {
+                path.add_point(point.x, point.y, PathNodeType::Point);
+            }
+            if loops.len() > 1 {
+                if i > 0 {
+                    path.add_point(contour[0].x, contour[0].y, PathNodeType::Point);
+                }
+                path.add_point(anchor.x, anchor.y, PathNodeType::Point);
+            }
+        }
+    }
+    path.set_closed(true);
+
+    let mut shape = a.clone();
+    shape.id = Uuid::new_v4().to_string();
+    shape.name = format!("{} ({:?})", a.name, op);
+    shape.shape_type = ShapeType::Custom { path };
+    shape.position = Point::new(0.0, 0.0);
+    shape.transform = Transform::identity();
+    shape.path_operation = PathOperation::None;
+    shape
+}
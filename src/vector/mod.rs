@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use gtk4::cairo::{LineCap, LineJoin, Path, Pattern};
 use gtk4::gdk::RGBA;
 use std::any::Any;
+use serde::{Deserialize, Serialize};
 use log::{debug, error, info, trace, warn};
 use crate::core::Canvas;
 
@@ -214,6 +215,99 @@ impl VectorPath {
         }
     }
 
+    /// Approximate the path as polylines, one per subpath.
+    ///
+    /// Curves and arcs are subdivided into `steps_per_curve` straight pieces.
+    /// Closed subpaths end with a copy of their starting point.
+    pub fn flatten(&self, steps_per_curve: usize) -> Vec<Vec<Point>> {
+        let steps = steps_per_curve.max(1);
+        let mut subpaths: Vec<Vec<Point>> = Vec::new();
+        let mut current: Vec<Point> = Vec::new();
+        
+        fn cubic(current: &mut Vec<Point>, p0: Point, c1: (f64, f64), c2: (f64, f64), end: (f64, f64), steps: usize) {
+            for i in 1..=steps {
+                let t = i as f64 / steps as f64;
+                let mt = 1.0 - t;
+                let a = mt * mt * mt;
+                let b = 3.0 * mt * mt * t;
+                let c = 3.0 * mt * t * t;
+                let d = t * t * t;
+                current.push(Point::new(
+                    a * p0.x + b * c1.0 + c * c2.0 + d * end.0,
+                    a * p0.y + b * c1.1 + c * c2.1 + d * end.1,
+                ));
+            }
+        }
+        
+        for segment in &self.segments {
+            let last = current.last().copied().unwrap_or(Point::new(0.0, 0.0));
+            match segment {
+                PathSegment::MoveTo(x, y) => {
+                    if current.len() > 1 {
+                        subpaths.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                    current.push(Point::new(*x, *y));
+                },
+                PathSegment::LineTo(x, y) => current.push(Point::new(*x, *y)),
+                PathSegment::CurveTo(x1, y1, x2, y2, x3, y3) => {
+                    cubic(&mut current, last, (*x1, *y1), (*x2, *y2), (*x3, *y3), steps);
+                },
+                PathSegment::QuadraticTo(x1, y1, x2, y2) => {
+                    let c1 = (last.x + 2.0/3.0 * (*x1 - last.x), last.y + 2.0/3.0 * (*y1 - last.y));
+                    let c2 = (*x2 + 2.0/3.0 * (*x1 - *x2), *y2 + 2.0/3.0 * (*y1 - *y2));
+                    cubic(&mut current, last, c1, c2, (*x2, *y2), steps);
+                },
+                PathSegment::ArcTo(rx, ry, angle, large_arc, sweep, x, y) => {
+                    let points = arc_to_bezier(last.x, last.y, *rx, *ry, *angle, *large_arc, *sweep, *x, *y);
+                    for i in (0..points.len()).step_by(6) {
+                        if i + 5 < points.len() {
+                            let start = current.last().copied().unwrap_or(last);
+                            cubic(&mut current, start, (points[i], points[i+1]), (points[i+2], points[i+3]), (points[i+4], points[i+5]), steps);
+                        }
+                    }
+                },
+                PathSegment::Close => {
+                    if let Some(&first) = current.first() {
+                        current.push(first);
+                        subpaths.push(std::mem::take(&mut current));
+                        // Cairo continues from the subpath's start after a close
+                        current.push(first);
+                    }
+                },
+            }
+        }
+        
+        if current.len() > 1 {
+            subpaths.push(current);
+        }
+        
+        subpaths
+    }
+    
+    /// Map gradient stops onto the path by arc length, so that position 0
+    /// is the start of the path and 1 is its end regardless of its shape.
+    pub fn gradient_along(&self, stops: &[(f64, Color)]) -> PathGradient {
+        let mut pieces = Vec::new();
+        let mut total_length = 0.0;
+        
+        for polyline in self.flatten(32) {
+            for pair in polyline.windows(2) {
+                let length = pair[0].distance_to(&pair[1]);
+                if length > 0.0 {
+                    pieces.push((pair[0], pair[1], total_length));
+                    total_length += length;
+                }
+            }
+        }
+        
+        PathGradient {
+            pieces,
+            total_length,
+            stops: stops.to_vec(),
+        }
+    }
+    
     pub fn draw_nodes(&self, context: &Context) {
         context.save().expect("Failed to save context");
         context.set_source_rgba(0.0, 0.0, 1.0, 0.8);
@@ -254,6 +348,87 @@ impl VectorPath {
     }
 }
 
+/// A gradient laid out along a path's arc length (see `VectorPath::gradient_along`)
+#[derive(Clone, Debug)]
+pub struct PathGradient {
+    /// Flattened pieces as (start, end, arc length at start)
+    pieces: Vec<(Point, Point, f64)>,
+    total_length: f64,
+    stops: Vec<(f64, Color)>,
+}
+
+impl PathGradient {
+    /// Length of the path the gradient is spread over
+    pub fn total_length(&self) -> f64 {
+        self.total_length
+    }
+    
+    /// Gradient color at parameter `t` along the path
+    pub fn color_at(&self, t: f64) -> Color {
+        shape::interpolate_stops(&self.stops, t)
+    }
+    
+    /// Gradient color at `distance` units from the start of the path
+    pub fn color_at_length(&self, distance: f64) -> Color {
+        if self.total_length <= 0.0 {
+            return self.color_at(0.0);
+        }
+        self.color_at(distance / self.total_length)
+    }
+    
+    /// Point on the path at parameter `t` (0..1 by arc length)
+    pub fn point_at(&self, t: f64) -> Option<Point> {
+        let target = t.clamp(0.0, 1.0) * self.total_length;
+        for &(start, end, offset) in &self.pieces {
+            let length = start.distance_to(&end);
+            if target <= offset + length {
+                return Some(start.lerp(&end, (target - offset) / length));
+            }
+        }
+        self.pieces.last().map(|&(_, end, _)| end)
+    }
+    
+    /// Color of the stroke at the path position closest to `point`
+    pub fn color_near(&self, point: &Point) -> Color {
+        let mut best_distance = f64::MAX;
+        let mut best_length = 0.0;
+        
+        for &(start, end, offset) in &self.pieces {
+            let dx = end.x - start.x;
+            let dy = end.y - start.y;
+            let length_sq = dx * dx + dy * dy;
+            let t = (((point.x - start.x) * dx + (point.y - start.y) * dy) / length_sq).clamp(0.0, 1.0);
+            let projected = start.lerp(&end, t);
+            let distance = projected.distance_to(point);
+            if distance < best_distance {
+                best_distance = distance;
+                best_length = offset + t * length_sq.sqrt();
+            }
+        }
+        
+        self.color_at_length(best_length)
+    }
+    
+    /// Stroke the path with the gradient following it
+    pub fn stroke(&self, cr: &Context, width: f64) {
+        cr.save().expect("Failed to save Cairo context");
+        cr.set_line_width(width);
+        // Butt caps keep neighbouring pieces from painting over each other
+        cr.set_line_cap(cairo::LineCap::Butt);
+        
+        for &(start, end, offset) in &self.pieces {
+            let mid = offset + start.distance_to(&end) / 2.0;
+            let color = self.color_at_length(mid);
+            cr.set_source_rgba(color.r, color.g, color.b, color.a);
+            cr.move_to(start.x, start.y);
+            cr.line_to(end.x, end.y);
+            cr.stroke().expect("Failed to stroke path gradient");
+        }
+        
+        cr.restore().expect("Failed to restore Cairo context");
+    }
+}
+
 fn draw_node_point(context: &Context, x: f64, y: f64, node_type: PathNodeType) {
     match node_type {
         PathNodeType::Point => {
@@ -645,22 +820,24 @@ impl VectorDocument {
 }
 
 // Helper function to convert SVG-style arc to bezier curves
-fn arc_to_bezier(x1: f64, y1: f64, rx: f64, ry: f64, angle: f64, large_arc: bool, sweep: bool, x2: f64, y2: f64) -> Vec<f64> {
+pub(crate) fn arc_to_bezier(x1: f64, y1: f64, rx: f64, ry: f64, angle: f64, large_arc: bool, sweep: bool, x2: f64, y2: f64) -> Vec<f64> {
     // Implementation based on SVG spec for approximating arcs with bezier curves
     // This is a simplified version - a full implementation would be more complex
     
     // Convert angles to radians
     let angle_rad = angle * PI / 180.0;
     
-    // Ensure radii are positive
-    let rx = rx.abs();
-    let ry = ry.abs();
-    
-    // If the arc is empty, connect with a straight line
+    // An arc between coincident points draws nothing
     if x1 == x2 && y1 == y2 {
         return vec![];
     }
     
+    // Without a usable radius (zero, negative or NaN) the arc is flat; any
+    // division by it below would turn the whole path into NaN
+    if !(rx > 0.0 && ry > 0.0 && rx.is_finite() && ry.is_finite() && angle_rad.is_finite()) {
+        return line_as_bezier(x1, y1, x2, y2);
+    }
+    
     // Step 1: Transform to origin
     let dx = (x1 - x2) / 2.0;
     let dy = (y1 - y2) / 2.0;
@@ -688,8 +865,11 @@ fn arc_to_bezier(x1: f64, y1: f64, rx: f64, ry: f64, angle: f64, large_arc: bool
     let x1psq = x1p.powi(2);
     let y1psq = y1p.powi(2);
     
+    // After scaling up the radii the numerator is zero in theory but can
+    // come out slightly negative; either way the center is the midpoint
     let term = rxsq * rysq - rxsq * y1psq - rysq * x1psq;
-    let term_sqrt = if term <= 0.0 { 0.0 } else { (term / (rxsq * y1psq + rysq * x1psq)).sqrt() };
+    let denominator = rxsq * y1psq + rysq * x1psq;
+    let term_sqrt = if term > 0.0 && denominator > 0.0 { (term / denominator).sqrt() } else { 0.0 };
     
     let sign = if large_arc == sweep { -1.0 } else { 1.0 };
     let cxp = sign * term_sqrt * rx_scaled * y1p / ry_scaled;
@@ -736,11 +916,12 @@ fn arc_to_bezier(x1: f64, y1: f64, rx: f64, ry: f64, angle: f64, large_arc: bool
     for _i in 0..segments {
         let angle = current_angle + delta_per_segment;
         
-        let p1x = cx + rx_scaled * current_angle.cos();
-        let p1y = cy + ry_scaled * current_angle.sin();
+        // Relative to the center; `t` rotates and moves them into place
+        let p1x = rx_scaled * current_angle.cos();
+        let p1y = ry_scaled * current_angle.sin();
         
-        let p2x = cx + rx_scaled * angle.cos();
-        let p2y = cy + ry_scaled * angle.sin();
+        let p2x = rx_scaled * angle.cos();
+        let p2y = ry_scaled * angle.sin();
         
         let tan = segment_angle;
         
@@ -766,9 +947,26 @@ fn arc_to_bezier(x1: f64, y1: f64, rx: f64, ry: f64, angle: f64, large_arc: bool
         current_angle = angle;
     }
     
+    if result.iter().any(|v| !v.is_finite()) {
+        return line_as_bezier(x1, y1, x2, y2);
+    }
     result
 }
 
+/// A straight line from (x1, y1) to (x2, y2) in `arc_to_bezier`'s format:
+/// one cubic segment with its control points a third of the way along
+fn line_as_bezier(x1: f64, y1: f64, x2: f64, y2: f64) -> Vec<f64> {
+    if !(x1.is_finite() && y1.is_finite() && x2.is_finite() && y2.is_finite()) {
+        return vec![];
+    }
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    vec![
+        x1 + dx / 3.0, y1 + dy / 3.0,
+        x1 + dx * 2.0 / 3.0, y1 + dy * 2.0 / 3.0,
+        x2, y2,
+    ]
+}
+
 fn vector_angle(ux: f64, uy: f64, vx: f64, vy: f64) -> f64 {
     let dot = ux * vx + uy * vy;
     let len = ((ux * ux + uy * uy) * (vx * vx + vy * vy)).sqrt();
@@ -824,16 +1022,19 @@ pub mod shape;
 pub mod path;
 pub mod text;
 pub mod document;
+pub mod boolean;
+pub mod svg;
 
-pub use self::shape::{VectorShape as ShapeImpl, ShapeType, FillStyle, StrokeStyle, Gradient, GradientType, Color, LineDash};
+pub use self::shape::{VectorShape as ShapeImpl, ShapeType, FillStyle, StrokeStyle, Gradient, GradientInterpolation, GradientSegment, GradientType, Color, LineDash, PatternFill};
 pub use self::path::{PathNode, PathNodeType, BezierPoint};
 pub use self::text::{TextShape, TextStyle, TextAlignment, FontWeight, FontStyle};
 pub use self::document::{VectorDocument as DocumentImpl, VectorLayer as LayerImpl};
+pub use self::boolean::combine_shapes;
 
 // Basic structures
 
 /// Point in 2D space
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -866,10 +1067,38 @@ impl Point {
             y: self.y + (other.y - self.y) * t_clamped,
         }
     }
+    
+    /// Component-wise sum, treating both points as vectors
+    pub fn add(&self, other: &Point) -> Self {
+        Self { x: self.x + other.x, y: self.y + other.y }
+    }
+    
+    /// Vector from `other` to this point
+    pub fn sub(&self, other: &Point) -> Self {
+        Self { x: self.x - other.x, y: self.y - other.y }
+    }
+    
+    pub fn scale(&self, factor: f64) -> Self {
+        Self { x: self.x * factor, y: self.y * factor }
+    }
+    
+    pub fn dot(&self, other: &Point) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+    
+    /// Unit vector in the same direction. A zero-length vector has no
+    /// direction and stays zero rather than turning into NaN.
+    pub fn normalize(&self) -> Self {
+        let length = self.distance(&Point { x: 0.0, y: 0.0 });
+        if length == 0.0 || !length.is_finite() {
+            return Self { x: 0.0, y: 0.0 };
+        }
+        Self { x: self.x / length, y: self.y / length }
+    }
 }
 
 /// Rectangle in 2D space
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Rect {
     pub x: f64,
     pub y: f64,
@@ -941,6 +1170,63 @@ impl Rect {
         debug!("Rect union: ({}, {}, {}, {})", x, y, width, height);
         Self { x, y, width, height }
     }
+    
+    /// Shrink by `amount` on every side, or grow for a negative amount. A
+    /// side that would cross its opposite collapses onto the center.
+    pub fn inset(&self, amount: f64) -> Self {
+        let width = (self.width - 2.0 * amount).max(0.0);
+        let height = (self.height - 2.0 * amount).max(0.0);
+        let center = self.center();
+        Self {
+            x: center.x - width / 2.0,
+            y: center.y - height / 2.0,
+            width,
+            height,
+        }
+    }
+    
+    pub fn offset(&self, dx: f64, dy: f64) -> Self {
+        Self { x: self.x + dx, y: self.y + dy, width: self.width, height: self.height }
+    }
+    
+    /// The part of this rectangle inside `bounds`. Without any overlap the
+    /// result is an empty rectangle on the nearest edge of `bounds`.
+    pub fn clamp_to(&self, bounds: &Rect) -> Self {
+        let right = bounds.x + bounds.width;
+        let bottom = bounds.y + bounds.height;
+        let x = self.x.max(bounds.x).min(right);
+        let y = self.y.max(bounds.y).min(bottom);
+        let width = ((self.x + self.width).min(right) - x).max(0.0);
+        let height = ((self.y + self.height).min(bottom) - y).max(0.0);
+        Self { x, y, width, height }
+    }
+}
+
+// Conversions to and from the document-space core types. Both sides use f64
+// document coordinates, so these are plain field copies.
+
+impl From<crate::core::Point> for Point {
+    fn from(point: crate::core::Point) -> Self {
+        Self { x: point.x, y: point.y }
+    }
+}
+
+impl From<Point> for crate::core::Point {
+    fn from(point: Point) -> Self {
+        Self { x: point.x, y: point.y }
+    }
+}
+
+impl From<crate::core::selection::Rect> for Rect {
+    fn from(rect: crate::core::selection::Rect) -> Self {
+        Self { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+}
+
+impl From<Rect> for crate::core::selection::Rect {
+    fn from(rect: Rect) -> Self {
+        Self { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
 }
 
 /// Affine transformation matrix
@@ -992,6 +1278,51 @@ impl Transform {
         }
     }
     
+    /// Shear along x by `sx_radians` and along y by `sy_radians`: a point
+    /// moves right by `tan(sx) * y` and down by `tan(sy) * x`
+    pub fn skew(sx_radians: f64, sy_radians: f64) -> Self {
+        debug!("Creating skew transform: ({}, {}) radians", sx_radians, sy_radians);
+        Self {
+            a: 1.0, c: sx_radians.tan(), e: 0.0,
+            b: sy_radians.tan(), d: 1.0, f: 0.0,
+        }
+    }
+    
+    /// Build `translation * rotation * skew * scale`, the inverse of `decompose`
+    pub fn from_components(translation: Point, rotation_degrees: f64, scale: (f64, f64), skew: (f64, f64)) -> Self {
+        Transform::translation(translation.x, translation.y)
+            .multiply(&Transform::rotation(rotation_degrees))
+            .multiply(&Transform::skew(skew.0, skew.1))
+            .multiply(&Transform::scale(scale.0, scale.1))
+    }
+    
+    /// Split the matrix into translation, rotation in degrees, scale and
+    /// skew in radians, such that `from_components` rebuilds it.
+    ///
+    /// Any affine matrix factors as translate * rotate * skew-x * scale, so
+    /// the y skew is always 0. A mirrored matrix comes out with a negative
+    /// y scale; a degenerate one with zero scale and no skew.
+    pub fn decompose(&self) -> (Point, f64, (f64, f64), (f64, f64)) {
+        let translation = Point::new(self.e, self.f);
+        let scale_x = (self.a * self.a + self.b * self.b).sqrt();
+        if scale_x < 1e-10 {
+            // The x axis collapses; all that's left is the y column
+            let scale_y = (self.c * self.c + self.d * self.d).sqrt();
+            let rotation = (-self.c).atan2(self.d) * 180.0 / PI;
+            return (translation, rotation, (0.0, scale_y), (0.0, 0.0));
+        }
+        
+        let rotation = self.b.atan2(self.a) * 180.0 / PI;
+        let determinant = self.a * self.d - self.b * self.c;
+        let scale_y = determinant / scale_x;
+        let skew_x = if determinant.abs() < 1e-10 {
+            0.0
+        } else {
+            ((self.a * self.c + self.b * self.d) / determinant).atan()
+        };
+        (translation, rotation, (scale_x, scale_y), (skew_x, 0.0))
+    }
+    
     pub fn multiply(&self, other: &Transform) -> Self {
         debug!("Multiplying transforms");
         Self {
@@ -417,6 +417,173 @@ impl Path {
         self.bounds = None; // Invalidate cached bounds
     }
     
+    /// The path as a polyline, with curved segments split into `steps` lines
+    pub fn flatten(&self, steps: usize) -> Vec<Point> {
+        let steps = steps.max(1);
+        let mut points: Vec<Point> = Vec::new();
+        if self.nodes.is_empty() {
+            return points;
+        }
+        
+        let segment_count = if self.closed { self.nodes.len() } else { self.nodes.len() - 1 };
+        points.push(self.nodes[0].point.position);
+        for i in 0..segment_count {
+            let from = &self.nodes[i].point;
+            let to = &self.nodes[(i + 1) % self.nodes.len()].point;
+            
+            if from.control_out != from.position || to.control_in != to.position {
+                for step in 1..=steps {
+                    let t = step as f64 / steps as f64;
+                    let u = 1.0 - t;
+                    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                    points.push(Point::new(
+                        a * from.position.x + b * from.control_out.x + c * to.control_in.x + d * to.position.x,
+                        a * from.position.y + b * from.control_out.y + c * to.control_in.y + d * to.position.y,
+                    ));
+                }
+            } else {
+                points.push(to.position);
+            }
+        }
+        
+        // Drop repeated points, which have no direction to offset along
+        points.dedup_by(|a, b| a.distance(b) < 1e-9);
+        if self.closed && points.len() > 1 && points[0].distance(&points[points.len() - 1]) < 1e-9 {
+            points.pop();
+        }
+        points
+    }
+    
+    /// A parallel path `distance` away from this one.
+    ///
+    /// For closed paths a positive distance grows the shape outward and a
+    /// negative one insets it, whatever the winding. For open paths positive
+    /// offsets go to the right of the direction of travel (in y-down canvas
+    /// coordinates). Corners on the outside of a turn are filled according
+    /// to `join`; curves are flattened first, so the result is made of lines.
+    ///
+    /// Self-intersections are not removed: insetting past a narrow feature
+    /// or offsetting a tight curve by more than its radius leaves loops that
+    /// need a separate cleanup pass.
+    pub fn offset(&self, distance: f64, join: LineJoin) -> Path {
+        const CURVE_STEPS: usize = 16;
+        const MITER_LIMIT: f64 = 4.0;
+        const ROUND_STEP: f64 = std::f64::consts::PI / 16.0;
+        
+        let points = self.flatten(CURVE_STEPS);
+        let mut result = Path::new();
+        result.closed = self.closed;
+        if points.len() < 2 || distance == 0.0 {
+            for point in &points {
+                result.add_point(point.x, point.y, PathNodeType::Point);
+            }
+            return result;
+        }
+        
+        // Winding decides which side is "outside" for a closed shape
+        let n = points.len();
+        let side = if self.closed {
+            let area: f64 = (0..n)
+                .map(|i| points[i].x * points[(i + 1) % n].y - points[(i + 1) % n].x * points[i].y)
+                .sum();
+            if area >= 0.0 { 1.0 } else { -1.0 }
+        } else {
+            -1.0
+        };
+        
+        // Unit normal of each edge, pointing to the offset side
+        let edge_count = if self.closed { n } else { n - 1 };
+        let normals: Vec<(f64, f64)> = (0..edge_count)
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % n]);
+                let length = a.distance(&b);
+                let (dx, dy) = ((b.x - a.x) / length, (b.y - a.y) / length);
+                (side * dy, -side * dx)
+            })
+            .collect();
+        let shifted = |p: Point, normal: (f64, f64)| Point::new(p.x + normal.0 * distance, p.y + normal.1 * distance);
+        
+        let mut output: Vec<Point> = Vec::new();
+        for i in 0..n {
+            let incoming = if i > 0 { Some(i - 1) } else if self.closed { Some(edge_count - 1) } else { None };
+            let outgoing = if i < edge_count { Some(i) } else { None };
+            
+            let (a, b) = match (incoming, outgoing) {
+                (Some(a), Some(b)) => (a, b),
+                // Open ends just get pushed out along their one edge
+                (Some(edge), None) | (None, Some(edge)) => {
+                    output.push(shifted(points[i], normals[edge]));
+                    continue;
+                },
+                (None, None) => continue,
+            };
+            
+            let vertex = points[i];
+            let end_of_a = shifted(vertex, normals[a]);
+            let start_of_b = shifted(vertex, normals[b]);
+            
+            // Edge directions, recovered from the normals
+            let dir_a = (-side * normals[a].1, side * normals[a].0);
+            let dir_b = (-side * normals[b].1, side * normals[b].0);
+            let cross = dir_a.0 * dir_b.1 - dir_a.1 * dir_b.0;
+            
+            if cross.abs() < 1e-9 {
+                // Straight through (or a full reversal, which we bevel)
+                output.push(end_of_a);
+                if end_of_a.distance(&start_of_b) > 1e-9 {
+                    output.push(start_of_b);
+                }
+                continue;
+            }
+            
+            // Where the two offset edges (as infinite lines) meet, measured
+            // along the incoming edge from its offset end
+            let (ex, ey) = (start_of_b.x - end_of_a.x, start_of_b.y - end_of_a.y);
+            let t = (ex * dir_b.1 - ey * dir_b.0) / cross;
+            let meet = Point::new(end_of_a.x + dir_a.0 * t, end_of_a.y + dir_a.1 * t);
+            
+            if t <= 0.0 {
+                // Inside of the turn: the offset edges overlap, trim them
+                output.push(meet);
+                continue;
+            }
+            
+            match join {
+                LineJoin::Round => {
+                    let start = normals[a].1.atan2(normals[a].0);
+                    let mut sweep = normals[b].1.atan2(normals[b].0) - start;
+                    if sweep > std::f64::consts::PI {
+                        sweep -= 2.0 * std::f64::consts::PI;
+                    } else if sweep < -std::f64::consts::PI {
+                        sweep += 2.0 * std::f64::consts::PI;
+                    }
+                    let steps = (sweep.abs() / ROUND_STEP).ceil().max(1.0) as usize;
+                    for step in 0..=steps {
+                        let angle = start + sweep * step as f64 / steps as f64;
+                        output.push(shifted(vertex, (angle.cos(), angle.sin())));
+                    }
+                },
+                LineJoin::Bevel => {
+                    output.push(end_of_a);
+                    output.push(start_of_b);
+                },
+                _ => {
+                    if meet.distance(&vertex) <= MITER_LIMIT * distance.abs() {
+                        output.push(meet);
+                    } else {
+                        output.push(end_of_a);
+                        output.push(start_of_b);
+                    }
+                },
+            }
+        }
+        
+        for point in output {
+            result.add_point(point.x, point.y, PathNodeType::Point);
+        }
+        result
+    }
+    
     pub fn build_path(&self, context: &Context) {
         if self.nodes.is_empty() {
             return;
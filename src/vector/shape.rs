@@ -3,8 +3,12 @@ use cairo::{Context, LineCap, LineJoin};
 use uuid::Uuid;
 use crate::vector::{Point, Rect, Transform, VectorObject, SelectionState, PathOperation};
 use crate::vector::path::Path;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::f64::consts::PI;
+use log::{debug, warn};
 use crate::vector::text::{TextShape, TextStyle, FontWeight};
+use crate::filters::{lab_to_rgb, rgb_to_lab};
 
 /// Represents a vector shape in the document.
 /// Shapes can be rectangles, ellipses, paths, or text.
@@ -60,6 +64,126 @@ impl Color {
     pub fn transparent() -> Self {
         Self::new(0.0, 0.0, 0.0, 0.0)
     }
+    
+    /// Linearly interpolate towards `other`; `t` is clamped to 0..1
+    pub fn lerp(&self, other: &Color, t: f64) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        Self {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+    
+    /// Hue in degrees (0-360), saturation and lightness (0-1)
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        
+        if delta <= f64::EPSILON {
+            return (0.0, 0.0, l);
+        }
+        
+        let s = if l > 0.5 { delta / (2.0 - max - min) } else { delta / (max + min) };
+        let h = if max == self.r {
+            ((self.g - self.b) / delta).rem_euclid(6.0)
+        } else if max == self.g {
+            (self.b - self.r) / delta + 2.0
+        } else {
+            (self.r - self.g) / delta + 4.0
+        };
+        
+        (h * 60.0, s, l)
+    }
+    
+    /// Build a color from hue in degrees (wrapped into 0-360), saturation and lightness
+    pub fn from_hsl(h: f64, s: f64, l: f64, a: f64) -> Self {
+        let h = h.rem_euclid(360.0);
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+        
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+        let m = l - c / 2.0;
+        let (r, g, b) = match (h / 60.0) as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        
+        Self::new(r + m, g + m, b + m, a)
+    }
+    
+    /// The same color with its hue rotated by `degrees`
+    pub fn rotate_hue(&self, degrees: f64) -> Self {
+        let (h, s, l) = self.to_hsl();
+        Self::from_hsl(h + degrees, s, l, self.a)
+    }
+    
+    /// The color opposite on the color wheel
+    pub fn complementary(&self) -> Self {
+        self.rotate_hue(180.0)
+    }
+    
+    /// `count` colors spaced evenly across `spread` degrees of hue, centered
+    /// on this color. With an odd count the middle entry is this color.
+    pub fn analogous(&self, count: usize, spread: f64) -> Vec<Color> {
+        if count <= 1 {
+            return vec![*self; count];
+        }
+        
+        let step = spread / (count - 1) as f64;
+        (0..count)
+            .map(|i| self.rotate_hue(-spread / 2.0 + step * i as f64))
+            .collect()
+    }
+    
+    /// The two colors that form an evenly spaced triad with this one
+    pub fn triadic(&self) -> [Color; 2] {
+        [self.rotate_hue(120.0), self.rotate_hue(240.0)]
+    }
+    
+    /// The two neighbours of the complementary color, 30° either side of it
+    pub fn split_complementary(&self) -> [Color; 2] {
+        [self.rotate_hue(150.0), self.rotate_hue(210.0)]
+    }
+}
+
+/// Evaluate a list of `(position, color)` gradient stops at `t`.
+///
+/// Stops are expected in ascending order; positions before the first stop
+/// or after the last one take that stop's color.
+pub fn interpolate_stops(stops: &[(f64, Color)], t: f64) -> Color {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Color::transparent(),
+    };
+    
+    if t <= first.0 {
+        return first.1;
+    }
+    if t >= last.0 {
+        return last.1;
+    }
+    
+    for pair in stops.windows(2) {
+        let (p0, c0) = pair[0];
+        let (p1, c1) = pair[1];
+        if t >= p0 && t <= p1 {
+            if p1 - p0 <= f64::EPSILON {
+                return c1;
+            }
+            return c0.lerp(&c1, (t - p0) / (p1 - p0));
+        }
+    }
+    
+    last.1
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -69,10 +193,169 @@ pub enum GradientType {
     Conical { center: Point, angle: f64 },
 }
 
+/// Color space a gradient segment blends through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientInterpolation {
+    /// Straight RGB blend; complementary colors meet in gray
+    Rgb,
+    /// Perceptual blend that keeps lightness changing evenly
+    Lab,
+    /// Blend around the hue wheel the short way, keeping colors saturated
+    Hsl,
+}
+
+impl Default for GradientInterpolation {
+    fn default() -> Self {
+        GradientInterpolation::Rgb
+    }
+}
+
+/// How the colors between two neighbouring stops are blended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientSegment {
+    pub interpolation: GradientInterpolation,
+    /// Where between the two stops (0-1) the colors are half blended;
+    /// 0.5 spreads the blend evenly
+    pub midpoint: f64,
+}
+
+impl Default for GradientSegment {
+    fn default() -> Self {
+        Self {
+            interpolation: GradientInterpolation::Rgb,
+            midpoint: 0.5,
+        }
+    }
+}
+
+impl GradientSegment {
+    pub fn new(interpolation: GradientInterpolation, midpoint: f64) -> Self {
+        Self { interpolation, midpoint }
+    }
+    
+    /// Whether the segment is a plain even RGB blend, which Cairo can draw
+    /// from the two end stops alone
+    pub fn is_linear_rgb(&self) -> bool {
+        self.interpolation == GradientInterpolation::Rgb && (self.midpoint - 0.5).abs() < 1e-9
+    }
+    
+    /// Blend `from` into `to` at `t` (0-1) within this segment
+    pub fn blend(&self, from: &Color, to: &Color, t: f64) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        // Bias so that t == midpoint maps to an even blend
+        let midpoint = self.midpoint.clamp(0.01, 0.99);
+        let t = if (midpoint - 0.5).abs() < 1e-9 { t } else { t.powf(0.5f64.ln() / midpoint.ln()) };
+        
+        match self.interpolation {
+            GradientInterpolation::Rgb => from.lerp(to, t),
+            GradientInterpolation::Lab => {
+                let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+                let lab = |c: &Color| rgb_to_lab([channel(c.r), channel(c.g), channel(c.b)]);
+                let (a, b) = (lab(from), lab(to));
+                let t32 = t as f32;
+                let mixed = lab_to_rgb([
+                    a[0] + (b[0] - a[0]) * t32,
+                    a[1] + (b[1] - a[1]) * t32,
+                    a[2] + (b[2] - a[2]) * t32,
+                ]);
+                let mut color = Color::from_rgb(mixed[0], mixed[1], mixed[2]);
+                color.a = from.a + (to.a - from.a) * t;
+                color
+            },
+            GradientInterpolation::Hsl => {
+                let (h0, s0, l0) = from.to_hsl();
+                let (h1, s1, l1) = to.to_hsl();
+                // A gray has no hue of its own; borrow the other end's
+                let h0 = if s0 <= f64::EPSILON { h1 } else { h0 };
+                let h1 = if s1 <= f64::EPSILON { h0 } else { h1 };
+                let dh = (h1 - h0 + 540.0).rem_euclid(360.0) - 180.0;
+                Color::from_hsl(
+                    h0 + dh * t,
+                    s0 + (s1 - s0) * t,
+                    l0 + (l1 - l0) * t,
+                    from.a + (to.a - from.a) * t,
+                )
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Gradient {
     pub gradient_type: GradientType,
     pub stops: Vec<(f64, Color)>, // position (0-1), color
+    /// Blend settings between stop `i` and stop `i + 1`; missing entries
+    /// are even RGB blends
+    pub segments: Vec<GradientSegment>,
+}
+
+impl Gradient {
+    /// Stops drawn per segment when a segment isn't a plain RGB blend
+    const SEGMENT_SAMPLES: usize = 16;
+    
+    /// Blend settings of the segment starting at stop `index`
+    pub fn segment(&self, index: usize) -> GradientSegment {
+        self.segments.get(index).copied().unwrap_or_default()
+    }
+    
+    /// Set the blend of the segment starting at stop `index`
+    pub fn set_segment(&mut self, index: usize, segment: GradientSegment) {
+        if self.segments.len() <= index {
+            self.segments.resize(index + 1, GradientSegment::default());
+        }
+        self.segments[index] = segment;
+    }
+    
+    /// Color of the gradient at parameter `t` (0 = first stop, 1 = last stop)
+    pub fn color_at(&self, t: f64) -> Color {
+        if self.segments.iter().all(GradientSegment::is_linear_rgb) {
+            return interpolate_stops(&self.stops, t);
+        }
+        
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Color::transparent(),
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+        for (index, pair) in self.stops.windows(2).enumerate() {
+            let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+            if t >= p0 && t <= p1 {
+                if p1 - p0 <= f64::EPSILON {
+                    return c1;
+                }
+                return self.segment(index).blend(&c0, &c1, (t - p0) / (p1 - p0));
+            }
+        }
+        last.1
+    }
+    
+    /// Stops for a renderer that only blends linearly in RGB (like Cairo):
+    /// segments with another color space or a moved midpoint are
+    /// approximated with extra stops
+    pub fn render_stops(&self) -> Vec<(f64, Color)> {
+        let mut stops = Vec::with_capacity(self.stops.len());
+        for (index, &(offset, color)) in self.stops.iter().enumerate() {
+            stops.push((offset, color));
+            let next = match self.stops.get(index + 1) {
+                Some(&next) => next,
+                None => break,
+            };
+            let segment = self.segment(index);
+            if segment.is_linear_rgb() {
+                continue;
+            }
+            for i in 1..Self::SEGMENT_SAMPLES {
+                let f = i as f64 / Self::SEGMENT_SAMPLES as f64;
+                stops.push((offset + (next.0 - offset) * f, segment.blend(&color, &next.1, f)));
+            }
+        }
+        stops
+    }
 }
 
 impl Default for Gradient {
@@ -80,6 +363,7 @@ impl Default for Gradient {
         Self {
             gradient_type: GradientType::Linear { start: Point::new(0.0, 0.0), end: Point::new(1.0, 1.0) },
             stops: vec![(0.0, Color::black()), (1.0, Color::white())],
+            segments: Vec::new(),
         }
     }
 }
@@ -89,7 +373,90 @@ pub enum FillStyle {
     None,
     Solid(Color),
     Gradient(Gradient),
-    Pattern(String), // Path to image or pattern name
+    Pattern(PatternFill),
+}
+
+/// An image file tiled across the filled area
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternFill {
+    /// Path of the image to tile
+    pub path: String,
+    /// Size of one tile relative to the image's pixels
+    pub scale: f64,
+    /// Where a tile's top-left corner sits, in shape coordinates
+    pub offset: Point,
+}
+
+impl PatternFill {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            scale: 1.0,
+            offset: Point::new(0.0, 0.0),
+        }
+    }
+    
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+    
+    pub fn with_offset(mut self, offset: Point) -> Self {
+        self.offset = offset;
+        self
+    }
+    
+    /// A repeating pattern of the image, or None when it can't be loaded
+    pub fn to_cairo_pattern(&self) -> Option<cairo::SurfacePattern> {
+        let surface = load_pattern_surface(&self.path)?;
+        let pattern = cairo::SurfacePattern::create(&surface);
+        pattern.set_extend(cairo::Extend::Repeat);
+        
+        // The pattern matrix maps user space into the image's pixels
+        let scale = if self.scale.abs() > 1e-6 { self.scale } else { 1.0 };
+        pattern.set_matrix(cairo::Matrix::new(
+            1.0 / scale, 0.0,
+            0.0, 1.0 / scale,
+            -self.offset.x / scale, -self.offset.y / scale,
+        ));
+        Some(pattern)
+    }
+}
+
+thread_local! {
+    // Cairo surfaces can't cross threads, so each thread keeps its own
+    static PATTERN_CACHE: RefCell<HashMap<String, cairo::ImageSurface>> = RefCell::new(HashMap::new());
+}
+
+/// Forget every pattern image loaded so far, so edited files are re-read
+pub fn clear_pattern_cache() {
+    PATTERN_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+fn load_pattern_surface(path: &str) -> Option<cairo::ImageSurface> {
+    if let Some(surface) = PATTERN_CACHE.with(|cache| cache.borrow().get(path).cloned()) {
+        return Some(surface);
+    }
+    
+    let image = match image::open(path) {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            warn!("Failed to load pattern {}: {}", path, e);
+            return None;
+        },
+    };
+    let (width, height) = image.dimensions();
+    let surface = match crate::core::tiles::to_cairo_surface(&image) {
+        Ok(surface) => surface,
+        Err(e) => {
+            warn!("Failed to convert pattern {}: {}", path, e);
+            return None;
+        },
+    };
+    
+    debug!("Loaded {}x{} pattern {}", width, height, path);
+    PATTERN_CACHE.with(|cache| cache.borrow_mut().insert(path.to_string(), surface.clone()));
+    Some(surface)
 }
 
 impl Default for FillStyle {
@@ -113,7 +480,7 @@ impl FillStyle {
                 match &gradient.gradient_type {
                     GradientType::Linear { start, end } => {
                         let linear = cairo::LinearGradient::new(start.x, start.y, end.x, end.y);
-                        for &(offset, color) in &gradient.stops {
+                        for (offset, color) in gradient.render_stops() {
                             linear.add_color_stop_rgba(
                                 offset,
                                 color.r,
@@ -129,7 +496,7 @@ impl FillStyle {
                             center.x, center.y, 0.0,
                             center.x, center.y, *radius
                         );
-                        for &(offset, color) in &gradient.stops {
+                        for (offset, color) in gradient.render_stops() {
                             radial.add_color_stop_rgba(
                                 offset,
                                 color.r,
@@ -140,21 +507,20 @@ impl FillStyle {
                         }
                         context.set_source(&radial).expect("Failed to set gradient source");
                     }
-                    GradientType::Conical { center: _, angle } => {
-                        let default_color = Color::new(0.0, 0.0, 0.0, 1.0);
-                        let color = gradient.stops.first().map(|(_, c)| c.clone()).unwrap_or(default_color);
-                        context.set_source_rgba(
-                            color.r,
-                            color.g,
-                            color.b,
-                            color.a
-                        );
+                    GradientType::Conical { center, angle } => {
+                        let conical = build_conical_pattern(*center, *angle, &gradient.render_stops());
+                        context.set_source(&conical).expect("Failed to set gradient source");
                     }
                 }
             }
-            FillStyle::Pattern(_) => {
-                // TODO: Implement pattern fill
-                context.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+            FillStyle::Pattern(pattern) => {
+                match pattern.to_cairo_pattern() {
+                    Some(surface_pattern) => {
+                        context.set_source(&surface_pattern).expect("Failed to set pattern source");
+                    }
+                    // Already warned about; fill with nothing rather than a wrong color
+                    None => context.set_source_rgba(0.0, 0.0, 0.0, 0.0),
+                }
             }
             FillStyle::None => {
                 context.set_source_rgba(0.0, 0.0, 0.0, 0.0);
@@ -163,6 +529,101 @@ impl FillStyle {
     }
 }
 
+/// How far a conical gradient reaches from its center. Cairo has no conic
+/// gradient, so it is built from mesh patches that have to end somewhere.
+const CONICAL_RADIUS: f64 = 10_000.0;
+
+/// Largest angular step (as a fraction of a full turn) of one mesh patch;
+/// small enough that each patch's color runs evenly with the angle.
+const CONICAL_STEP: f64 = 1.0 / 64.0;
+
+/// Color of a conical gradient at position `t` (0.0 - 1.0 around the circle),
+/// wrapping from the last stop back to the first
+fn conical_color_at(stops: &[(f64, Color)], t: f64) -> Color {
+    let first = stops[0];
+    let last = stops[stops.len() - 1];
+    
+    let lerp = |a: (f64, Color), b: (f64, Color), t: f64| {
+        let span = b.0 - a.0;
+        let f = if span > 0.0 { ((t - a.0) / span).clamp(0.0, 1.0) } else { 0.0 };
+        Color::new(
+            a.1.r + (b.1.r - a.1.r) * f,
+            a.1.g + (b.1.g - a.1.g) * f,
+            a.1.b + (b.1.b - a.1.b) * f,
+            a.1.a + (b.1.a - a.1.a) * f,
+        )
+    };
+    
+    if t < first.0 {
+        return lerp((last.0 - 1.0, last.1), first, t);
+    }
+    if t >= last.0 {
+        return lerp(last, (first.0 + 1.0, first.1), t);
+    }
+    let next = stops.iter().position(|&(offset, _)| offset > t).unwrap_or(stops.len() - 1);
+    lerp(stops[next - 1], stops[next], t)
+}
+
+/// Build a conical (angular) gradient around `center`.
+///
+/// `angle` is where the first stop sits, in radians clockwise from the
+/// positive x axis (Cairo's y-down convention), and wraps around 2π. The
+/// colors sweep clockwise through the stops and blend from the last stop
+/// back into the first. The gradient is a mesh pattern covering
+/// `CONICAL_RADIUS` around the center; outside that it is transparent.
+pub fn build_conical_pattern(center: Point, angle: f64, stops: &[(f64, Color)]) -> cairo::Pattern {
+    let mut stops: Vec<(f64, Color)> = stops.iter()
+        .map(|&(offset, color)| (offset.clamp(0.0, 1.0), color))
+        .collect();
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    if stops.is_empty() {
+        stops.push((0.0, Color::new(0.0, 0.0, 0.0, 1.0)));
+    }
+    
+    // Patch boundaries: every stop, plus enough steps in between
+    let mut breaks: Vec<f64> = vec![0.0, 1.0];
+    breaks.extend(stops.iter().map(|&(offset, _)| offset));
+    let steps = (1.0 / CONICAL_STEP) as usize;
+    breaks.extend((1..steps).map(|i| i as f64 * CONICAL_STEP));
+    breaks.sort_by(f64::total_cmp);
+    breaks.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+    
+    let start = angle.rem_euclid(2.0 * PI);
+    let point_at = |a: f64, r: f64| (center.x + r * a.cos(), center.y + r * a.sin());
+    
+    let mesh = cairo::Mesh::new();
+    for pair in breaks.windows(2) {
+        let (t0, t1) = (pair[0], pair[1]);
+        let (a0, a1) = (start + t0 * 2.0 * PI, start + t1 * 2.0 * PI);
+        // Just before t1, so a stop sitting exactly at t1 colors the next patch
+        let c0 = conical_color_at(&stops, t0);
+        let c1 = conical_color_at(&stops, t1 - 1e-9);
+        
+        // Circular arc as a cubic: control points at 4/3 tan(θ/4) of the radius
+        let k = 4.0 / 3.0 * ((a1 - a0) / 4.0).tan() * CONICAL_RADIUS;
+        let (x0, y0) = point_at(a0, CONICAL_RADIUS);
+        let (x1, y1) = point_at(a1, CONICAL_RADIUS);
+        
+        mesh.begin_patch();
+        mesh.move_to(center.x, center.y);
+        mesh.line_to(x0, y0);
+        mesh.curve_to(
+            x0 - k * a0.sin(), y0 + k * a0.cos(),
+            x1 + k * a1.sin(), y1 - k * a1.cos(),
+            x1, y1,
+        );
+        mesh.line_to(center.x, center.y);
+        
+        mesh.set_corner_color_rgba(cairo::MeshCorner::MeshCorner0, c0.r, c0.g, c0.b, c0.a);
+        mesh.set_corner_color_rgba(cairo::MeshCorner::MeshCorner1, c0.r, c0.g, c0.b, c0.a);
+        mesh.set_corner_color_rgba(cairo::MeshCorner::MeshCorner2, c1.r, c1.g, c1.b, c1.a);
+        mesh.set_corner_color_rgba(cairo::MeshCorner::MeshCorner3, c1.r, c1.g, c1.b, c1.a);
+        mesh.end_patch();
+    }
+    
+    (*mesh).clone()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineDash {
     Solid,
@@ -302,6 +763,84 @@ impl VectorShape {
         shape
     }
     
+    /// The shape's outline as a polygon in document coordinates, with
+    /// position and transform applied. Curves are split into line segments.
+    pub fn to_polygon(&self) -> Vec<Point> {
+        const CURVE_STEPS: usize = 64;
+        let ellipse = |rx: f64, ry: f64| -> Vec<Point> {
+            (0..CURVE_STEPS)
+                .map(|i| {
+                    let angle = 2.0 * PI * i as f64 / CURVE_STEPS as f64;
+                    Point::new(rx * angle.cos(), ry * angle.sin())
+                })
+                .collect()
+        };
+        
+        let local: Vec<Point> = match &self.shape_type {
+            ShapeType::Rectangle { width, height, corner_radius } => {
+                let radius = corner_radius.min(*width / 2.0).min(*height / 2.0);
+                if radius <= 0.0 {
+                    vec![
+                        Point::new(0.0, 0.0),
+                        Point::new(*width, 0.0),
+                        Point::new(*width, *height),
+                        Point::new(0.0, *height),
+                    ]
+                } else {
+                    // Quarter circles in the same order build_path draws them
+                    let corners = [
+                        (*width - radius, radius, -PI / 2.0),
+                        (*width - radius, *height - radius, 0.0),
+                        (radius, *height - radius, PI / 2.0),
+                        (radius, radius, PI),
+                    ];
+                    let steps = CURVE_STEPS / 4;
+                    corners.iter()
+                        .flat_map(|&(cx, cy, start)| {
+                            (0..=steps).map(move |i| {
+                                let angle = start + PI / 2.0 * i as f64 / steps as f64;
+                                Point::new(cx + radius * angle.cos(), cy + radius * angle.sin())
+                            })
+                        })
+                        .collect()
+                }
+            },
+            ShapeType::Ellipse { radius_x, radius_y } => ellipse(*radius_x, *radius_y),
+            ShapeType::Circle { radius } => ellipse(*radius, *radius),
+            ShapeType::Polygon { sides, radius } => {
+                if *sides < 3 {
+                    return Vec::new();
+                }
+                (0..*sides)
+                    .map(|i| {
+                        let angle = 2.0 * PI * i as f64 / *sides as f64;
+                        Point::new(radius * angle.cos(), radius * angle.sin())
+                    })
+                    .collect()
+            },
+            ShapeType::Star { outer_radius, inner_radius, points } => {
+                if *points < 3 {
+                    return Vec::new();
+                }
+                (0..*points * 2)
+                    .map(|i| {
+                        let angle = PI * i as f64 / *points as f64;
+                        let radius = if i % 2 == 1 { *inner_radius } else { *outer_radius };
+                        Point::new(radius * angle.cos(), radius * angle.sin())
+                    })
+                    .collect()
+            },
+            ShapeType::Custom { path } => path.flatten(CURVE_STEPS / 4),
+        };
+        
+        local.iter()
+            .map(|point| {
+                let transformed = self.transform.apply_to_point(point);
+                Point::new(transformed.x + self.position.x, transformed.y + self.position.y)
+            })
+            .collect()
+    }
+    
     /// Set fill properties for the shape
     pub fn set_fill(&mut self, fill: FillStyle) {
         self.fill = fill;
@@ -0,0 +1,569 @@
+// Importing SVG files into a vector document.
+//
+// Only the basic shapes are understood: rect, circle, ellipse, polygon,
+// polyline and path, filled with a color or a linear or radial gradient.
+// Everything else (text, images, filters, transforms, ...) is skipped with
+// a debug message, so a drawing that uses them still opens with whatever
+// could be read. Shapes are added to the document's first layer in the
+// order they appear, groups included.
+
+use log::{debug, warn};
+use crate::vector::{arc_to_bezier, Point, VectorObject};
+use crate::vector::document::VectorDocument;
+use crate::vector::path::{BezierPoint, Path, PathNode, PathNodeType};
+use crate::vector::shape::{Color, FillStyle, Gradient, GradientType, LineDash, ShapeType, StrokeStyle, VectorShape};
+
+/// Document size used when the SVG gives neither width/height nor a viewBox
+const DEFAULT_SIZE: (f64, f64) = (800.0, 600.0);
+
+/// Parse the SVG markup in `source` into a new document called `name`
+pub fn parse_svg(source: &str, name: &str) -> Result<VectorDocument, String> {
+    let tree = roxmltree::Document::parse(source).map_err(|e| format!("Invalid SVG: {}", e))?;
+    let root = tree.root_element();
+    if root.tag_name().name() != "svg" {
+        return Err(format!("Expected an <svg> root element, found <{}>", root.tag_name().name()));
+    }
+
+    let view_box: Vec<f64> = root.attribute("viewBox").map(parse_numbers).unwrap_or_default();
+    let (box_width, box_height) = if view_box.len() == 4 { (view_box[2], view_box[3]) } else { DEFAULT_SIZE };
+    let width = root.attribute("width").and_then(|v| parse_length(v, box_width)).unwrap_or(box_width);
+    let height = root.attribute("height").and_then(|v| parse_length(v, box_height)).unwrap_or(box_height);
+
+    let mut document = VectorDocument::new(name.to_string(), width, height);
+    let mut skipped = 0;
+    for node in root.descendants().filter(|n| n.is_element()) {
+        // Definitions are only drawn where something references them
+        let defined = node.ancestors().skip(1).any(|a| {
+            matches!(a.tag_name().name(), "defs" | "clipPath" | "mask" | "marker" | "pattern" | "symbol")
+        });
+        if defined {
+            continue;
+        }
+        let shapes = match node.tag_name().name() {
+            "rect" => rect(&node).into_iter().collect(),
+            "circle" => {
+                let r = length(&node, "r", 0.0);
+                if r > 0.0 {
+                    let mut shape = VectorShape::default();
+                    shape.position = Point::new(length(&node, "cx", 0.0), length(&node, "cy", 0.0));
+                    shape.shape_type = ShapeType::Circle { radius: r };
+                    vec![shape]
+                } else {
+                    Vec::new()
+                }
+            },
+            "ellipse" => {
+                let (rx, ry) = (length(&node, "rx", 0.0), length(&node, "ry", 0.0));
+                if rx > 0.0 && ry > 0.0 {
+                    vec![VectorShape::new_ellipse(length(&node, "cx", 0.0), length(&node, "cy", 0.0), rx, ry)]
+                } else {
+                    Vec::new()
+                }
+            },
+            "polygon" | "polyline" => {
+                let numbers = node.attribute("points").map(parse_numbers).unwrap_or_default();
+                let mut path = Path::new();
+                for pair in numbers.chunks_exact(2) {
+                    path.add_point(pair[0], pair[1], PathNodeType::Point);
+                }
+                path.set_closed(node.tag_name().name() == "polygon");
+                if path.node_count() >= 2 { vec![custom_shape(path)] } else { Vec::new() }
+            },
+            "path" => node.attribute("d")
+                .map(parse_path_data)
+                .unwrap_or_default()
+                .into_iter()
+                .map(custom_shape)
+                .collect(),
+            "svg" | "g" | "defs" | "title" | "desc" | "metadata" | "linearGradient" | "radialGradient" | "stop" => continue,
+            other => {
+                debug!("Skipping unsupported SVG element <{}>", other);
+                skipped += 1;
+                continue;
+            },
+        };
+
+        for mut shape in shapes {
+            if let Some(id) = node.attribute("id") {
+                shape.name = id.to_string();
+            }
+            apply_style(&tree, &node, &mut shape);
+            document.add_shape_to_active_layer(shape);
+        }
+    }
+
+    if skipped > 0 {
+        warn!("Skipped {} unsupported elements importing {}", skipped, name);
+    }
+    Ok(document)
+}
+
+fn rect(node: &roxmltree::Node) -> Option<VectorShape> {
+    let (width, height) = (length(node, "width", 0.0), length(node, "height", 0.0));
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    // A missing rx or ry takes the other's value; the shape only has one
+    let radius = match (node.attribute("rx"), node.attribute("ry")) {
+        (None, None) => 0.0,
+        (Some(_), _) => length(node, "rx", 0.0),
+        (None, Some(_)) => length(node, "ry", 0.0),
+    };
+    Some(VectorShape::new_rectangle(length(node, "x", 0.0), length(node, "y", 0.0), width, height, radius.max(0.0)))
+}
+
+fn custom_shape(path: Path) -> VectorShape {
+    let mut shape = VectorShape::default();
+    shape.name = "Path".to_string();
+    shape.shape_type = ShapeType::Custom { path };
+    shape
+}
+
+/// A length attribute in user units; percentages are of `reference`
+fn length(node: &roxmltree::Node, name: &str, default: f64) -> f64 {
+    node.attribute(name).and_then(|v| parse_length(v, 0.0)).unwrap_or(default)
+}
+
+fn parse_length(value: &str, reference: f64) -> Option<f64> {
+    let value = value.trim();
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent.trim().parse::<f64>().ok().map(|p| p / 100.0 * reference);
+    }
+    let number = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    number.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// All the numbers in a whitespace or comma separated list
+fn parse_numbers(value: &str) -> Vec<f64> {
+    let mut lexer = PathLexer::new(value);
+    let mut numbers = Vec::new();
+    while let Some(number) = lexer.number() {
+        numbers.push(number);
+    }
+    numbers
+}
+
+/// The value of presentation property `name` for `node`, from its style
+/// attribute, its own attribute or those of the nearest ancestor setting it
+fn property<'a, 'input>(node: &roxmltree::Node<'a, 'input>, name: &str) -> Option<&'a str> {
+    node.ancestors().filter(|n| n.is_element()).find_map(|n| {
+        let from_style = n.attribute("style").and_then(|style| {
+            style.split(';').find_map(|declaration| {
+                let (key, value) = declaration.split_once(':')?;
+                (key.trim() == name).then(|| value.trim())
+            })
+        });
+        from_style.or_else(|| n.attribute(name))
+    })
+}
+
+fn opacity(node: &roxmltree::Node, name: &str) -> f64 {
+    property(node, name)
+        .and_then(|v| parse_length(v, 1.0))
+        .map(|v| v.clamp(0.0, 1.0))
+        .unwrap_or(1.0)
+}
+
+fn apply_style(tree: &roxmltree::Document, node: &roxmltree::Node, shape: &mut VectorShape) {
+    let overall = opacity(node, "opacity");
+
+    shape.fill = match property(node, "fill").unwrap_or("black") {
+        "none" => FillStyle::None,
+        value => {
+            let alpha = opacity(node, "fill-opacity") * overall;
+            match value.strip_prefix("url(") {
+                Some(reference) => {
+                    let id = reference.trim_end_matches(')').trim().trim_start_matches('#');
+                    match gradient(tree, id, shape) {
+                        Some(gradient) => FillStyle::Gradient(gradient),
+                        None => {
+                            debug!("Unsupported fill reference {}; leaving the shape unfilled", value);
+                            FillStyle::None
+                        },
+                    }
+                },
+                None => match parse_color(value) {
+                    Some(mut color) => {
+                        color.a *= alpha;
+                        FillStyle::Solid(color)
+                    },
+                    None => FillStyle::Solid(Color::black()),
+                },
+            }
+        },
+    };
+
+    let mut stroke = StrokeStyle::default();
+    match property(node, "stroke").and_then(parse_color) {
+        Some(mut color) => {
+            color.a *= opacity(node, "stroke-opacity") * overall;
+            stroke.color = color;
+            stroke.width = property(node, "stroke-width").and_then(|v| parse_length(v, 0.0)).unwrap_or(1.0);
+        },
+        None => stroke.line_dash = LineDash::None,
+    }
+    shape.stroke = stroke;
+}
+
+/// Gradient `id` mapped into `shape`'s local coordinates. Stops may be
+/// inherited from another gradient through `href`.
+fn gradient(tree: &roxmltree::Document, id: &str, shape: &VectorShape) -> Option<Gradient> {
+    let find = |id: &str| tree.descendants().find(|n| n.is_element() && n.attribute("id") == Some(id));
+    let href = |n: &roxmltree::Node| {
+        n.attribute(("http://www.w3.org/1999/xlink", "href"))
+            .or_else(|| n.attribute("href"))
+            .map(|v| v.trim_start_matches('#').to_string())
+    };
+    let node = find(id)?;
+
+    // Follow the href chain to the first gradient that has stops
+    let mut source = node;
+    for _ in 0..8 {
+        if source.children().any(|c| c.tag_name().name() == "stop") {
+            break;
+        }
+        match href(&source).and_then(|id| find(&id)) {
+            Some(next) => source = next,
+            None => break,
+        }
+    }
+    let mut stops: Vec<(f64, Color)> = source.children()
+        .filter(|c| c.tag_name().name() == "stop")
+        .map(|stop| {
+            let offset = stop.attribute("offset").and_then(|v| parse_length(v, 1.0)).unwrap_or(0.0).clamp(0.0, 1.0);
+            let mut color = property(&stop, "stop-color").and_then(parse_color).unwrap_or_else(Color::black);
+            color.a *= opacity(&stop, "stop-opacity");
+            (offset, color)
+        })
+        .collect();
+    if stops.is_empty() {
+        return None;
+    }
+    // Offsets never go backwards
+    for i in 1..stops.len() {
+        stops[i].0 = stops[i].0.max(stops[i - 1].0);
+    }
+
+    // Bounding box units (the default) are fractions of the shape's bounds;
+    // user space units are document coordinates
+    let bounds = shape.get_bounds();
+    let user_space = node.attribute("gradientUnits") == Some("userSpaceOnUse");
+    let (reference_width, reference_height) = if user_space { (0.0, 0.0) } else { (1.0, 1.0) };
+    let coordinate = |name: &str, default: f64, horizontal: bool| {
+        let reference = if horizontal { reference_width } else { reference_height };
+        let value = node.attribute(name).and_then(|v| parse_length(v, reference)).unwrap_or(default);
+        if user_space {
+            value - if horizontal { shape.position.x } else { shape.position.y }
+        } else if horizontal {
+            bounds.x + value * bounds.width - shape.position.x
+        } else {
+            bounds.y + value * bounds.height - shape.position.y
+        }
+    };
+
+    let gradient_type = match node.tag_name().name() {
+        "linearGradient" => GradientType::Linear {
+            start: Point::new(coordinate("x1", 0.0, true), coordinate("y1", 0.0, false)),
+            end: Point::new(coordinate("x2", 1.0, true), coordinate("y2", 0.0, false)),
+        },
+        "radialGradient" => {
+            let default_center = if user_space { (bounds.x + bounds.width / 2.0, bounds.y + bounds.height / 2.0) } else { (0.5, 0.5) };
+            let r = node.attribute("r").and_then(|v| parse_length(v, 1.0)).unwrap_or(0.5);
+            GradientType::Radial {
+                center: Point::new(coordinate("cx", default_center.0, true), coordinate("cy", default_center.1, false)),
+                radius: if user_space { r } else { r * (bounds.width + bounds.height) / 2.0 },
+            }
+        },
+        _ => return None,
+    };
+    Some(Gradient { gradient_type, stops, segments: Vec::new() })
+}
+
+/// A color in any of the forms SVG allows except the full named list
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return match hex.len() {
+            3 => Some(Color::from_rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 => Some(Color::from_rgb(byte(0)?, byte(2)?, byte(4)?)),
+            _ => None,
+        };
+    }
+    if let Some(arguments) = value.strip_prefix("rgb(").or_else(|| value.strip_prefix("rgba(")) {
+        let parts: Vec<&str> = arguments.trim_end_matches(')').split(',').map(str::trim).collect();
+        if parts.len() < 3 {
+            return None;
+        }
+        let channel = |part: &str| parse_length(part, 255.0).map(|v| v.round().clamp(0.0, 255.0) as u8);
+        let mut color = Color::from_rgb(channel(parts[0])?, channel(parts[1])?, channel(parts[2])?);
+        if let Some(alpha) = parts.get(3) {
+            color.a = parse_length(alpha, 1.0)?.clamp(0.0, 1.0);
+        }
+        return Some(color);
+    }
+    let rgb = match value.to_ascii_lowercase().as_str() {
+        "black" => (0, 0, 0),
+        "white" => (255, 255, 255),
+        "red" => (255, 0, 0),
+        "lime" => (0, 255, 0),
+        "green" => (0, 128, 0),
+        "blue" => (0, 0, 255),
+        "yellow" => (255, 255, 0),
+        "cyan" | "aqua" => (0, 255, 255),
+        "magenta" | "fuchsia" => (255, 0, 255),
+        "gray" | "grey" => (128, 128, 128),
+        "silver" => (192, 192, 192),
+        "maroon" => (128, 0, 0),
+        "navy" => (0, 0, 128),
+        "olive" => (128, 128, 0),
+        "purple" => (128, 0, 128),
+        "teal" => (0, 128, 128),
+        "orange" => (255, 165, 0),
+        "transparent" => return Some(Color::transparent()),
+        _ => return None,
+    };
+    Some(Color::from_rgb(rgb.0, rgb.1, rgb.2))
+}
+
+/// Reads the commands, numbers and arc flags of path data
+struct PathLexer<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PathLexer<'a> {
+    fn new(data: &'a str) -> Self {
+        Self { bytes: data.as_bytes(), position: 0 }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.position < self.bytes.len()
+            && (self.bytes[self.position].is_ascii_whitespace() || self.bytes[self.position] == b',') {
+            self.position += 1;
+        }
+    }
+
+    /// The next command letter, if a letter comes next
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let byte = *self.bytes.get(self.position)?;
+        if byte.is_ascii_alphabetic() {
+            self.position += 1;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.position >= self.bytes.len()
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip_separators();
+        let start = self.position;
+        let mut end = start;
+        let at = |i: usize| self.bytes.get(i).copied().unwrap_or(0);
+        if at(end) == b'+' || at(end) == b'-' {
+            end += 1;
+        }
+        let mut seen_dot = false;
+        let mut digits = 0;
+        while at(end).is_ascii_digit() || (at(end) == b'.' && !seen_dot) {
+            seen_dot |= at(end) == b'.';
+            digits += at(end).is_ascii_digit() as usize;
+            end += 1;
+        }
+        if digits == 0 {
+            return None;
+        }
+        if at(end) == b'e' || at(end) == b'E' {
+            let mut exponent = end + 1;
+            if at(exponent) == b'+' || at(exponent) == b'-' {
+                exponent += 1;
+            }
+            if at(exponent).is_ascii_digit() {
+                while at(exponent).is_ascii_digit() {
+                    exponent += 1;
+                }
+                end = exponent;
+            }
+        }
+        let value = std::str::from_utf8(&self.bytes[start..end]).ok()?.parse().ok()?;
+        self.position = end;
+        Some(value)
+    }
+
+    /// An arc flag, which may be written without a separator before the
+    /// next number
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let value = match self.bytes.get(self.position)? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.position += 1;
+        Some(value)
+    }
+}
+
+/// Collects the nodes of the subpath being read
+struct SubpathBuilder {
+    paths: Vec<Path>,
+    current: Path,
+}
+
+impl SubpathBuilder {
+    fn move_to(&mut self, point: Point) {
+        self.finish(false);
+        self.current.add_point(point.x, point.y, PathNodeType::Point);
+    }
+
+    fn line_to(&mut self, from: Point, to: Point) {
+        if self.current.is_empty() {
+            self.current.add_point(from.x, from.y, PathNodeType::Point);
+        }
+        self.current.add_point(to.x, to.y, PathNodeType::Line);
+    }
+
+    fn curve_to(&mut self, from: Point, control_out: Point, control_in: Point, to: Point) {
+        if self.current.is_empty() {
+            self.current.add_point(from.x, from.y, PathNodeType::Point);
+        }
+        if let Some(last) = self.current.nodes.last_mut() {
+            last.point.control_out = control_out;
+        }
+        self.current.add_node(PathNode::with_bezier(BezierPoint::with_controls(to, control_in, to), PathNodeType::Asymmetric));
+    }
+
+    /// End the current subpath, keeping it if it draws anything. A closed
+    /// subpath that returned to its start merges the two end nodes.
+    fn finish(&mut self, closed: bool) {
+        let mut path = std::mem::replace(&mut self.current, Path::new());
+        if closed && path.node_count() > 2 {
+            let (first, last) = (&path.nodes[0].point, &path.nodes[path.node_count() - 1].point);
+            if first.position.distance(&last.position) < 1e-9 {
+                let control_in = last.control_in;
+                path.nodes.pop();
+                path.nodes[0].point.control_in = control_in;
+            }
+        }
+        path.set_closed(closed);
+        if path.node_count() >= 2 {
+            self.paths.push(path);
+        }
+    }
+}
+
+/// Split path data into one `Path` per subpath. Reading stops at the first
+/// error, keeping what came before it, as SVG renderers do.
+fn parse_path_data(data: &str) -> Vec<Path> {
+    let mut lexer = PathLexer::new(data);
+    let mut builder = SubpathBuilder { paths: Vec::new(), current: Path::new() };
+    let mut current = Point::new(0.0, 0.0);
+    let mut start = current;
+    let mut command = None;
+
+    while !lexer.at_end() {
+        if let Some(letter) = lexer.command() {
+            command = Some(letter);
+        }
+        let letter = match command {
+            Some(letter) => letter,
+            None => break,
+        };
+        let relative = letter.is_ascii_lowercase();
+        let base = if relative { current } else { Point::new(0.0, 0.0) };
+        let point = |lexer: &mut PathLexer| -> Option<Point> {
+            Some(Point::new(base.x + lexer.number()?, base.y + lexer.number()?))
+        };
+
+        let read = match letter.to_ascii_uppercase() {
+            b'M' => point(&mut lexer).map(|to| {
+                builder.move_to(to);
+                start = to;
+                current = to;
+                // Further pairs after a moveto are linetos
+                command = Some(if relative { b'l' } else { b'L' });
+            }),
+            b'L' => point(&mut lexer).map(|to| {
+                builder.line_to(current, to);
+                current = to;
+            }),
+            b'H' => lexer.number().map(|x| {
+                let to = Point::new(if relative { current.x + x } else { x }, current.y);
+                builder.line_to(current, to);
+                current = to;
+            }),
+            b'V' => lexer.number().map(|y| {
+                let to = Point::new(current.x, if relative { current.y + y } else { y });
+                builder.line_to(current, to);
+                current = to;
+            }),
+            b'C' => (|| Some((point(&mut lexer)?, point(&mut lexer)?, point(&mut lexer)?)))().map(|(c1, c2, to)| {
+                builder.curve_to(current, c1, c2, to);
+                current = to;
+            }),
+            b'Q' => (|| Some((point(&mut lexer)?, point(&mut lexer)?)))().map(|(control, to)| {
+                // The same curve as a cubic: controls two thirds of the way
+                // from each end to the quadratic control point
+                let c1 = current.lerp(&control, 2.0 / 3.0);
+                let c2 = to.lerp(&control, 2.0 / 3.0);
+                builder.curve_to(current, c1, c2, to);
+                current = to;
+            }),
+            b'A' => (|| {
+                let (rx, ry, angle) = (lexer.number()?, lexer.number()?, lexer.number()?);
+                let (large_arc, sweep) = (lexer.flag()?, lexer.flag()?);
+                Some((rx.abs(), ry.abs(), angle, large_arc, sweep, point(&mut lexer)?))
+            })().map(|(rx, ry, angle, large_arc, sweep, to)| {
+                let curves = arc_to_bezier(current.x, current.y, rx, ry, angle, large_arc, sweep, to.x, to.y);
+                let mut from = current;
+                for c in curves.chunks_exact(6) {
+                    let end = Point::new(c[4], c[5]);
+                    builder.curve_to(from, Point::new(c[0], c[1]), Point::new(c[2], c[3]), end);
+                    from = end;
+                }
+                current = to;
+            }),
+            b'Z' => {
+                builder.finish(true);
+                current = start;
+                command = None;
+                Some(())
+            },
+            _ => {
+                debug!("Unsupported path command '{}'", letter as char);
+                None
+            },
+        };
+        if read.is_none() {
+            warn!("Malformed path data; keeping the part read before it");
+            break;
+        }
+    }
+    builder.finish(false);
+    builder.paths
+}
+
+impl VectorDocument {
+    /// Import the SVG file at `path`, named after the file
+    pub fn from_svg(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let name = std::path::Path::new(path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Untitled");
+        parse_svg(&source, name)
+    }
+
+    /// Import SVG markup that is already in memory
+    pub fn from_svg_str(source: &str) -> Result<Self, String> {
+        parse_svg(source, "Untitled")
+    }
+}
@@ -210,6 +210,116 @@ impl TextShape {
         )
     }
     
+    /// Smallest font size `fit_to_box` will shrink text to
+    pub const MIN_FIT_FONT_SIZE: f64 = 4.0;
+    /// Largest font size `fit_to_box` will grow text to
+    pub const MAX_FIT_FONT_SIZE: f64 = 512.0;
+    
+    /// Break the text into lines no wider than `max_width` at the current
+    /// font settings of `context`, following the style's wrapping mode.
+    /// Explicit newlines always start a new line.
+    pub fn wrap_lines(&self, context: &Context, max_width: Option<f64>) -> Vec<String> {
+        let advance = |text: &str| context.text_extents(text).map(|e| e.x_advance()).unwrap_or(0.0);
+        let mut lines = Vec::new();
+        
+        for paragraph in self.text.split('\n') {
+            let max_width = match (max_width, self.style.wrapping) {
+                (Some(w), TextWrap::Word) | (Some(w), TextWrap::Character) => w,
+                _ => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                }
+            };
+            
+            let mut current = String::new();
+            if self.style.wrapping == TextWrap::Character {
+                for ch in paragraph.chars() {
+                    let mut candidate = current.clone();
+                    candidate.push(ch);
+                    if !current.is_empty() && advance(&candidate) > max_width {
+                        lines.push(std::mem::take(&mut current));
+                        current.push(ch);
+                    } else {
+                        current = candidate;
+                    }
+                }
+            } else {
+                for word in paragraph.split_whitespace() {
+                    let candidate = if current.is_empty() {
+                        word.to_string()
+                    } else {
+                        format!("{} {}", current, word)
+                    };
+                    // A word wider than the box still gets its own line
+                    if !current.is_empty() && advance(&candidate) > max_width {
+                        lines.push(std::mem::replace(&mut current, word.to_string()));
+                    } else {
+                        current = candidate;
+                    }
+                }
+            }
+            lines.push(current);
+        }
+        
+        lines
+    }
+    
+    /// Width and height of the wrapped text block at `font_size`
+    pub fn measure_wrapped(&self, context: &Context, font_size: f64, max_width: Option<f64>) -> (f64, f64) {
+        context.save();
+        context.select_font_face(
+            &self.style.font_family,
+            self.style.font_style.into(),
+            self.style.font_weight.into()
+        );
+        context.set_font_size(font_size);
+        
+        let lines = self.wrap_lines(context, max_width);
+        let width = lines.iter()
+            .map(|line| context.text_extents(line).map(|e| e.x_advance()).unwrap_or(0.0))
+            .fold(0.0, f64::max);
+        let height = lines.len() as f64 * font_size * self.style.line_height;
+        
+        context.restore();
+        (width, height)
+    }
+    
+    /// Pick the largest font size at which the text, wrapped to `width`,
+    /// fits inside a `width` x `height` box
+    pub fn fit_to_box(&mut self, width: f64, height: f64) {
+        self.fit_to_box_within(width, height, Self::MIN_FIT_FONT_SIZE, Self::MAX_FIT_FONT_SIZE);
+    }
+    
+    /// `fit_to_box` with explicit font size bounds. If the text doesn't fit
+    /// even at `min_size`, `min_size` is used.
+    pub fn fit_to_box_within(&mut self, width: f64, height: f64, min_size: f64, max_size: f64) {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+        let context = Context::new(&surface).unwrap();
+        
+        let fits = |size: f64| {
+            let (w, h) = self.measure_wrapped(&context, size, Some(width));
+            w <= width && h <= height
+        };
+        
+        let (mut low, mut high) = (min_size.min(max_size), max_size.max(min_size));
+        if fits(high) {
+            low = high;
+        } else {
+            // Text size vs. extent is monotonic enough for a plain bisection
+            for _ in 0..24 {
+                let mid = (low + high) / 2.0;
+                if fits(mid) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+        }
+        
+        self.style.max_width = Some(width);
+        self.set_font_size(low);
+    }
+    
     pub fn update_path(&mut self) {
         // Create a new in-memory surface to generate the path
         let surface = cairo::ImageSurface::create(
@@ -271,20 +381,20 @@ impl TextShape {
             self.style.color.a
         );
         
-        // Get text extents
-        let extents = context.text_extents(&self.text).unwrap();
-        
-        // Position text based on alignment
-        let (x, y) = match self.style.alignment {
-            TextAlignment::Left => (0.0, 0.0),
-            TextAlignment::Center => (-extents.width() / 2.0, 0.0),
-            TextAlignment::Right => (-extents.width(), 0.0),
-            TextAlignment::Justified => (0.0, 0.0), // Same as left for single line
-        };
-        
-        // Draw text
-        context.move_to(x, y);
-        context.show_text(&self.text).unwrap();
+        // Draw each wrapped line, positioned based on alignment
+        let line_advance = self.style.font_size * self.style.line_height;
+        for (i, line) in self.wrap_lines(context, self.style.max_width).iter().enumerate() {
+            let extents = context.text_extents(line).unwrap();
+            let x = match self.style.alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => -extents.width() / 2.0,
+                TextAlignment::Right => -extents.width(),
+                TextAlignment::Justified => 0.0, // Justification is not implemented yet
+            };
+            
+            context.move_to(x, i as f64 * line_advance);
+            context.show_text(line).unwrap();
+        }
         
         // Draw selection indicators if selected
         if self.selection_state != SelectionState::None {